@@ -0,0 +1,123 @@
+//! Benchmarks of the render path: rendering and wrapping the entries of the history, ranking and
+//! filtering completions, and drawing the whole shell on a key press with a long history.
+//!
+//! Run them with `cargo bench`, and compare against a previous run before a release.
+
+// The functions generated by `criterion_group!` cannot be documented.
+#![allow(missing_docs)]
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use shelgon::config::Config;
+use shelgon::output::{self, HistoryEntryWidget};
+use shelgon::rank::{self, Candidate, Frecency};
+use shelgon::renderer::App;
+use shelgon::testing::{synthetic_history, Echo, MockTerminal, Script};
+use shelgon::theme::Theme;
+
+/// The sizes of the history, and of the lists of completions, measured.
+const SIZES: [usize; 3] = [100, 1_000, 10_000];
+
+fn app(history: usize, words: usize) -> App<Echo> {
+    let words = (0..words).map(|word| format!("git-{}", word)).collect();
+    App::new_with_executor(
+        //
+        // # Safety: a benchmark cannot go on without a runtime.
+        //
+        #[allow(clippy::expect_used)]
+        tokio::runtime::Runtime::new().expect("runtime failed"),
+        Echo { words },
+        (),
+    )
+    .with_config(Config::default())
+    .with_history(synthetic_history(history))
+}
+
+fn history_entry(c: &mut Criterion) {
+    let theme = Theme::default();
+    let renderers = output::builtin();
+    let entries = synthetic_history(4);
+    let kinds = ["text", "json", "table", "stderr"];
+
+    let mut group = c.benchmark_group("history_entry");
+    for (kind, entry) in kinds.iter().zip(&entries) {
+        group.bench_with_input(BenchmarkId::new("lines", kind), entry, |b, entry| {
+            b.iter(|| {
+                HistoryEntryWidget::new(entry, &theme)
+                    .renderers(&renderers)
+                    .lines()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn wrapping(c: &mut Criterion) {
+    let theme = Theme::default();
+    let renderers = output::builtin();
+    let entries = synthetic_history(100);
+
+    let mut group = c.benchmark_group("wrapping");
+    for width in [40, 80, 200] {
+        group.bench_with_input(BenchmarkId::new("wrapped", width), &width, |b, &width| {
+            b.iter(|| {
+                entries
+                    .iter()
+                    .map(|entry| {
+                        let widget = HistoryEntryWidget::new(entry, &theme).renderers(&renderers);
+                        widget.wrapped(width).len()
+                    })
+                    .sum::<usize>()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn completion(c: &mut Criterion) {
+    let mut group = c.benchmark_group("completion");
+    for size in SIZES {
+        let texts = (0..size)
+            .map(|text| format!("git-{}", text))
+            .collect::<Vec<_>>();
+        let candidates = texts
+            .iter()
+            .enumerate()
+            .map(|(index, text)| Candidate {
+                text,
+                count: (index % 17) as u64,
+                age: None,
+            })
+            .collect::<Vec<_>>();
+        group.bench_with_input(
+            BenchmarkId::new("rank", size),
+            &candidates,
+            |b, candidates| b.iter(|| rank::rank(&Frecency, candidates)),
+        );
+        group.bench_function(BenchmarkId::new("tab", size), |b| {
+            b.iter_batched(
+                || app(0, size),
+                |app| MockTerminal::new(80, 24).run(app, Script::new().text("git-1").key("Tab")),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn keystroke(c: &mut Criterion) {
+    let mut group = c.benchmark_group("keystroke");
+    group.sample_size(20);
+    for size in SIZES {
+        group.bench_function(BenchmarkId::new("history", size), |b| {
+            b.iter_batched(
+                || app(size, 0),
+                |app| MockTerminal::new(80, 24).run(app, Script::new().text("echo hello")),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, history_entry, wrapping, completion, keystroke);
+criterion_main!(benches);
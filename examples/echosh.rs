@@ -3,7 +3,7 @@
 //! This example implements a simple shell that:
 //! - Echoes back any command entered
 //! - Provides special handling for the "cat" command to demonstrate STDIN support
-//! - Uses a minimal custom context
+//! - Registers a "count" builtin, counting the commands echoed in its context
 //!
 //! # Usage
 //!
@@ -14,13 +14,16 @@
 //! After running, you can:
 //! - Type any command to see it echoed back
 //! - Use the "cat" command to test multi-line input (Ctrl+D to finish)
+//! - Use "count" to see how many commands were echoed, and "count reset" to start over
 //! - Press Ctrl+C or Ctrl+D to exit
 
 /// An executor that echoes back commands
 pub struct Executor {}
 
-/// Empty context since this example doesn't need state
-pub struct Context {}
+/// The number of commands echoed so far
+pub struct Context {
+    count: usize,
+}
 
 impl shelgon::command::New for Executor {
     fn new() -> anyhow::Result<(Self, Self::Context)>
@@ -28,7 +31,7 @@ impl shelgon::command::New for Executor {
         Self: Sized,
     {
         // Initialize with empty context
-        Ok((Self {}, Self::Context {}))
+        Ok((Self {}, Self::Context { count: 0 }))
     }
 }
 
@@ -56,18 +59,43 @@ impl shelgon::command::Execute for Executor {
         }
     }
 
+    fn builtins(&self) -> Vec<shelgon::command::Builtin<Self::Context>> {
+        // A builtin working on the context, rather than being echoed
+        let count = shelgon::command::Builtin::new("count", |ctx: &mut Context, cmd| {
+            if cmd.command.split_whitespace().nth(1) == Some("reset") {
+                ctx.count = 0;
+            }
+            let output = shelgon::command::CommandOutput {
+                prompt: cmd.prompt,
+                command: cmd.command,
+                stdin: Vec::new(),
+                stdout: vec![format!("{} commands echoed", ctx.count)],
+                stderr: Vec::new(),
+                ..Default::default()
+            };
+            Ok(shelgon::command::OutputAction::Command(output))
+        })
+        .with_help("Print the number of commands echoed so far, or reset it with `count reset`.")
+        .with_usage("count [reset]")
+        .with_completions(["reset"]);
+
+        vec![count]
+    }
+
     fn execute(
         &self,
-        _ctx: &mut Self::Context,
+        ctx: &mut Self::Context,
         cmd: shelgon::command::CommandInput,
     ) -> anyhow::Result<shelgon::command::OutputAction> {
         // Echo the command back as output
+        ctx.count += 1;
         let output = shelgon::command::CommandOutput {
             prompt: cmd.prompt,
             command: cmd.command.clone(),
             stdin: cmd.stdin.unwrap_or_default(),
             stdout: vec![cmd.command],
             stderr: Vec::new(),
+            ..Default::default()
         };
         Ok(shelgon::command::OutputAction::Command(output))
     }
@@ -0,0 +1,418 @@
+//! Arithmetic expansion: `$((EXPRESSION))` in a command stands for the value of `EXPRESSION`.
+//!
+//! Expressions are on 64-bit integers, with the operators of POSIX shells, by decreasing
+//! precedence: the unary `+ - ! ~`, `**`, `* / %`, `+ -`, `<< >>`, `< <= > >=`, `== !=`, `&`, `^`,
+//! `|`, `&&`, `||` and `?:`, grouped with parentheses. As in bash, `-2 ** 2` is `4`, and `**` is
+//! right-associative: `2 ** 3 ** 2` is `512`. The operands `&&`, `||` and `?:` do not take are
+//! not evaluated, so `0 && 1 / 0` is no division by zero. Numbers are decimal, hexadecimal (`0x1f`)
+//! or octal (`017`). A name stands for the environment variable, with or without a `$`: `$((N +
+//! 1))` and `$(($N + 1))` are the same, and an unset or empty variable is `0`.
+//!
+//! An expression that does not parse, divides by zero or uses a variable that is not a number is
+//! an error, shown in place of the command: the command is not run with the expression as it is.
+//! Expansions within single quotes, or escaped with `\`, are left as they are.
+
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+/// The binary operators, by increasing precedence, up to the multiplicative ones. (`**` binds
+/// tighter, though less than the unary operators, and is parsed apart: see [`Parser::power`])
+const BINARY: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+/// The operators, the longest first, as they are read.
+const OPERATORS: &[&str] = &[
+    "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "&",
+    "|", "^", "!", "~", "?", ":", "(", ")",
+];
+
+/// Replace every arithmetic expansion of a command with its value. The expansions nested in an
+/// expression are replaced first.
+pub(crate) fn expand(command: &str) -> anyhow::Result<String> {
+    let mut command = command.to_string();
+    let mut from = 0;
+    while let Some((start, end)) = find(&command, from) {
+        let expression = expand(&command[start + 3..end - 1])?;
+        let value = evaluate(&expression)
+            .map_err(|e| anyhow::anyhow!("`$(({}))`: {}", expression.trim(), e))?
+            .to_string();
+        command.replace_range(start..=end, &value);
+        from = start + value.len();
+    }
+    Ok(command)
+}
+
+/// Evaluate an arithmetic expression.
+fn evaluate(expression: &str) -> anyhow::Result<i64> {
+    let mut parser = Parser {
+        tokens: tokens(expression)?.into_iter().peekable(),
+        skipping: 0,
+    };
+    let value = parser.ternary()?;
+    match parser.tokens.next() {
+        Some(token) => anyhow::bail!("unexpected `{}`", token),
+        None => Ok(value),
+    }
+}
+
+/// The first expansion of a command starting at `from` or after, as the indices of its `$` and of
+/// its last closing parenthesis.
+fn find(command: &str, from: usize) -> Option<(usize, usize)> {
+    let bytes = command.as_bytes();
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, &byte) in bytes.iter().enumerate() {
+        match (quote, byte) {
+            _ if escaped => escaped = false,
+            (Some(b'\''), b'\'') | (Some(b'"'), b'"') => quote = None,
+            (Some(b'\''), _) => {}
+            (_, b'\\') => escaped = true,
+            (None, b'\'' | b'"') => quote = Some(byte),
+            (_, b'$') if index >= from && bytes[index..].starts_with(b"$((") => {
+                let mut depth = 0usize;
+                for (offset, &byte) in bytes[index + 3..].iter().enumerate() {
+                    let at = index + 3 + offset;
+                    match byte {
+                        b'(' => depth += 1,
+                        b')' if depth == 0 && bytes.get(at + 1) == Some(&b')') => {
+                            return Some((index, at + 1));
+                        }
+                        b')' if depth == 0 => break,
+                        b')' => depth -= 1,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A token of an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// A number.
+    Number(i64),
+    /// The name of a variable.
+    Name(String),
+    /// An operator, or a parenthesis.
+    Operator(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Name(name) => f.write_str(name),
+            Token::Operator(operator) => f.write_str(operator),
+        }
+    }
+}
+
+/// Read the tokens of an expression.
+fn tokens(expression: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.char_indices().peekable();
+    while let Some(&(index, c)) = chars.peek() {
+        let rest = &expression[index..];
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            let word = take(&mut chars, rest, |c| c.is_ascii_alphanumeric());
+            tokens.push(Token::Number(number(word)?));
+        } else if c == '$' || c == '_' || c.is_ascii_alphabetic() {
+            let name = rest.strip_prefix('$').unwrap_or(rest);
+            if c == '$' {
+                chars.next();
+            }
+            let name = take(&mut chars, name, |c| c == '_' || c.is_ascii_alphanumeric());
+            if name.is_empty() {
+                anyhow::bail!("unexpected `$`");
+            }
+            tokens.push(Token::Name(name.to_string()));
+        } else {
+            let Some(operator) = OPERATORS
+                .iter()
+                .find(|operator| rest.starts_with(*operator))
+            else {
+                anyhow::bail!("unexpected `{}`", c);
+            };
+            for _ in 0..operator.len() {
+                chars.next();
+            }
+            tokens.push(Token::Operator(operator));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Take the characters at the start of the text matching the predicate.
+fn take<'a>(
+    chars: &mut Peekable<CharIndices<'_>>,
+    text: &'a str,
+    matches: impl Fn(char) -> bool,
+) -> &'a str {
+    let end = text.find(|c| !matches(c)).unwrap_or(text.len());
+    for _ in text[..end].chars() {
+        chars.next();
+    }
+    &text[..end]
+}
+
+/// A number, decimal, hexadecimal (`0x`) or octal (a leading `0`).
+fn number(word: &str) -> anyhow::Result<i64> {
+    let parsed = match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None if word.len() > 1 && word.starts_with('0') => i64::from_str_radix(&word[1..], 8),
+        None => word.parse(),
+    };
+    parsed.map_err(|_| anyhow::anyhow!("`{}` is not a number", word))
+}
+
+/// The value of a variable: the environment variable of the name, or `0` if it is unset or empty.
+fn variable(name: &str) -> anyhow::Result<i64> {
+    let value = std::env::var(name).unwrap_or_default();
+    match value.trim() {
+        "" => Ok(0),
+        value => value
+            .strip_prefix('-')
+            .map_or_else(
+                || number(value),
+                |value| number(value).map(i64::wrapping_neg),
+            )
+            .map_err(|_| anyhow::anyhow!("`{}` is not a number: {}", name, value)),
+    }
+}
+
+///
+/// [`Parser`] evaluates an expression as it reads its tokens.
+///
+struct Parser {
+    /// The tokens not read yet.
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+    /// How deep the parser is in operands not evaluated, such as the right of `0 && ...`, where
+    /// dividing by zero is not an error.
+    skipping: usize,
+}
+
+impl Parser {
+    /// `CONDITION ? THEN : ELSE`, or a binary expression.
+    fn ternary(&mut self) -> anyhow::Result<i64> {
+        let condition = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let then = self.skip(condition == 0, Self::ternary)?;
+        self.expect(":")?;
+        let otherwise = self.skip(condition != 0, Self::ternary)?;
+        Ok(if condition != 0 { then } else { otherwise })
+    }
+
+    /// A binary expression of the operators of the level, or above. (See [`BINARY`])
+    fn binary(&mut self, level: usize) -> anyhow::Result<i64> {
+        let Some(operators) = BINARY.get(level) else {
+            return self.power();
+        };
+        let mut left = self.binary(level + 1)?;
+        while let Some(operator) = self.operator(operators) {
+            let skipped = match operator {
+                "&&" => left == 0,
+                "||" => left != 0,
+                _ => false,
+            };
+            let right = self.skip(skipped, |parser| parser.binary(level + 1))?;
+            left = self.apply(operator, left, right)?;
+        }
+        Ok(left)
+    }
+
+    /// `BASE ** EXPONENT`, right-associative, or a unary expression.
+    fn power(&mut self) -> anyhow::Result<i64> {
+        let base = self.unary()?;
+        if !self.eat("**") {
+            return Ok(base);
+        }
+        let exponent = self.power()?;
+        self.apply("**", base, exponent)
+    }
+
+    /// A unary operator and its operand, or a primary expression.
+    fn unary(&mut self) -> anyhow::Result<i64> {
+        if let Some(operator) = self.operator(&["+", "-", "!", "~"]) {
+            let operand = self.unary()?;
+            return Ok(match operator {
+                "-" => operand.wrapping_neg(),
+                "!" => i64::from(operand == 0),
+                "~" => !operand,
+                _ => operand,
+            });
+        }
+        match self.tokens.next() {
+            Some(Token::Number(number)) => Ok(number),
+            Some(Token::Name(name)) => variable(&name),
+            Some(Token::Operator("(")) => {
+                let value = self.ternary()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            Some(token) => anyhow::bail!("unexpected `{}`", token),
+            None => anyhow::bail!("unexpected end of expression"),
+        }
+    }
+
+    /// Apply a binary operator.
+    fn apply(&self, operator: &str, left: i64, right: i64) -> anyhow::Result<i64> {
+        let value = match operator {
+            "/" | "%" if right == 0 && self.skipping > 0 => 0,
+            "/" | "%" if right == 0 => anyhow::bail!("division by zero"),
+            "/" => left.wrapping_div(right),
+            "%" => left.wrapping_rem(right),
+            "**" => match u32::try_from(right) {
+                Ok(exponent) => left.wrapping_pow(exponent),
+                Err(_) if self.skipping > 0 => 0,
+                Err(_) => anyhow::bail!("negative exponent"),
+            },
+            "*" => left.wrapping_mul(right),
+            "+" => left.wrapping_add(right),
+            "-" => left.wrapping_sub(right),
+            "<<" => left.wrapping_shl(right.rem_euclid(64) as u32),
+            ">>" => left.wrapping_shr(right.rem_euclid(64) as u32),
+            "<" => i64::from(left < right),
+            "<=" => i64::from(left <= right),
+            ">" => i64::from(left > right),
+            ">=" => i64::from(left >= right),
+            "==" => i64::from(left == right),
+            "!=" => i64::from(left != right),
+            "&" => left & right,
+            "^" => left ^ right,
+            "|" => left | right,
+            "&&" => i64::from(left != 0 && right != 0),
+            "||" => i64::from(left != 0 || right != 0),
+            _ => anyhow::bail!("unexpected `{}`", operator),
+        };
+        Ok(value)
+    }
+
+    /// Parse an operand, as not evaluated if `skipped`.
+    fn skip(
+        &mut self,
+        skipped: bool,
+        parse: impl FnOnce(&mut Self) -> anyhow::Result<i64>,
+    ) -> anyhow::Result<i64> {
+        self.skipping += usize::from(skipped);
+        let value = parse(self);
+        self.skipping -= usize::from(skipped);
+        value
+    }
+
+    /// Read the next token if it is one of the operators.
+    fn operator(&mut self, operators: &[&str]) -> Option<&'static str> {
+        match self.tokens.peek() {
+            Some(Token::Operator(operator)) if operators.contains(operator) => {
+                let operator = *operator;
+                self.tokens.next();
+                Some(operator)
+            }
+            _ => None,
+        }
+    }
+
+    /// Read the next token if it is the operator.
+    fn eat(&mut self, operator: &str) -> bool {
+        self.operator(&[operator]).is_some()
+    }
+
+    /// Read the operator, which must come next.
+    fn expect(&mut self, operator: &str) -> anyhow::Result<()> {
+        match self.tokens.next() {
+            Some(Token::Operator(next)) if next == operator => Ok(()),
+            Some(token) => anyhow::bail!("expected `{}`, found `{}`", operator, token),
+            None => anyhow::bail!("expected `{}`", operator),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operators_bind_by_precedence() -> anyhow::Result<()> {
+        assert_eq!(evaluate("1 + 2 * 3")?, 7);
+        assert_eq!(evaluate("(1 + 2) * 3")?, 9);
+        assert_eq!(evaluate("10 - 4 - 3")?, 3);
+        assert_eq!(evaluate("7 % 4 * 2")?, 6);
+        assert_eq!(evaluate("1 << 2 + 1")?, 8);
+        assert_eq!(evaluate("1 | 2 ^ 3 & 1")?, 3);
+        assert_eq!(evaluate("5 > 3 == 1")?, 1);
+        assert_eq!(evaluate("1 || 0 && 0")?, 1);
+        assert_eq!(evaluate("0 ? 1 : 1 ? 2 : 3")?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn unary_operators_bind_tighter_than_power() -> anyhow::Result<()> {
+        assert_eq!(evaluate("-2 ** 2")?, 4);
+        assert_eq!(evaluate("-(2 ** 2)")?, -4);
+        assert_eq!(evaluate("2 ** 3 ** 2")?, 512);
+        assert_eq!(evaluate("2 * 3 ** 2")?, 18);
+        assert_eq!(evaluate("!0 + ~0")?, 0);
+        assert!(evaluate("2 ** -1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn dividing_by_zero_is_an_error() {
+        assert!(evaluate("1 / 0").is_err());
+        assert!(evaluate("1 % (2 - 2)").is_err());
+        assert!(evaluate("1 && 1 / 0").is_err());
+        assert!(expand("echo $((1 / 0))").is_err());
+    }
+
+    #[test]
+    fn operands_not_taken_are_not_evaluated() -> anyhow::Result<()> {
+        assert_eq!(evaluate("0 && 1 / 0")?, 0);
+        assert_eq!(evaluate("1 || 1 % 0")?, 1);
+        assert_eq!(evaluate("1 ? 2 : 1 / 0")?, 2);
+        assert_eq!(evaluate("0 ? 1 / 0 : 3")?, 3);
+        assert_eq!(evaluate("0 && (1 ? 2 ** -1 : 0)")?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn numbers_are_decimal_hexadecimal_or_octal() -> anyhow::Result<()> {
+        assert_eq!(evaluate("0x1f")?, 31);
+        assert_eq!(evaluate("0XFF")?, 255);
+        assert_eq!(evaluate("017")?, 15);
+        assert_eq!(evaluate("0")?, 0);
+        assert_eq!(evaluate("010 + 0x10 + 10")?, 34);
+        assert!(evaluate("08").is_err());
+        assert!(evaluate("0xg").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn expansions_are_replaced_outside_of_single_quotes() -> anyhow::Result<()> {
+        assert_eq!(expand("echo $((1 + 2)) $(( $((2)) * 3 ))")?, "echo 3 6");
+        assert_eq!(
+            expand("echo '$((1 + 2))' \\$((1))")?,
+            "echo '$((1 + 2))' \\$((1))"
+        );
+        assert_eq!(
+            expand("echo \"$((SHELGON_UNSET_VARIABLE + 1))\"")?,
+            "echo \"1\""
+        );
+        Ok(())
+    }
+}
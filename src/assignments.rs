@@ -0,0 +1,68 @@
+//! Environment assignments before a command, as in POSIX shells, for executors running commands
+//! of their own.
+//!
+//! [`split`] takes the `NAME=VALUE` words at the start of a command, `FOO=bar BAZ=qux cmd args`,
+//! apart from the rest of it. An executor spawning a process for the command sets the variables
+//! for that process alone, such as with [`std::process::Command::envs`], leaving the environment
+//! kept in its context as it is: the variables are gone once the command completes.
+//!
+//! A name is made of letters, digits and `_`, and does not start with a digit. Values may be
+//! quoted, with `'` or `"`, and are unquoted. A command made of assignments alone has no rest,
+//! and is left to the executor: POSIX shells set such variables in the shell itself.
+//!
+//! ```rust
+//! use shelgon::assignments;
+//!
+//! let (variables, rest) = assignments::split("FOO=bar BAZ='q u x' cmd args");
+//! assert_eq!(
+//!     variables,
+//!     [
+//!         ("FOO".to_string(), "bar".to_string()),
+//!         ("BAZ".to_string(), "q u x".to_string())
+//!     ]
+//! );
+//! assert_eq!(rest, "cmd args");
+//!
+//! assert_eq!(assignments::split("cmd FOO=bar"), (Vec::new(), "cmd FOO=bar"));
+//! assert_eq!(assignments::split("1FOO=bar cmd").1, "1FOO=bar cmd");
+//! ```
+
+/// Split the assignments at the start of a command from the rest of it, which starts at the first
+/// word that is not an assignment.
+pub fn split(command: &str) -> (Vec<(String, String)>, &str) {
+    let mut variables = Vec::new();
+    let mut rest = command.trim_start();
+    while let Some((name, value, after)) = assignment(rest) {
+        variables.push((name.to_string(), value));
+        rest = after.trim_start();
+    }
+    (variables, rest)
+}
+
+/// The assignment at the start of the text, with its name, its value unquoted and the text after
+/// it. This returns `None` if the text does not start with an assignment.
+fn assignment(text: &str) -> Option<(&str, String, &str)> {
+    let (name, after) = text.split_once('=')?;
+    let mut chars = name.chars();
+    if !chars
+        .next()
+        .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        || !chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+    {
+        return None;
+    }
+
+    let mut value = String::new();
+    let mut quote = None;
+    for (index, c) in after.char_indices() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), c) => value.push(c),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, c) if c.is_whitespace() => return Some((name, value, &after[index..])),
+            (None, c) => value.push(c),
+        }
+    }
+    // An unterminated quote is not an assignment, but the start of a command to be told about it.
+    quote.is_none().then_some((name, value, ""))
+}
@@ -0,0 +1,81 @@
+//! Corrections of mistyped commands.
+//!
+//! When the first word of a command, past its environment assignments (see
+//! [`crate::assignments`]), is not one the shell knows (a command of the executor, see
+//! [`crate::command::Execute::commands`], or a builtin such as `:theme`) but is close to one, the
+//! shell offers to run the corrected command instead, before running anything:
+//!
+//! - `y` (or `Enter`) runs the corrected command.
+//! - `n` runs the command as typed.
+//! - `e` puts the corrected command on the prompt, to be edited.
+//! - `Esc` goes back to the command as typed.
+//!
+//! Close means one edit away (a character inserted, deleted, replaced, or two characters swapped),
+//! or two edits away for words longer than [`SHORT`] characters. Corrections are turned off with
+//! `ui.autocorrect` in [`crate::config::Ui`].
+
+/// The length of the words only corrected when one edit away.
+const SHORT: usize = 4;
+
+use crate::assignments;
+
+/// The command with its first word corrected to the closest of the known words, if it is not
+/// known itself. Among words as close, the first one is picked.
+pub(crate) fn correct(command: &str, known: &[String]) -> Option<String> {
+    let rest = assignments::split(command).1;
+    let start = command.len() - rest.len();
+    let word = rest.split_whitespace().next()?;
+    let limit = match word.chars().count() > SHORT {
+        true => 2,
+        false => 1,
+    };
+
+    let mut closest: Option<(usize, &String)> = None;
+    for candidate in known {
+        let distance = distance(word, candidate);
+        if distance == 0 {
+            return None;
+        }
+        if distance <= limit && closest.map_or(true, |(closest, _)| distance < closest) {
+            closest = Some((distance, candidate));
+        }
+    }
+
+    let (_, correction) = closest?;
+    let end = start + word.len();
+    Some(format!(
+        "{}{}{}",
+        &command[..start],
+        correction,
+        &command[end..]
+    ))
+}
+
+/// The number of edits turning `a` into `b`: characters inserted, deleted or replaced, and
+/// adjacent characters swapped. (The optimal string alignment distance)
+fn distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+
+    // The distances between the prefixes of `a` and `b`, two rows back, one row back, and on the
+    // current row.
+    let mut before = vec![0; b.len() + 1];
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
@@ -0,0 +1,126 @@
+//! Autosave, and recovering a session after a crash.
+//!
+//! While the shell runs, its state is saved every `history.autosave` seconds, once a command ran
+//! since the last save (see [`crate::config::History`]): the history, the working directory and
+//! the environment variables changed in the session. The state is written as a transcript (see
+//! [`crate::transcript`]) in `autosave/PID.shtr` in the data directory, and removed when the shell
+//! exits.
+//!
+//! A file left behind by a shell that is no longer running is of a session that crashed, or was
+//! killed. On the next launch, the shell offers to restore it: its entries are added to the
+//! history, and the working directory and the environment variables are set back. The file is
+//! removed once answered, either way.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::transcript::Transcript;
+
+/// The directory of the autosaves. (`autosave` in the data directory)
+fn directory() -> Option<PathBuf> {
+    config::data_dir().map(|dir| dir.join("autosave"))
+}
+
+/// The file the state of this shell is saved to.
+pub(crate) fn path() -> Option<PathBuf> {
+    directory().map(|dir| dir.join(format!("{}.shtr", std::process::id())))
+}
+
+/// Save a transcript to the file, replacing it at once: a crash while saving keeps the last save.
+///
+/// The session may hold secrets in its output and environment, so on unix the directory is created
+/// with mode `0700`, and the file with mode `0600`.
+pub(crate) fn save(path: &Path, transcript: &Transcript) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(dir)?;
+    }
+    let partial = path.with_extension("shtr.partial");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    transcript.write(io::BufWriter::new(options.open(&partial)?))?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// The process that wrote an autosave, from its name. (`PID.shtr`, or `PID.shtr.partial` while
+/// being written)
+fn writer(path: &Path) -> Option<u32> {
+    let name = path.file_name()?.to_str()?;
+    let pid = name
+        .strip_suffix(".shtr")
+        .or_else(|| name.strip_suffix(".shtr.partial"))?;
+    pid.parse().ok()
+}
+
+/// The autosave left behind by a shell that is no longer running, the most recent one if there
+/// are several.
+///
+/// A partial save left behind, by a shell that crashed while saving, is removed: the last whole
+/// save is the one to restore.
+pub(crate) fn orphan() -> Option<PathBuf> {
+    let entries = fs::read_dir(directory()?).ok()?;
+    let (partial, saved): (Vec<_>, Vec<_>) = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| writer(path).is_some_and(|pid| !running(pid)))
+        .partition(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "partial")
+        });
+    for path in partial {
+        let _ = fs::remove_file(path);
+    }
+    saved
+        .into_iter()
+        .max_by_key(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+}
+
+/// Check whether a process is running.
+#[cfg(unix)]
+fn running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: `kill` with the signal 0 only checks that the process exists, sending nothing.
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Check whether a process is running: without a way to tell, every process is, so no autosave
+/// is offered to be restored.
+#[cfg(not(unix))]
+fn running(_pid: u32) -> bool {
+    true
+}
+
+/// The environment variables of the process, as a snapshot to compare to later. (See
+/// [`changes`])
+pub(crate) fn environment() -> BTreeMap<String, String> {
+    std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+        .collect()
+}
+
+/// The environment variables changed since the snapshot, with their value now, or `None` if they
+/// were unset.
+pub(crate) fn changes(snapshot: &BTreeMap<String, String>) -> BTreeMap<String, Option<String>> {
+    let now = environment();
+    let unset = snapshot
+        .keys()
+        .filter(|name| !now.contains_key(*name))
+        .map(|name| (name.clone(), None));
+    let set = now
+        .iter()
+        .filter(|(name, value)| snapshot.get(*name) != Some(*value))
+        .map(|(name, value)| (name.clone(), Some(value.clone())));
+    unset.chain(set).collect()
+}
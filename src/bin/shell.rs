@@ -0,0 +1,508 @@
+//! The `sheller` binary.
+//!
+//! # Usage
+//!
+//! ```bash
+//! sheller                      # start the shell
+//! sheller --screen-reader      # start the shell in screen reader mode
+//! sheller --no-color           # start the shell without colors
+//! sheller --stress N           # start the shell with N made-up history entries, to profile it
+//! sheller --restore FILE       # start the shell with the history of a transcript
+//! sheller --latency MS         # delay every round trip to the executor, as over a slow link
+//! sheller --system             # run the commands on the system, rather than echoing them
+//! sheller runbook FILE.md      # run the commands of a Markdown runbook, step by step
+//! sheller runbook --system FILE.md  # the same, running them on the system
+//! sheller config check [PATH]  # validate the configuration file
+//! sheller history import --from FORMAT [PATH]  # import the history of another shell
+//! sheller history export --to FORMAT [PATH]    # export the history for another shell
+//! sheller history sync [PROFILE]               # sync the history with other machines
+//! sheller transcript cat FILE                   # print the commands of a transcript
+//! sheller transcript pack FILE                  # write a transcript of JSON lines on stdin
+//! sheller transcript unpack FILE                # print a transcript as JSON lines
+//! sheller daemon [SESSION]     # start a detachable session in the background
+//! sheller attach [SESSION]     # attach to a session (detach with Ctrl+Q)
+//! sheller sessions             # list the sessions
+//! sheller embed [--msgpack]    # exchange structured events on stdin/stdout, for GUIs
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use shelgon::{
+    braces, command,
+    config::Config,
+    history,
+    protocol::{Entry, Format},
+    renderer,
+    runbook::Runbook,
+    testing,
+    theme::ColorSupport,
+    transcript::Transcript,
+};
+
+/// An executor that echoes back commands, with their braces expanded.
+struct Executor {
+    /// The delay of every round trip, as if the commands ran over a slow link, if any.
+    latency: Option<Duration>,
+}
+
+impl command::New for Executor {
+    fn new() -> anyhow::Result<(Self, Self::Context)> {
+        Ok((Self { latency: None }, ()))
+    }
+}
+
+impl command::Execute for Executor {
+    type Context = ();
+
+    fn prompt(&self, _ctx: &Self::Context) -> String {
+        std::thread::sleep(self.latency.unwrap_or_default());
+        "$".to_string()
+    }
+
+    fn prepare(&self, cmd: &str) -> command::Prepare {
+        command::Prepare {
+            command: braces::expand(cmd),
+            stdin_required: false,
+        }
+    }
+
+    fn remote(&self) -> bool {
+        self.latency.is_some()
+    }
+
+    // The prompt never changes.
+    fn predict_prompt(&self, _ctx: &Self::Context, _cmd: &str) -> Option<String> {
+        Some("$".to_string())
+    }
+
+    // Every tab echoes alike: a session holds nothing of its own.
+    fn open_session(&self, _ctx: &Self::Context) -> anyhow::Result<Option<Self::Context>> {
+        Ok(Some(()))
+    }
+
+    fn execute(
+        &self,
+        _ctx: &mut Self::Context,
+        cmd: command::CommandInput,
+    ) -> anyhow::Result<command::OutputAction> {
+        std::thread::sleep(self.latency.unwrap_or_default());
+        Ok(command::OutputAction::Command(command::CommandOutput {
+            prompt: cmd.prompt,
+            command: cmd.command.clone(),
+            stdin: cmd.stdin.unwrap_or_default(),
+            stdout: vec![braces::expand(&cmd.command)],
+            stderr: Vec::new(),
+            exit_code: Some(0),
+            ..Default::default()
+        }))
+    }
+}
+
+const USAGE: &str = "usage: sheller [OPTIONS] | runbook [OPTIONS] FILE.md | config check [PATH] \
+                     | history import --from FORMAT [PATH] | history export --to FORMAT [PATH] \
+                     | history sync [PROFILE] | transcript cat|pack|unpack FILE \
+                     | daemon [OPTIONS] [SESSION] | attach [SESSION] | sessions \
+                     | embed [OPTIONS] [--msgpack]\n\
+                     options: [--screen-reader] [--no-color] [--stress N] [--restore FILE] \
+                     [--latency MS | --system]";
+
+/// The session used when none is named.
+#[cfg(unix)]
+const DEFAULT_SESSION: &str = "default";
+
+/// The options of the shell, given on the command line.
+#[derive(Default)]
+struct Options {
+    /// Run the shell in screen reader mode.
+    screen_reader: bool,
+    /// Disable colors.
+    no_color: bool,
+    /// The number of made-up entries to start the history with, to measure the render path.
+    stress: usize,
+    /// The transcript to start the history with.
+    restore: Option<PathBuf>,
+    /// The delay of every round trip to the executor, as if it ran over a slow link.
+    latency: Option<Duration>,
+    /// Run the commands on the system, rather than echoing them.
+    system: bool,
+}
+
+impl Options {
+    /// Parse the options of the shell, returning them with the other arguments, in order. An
+    /// unknown option, or one missing its value, prints the usage and exits.
+    fn parse<'a>(args: &[&'a str]) -> (Self, Vec<&'a str>) {
+        let mut options = Self::default();
+        let mut rest = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "--screen-reader" => options.screen_reader = true,
+                "--no-color" => options.no_color = true,
+                "--system" => options.system = true,
+                "--stress" => match args.next().and_then(|entries| entries.parse().ok()) {
+                    Some(entries) => options.stress = entries,
+                    None => usage(),
+                },
+                "--restore" => match args.next() {
+                    Some(path) => options.restore = Some(PathBuf::from(path)),
+                    None => usage(),
+                },
+                "--latency" => match args.next().and_then(|ms| ms.parse().ok()) {
+                    Some(ms) => options.latency = Some(Duration::from_millis(ms)),
+                    None => usage(),
+                },
+                flag if flag.starts_with("--") => usage(),
+                arg => rest.push(arg),
+            }
+        }
+        // The commands run on the system are not delayed: the latency is only made up by the echo.
+        if options.system && options.latency.is_some() {
+            usage();
+        }
+        (options, rest)
+    }
+
+    /// The options, as given on the command line.
+    #[cfg(unix)]
+    fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.screen_reader {
+            args.push("--screen-reader".to_string());
+        }
+        if self.no_color {
+            args.push("--no-color".to_string());
+        }
+        if self.stress > 0 {
+            args.extend(["--stress".to_string(), self.stress.to_string()]);
+        }
+        if let Some(path) = &self.restore {
+            args.extend(["--restore".to_string(), path.display().to_string()]);
+        }
+        if let Some(latency) = self.latency {
+            args.extend(["--latency".to_string(), latency.as_millis().to_string()]);
+        }
+        if self.system {
+            args.push("--system".to_string());
+        }
+        args
+    }
+}
+
+/// What the shell is run for, once built.
+enum Mode {
+    /// The interactive shell.
+    Interactive,
+    /// The interactive shell, offering the commands of a runbook.
+    Runbook(Runbook),
+    /// A detachable session, served on its socket.
+    #[cfg(unix)]
+    Serve(String),
+    /// Another front-end, exchanging structured events on stdin and stdout.
+    Embed(Format),
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    match args.as_slice() {
+        ["runbook", args @ ..] => match Options::parse(args) {
+            (options, args) if args.len() == 1 => runbook(args[0], options),
+            _ => usage(),
+        },
+        ["config", "check"] => config_check(Config::path()),
+        ["config", "check", path] => config_check(Some(PathBuf::from(path))),
+        ["config", ..] => usage(),
+        ["history", "import", "--from", format, path @ ..] if path.len() <= 1 => {
+            history_import(format.parse()?, path.first().map(PathBuf::from))
+        }
+        ["history", "export", "--to", format, path @ ..] if path.len() <= 1 => {
+            history_export(format.parse()?, path.first().map(PathBuf::from))
+        }
+        #[cfg(feature = "sync")]
+        ["history", "sync", profile @ ..] if profile.len() <= 1 => {
+            history_sync(profile.first().copied())
+        }
+        ["history", ..] => usage(),
+        ["transcript", command @ ("cat" | "pack" | "unpack"), path] => transcript(command, path),
+        ["transcript", ..] => usage(),
+        #[cfg(unix)]
+        ["daemon", "--foreground", session, args @ ..] => match Options::parse(args) {
+            (options, args) if args.is_empty() => run(&options, Mode::Serve(session.to_string())),
+            _ => usage(),
+        },
+        #[cfg(unix)]
+        ["daemon", args @ ..] => match Options::parse(args) {
+            (options, args) if args.len() <= 1 => {
+                daemon(args.first().unwrap_or(&DEFAULT_SESSION), &options)
+            }
+            _ => usage(),
+        },
+        #[cfg(unix)]
+        ["attach"] => attach(DEFAULT_SESSION),
+        #[cfg(unix)]
+        ["attach", session] => attach(session),
+        #[cfg(unix)]
+        ["sessions"] => {
+            for session in shelgon::session::sessions() {
+                println!("{}", session);
+            }
+            Ok(())
+        }
+        ["embed", args @ ..] => {
+            let format = match args.contains(&"--msgpack") {
+                true => Format::MsgPack,
+                false => Format::Json,
+            };
+            let args = args.iter().filter(|arg| **arg != "--msgpack");
+            match Options::parse(&args.copied().collect::<Vec<_>>()) {
+                (options, args) if args.is_empty() => run(&options, Mode::Embed(format)),
+                _ => usage(),
+            }
+        }
+        args => match Options::parse(args) {
+            (options, args) if args.is_empty() => run(&options, Mode::Interactive),
+            _ => usage(),
+        },
+    }
+}
+
+/// Print the usage, and exit.
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
+/// Print a transcript, as text or JSON lines, or pack JSON lines on stdin into one.
+fn transcript(command: &str, path: &str) -> anyhow::Result<()> {
+    match command {
+        "pack" => {
+            let transcript = Transcript::pack(std::io::stdin().lock())?;
+            transcript.write(std::io::BufWriter::new(std::fs::File::create(path)?))?;
+            eprintln!("packed {} entries into {}", transcript.entries.len(), path);
+            Ok(())
+        }
+        "unpack" => Transcript::read(open(path)?)?.unpack(std::io::stdout().lock()),
+        _ => Transcript::read(open(path)?)?.cat(std::io::stdout().lock()),
+    }
+}
+
+/// Open a file for reading, buffered.
+fn open(path: impl AsRef<Path>) -> anyhow::Result<std::io::BufReader<std::fs::File>> {
+    let path = path.as_ref();
+    let file = std::fs::File::open(path)
+        .map_err(|e| anyhow::anyhow!("unable to open `{}`: {}", path.display(), e))?;
+    Ok(std::io::BufReader::new(file))
+}
+
+/// Build the shell with the executor the options pick, and run it.
+fn run(options: &Options, mode: Mode) -> anyhow::Result<()> {
+    if options.system {
+        // The processes of the commands are waited on through the I/O driver of the runtime.
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let app = renderer::App::<command::system::Executor>::new(rt)?;
+        return launch(app, options, mode);
+    }
+
+    let rt = tokio::runtime::Builder::new_current_thread().build()?;
+    let app = match options.latency {
+        Some(latency) => {
+            let executor = Executor {
+                latency: Some(latency),
+            };
+            renderer::App::new_with_executor(rt, executor, ())
+        }
+        None => renderer::App::<Executor>::new(rt)?,
+    };
+    launch(app, options, mode)
+}
+
+/// Run the shell, with the history and the configuration the options give.
+fn launch<T: command::Execute>(
+    app: renderer::App<T>,
+    options: &Options,
+    mode: Mode,
+) -> anyhow::Result<()> {
+    let mut entries = match &options.restore {
+        Some(path) => Transcript::read(open(path)?)?.entries,
+        None => Vec::new(),
+    };
+    entries.extend(
+        testing::synthetic_history(options.stress)
+            .iter()
+            .map(Entry::from),
+    );
+    let app = configure(app, options).with_entries(entries);
+
+    match mode {
+        Mode::Interactive => app.execute()?,
+        Mode::Runbook(runbook) => app.with_runbook(runbook).execute()?,
+        #[cfg(unix)]
+        Mode::Serve(session) => app.serve(&session)?,
+        Mode::Embed(format) => {
+            app.embed(std::io::stdin().lock(), Box::new(std::io::stdout()), format)?
+        }
+    };
+
+    Ok(())
+}
+
+/// Apply the options of the command line to the configuration of the shell.
+fn configure<T: command::Execute>(app: renderer::App<T>, options: &Options) -> renderer::App<T> {
+    app.configure(|config| {
+        config.ui.screen_reader |= options.screen_reader;
+        if options.no_color {
+            config.ui.colors = Some(ColorSupport::NoColor);
+        }
+    })
+}
+
+/// Run the interactive shell, offering the commands of a runbook one at a time, and write the
+/// results next to it.
+fn runbook(path: &str, options: Options) -> anyhow::Result<()> {
+    let runbook = Runbook::read(path)?;
+    if runbook.commands().is_empty() {
+        anyhow::bail!("`{}` has no shell code blocks to run", path);
+    }
+
+    run(&options, Mode::Runbook(runbook))
+}
+
+/// Start a session in a background process, detached from the terminal, with the options given,
+/// and wait for it to listen on its socket.
+#[cfg(unix)]
+fn daemon(session: &str, options: &Options) -> anyhow::Result<()> {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    let socket = shelgon::session::socket(session)?;
+    if std::os::unix::net::UnixStream::connect(&socket).is_ok() {
+        anyhow::bail!("session `{}` is already running", session);
+    }
+
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .args(["daemon", "--foreground", session])
+        .args(options.args())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // SAFETY: `setsid` is async-signal-safe, and the child only calls it before `exec`.
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+
+    for _ in 0..50 {
+        if std::os::unix::net::UnixStream::connect(&socket).is_ok() {
+            println!(
+                "session `{}` started, attach with `sheller attach {}`",
+                session, session
+            );
+            return Ok(());
+        }
+        if let Some(status) = child.try_wait()? {
+            anyhow::bail!("session `{}` exited on startup ({})", session, status);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+
+    anyhow::bail!("session `{}` did not start", session)
+}
+
+/// Attach the terminal to a session.
+#[cfg(unix)]
+fn attach(session: &str) -> anyhow::Result<()> {
+    shelgon::session::attach(session)?;
+    println!("[detached from session `{}`]", session);
+
+    Ok(())
+}
+
+/// Validate the configuration file, printing every problem found.
+fn config_check(path: Option<PathBuf>) -> anyhow::Result<()> {
+    let path = path.ok_or_else(|| anyhow::anyhow!("unable to determine the configuration path"))?;
+
+    match Config::load_from(&path) {
+        Ok(_) => {
+            println!("{}: ok", path.display());
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Import the history of another shell, from its usual location unless a path is given.
+fn history_import(format: history::Format, path: Option<PathBuf>) -> anyhow::Result<()> {
+    let path = path
+        .or_else(|| format.default_path())
+        .ok_or_else(|| anyhow::anyhow!("unable to determine the {} history path", format))?;
+
+    let imported = history::import(format, &path)?;
+    println!("imported {} commands from {}", imported, path.display());
+    Ok(())
+}
+
+/// Export the history for another shell: to a file if a path is given, or else to the standard
+/// output (the usual location of the database, for Atuin).
+fn history_export(format: history::Format, path: Option<PathBuf>) -> anyhow::Result<()> {
+    let path = match (path, format) {
+        (None, history::Format::Atuin) => format.default_path(),
+        (path, _) => path,
+    };
+
+    match path {
+        Some(path) => {
+            let exported = history::export(format, &path)?;
+            println!("exported {} commands to {}", exported, path.display());
+        }
+        None => {
+            history::write_to(format, &mut std::io::stdout().lock())?;
+        }
+    }
+    Ok(())
+}
+
+/// Sync the history with other machines, through the profile named, or else every profile of the
+/// configuration.
+#[cfg(feature = "sync")]
+fn history_sync(name: Option<&str>) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let profiles = match name {
+        Some(name) => match config.sync.get_key_value(name) {
+            Some(profile) => vec![profile],
+            None => anyhow::bail!("unknown sync profile `{}`", name),
+        },
+        None => config.sync.iter().collect(),
+    };
+    if profiles.is_empty() {
+        anyhow::bail!("no sync profile is set up, add a [sync.NAME] section to the configuration");
+    }
+
+    let mut failed = false;
+    for (name, profile) in profiles {
+        match shelgon::sync::sync_profile(profile) {
+            Ok(report) => println!(
+                "{}: {} commands received from {} other machines, {} sent",
+                name, report.received, report.machines, report.sent
+            ),
+            Err(e) => {
+                eprintln!("{}: unable to sync: {}", name, e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
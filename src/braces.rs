@@ -0,0 +1,169 @@
+//! Brace expansion, as in bash, for executors running commands of their own.
+//!
+//! [`expand`] expands the braces of a command: a list of alternatives, `{a,b,c}`, or a sequence of
+//! numbers or letters, `{1..5}`, `{a..e}` or `{0..10..2}`. A word with braces becomes a word per
+//! alternative, with the text around the braces repeated, so `mkdir -p src/{bin,lib,tests}` makes
+//! three directories. Braces nest, and several braces in a word combine.
+//!
+//! Braces within quotes, escaped with `\`, holding neither a comma nor a sequence, or of a
+//! variable (`${HOME}`) are left as they are.
+//!
+//! ```rust
+//! use shelgon::braces;
+//!
+//! assert_eq!(
+//!     braces::expand("mkdir -p src/{bin,lib,tests}"),
+//!     "mkdir -p src/bin src/lib src/tests"
+//! );
+//! assert_eq!(braces::expand("touch {1..3}{a,b}"), "touch 1a 1b 2a 2b 3a 3b");
+//! assert_eq!(braces::expand("cp file{,.bak}"), "cp file file.bak");
+//! assert_eq!(braces::expand("echo '{a,b}' ${HOME} {x}"), "echo '{a,b}' ${HOME} {x}");
+//! ```
+
+/// Expand the braces of a command. The whitespace between the words is kept, and the words a
+/// word expands to are separated by a space.
+pub fn expand(command: &str) -> String {
+    let mut expanded = String::with_capacity(command.len());
+    let mut rest = command;
+    while !rest.is_empty() {
+        let start = rest.len() - rest.trim_start().len();
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = unquoted(rest)
+            .find(|(_, c)| c.is_whitespace())
+            .map_or(rest.len(), |(index, _)| index);
+        expanded.push_str(&word(&rest[..end]).join(" "));
+        rest = &rest[end..];
+    }
+    expanded
+}
+
+/// The words a word expands to.
+fn word(word: &str) -> Vec<String> {
+    let mut from = 0;
+    while let Some((open, close)) = braces(word, from) {
+        if let Some(alternatives) = alternatives(&word[open + 1..close]) {
+            let (prefix, suffix) = (&word[..open], &word[close + 1..]);
+            return alternatives
+                .iter()
+                .flat_map(|alternative| self::word(&format!("{}{}{}", prefix, alternative, suffix)))
+                .collect();
+        }
+        from = open + 1;
+    }
+    vec![word.to_string()]
+}
+
+/// The first pair of braces of a word opening at `from` or after, outside of quotes.
+fn braces(word: &str, from: usize) -> Option<(usize, usize)> {
+    let mut opens = Vec::new();
+    let mut pairs = Vec::new();
+    for (index, c) in unquoted(word) {
+        match c {
+            '{' => opens.push((index, word[..index].ends_with('$'))),
+            '}' => match opens.pop() {
+                Some((open, false)) if open >= from => pairs.push((open, index)),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    pairs.into_iter().min()
+}
+
+/// The alternatives held by braces: the parts between their commas, or the items of a sequence.
+/// This returns `None` for braces holding neither.
+fn alternatives(body: &str) -> Option<Vec<String>> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0usize, 0);
+    for (index, c) in unquoted(body) {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(body[start..index].to_string());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    if parts.is_empty() {
+        return sequence(body);
+    }
+    parts.push(body[start..].to_string());
+    Some(parts)
+}
+
+/// The items of a sequence, `START..END` or `START..END..STEP`, of numbers or of letters. Numbers
+/// written with leading zeros are padded to the same width.
+fn sequence(body: &str) -> Option<Vec<String>> {
+    let bounds = body.split("..").collect::<Vec<_>>();
+    let (start, end, step) = match bounds.as_slice() {
+        [start, end] => (*start, *end, 1),
+        [start, end, step] => (*start, *end, step.parse::<i64>().ok()?.checked_abs()?),
+        _ => return None,
+    };
+    let step = usize::try_from(step.max(1)).ok()?;
+
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let padded = |bound: &str| {
+            let digits = bound.trim_start_matches('-');
+            digits.len() > 1 && digits.starts_with('0')
+        };
+        let width = match padded(start) || padded(end) {
+            true => start.len().max(end.len()),
+            false => 0,
+        };
+        return Some(
+            range(first, last, step)
+                .map(|n| format!("{:0width$}", n, width = width))
+                .collect(),
+        );
+    }
+
+    let mut chars = (start.chars(), end.chars());
+    match (
+        chars.0.next(),
+        chars.0.next(),
+        chars.1.next(),
+        chars.1.next(),
+    ) {
+        (Some(first), None, Some(last), None)
+            if first.is_ascii_alphabetic() && last.is_ascii_alphabetic() =>
+        {
+            let items = range(i64::from(first as u8), i64::from(last as u8), step);
+            Some(items.map(|c| char::from(c as u8).to_string()).collect())
+        }
+        _ => None,
+    }
+}
+
+/// The numbers from `first` to `last`, both included, counting up or down by `step`.
+fn range(first: i64, last: i64, step: usize) -> Box<dyn Iterator<Item = i64>> {
+    match first <= last {
+        true => Box::new((first..=last).step_by(step)),
+        false => Box::new((last..=first).rev().step_by(step)),
+    }
+}
+
+/// The characters of the text outside of quotes and not escaped with `\`, with their index.
+fn unquoted(text: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut quote = None;
+    let mut escaped = false;
+    text.char_indices().filter(move |&(_, c)| {
+        if escaped {
+            escaped = false;
+            return false;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, _) => return true,
+        }
+        false
+    })
+}
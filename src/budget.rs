@@ -0,0 +1,74 @@
+//! Duration budgets: how long the commands starting with a prefix are expected to take.
+//!
+//! The `[budgets]` section of the configuration maps prefixes of commands to a duration (see
+//! [`crate::config::Config::budgets`]):
+//!
+//! ```toml
+//! [budgets]
+//! "terraform apply" = "10m"
+//! "cargo test" = "1m30s"
+//! ```
+//!
+//! A command taking longer than the budget of the longest prefix it starts with is marked with a
+//! warning under its entry, telling how long it took. With `ui.notify_over_budget`, the terminal
+//! is also asked to show a desktop notification. (OSC 9)
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Parse a duration: numbers followed by a unit, `h`, `m` or `s`, such as `"1h30m"`, or a number
+/// of seconds alone.
+pub(crate) fn parse(duration: &str) -> Option<Duration> {
+    let duration = duration.trim();
+    if let Ok(seconds) = duration.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let mut total = 0u64;
+    let mut rest = duration;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let number = rest[..digits].parse::<u64>().ok()?;
+        let unit = match rest[digits..].chars().next()? {
+            'h' => 3_600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        total = total.checked_add(number.checked_mul(unit)?)?;
+        rest = &rest[digits + 1..];
+    }
+    (!duration.is_empty()).then(|| Duration::from_secs(total))
+}
+
+/// Format a duration as `parse` reads it, such as `"12m5s"`, to the second.
+pub(crate) fn format(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let parts = [
+        (seconds / 3_600, 'h'),
+        (seconds / 60 % 60, 'm'),
+        (seconds % 60, 's'),
+    ];
+    let formatted = parts
+        .iter()
+        .filter(|(number, _)| *number > 0)
+        .map(|(number, unit)| format!("{}{}", number, unit))
+        .collect::<String>();
+    match formatted.is_empty() {
+        true => "0s".to_string(),
+        false => formatted,
+    }
+}
+
+/// The budget of a command: the one of the longest prefix it starts with, if any.
+pub(crate) fn find<'a>(
+    command: &str,
+    budgets: &'a BTreeMap<String, Duration>,
+) -> Option<(&'a str, Duration)> {
+    let command = command.trim_start();
+    budgets
+        .iter()
+        .filter(|(prefix, _)| command.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(prefix, budget)| (prefix.as_str(), *budget))
+}
@@ -7,6 +7,10 @@
 //! - [`CommandInput`]: Input data structure passed to command executors
 //! - [`CommandOutput`]: Output data structure for command results
 //! - [`OutputAction`]: Enum controlling shell behavior after command execution
+//! - [`OutputStream`]: The output of a command still running, drawn as it comes in
+//! - [`Cancel`]: Tells the executor that the command it runs is interrupted
+//! - [`Size`]: The size of the terminal, as the command should see it
+//! - [`Builtin`]: Commands handled apart, with their own help and completions
 //!
 //! # Architecture
 //!
@@ -19,6 +23,11 @@
 //! 5. Command is executed (via [`Execute::execute`])
 //! 6. Output is rendered based on returned [`OutputAction`]
 //!
+//! [`system::Executor`] runs the commands on the system, as a real shell does.
+//!
+//! Executors holding a connection, such as an ssh session, can open more sessions on it (via
+//! [`Execute::open_session`]), each in a tab of the shell with a context of its own.
+//!
 //! # Example
 //!
 //! ```rust
@@ -51,6 +60,7 @@
 //!             stdin: Vec::new(),
 //!             stdout: vec![format!("Executed: {}", input.command)],
 //!             stderr: Vec::new(),
+//!             ..Default::default()
 //!         }))
 //!     }
 //! }
@@ -60,18 +70,27 @@
 //!
 //! - **tokio**: Enables async runtime support via [`tokio::runtime::Runtime`] in [`CommandInput`]
 
-#[cfg(feature = "tokio")]
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
 #[cfg(feature = "tokio")]
 use tokio::runtime::Runtime;
 
+use crate::syntax::{Posix, Syntax};
+
+#[cfg(feature = "tokio")]
+pub mod system;
+
 ///
 /// [`CommandOutput`] is the output supplied to the renderer by the [`Execute`] trait.
 ///
 /// `prompt` & `command` are the prompt and command that were executed.
 /// `stdin` is the input that was supplied to the command. (optional)
 /// `stdout` & `stderr` are the output of the command.
+/// `exit_code` & `duration` are how the command ended, and how long it took.
 ///
+#[derive(Debug, Clone, Default)]
 pub struct CommandOutput {
     /// The prompt that was displayed.
     pub prompt: String,
@@ -83,6 +102,11 @@ pub struct CommandOutput {
     pub stdout: Vec<String>,
     /// The error output of the command. (optional)
     pub stderr: Vec<String>,
+    /// The exit code of the command, or `None` if the executor cannot tell it.
+    pub exit_code: Option<i32>,
+    /// How long the command took to run. The shell measures it for the commands it runs, unless
+    /// the executor gives it.
+    pub duration: Duration,
 }
 
 ///
@@ -100,6 +124,123 @@ pub enum OutputAction {
     Exit,
     /// Clear the screen.
     Clear,
+    /// Render the output of the command as it comes in. (See [`OutputStream`])
+    Stream(OutputStream),
+}
+
+///
+/// [`OutputStream`] is the output of a command still running, returned as
+/// [`OutputAction::Stream`]. Its lines come in through a channel as the command prints them, and
+/// the command is done once the sending end is dropped.
+///
+/// The shell draws the lines below the command as they come in, reacting to keys meanwhile, and
+/// adds the output to the history once the command is done. If the shell stops waiting for it,
+/// as when the command is interrupted with `Ctrl+C`, the [`Cancel`] of the command is cancelled
+/// and the receiving end is dropped: sending a line fails from then on too. Where frames are not
+/// drawn, in the screen reader mode or an embedded shell, the output is waited for whole.
+///
+/// The channel holds [`OutputStream::BOUND`] lines at most: sending a line waits while it is full,
+/// as when the shell is behind on the lines or their output is paused with `Ctrl+S`, so a command
+/// printing without end is slowed down to the pace of the shell.
+///
+/// # Example
+///
+/// ```rust
+/// use shelgon::command::{Chunk, OutputStream};
+///
+/// let (stream, sender) = OutputStream::channel("$", "build", Vec::new());
+/// std::thread::spawn(move || {
+///     for step in ["compiling", "linking"] {
+///         if sender.send(Chunk::Stdout(step.to_string())).is_err() {
+///             return;
+///         }
+///     }
+///     let _ = sender.send(Chunk::Stderr("1 warning".to_string()));
+/// });
+///
+/// let output = stream.collect();
+/// assert_eq!(output.stdout, ["compiling", "linking"]);
+/// assert_eq!(output.stderr, ["1 warning"]);
+/// ```
+///
+#[derive(Debug)]
+pub struct OutputStream {
+    /// The prompt that was displayed.
+    pub prompt: String,
+    /// The command that is running.
+    pub command: String,
+    /// The input that was supplied to the command. (optional)
+    pub stdin: Vec<String>,
+    /// The lines of the output, as they come in.
+    pub chunks: mpsc::Receiver<Chunk>,
+}
+
+///
+/// [`Chunk`] is a line of the output of a command, sent through an [`OutputStream`].
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chunk {
+    /// A line of the output.
+    Stdout(String),
+    /// A line of the error output.
+    Stderr(String),
+    /// The exit code of the command, once it is done.
+    Exit(i32),
+}
+
+impl OutputStream {
+    /// The number of lines sent and not taken in yet, at most.
+    pub const BOUND: usize = 10_000;
+
+    /// Create the stream of the output of a command, along with the sending end of its lines.
+    pub fn channel(
+        prompt: impl Into<String>,
+        command: impl Into<String>,
+        stdin: Vec<String>,
+    ) -> (Self, mpsc::SyncSender<Chunk>) {
+        let (sender, chunks) = mpsc::sync_channel(Self::BOUND);
+        let stream = Self {
+            prompt: prompt.into(),
+            command: command.into(),
+            stdin,
+            chunks,
+        };
+        (stream, sender)
+    }
+
+    /// Wait for the command to be done, and return its output whole.
+    pub fn collect(self) -> CommandOutput {
+        let mut output = CommandOutput {
+            prompt: self.prompt,
+            command: self.command,
+            stdin: self.stdin,
+            ..Default::default()
+        };
+        for chunk in self.chunks {
+            output.push(chunk);
+        }
+        output
+    }
+}
+
+impl CommandOutput {
+    /// Add a line of the output, or of the error output, of the command, or its exit code.
+    pub fn push(&mut self, chunk: Chunk) {
+        match chunk {
+            Chunk::Stdout(line) => self.stdout.push(line),
+            Chunk::Stderr(line) => self.stderr.push(line),
+            Chunk::Exit(code) => self.exit_code = Some(code),
+        }
+    }
+
+    /// Check whether the command failed: it exited with a code other than `0`, or, when the
+    /// executor cannot tell its exit code, wrote to its error output.
+    pub fn failed(&self) -> bool {
+        match self.exit_code {
+            Some(code) => code != 0,
+            None => !self.stderr.is_empty(),
+        }
+    }
 }
 
 ///
@@ -115,12 +256,140 @@ pub struct CommandInput {
     pub command: String,
     /// The input that is supplied to the command. (optional)
     pub stdin: Option<Vec<String>>,
+    /// Cancelled when the command is interrupted, with `Ctrl+C`. (See [`Cancel`])
+    pub cancel: Cancel,
+    /// The size of the area the output is drawn in, kept up to date as the terminal is resized.
+    /// (See [`Size`])
+    pub size: Size,
     #[cfg(feature = "tokio")]
     /// Supplying [`tokio::runtime::Runtime`] to the [`Execute`] trait. This is to facilitate
     /// executing [`std::future::Future`]s, creating [`tokio::task::JoinHandle`]s, etc.
     pub runtime: Arc<Runtime>,
 }
 
+///
+/// [`Cancel`] tells an executor that the command it runs is interrupted, as with `Ctrl+C`. It is
+/// supplied to [`Execute::execute`] in [`CommandInput`], and cancelled by the shell once it stops
+/// waiting for the command: the executor is expected to kill the process or task running it.
+///
+/// Clones of a token are cancelled together.
+///
+/// ```rust
+/// use shelgon::command::Cancel;
+///
+/// let cancel = Cancel::new();
+/// let running = cancel.clone();
+/// assert!(!running.is_cancelled());
+///
+/// cancel.cancel();
+/// assert!(running.is_cancelled());
+/// ```
+///
+#[derive(Debug, Clone, Default)]
+pub struct Cancel {
+    inner: Arc<CancelInner>,
+}
+
+/// The state shared by the clones of a [`Cancel`].
+#[derive(Debug, Default)]
+struct CancelInner {
+    /// Whether the command is cancelled.
+    cancelled: AtomicBool,
+    /// Wakes the tasks waiting for the command to be cancelled.
+    #[cfg(feature = "tokio")]
+    notify: tokio::sync::Notify,
+}
+
+impl Cancel {
+    /// Create a token, not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the command.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        #[cfg(feature = "tokio")]
+        self.inner.notify.notify_waiters();
+    }
+
+    /// Check whether the command is cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait for the command to be cancelled.
+    #[cfg(feature = "tokio")]
+    pub async fn cancelled(&self) {
+        loop {
+            let mut notified = std::pin::pin!(self.inner.notify.notified());
+            // Waiting is registered before checking, so a cancellation in between is not missed.
+            notified.as_mut().enable();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+///
+/// [`Size`] is the size of the area the output of a command is drawn in, in columns and rows, for
+/// the programs formatting their output to the width of the terminal, such as `ls` and table
+/// printers. It is supplied to [`Execute::execute`] in [`CommandInput`], and updated by the shell
+/// as the terminal is resized while the command runs: executors running the command on a
+/// pseudo-terminal resize it (`TIOCSWINSZ`), and others hand it to the command as `COLUMNS` and
+/// `LINES` when it starts.
+///
+/// Clones of a size are updated together.
+///
+/// ```rust
+/// use shelgon::command::Size;
+///
+/// let size = Size::new(80, 24);
+/// let seen = size.clone();
+/// size.set(120, 40);
+/// assert_eq!(seen.get(), (120, 40));
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct Size {
+    /// The columns, in the high half, and the rows, in the low half.
+    inner: Arc<AtomicU32>,
+}
+
+impl Size {
+    /// Create a size of `columns` by `rows`.
+    pub fn new(columns: u16, rows: u16) -> Self {
+        Self {
+            inner: Arc::new(AtomicU32::new(pack(columns, rows))),
+        }
+    }
+
+    /// The columns and the rows.
+    pub fn get(&self) -> (u16, u16) {
+        let packed = self.inner.load(Ordering::SeqCst);
+        ((packed >> 16) as u16, packed as u16)
+    }
+
+    /// Change the size, for every clone.
+    pub fn set(&self, columns: u16, rows: u16) {
+        self.inner.store(pack(columns, rows), Ordering::SeqCst);
+    }
+}
+
+// The size of a terminal when it cannot be told.
+impl Default for Size {
+    fn default() -> Self {
+        Self::new(80, 24)
+    }
+}
+
+/// The columns and the rows of a [`Size`], as one number, so they are changed together.
+fn pack(columns: u16, rows: u16) -> u32 {
+    (u32::from(columns) << 16) | u32::from(rows)
+}
+
 ///
 /// [`Prepare`] is the output of the [`Execute::prepare`] method.
 ///
@@ -129,7 +398,7 @@ pub struct CommandInput {
 ///
 /// Supported behaviors:
 /// - `stdin_required`: If the command requires stdin, the renderer should prompt the user for
-///     input.
+///   input.
 ///
 #[derive(Debug, Clone)]
 pub struct Prepare {
@@ -139,6 +408,96 @@ pub struct Prepare {
     pub stdin_required: bool,
 }
 
+///
+/// [`Builtin`] is a command handled by the executor itself, rather than by [`Execute::execute`],
+/// such as `cd`. Builtins are registered with [`Execute::builtins`], and run when the first word
+/// of a command is their name.
+///
+/// Along with its handler, a builtin has a help text (shown by `:help NAME`), a usage (shown
+/// below the prompt while it is typed) and the completions of its arguments.
+///
+/// # Example
+///
+/// ```rust
+/// use shelgon::command::{Builtin, CommandOutput, OutputAction};
+///
+/// struct Context {
+///     count: usize,
+/// }
+///
+/// let count = Builtin::new("count", |ctx: &mut Context, input| {
+///     if input.command.split_whitespace().nth(1) == Some("reset") {
+///         ctx.count = 0;
+///     }
+///     Ok(OutputAction::Command(CommandOutput {
+///         prompt: input.prompt,
+///         command: input.command,
+///         stdin: Vec::new(),
+///         stdout: vec![ctx.count.to_string()],
+///         stderr: Vec::new(),
+///         ..Default::default()
+///     }))
+/// })
+/// .with_help("Print the number of commands run, or reset it.")
+/// .with_usage("count [reset]")
+/// .with_completions(["reset"]);
+///
+/// assert_eq!(count.name, "count");
+/// ```
+///
+pub struct Builtin<C> {
+    /// The name of the builtin, which is the first word of the commands it runs.
+    pub name: String,
+    /// What the builtin does, shown by `:help NAME`.
+    pub help: String,
+    /// The usage of the builtin, such as `cd [DIR]`. (optional)
+    pub usage: Option<String>,
+    /// The completions of the arguments of the builtin.
+    pub completions: Vec<String>,
+    /// The handler of the builtin, running the command with the context of the executor.
+    pub handler: Handler<C>,
+}
+
+/// The handler of a [`Builtin`].
+pub type Handler<C> = Box<dyn Fn(&mut C, CommandInput) -> anyhow::Result<OutputAction>>;
+
+impl<C> Builtin<C> {
+    /// Create a builtin, without help, usage or completions.
+    pub fn new(
+        name: impl Into<String>,
+        handler: impl Fn(&mut C, CommandInput) -> anyhow::Result<OutputAction> + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            help: String::new(),
+            usage: None,
+            completions: Vec::new(),
+            handler: Box::new(handler),
+        }
+    }
+
+    /// Set the help text of the builtin.
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = help.into();
+        self
+    }
+
+    /// Set the usage of the builtin.
+    pub fn with_usage(mut self, usage: impl Into<String>) -> Self {
+        self.usage = Some(usage.into());
+        self
+    }
+
+    /// Set the completions of the arguments of the builtin.
+    pub fn with_completions(
+        mut self,
+        completions: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.completions = completions.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
 ///
 /// [`Execute`] this is the heart of the shell. This is the trait that is implemented by the
 /// commands that are to be executed.
@@ -147,7 +506,10 @@ pub struct Prepare {
 /// - Prompting the user for input.
 /// - Completing the command. (optional)
 /// - Preparing the command for execution.
+/// - Listing the known commands, to correct typos. (optional)
+/// - Registering builtins, handled apart from the other commands. (optional)
 /// - Executing the command.
+/// - Reporting the working directory. (optional)
 ///
 /// This is the only trait that is required by the user to implement to create a REPL.
 ///
@@ -179,6 +541,11 @@ pub trait Execute {
     /// The deterministic completion is applied to the command, and the non-deterministic is shown
     /// below the command.
     ///
+    /// The last word of the command is given unquoted, as `cat My Fi` for `cat 'My Fi` or
+    /// `cat My\ Fi`, and the completions are quoted the same way as they are inserted, so a path
+    /// returned as `les/a b.txt` is inserted as `les/a\ b.txt` after `My\ Fi`. Words are read and
+    /// quoted with the syntax of the executor. (See [`Execute::syntax`])
+    ///
     fn completion(
         &self,
         _ctx: &Self::Context,
@@ -195,10 +562,119 @@ pub trait Execute {
     /// This can be used to check if the command requires stdin, and prompt the user for input.
     ///
     fn prepare(&self, cmd: &str) -> Prepare;
+
+    ///
+    /// These are the commands known to the executor. This is optional, and defaults to none.
+    ///
+    /// A command whose first word is not known, but is close to a known command, is offered a
+    /// correction before it is run. (Say, `gti status` is offered `git status`) Executors that
+    /// know no commands are never corrected.
+    ///
+    fn commands(&self, _ctx: &Self::Context) -> Vec<String> {
+        Vec::new()
+    }
+
+    ///
+    /// This is the syntax of the commands of the executor: how words are quoted, where comments
+    /// start, and which expansions the shell applies. This is optional, and defaults to
+    /// [`Posix`], the syntax of POSIX shells.
+    ///
+    /// Executors running another language, such as SQL, should return its syntax here, so that
+    /// the shell does not expand `$(...)` or quote paths with `\` in their commands.
+    ///
+    fn syntax(&self) -> &dyn Syntax {
+        &Posix
+    }
+
+    ///
+    /// These are the builtins of the executor. This is optional, and defaults to none.
+    ///
+    /// The builtins are asked for once, when the shell starts. Commands whose first word is the
+    /// name of a builtin are run by its handler, instead of [`Execute::prepare`] and
+    /// [`Execute::execute`]. Builtins count as known commands. (See [`Execute::commands`])
+    ///
+    fn builtins(&self) -> Vec<Builtin<Self::Context>> {
+        Vec::new()
+    }
+
+    ///
+    /// This is the working directory of the shell, reported to the terminal so that new tabs and
+    /// windows open in the same directory. This is optional, and defaults to the working
+    /// directory of the process.
+    ///
+    /// Executors that keep track of their own working directory (say, by handling `cd` in the
+    /// context) should return it here.
+    ///
+    fn cwd(&self, _ctx: &Self::Context) -> Option<PathBuf> {
+        std::env::current_dir().ok()
+    }
+
+    ///
+    /// These are the names of the environment variables of the context, completed after `$` and
+    /// `${`. This is optional, and defaults to the environment variables of the process.
+    ///
+    /// Executors that keep an environment of their own in the context, or variables of the
+    /// session, should return their names here.
+    ///
+    fn variables(&self, _ctx: &Self::Context) -> Vec<String> {
+        let mut names = std::env::vars_os()
+            .filter_map(|(name, _)| name.into_string().ok())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    ///
+    /// This tells whether the executor runs the commands over a slow link, such as a connection
+    /// to another machine. This is optional, and defaults to `false`.
+    ///
+    /// The line being typed is always edited by the shell itself. For a remote executor, the shell
+    /// also asks for the prompt once per command, rather than every time it draws the line, and
+    /// echoes a command at once when it is submitted, before the executor gets it.
+    ///
+    fn remote(&self) -> bool {
+        false
+    }
+
+    ///
+    /// This is the prompt expected once the command runs, predicted without a round trip to the
+    /// other end (say, with the new directory for `cd DIR`), if it can be. This is optional, and
+    /// defaults to none.
+    ///
+    /// The prompt predicted is shown, underlined, under the command echoed by a remote executor
+    /// (see [`Execute::remote`]) while it runs, until the prompt of the executor replaces it.
+    ///
+    fn predict_prompt(&self, _ctx: &Self::Context, _cmd: &str) -> Option<String> {
+        None
+    }
+
+    ///
+    /// This opens another session of the executor, for a new tab of the shell, and returns its
+    /// context. This is optional, and defaults to none: the executor has one session, and new tabs
+    /// are not offered.
+    ///
+    /// Executors holding a connection (say, an ssh session) should open a channel of it here,
+    /// from the context of the tab the new one is opened from, so that every tab shares the
+    /// connection rather than connecting again.
+    ///
+    fn open_session(&self, _ctx: &Self::Context) -> anyhow::Result<Option<Self::Context>> {
+        Ok(None)
+    }
+
+    ///
+    /// This closes a session opened with [`Execute::open_session`], once its tab is closed. This
+    /// is optional, and defaults to dropping the context. The sessions of the tabs left open are
+    /// dropped when the shell exits.
+    ///
+    fn close_session(&self, _ctx: Self::Context) {}
+
     ///
     /// This is the execute method. This is called to execute the command. This is where the
     /// command is executed. This is where the command is executed, and the output is returned.
     ///
+    /// Commands taking a while should return [`OutputAction::Stream`] at once, running on another
+    /// thread or task, so the shell is not blocked until they are done.
+    ///
     fn execute(&self, ctx: &mut Self::Context, cmd: CommandInput) -> anyhow::Result<OutputAction>;
 }
 
@@ -206,9 +682,57 @@ pub trait Execute {
 /// [`New`] is the trait that is implemented by the commands that are to be executed. This is used
 /// to quickly create a new instance of the command.
 ///
+/// The renderer calls this only after the terminal UI is up, so a slow initialization is shown on
+/// a startup screen instead of a blank terminal.
+///
 pub trait New: Execute {
     /// This is the new method that is used to create a new instance of the command.
     fn new() -> anyhow::Result<(Self, Self::Context)>
     where
         Self: Sized;
+
+    ///
+    /// This is the asynchronous variant of [`New::new`]. Executors that need to do slow work
+    /// before the first prompt (connecting over ssh, attaching to a container, etc.) can implement
+    /// this and report what they are doing through [`Progress`].
+    ///
+    /// The future is driven on the runtime supplied to the `App`, and the startup screen is
+    /// redrawn every time it yields with new messages. The default implementation delegates to
+    /// [`New::new`].
+    ///
+    #[cfg(feature = "tokio")]
+    #[allow(async_fn_in_trait)]
+    async fn new_async(progress: Progress) -> anyhow::Result<(Self, Self::Context)>
+    where
+        Self: Sized,
+    {
+        let _ = progress;
+        Self::new()
+    }
+}
+
+///
+/// [`Progress`] is supplied to [`New::new_async`] to report progress messages while the executor
+/// is being initialized. The messages are rendered on the startup screen, in the order they are
+/// reported.
+///
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone)]
+pub struct Progress {
+    sender: mpsc::Sender<String>,
+}
+
+#[cfg(feature = "tokio")]
+impl Progress {
+    /// Create a new [`Progress`] reporter along with the receiving end used by the renderer.
+    #[cfg(feature = "tui")]
+    pub(crate) fn channel() -> (Self, mpsc::Receiver<String>) {
+        let (sender, receiver) = mpsc::channel();
+        (Self { sender }, receiver)
+    }
+
+    /// Report a progress message. This is a no-op once the startup screen is gone.
+    pub fn report(&self, message: impl Into<String>) {
+        let _ = self.sender.send(message.into());
+    }
 }
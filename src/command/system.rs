@@ -0,0 +1,594 @@
+//! An executor running commands on the system, for using the shell as a real one.
+//!
+//! [`Executor`] runs every command with `sh -c` (`cmd /C` on Windows), through
+//! [`tokio::process::Command`], in the working directory of its [`Context`]. The output and the
+//! error output of the command are streamed, line by line, as they come in (see
+//! [`super::OutputStream`]), along with the exit code: a command exiting with a code other than 0
+//! failed. (See [`CommandOutput::failed`]) A command killed by a signal has no exit code, and has
+//! the signal noted as the last line of its error output instead, such as `killed by signal 9`. A
+//! command interrupted with `Ctrl+C` is killed. (See [`super::Cancel`])
+//!
+//! The command is given the input typed while it runs, if any, and an empty input otherwise, so
+//! a command reading its input does not wait on the terminal. Its output being no terminal, it is
+//! told the size of the screen in `COLUMNS` and `LINES`, as it starts. (See [`super::Size`])
+//!
+//! The command runs in a process group of its own, so that what it starts is killed with it. On
+//! Unix, with the `tui` feature, it is spawned as a job of `crate::process`: with the resource
+//! limits of `[limits]`, the file mode mask and the priorities of `[process]`, and the environment
+//! of the shell as `[child_env]` makes it, so the commands it forces colors for keep them. The
+//! jobs still running when the shell exits are terminated or detached, following `jobs.on_exit`.
+//!
+//! Braces are expanded before the command runs, `sh` leaving them as they are. (See
+//! [`crate::braces`]) The assignments before a command alone, `FOO=bar cmd`, with no operator,
+//! redirection or expansion in it, are set in the environment of its program. (See
+//! [`crate::assignments`])
+//!
+//! The programs taking over the terminal, such as editors and pagers, are run in the foreground
+//! instead, on Unix with the `tui` feature, the terminal handed to them until they exit: their
+//! output is not captured, and `Ctrl+Z` stops them. `fg` resumes the program stopped last, and
+//! `jobs` lists the ones stopped. (See `Executor::with_terminal`)
+//!
+//! `cd` is a builtin, changing the working directory of the context (`cd` alone for the home
+//! directory, `cd -` for the previous one), and `exit` leaves the shell.
+//!
+//! `sh` expands `~`, `$(COMMAND)` and `$((EXPRESSION))` itself, with the variables it sets along
+//! the way: the shell leaves them to it, the syntax of the commands being [`crate::syntax::Sh`].
+//!
+//! The runtime supplied to the `App` must have its I/O driver enabled, as with
+//! `tokio::runtime::Runtime::new` (of the `rt-multi-thread` feature of tokio) or
+//! [`tokio::runtime::Builder::enable_all`].
+//!
+//! ```rust
+//! use std::sync::Arc;
+//!
+//! use shelgon::command::{system, Cancel, CommandInput, Execute, New, OutputAction, Size};
+//!
+//! let runtime = tokio::runtime::Builder::new_current_thread()
+//!     .enable_all()
+//!     .build()?;
+//! let (executor, mut ctx) = system::Executor::new()?;
+//! let input = CommandInput {
+//!     prompt: executor.prompt(&ctx),
+//!     command: "echo hello; exit 3".to_string(),
+//!     stdin: None,
+//!     cancel: Cancel::new(),
+//!     size: Size::new(80, 24),
+//!     runtime: Arc::new(runtime),
+//! };
+//! match executor.execute(&mut ctx, input)? {
+//!     OutputAction::Stream(stream) => {
+//!         let output = stream.collect();
+//!         assert_eq!(output.stdout, ["hello"]);
+//!         assert!(output.stderr.is_empty());
+//!         assert_eq!(output.exit_code, Some(3));
+//!         assert!(output.failed());
+//!     }
+//!     _ => panic!("expected the output of the command"),
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::{
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    sync::mpsc::{SyncSender, TrySendError},
+    task::Poll,
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+    process::Child,
+};
+
+use super::{
+    Builtin, Cancel, Chunk, CommandInput, CommandOutput, Execute, New, OutputAction, OutputStream,
+    Prepare,
+};
+use crate::syntax::{Sh, Syntax};
+use crate::{assignments, braces};
+
+/// The program running the commands, with the flag giving it one.
+#[cfg(not(windows))]
+const SHELL: (&str, &str) = ("sh", "-c");
+/// The program running the commands, with the flag giving it one.
+#[cfg(windows)]
+const SHELL: (&str, &str) = ("cmd", "/C");
+
+/// The programs taking over the terminal, run in the foreground by default.
+#[cfg(all(unix, feature = "tui"))]
+const TERMINAL: &[&str] = &[
+    "vi", "vim", "nvim", "nano", "emacs", "less", "more", "man", "top", "htop", "ssh", "tmux",
+    "screen",
+];
+
+/// The job a command is kept track of as until it exits. (See `crate::process`)
+#[cfg(all(unix, feature = "tui"))]
+type Job = crate::process::Job;
+/// The job a command is kept track of as until it exits: none without job control.
+#[cfg(not(all(unix, feature = "tui")))]
+type Job = ();
+
+///
+/// [`Executor`] runs commands on the system, as processes of their own. (See the
+/// [module](self) documentation)
+///
+#[derive(Debug, Clone)]
+pub struct Executor {
+    /// The programs taking over the terminal, run in the foreground.
+    #[cfg(all(unix, feature = "tui"))]
+    terminal: Vec<String>,
+}
+
+// Without job control, there are no programs run in the foreground to default to.
+#[cfg_attr(not(all(unix, feature = "tui")), allow(clippy::derivable_impls))]
+impl Default for Executor {
+    fn default() -> Self {
+        Self {
+            #[cfg(all(unix, feature = "tui"))]
+            terminal: TERMINAL.iter().map(|program| program.to_string()).collect(),
+        }
+    }
+}
+
+#[cfg(all(unix, feature = "tui"))]
+impl Executor {
+    /// Run the commands of these programs in the foreground, handing them the terminal, rather
+    /// than the editors and pagers run so by default.
+    pub fn with_terminal(mut self, programs: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.terminal = programs.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+///
+/// [`Context`] is the working directory the commands of an [`Executor`] run in, along with the
+/// one before the last `cd`, and the programs stopped with `Ctrl+Z`.
+///
+#[derive(Debug)]
+pub struct Context {
+    /// The working directory of the commands.
+    cwd: PathBuf,
+    /// The working directory before the last `cd`, for `cd -`.
+    previous: Option<PathBuf>,
+    /// The programs stopped, with their command, the last one stopped last.
+    #[cfg(all(unix, feature = "tui"))]
+    stopped: Vec<(Job, String)>,
+}
+
+impl Context {
+    /// A context running the commands in a directory.
+    pub fn new(cwd: impl Into<PathBuf>) -> Self {
+        Self {
+            cwd: cwd.into(),
+            previous: None,
+            #[cfg(all(unix, feature = "tui"))]
+            stopped: Vec::new(),
+        }
+    }
+
+    /// The working directory of the commands.
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    /// Change the working directory to a path, relative to the current one, or starting with `~`
+    /// for the home directory. `None` stands for the home directory, and `-` for the previous one.
+    fn change(&mut self, path: Option<&str>) -> Result<(), String> {
+        let target = match path {
+            None | Some("~") => home().ok_or("HOME is not set")?,
+            Some("-") => self.previous.clone().ok_or("no previous directory")?,
+            Some(path) => match path.strip_prefix("~/") {
+                Some(rest) => home().ok_or("HOME is not set")?.join(rest),
+                None => self.cwd.join(path),
+            },
+        };
+        let target = target
+            .canonicalize()
+            .map_err(|e| format!("{}: {}", path.unwrap_or("~"), e))?;
+        if !target.is_dir() {
+            return Err(format!("{}: not a directory", path.unwrap_or("~")));
+        }
+        self.previous = Some(std::mem::replace(&mut self.cwd, target));
+        Ok(())
+    }
+}
+
+impl New for Executor {
+    fn new() -> anyhow::Result<(Self, Self::Context)> {
+        let cwd = std::env::current_dir()?;
+        Ok((Self::default(), Context::new(cwd)))
+    }
+}
+
+impl Execute for Executor {
+    type Context = Context;
+
+    fn prompt(&self, ctx: &Self::Context) -> String {
+        let cwd = match home() {
+            Some(home) if ctx.cwd == home => "~".to_string(),
+            Some(home) => match ctx.cwd.strip_prefix(&home) {
+                Ok(rest) => format!("~/{}", rest.display()),
+                Err(_) => ctx.cwd.display().to_string(),
+            },
+            None => ctx.cwd.display().to_string(),
+        };
+        format!("{} $", cwd)
+    }
+
+    fn prepare(&self, cmd: &str) -> Prepare {
+        Prepare {
+            command: braces::expand(cmd),
+            stdin_required: false,
+        }
+    }
+
+    fn syntax(&self) -> &dyn Syntax {
+        &Sh
+    }
+
+    fn builtins(&self) -> Vec<Builtin<Self::Context>> {
+        let cd = Builtin::new("cd", |ctx: &mut Context, input: CommandInput| {
+            let words = Sh.words(&input.command);
+            let mut words = words.iter().skip(1);
+            let stderr = match (words.next(), words.next()) {
+                (path, None) => ctx.change(path.map(String::as_str)).err(),
+                _ => Some("too many arguments".to_string()),
+            };
+            Ok(OutputAction::Command(CommandOutput {
+                prompt: input.prompt,
+                command: input.command,
+                stdin: Vec::new(),
+                stdout: Vec::new(),
+                stderr: stderr
+                    .map(|error| format!("cd: {}", error))
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            }))
+        })
+        .with_help("Change the working directory: to the home directory alone, or back with `-`.")
+        .with_usage("cd [DIR|-]");
+        let exit = Builtin::new("exit", |_: &mut Context, _| Ok(OutputAction::Exit))
+            .with_help("Exit the shell.")
+            .with_usage("exit");
+        #[cfg(all(unix, feature = "tui"))]
+        {
+            let fg = Builtin::new("fg", |ctx: &mut Context, input: CommandInput| {
+                let output = CommandOutput {
+                    prompt: input.prompt,
+                    command: input.command,
+                    ..Default::default()
+                };
+                Ok(OutputAction::Command(match ctx.stopped.pop() {
+                    Some((job, command)) => {
+                        let resumed = crate::process::resume(job);
+                        came_back(ctx, output, resumed, command)
+                    }
+                    None => CommandOutput {
+                        stderr: vec!["fg: no stopped program".to_string()],
+                        ..output
+                    },
+                }))
+            })
+            .with_help("Resume the program stopped last with `Ctrl+Z`, in the foreground.")
+            .with_usage("fg");
+            let jobs = Builtin::new("jobs", |ctx: &mut Context, input: CommandInput| {
+                Ok(OutputAction::Command(CommandOutput {
+                    prompt: input.prompt,
+                    command: input.command,
+                    stdout: ctx
+                        .stopped
+                        .iter()
+                        .map(|(job, command)| format!("[{}] stopped: {}", job.pid(), command))
+                        .collect(),
+                    ..Default::default()
+                }))
+            })
+            .with_help("List the programs stopped with `Ctrl+Z`, the last one stopped last.")
+            .with_usage("jobs");
+            vec![cd, exit, fg, jobs]
+        }
+        #[cfg(not(all(unix, feature = "tui")))]
+        vec![cd, exit]
+    }
+
+    fn cwd(&self, ctx: &Self::Context) -> Option<PathBuf> {
+        Some(ctx.cwd.clone())
+    }
+
+    // A tab starts in the working directory of the one it is opened from.
+    fn open_session(&self, ctx: &Self::Context) -> anyhow::Result<Option<Self::Context>> {
+        Ok(Some(Context::new(ctx.cwd.clone())))
+    }
+
+    fn execute(&self, ctx: &mut Self::Context, cmd: CommandInput) -> anyhow::Result<OutputAction> {
+        // The command comes prepared, its braces expanded. (See `Executor::prepare`)
+        let command = cmd.command.clone();
+        // The assignments are left to `sh` if they are all there is, or if the command is not a
+        // single one, as they would be for the other commands too.
+        let (variables, rest) = match assignments::split(&command) {
+            (variables, rest) if !rest.is_empty() && simple(&command) => (variables, rest),
+            _ => (Vec::new(), command.as_str()),
+        };
+        #[cfg(all(unix, feature = "tui"))]
+        if let Some(program) = rest.split_whitespace().next() {
+            if self.terminal.iter().any(|terminal| terminal == program) {
+                return Ok(OutputAction::Command(foreground(ctx, cmd, rest, variables)));
+            }
+        }
+
+        let stdin = cmd.stdin.clone().unwrap_or_default();
+        let (stream, sender) = OutputStream::channel(cmd.prompt, cmd.command.clone(), stdin);
+        let (columns, rows) = cmd.size.get();
+        let mut process = tokio::process::Command::new(SHELL.0);
+        process
+            .arg(SHELL.1)
+            .arg(rest)
+            .current_dir(&ctx.cwd)
+            .envs(variables)
+            .env("COLUMNS", columns.to_string())
+            .env("LINES", rows.to_string())
+            .stdin(match cmd.stdin {
+                Some(_) => Stdio::piped(),
+                None => Stdio::null(),
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        // Spawned now, with the settings of the command being run, and waited for on a thread of
+        // its own, so the shell is not blocked until it is done.
+        let spawned = {
+            let _runtime = cmd.runtime.enter();
+            spawn(&mut process)
+        };
+        let (child, job) = match spawned {
+            Ok(spawned) => spawned,
+            Err(e) => {
+                let _ = sender.send(Chunk::Stderr(unable(e)));
+                return Ok(OutputAction::Stream(stream));
+            }
+        };
+        std::thread::spawn(move || {
+            let run = run(child, job, cmd.stdin, &cmd.cancel, &sender);
+            if let Err(e) = cmd.runtime.block_on(run) {
+                let _ = sender.send(Chunk::Stderr(unable(e)));
+            }
+        });
+        Ok(OutputAction::Stream(stream))
+    }
+}
+
+/// Spawn a command in a process group of its own, kept track of as a job with the settings of the
+/// command being run. (See `crate::process`)
+#[cfg(all(unix, feature = "tui"))]
+fn spawn(command: &mut tokio::process::Command) -> io::Result<(Child, Job)> {
+    let limits = crate::process::prepare(command.as_std_mut());
+    let child = command.spawn()?;
+    let id = child
+        .id()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "process exited already"))?;
+    let job = crate::process::track(id, limits)?;
+    Ok((child, job))
+}
+
+/// Spawn a command in a process group of its own.
+#[cfg(not(all(unix, feature = "tui")))]
+fn spawn(command: &mut tokio::process::Command) -> io::Result<(Child, Job)> {
+    #[cfg(unix)]
+    command.process_group(0);
+    Ok((command.spawn()?, ()))
+}
+
+/// Stop keeping track of the job of a command, which exited with the status, if any, or was
+/// killed.
+#[cfg(all(unix, feature = "tui"))]
+fn finished(job: Job, status: Option<ExitStatus>) {
+    crate::process::finished(job, status);
+}
+
+/// Stop keeping track of the job of a command: there is none without job control.
+#[cfg(not(all(unix, feature = "tui")))]
+fn finished(_job: Job, _status: Option<ExitStatus>) {}
+
+/// Run a command in the foreground, handing it the terminal until it exits or stops.
+#[cfg(all(unix, feature = "tui"))]
+fn foreground(
+    ctx: &mut Context,
+    cmd: CommandInput,
+    command: &str,
+    variables: Vec<(String, String)>,
+) -> CommandOutput {
+    // A single command replaces `sh`, so the program stopped is the one handed the terminal: `sh`
+    // would stop first, and the program set the terminal as it was after the shell took it back.
+    let command = match simple(command) {
+        true => format!("exec {}", command),
+        false => command.to_string(),
+    };
+    let mut process = std::process::Command::new(SHELL.0);
+    process
+        .arg(SHELL.1)
+        .arg(command)
+        .current_dir(&ctx.cwd)
+        .envs(variables);
+    let ran = crate::process::run(&mut process);
+    let output = CommandOutput {
+        prompt: cmd.prompt,
+        command: cmd.command.clone(),
+        ..Default::default()
+    };
+    came_back(ctx, output, ran, cmd.command)
+}
+
+/// Note how a command run in the foreground came back to the shell: the status it exited with, or
+/// the job it was stopped as, kept in the context for `fg`.
+#[cfg(all(unix, feature = "tui"))]
+fn came_back(
+    ctx: &mut Context,
+    mut output: CommandOutput,
+    ran: io::Result<crate::process::Foreground>,
+    command: String,
+) -> CommandOutput {
+    match ran {
+        Ok(crate::process::Foreground::Exited(status)) => {
+            output.stderr.extend(killed(status));
+            output.exit_code = status.code();
+        }
+        Ok(crate::process::Foreground::Stopped(job)) => {
+            output
+                .stdout
+                .push(format!("[{}] stopped: {}", job.pid(), command));
+            ctx.stopped.push((job, command));
+        }
+        Err(e) => output.stderr.push(unable(e)),
+    }
+    output
+}
+
+/// Run a command spawned, given its input, if any, sending the lines of its output as they come
+/// in. The command is killed once cancelled.
+async fn run(
+    mut child: Child,
+    job: Job,
+    stdin: Option<Vec<String>>,
+    cancel: &Cancel,
+    sender: &SyncSender<Chunk>,
+) -> io::Result<()> {
+    // The input is written while the output is read, so that neither pipe fills up. A command
+    // exiting before reading it all is no error.
+    if let (Some(mut pipe), Some(lines)) = (child.stdin.take(), stdin) {
+        tokio::spawn(async move {
+            for line in lines {
+                if pipe
+                    .write_all(format!("{}\n", line).as_bytes())
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+    }
+    let stdout = tokio::spawn(forward(child.stdout.take(), Chunk::Stdout, sender.clone()));
+    let stderr = tokio::spawn(forward(child.stderr.take(), Chunk::Stderr, sender.clone()));
+
+    // The command is waited for until it exits, or is cancelled.
+    let status = {
+        let mut exited = std::pin::pin!(child.wait());
+        let mut cancelled = std::pin::pin!(cancel.cancelled());
+        std::future::poll_fn(|cx| match exited.as_mut().poll(cx) {
+            Poll::Ready(status) => Poll::Ready(Some(status)),
+            Poll::Pending => cancelled.as_mut().poll(cx).map(|()| None),
+        })
+        .await
+    };
+    let Some(status) = status else {
+        stdout.abort();
+        stderr.abort();
+        #[cfg(unix)]
+        if let Some(pid) = child.id() {
+            // SAFETY: `kill` has no memory safety requirements.
+            unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+        }
+        let killed = child.kill().await;
+        finished(job, None);
+        return killed;
+    };
+    finished(job, status.as_ref().ok().copied());
+
+    // The exit status is noted after the rest of the output.
+    let status = status?;
+    let sent = stdout.await.unwrap_or(false) & stderr.await.unwrap_or(false);
+    if let Some(killed) = killed(status).filter(|_| sent) {
+        send(sender, Chunk::Stderr(killed)).await;
+    }
+    if let Some(code) = status.code() {
+        send(sender, Chunk::Exit(code)).await;
+    }
+    Ok(())
+}
+
+/// Send the lines read from a pipe, as chunks of one kind, until it is closed. This returns
+/// whether every line was sent.
+async fn forward(
+    pipe: Option<impl AsyncRead + Unpin>,
+    chunk: fn(String) -> Chunk,
+    sender: SyncSender<Chunk>,
+) -> bool {
+    let Some(pipe) = pipe else {
+        return true;
+    };
+    let mut pipe = BufReader::new(pipe);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match pipe.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => return true,
+            Ok(_) => {}
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\n', '\r']).to_string();
+        if !send(&sender, chunk(text)).await {
+            return false;
+        }
+    }
+}
+
+/// Send a chunk, waiting while the channel is full on a thread of its own rather than on the one
+/// running the commands. This returns whether it was sent.
+async fn send(sender: &SyncSender<Chunk>, chunk: Chunk) -> bool {
+    match sender.try_send(chunk) {
+        Ok(()) => true,
+        Err(TrySendError::Disconnected(_)) => false,
+        Err(TrySendError::Full(chunk)) => {
+            let sender = sender.clone();
+            tokio::task::spawn_blocking(move || sender.send(chunk).is_ok())
+                .await
+                .unwrap_or(false)
+        }
+    }
+}
+
+/// The line noting how a command was killed, if it has no exit code.
+fn killed(status: ExitStatus) -> Option<String> {
+    if status.code().is_some() {
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return Some(format!("killed by signal {}", signal));
+        }
+    }
+    Some(format!("{}", status))
+}
+
+/// Whether a command is a single one, with no operator, redirection or expansion in it: the
+/// assignments before it are then for its program alone.
+fn simple(command: &str) -> bool {
+    let mut quote = None;
+    for c in command.chars() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some('\''), _) => {}
+            (_, '$' | '`' | '\\') => return false,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, ';' | '&' | '|' | '<' | '>' | '(' | ')' | '\n') => return false,
+            (None, _) => {}
+        }
+    }
+    true
+}
+
+/// The line telling a command could not run.
+fn unable(error: io::Error) -> String {
+    format!("unable to run `{}`: {}", SHELL.0, error)
+}
+
+/// The home directory of the user.
+fn home() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(PathBuf::from)
+}
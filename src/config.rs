@@ -0,0 +1,1490 @@
+//! Configuration file loading and validation.
+//!
+//! The shell reads an optional TOML configuration file on startup. The file is looked up in the
+//! following order:
+//!
+//! 1. The path in the `SHELLER_CONFIG` environment variable
+//! 2. `$XDG_CONFIG_HOME/sheller/config.toml`
+//! 3. `$HOME/.config/sheller/config.toml`
+//!
+//! Projects can add a `.sheller.toml` of their own, merged over this file with
+//! [`Config::merge`] while working in the project.
+//!
+//! A missing file is not an error, the [`Config::default`] is used instead. A file that fails to
+//! parse, or that does not match the schema, produces a [`ConfigError`] listing every offending
+//! key along with its line number. The renderer shows these on a dedicated screen before falling
+//! back to the defaults, and `sheller config check` prints them.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::path::Path;
+//! use shelgon::config::Config;
+//!
+//! let source = r#"
+//! [ui]
+//! alternate_screen = "yes"
+//! theme = "gruvbox"
+//!
+//! [history]
+//! limit = 500
+//! colour = true
+//! "#;
+//!
+//! let error = Config::parse(source, Path::new("config.toml")).unwrap_err();
+//! assert_eq!(error.issues.len(), 2);
+//! assert_eq!(error.issues[0].line, Some(3));
+//! assert_eq!(error.issues[1].key.as_deref(), Some("history.colour"));
+//! ```
+//!
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use toml_edit::{DocumentMut, ImDocument, Item, Table};
+
+use crate::budget;
+use crate::keymap::{Action, Key, Keymap};
+use crate::theme::{self, Appearance, ColorSupport};
+
+/// The environment variable that overrides the location of the configuration file.
+pub const CONFIG_ENV: &str = "SHELLER_CONFIG";
+
+///
+/// [`Config`] is the validated configuration of the shell.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Settings for the terminal UI. (`[ui]`)
+    pub ui: Ui,
+    /// Settings for the command history. (`[history]`)
+    pub history: History,
+    /// Settings for the commands written from a description. (`[suggest]`)
+    pub suggest: Suggest,
+    /// Settings for the completions of `Tab`. (`[completion]`)
+    pub completion: Completion,
+    /// Settings for the picker of `:pick`. (`[pick]`)
+    pub pick: Pick,
+    /// Settings for locking the screen when the shell is left idle. (`[lock]`)
+    pub lock: Lock,
+    /// Settings for the cues played when a command completes. (`[cues]`)
+    pub cues: Cues,
+    /// Settings for the jobs of the executor. (`[jobs]`)
+    pub jobs: Jobs,
+    /// The resource limits of the programs the commands run. (`[limits]`)
+    pub limits: Limits,
+    /// The file mode mask and the priorities of the programs the commands run. (`[process]`)
+    pub process: Process,
+    /// The environment the programs the commands run get from the shell. (`[child_env]`)
+    pub child_env: ChildEnv,
+    /// The keybindings, with the rebindings of the `[keys]` section applied.
+    pub keys: Keymap,
+    /// The aliases, expanding the first word of a command into a command. (`[aliases]`)
+    pub aliases: BTreeMap<String, String>,
+    /// The environment variables set for the session. (`[env]`)
+    pub env: BTreeMap<String, String>,
+    /// The named directories, written `~NAME` in commands. (`[directories]`, see `crate::tilde`)
+    pub directories: BTreeMap<String, String>,
+    /// The tasks, run by name with `:task NAME`. (`[tasks]`)
+    pub tasks: BTreeMap<String, String>,
+    /// The guards, asking for confirmation before running the commands starting with a prefix,
+    /// with the reason why. (`[guards]`)
+    pub guards: BTreeMap<String, String>,
+    /// How long the commands starting with a prefix are expected to take, warned about when they
+    /// take longer. (`[budgets]`, such as `"terraform apply" = "10m"`, see `crate::budget`)
+    pub budgets: BTreeMap<String, Duration>,
+    /// The profiles syncing the command history with other machines, by name. (`[sync.NAME]`)
+    pub sync: BTreeMap<String, SyncProfile>,
+}
+
+/// Settings for the terminal UI.
+#[derive(Debug, Clone)]
+pub struct Ui {
+    /// Render the shell on the alternate screen. (`alternate_screen`, default: `true`)
+    pub alternate_screen: bool,
+    /// The name of the color theme. (`theme`, default: `"default"`, see [`theme::BUILTIN`])
+    pub theme: String,
+    /// The terminal background the theme variant is picked for. (`background`, one of `"auto"`,
+    /// `"light"` or `"dark"`, default: `"auto"`)
+    ///
+    /// `None` detects the background from the terminal.
+    pub background: Option<Appearance>,
+    /// The colors supported by the terminal. (`colors`, one of `"auto"`, `"truecolor"`, `"256"`,
+    /// `"16"` or `"none"`, default: `"auto"`)
+    ///
+    /// `None` detects the supported colors from `NO_COLOR`, `COLORTERM` and `TERM`.
+    pub colors: Option<ColorSupport>,
+    /// Run the shell in screen reader mode: no full screen UI and no styling, just a linear
+    /// transcript with textual markers. (`screen_reader`, default: `false`)
+    pub screen_reader: bool,
+    /// The locale of the messages of the shell. (`locale`, such as `"de"`, default: `"auto"`)
+    ///
+    /// `None` detects the locale from the environment. (See [`crate::i18n`])
+    pub locale: Option<String>,
+    /// Emit the shell integration escape sequences: prompt markers and working directory reports.
+    /// (`shell_integration`, default: `true`, see [`crate::renderer`])
+    pub shell_integration: bool,
+    /// The window title while the shell is idle, with `{cwd}` standing for the working directory.
+    /// (`title`, default: `"{cwd} — sheller"`)
+    ///
+    /// An empty title leaves the window title alone.
+    pub title: String,
+    /// The window title while a command is running, with `{command}` standing for the command and
+    /// `{cwd}` for the working directory. (`title_running`, default: `"{command} — sheller"`)
+    pub title_running: String,
+    /// Show a hint on the usage of the command being typed below the prompt, from its completion
+    /// spec. (`hints`, default: `true`)
+    pub hints: bool,
+    /// Offer to correct the first word of a command when it is close to a known command, before
+    /// running it. (`autocorrect`, default: `true`)
+    pub autocorrect: bool,
+    /// The output shown of the commands of the rc file, run at startup. (`rc_output`, one of
+    /// `"all"`, `"errors"` or `"none"`, default: `"all"`)
+    pub rc_output: RcOutput,
+    /// Ask the terminal to show a desktop notification when a command takes longer than its
+    /// budget. (`notify_over_budget`, default: `false`, see [`Config::budgets`])
+    pub notify_over_budget: bool,
+    /// The lines of output of a command added to the history per frame, at most: the rest of a
+    /// longer output comes in over the next frames, and can be paused or dropped. (`flood_lines`,
+    /// default: `1000`)
+    ///
+    /// `0` adds the output at once, however long.
+    pub flood_lines: usize,
+    /// Scroll the history with the mouse wheel. (`mouse`, default: `true`)
+    ///
+    /// The terminal then sends the mouse to the shell: most terminals select text with `Shift`
+    /// held down instead.
+    pub mouse: bool,
+}
+
+impl Default for Ui {
+    fn default() -> Self {
+        Self {
+            alternate_screen: true,
+            theme: "default".to_string(),
+            background: None,
+            colors: None,
+            screen_reader: false,
+            locale: None,
+            shell_integration: true,
+            title: "{cwd} — sheller".to_string(),
+            title_running: "{command} — sheller".to_string(),
+            hints: true,
+            autocorrect: true,
+            rc_output: RcOutput::All,
+            notify_over_budget: false,
+            flood_lines: 1000,
+            mouse: true,
+        }
+    }
+}
+
+/// Settings for the command history.
+#[derive(Debug, Clone)]
+pub struct History {
+    /// The maximum number of entries kept in the scrollback. (`limit`, default: unlimited)
+    pub limit: Option<usize>,
+    /// The number of commands suggested for the working directory, among the ones run there most
+    /// often and most recently. (`suggestions`, default: `5`, see [`crate::rank`])
+    ///
+    /// `0` disables the suggestions, and stops counting the commands run in each directory.
+    pub suggestions: usize,
+    /// Suggest commands below the prompt whenever it is empty, rather than only on a key press.
+    /// (`suggest_on_empty`, default: `false`)
+    pub suggest_on_empty: bool,
+    /// Keep the commands run in a journal shared by every session, so a command run in one window
+    /// can be recalled in every other one. (`share`, default: `true`, see `crate::journal`)
+    ///
+    /// When turned off, only the commands run in the session are recalled.
+    pub share: bool,
+    /// The memory the history may take, in MiB, past which the output of the oldest entries is
+    /// compressed, and then the oldest entries are spilled to disk. (`memory_budget`, default:
+    /// unlimited, see `crate::memory`)
+    ///
+    /// This is read when the shell starts.
+    pub memory_budget: Option<usize>,
+    /// Add the commands run for a command substitution, `$(COMMAND)`, to the history too.
+    /// (`record_substitutions`, default: `false`, see `crate::substitution`)
+    pub record_substitutions: bool,
+    /// The seconds between two saves of the state of the session, restored after a crash.
+    /// (`autosave`, default: `30`, see `crate::autosave`)
+    ///
+    /// `0` disables the autosave.
+    pub autosave: u64,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            limit: None,
+            suggestions: 5,
+            suggest_on_empty: false,
+            share: true,
+            memory_budget: None,
+            record_substitutions: false,
+            autosave: 30,
+        }
+    }
+}
+
+/// Settings for the commands written from a description by a language model. These need the
+/// `suggest` feature. (See `crate::suggest`)
+#[derive(Debug, Clone)]
+pub struct Suggest {
+    /// The backend writing the commands. (`backend`, one of `"none"`, `"program"` or `"http"`,
+    /// default: `"none"`)
+    ///
+    /// `None` disables the suggestions: lines starting with `#` are run like any other.
+    pub backend: Option<SuggestBackend>,
+    /// The program run by the `program` backend, along with its arguments. (`program`, default:
+    /// `"ollama run llama3.2"`)
+    pub program: String,
+    /// The OpenAI-compatible chat completions endpoint called by the `http` backend. (`url`,
+    /// default: `"http://localhost:11434/v1/chat/completions"`, served by Ollama)
+    pub url: String,
+    /// The model asked by the `http` backend. (`model`, default: `"llama3.2"`)
+    pub model: String,
+    /// The environment variable holding the API key sent by the `http` backend. (`api_key_env`,
+    /// such as `"OPENAI_API_KEY"`, default: none)
+    ///
+    /// The key itself never goes in the configuration file. `None` sends no key.
+    pub api_key_env: Option<String>,
+}
+
+impl Default for Suggest {
+    fn default() -> Self {
+        Self {
+            backend: None,
+            program: "ollama run llama3.2".to_string(),
+            url: "http://localhost:11434/v1/chat/completions".to_string(),
+            model: "llama3.2".to_string(),
+            api_key_env: None,
+        }
+    }
+}
+
+/// Settings for the completions of `Tab`.
+#[derive(Debug, Clone)]
+pub struct Completion {
+    /// The programs whose arguments complete to the hosts known to ssh. (`hosts`, default:
+    /// `["ssh", "scp", "sftp"]`, see `crate::hosts`)
+    pub hosts: Vec<String>,
+}
+
+impl Default for Completion {
+    fn default() -> Self {
+        Self {
+            hosts: ["ssh", "scp", "sftp"].map(String::from).to_vec(),
+        }
+    }
+}
+
+/// Settings for the picker of `:pick`, choosing a line of the output of the last command.
+#[derive(Debug, Clone)]
+pub struct Pick {
+    /// The picker. (`finder`, one of `"internal"` or `"external"`, default: `"internal"`)
+    pub finder: Finder,
+    /// The command of the external picker, reading the lines on its input and writing the one
+    /// chosen. (`command`, default: `"fzf"`)
+    pub command: String,
+}
+
+impl Default for Pick {
+    fn default() -> Self {
+        Self {
+            finder: Finder::Internal,
+            command: "fzf".to_string(),
+        }
+    }
+}
+
+/// The picker of `:pick`. (See [`Pick::finder`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Finder {
+    /// The picker of the shell, in an overlay.
+    #[default]
+    Internal,
+    /// A fuzzy finder such as fzf or skim, handed the terminal while it runs. The picker of the
+    /// shell is used where it cannot run, such as in a detached session.
+    External,
+}
+
+/// Settings for locking the screen when the shell is left idle: the history is hidden until the
+/// passphrase is typed, or any key is pressed. This is only done in the full screen interface. The
+/// overlays opening while the screen is locked, such as the next step of a runbook, wait until it
+/// is unlocked.
+///
+/// A project file cannot change these settings: they are the user's own.
+#[derive(Debug, Clone, Default)]
+pub struct Lock {
+    /// The minutes without input after which the screen is locked. (`after`, default: `0`)
+    ///
+    /// `0` never locks the screen.
+    pub after: u64,
+    /// The SHA-256 of the passphrase unlocking the screen, in lowercase hexadecimal, such as
+    /// printed by `sha256sum`. (`passphrase_sha256`, default: none)
+    ///
+    /// `None` unlocks the screen with any key. Only the hash is kept, so the passphrase cannot be
+    /// read from the configuration file.
+    pub passphrase_sha256: Option<String>,
+}
+
+/// Settings for the cues played when a command completes, such as when the shell runs in a pane
+/// in the background.
+#[derive(Debug, Clone, Default)]
+pub struct Cues {
+    /// The commands completing with a cue. (`on`, one of `"never"`, `"always"`, `"failure"` or
+    /// `"success"`, default: `"never"`)
+    pub on: CueOn,
+    /// The seconds a command must take to complete with a cue. (`after`, default: `0`)
+    ///
+    /// `0` plays the cue for every command, however quick.
+    pub after: u64,
+    /// The cue played. (`sound`, one of `"bell"` or `"notification"`, default: `"bell"`)
+    pub sound: CueSound,
+}
+
+/// The commands completing with a cue. (See [`Cues::on`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CueOn {
+    /// No command.
+    #[default]
+    Never,
+    /// Every command.
+    Always,
+    /// The commands that failed, exiting with a code other than 0.
+    Failure,
+    /// The commands that succeeded.
+    Success,
+}
+
+/// The cue played when a command completes. (See [`Cues::sound`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CueSound {
+    /// The terminal bell. (BEL)
+    #[default]
+    Bell,
+    /// A desktop notification telling the command and whether it failed, with the sound the
+    /// terminal plays for it. (OSC 9)
+    Notification,
+}
+
+/// Settings for the jobs of the executor, the programs it runs in process groups of their own.
+/// (See `crate::process`)
+#[derive(Debug, Clone, Default)]
+pub struct Jobs {
+    /// What becomes of the jobs left when the shell exits. (`on_exit`, one of `"terminate"` or
+    /// `"detach"`, default: `"terminate"`)
+    ///
+    /// This is read when the shell starts.
+    pub on_exit: OnExit,
+}
+
+/// What becomes of the jobs left when the shell exits. (See [`Jobs::on_exit`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnExit {
+    /// The jobs are terminated, and killed if they do not exit after a moment.
+    #[default]
+    Terminate,
+    /// The jobs keep running, the stopped ones continued.
+    Detach,
+}
+
+/// The resource limits of the programs the commands run, set before they start. The limits of
+/// the commands starting with a prefix are set in a table of the prefix, over the ones of every
+/// command: (See `crate::process`)
+///
+/// ```toml
+/// [limits]
+/// memory = 4096
+///
+/// [limits."cargo test"]
+/// cpu = "10m"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// The processor time a program may take. (`cpu`, a duration such as `"10m"`, default: none)
+    pub cpu: Option<Duration>,
+    /// The memory a program may map, in MiB. (`memory`, default: none)
+    pub memory: Option<u64>,
+    /// The number of files a program may have open at once. (`files`, default: none)
+    pub files: Option<u64>,
+    /// The limits of the commands starting with a prefix, by prefix. (`[limits."PREFIX"]`)
+    pub commands: BTreeMap<String, Limits>,
+}
+
+impl Limits {
+    /// The limits of a command: the ones of the longest prefix it starts with, over the ones of
+    /// every command.
+    pub fn of(&self, command: &str) -> Limits {
+        let command = command.trim_start();
+        let specific = self
+            .commands
+            .iter()
+            .filter(|(prefix, _)| command.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, limits)| limits);
+        Limits {
+            cpu: specific.and_then(|limits| limits.cpu).or(self.cpu),
+            memory: specific.and_then(|limits| limits.memory).or(self.memory),
+            files: specific.and_then(|limits| limits.files).or(self.files),
+            commands: BTreeMap::new(),
+        }
+    }
+
+    /// Read the limits of a table, over the ones already set.
+    fn read(&mut self, table: &Table) {
+        if let Some(value) = table.get("cpu").and_then(Item::as_str) {
+            self.cpu = budget::parse(value);
+        }
+        if let Some(value) = table.get("memory").and_then(Item::as_integer) {
+            self.memory = u64::try_from(value).ok();
+        }
+        if let Some(value) = table.get("files").and_then(Item::as_integer) {
+            self.files = u64::try_from(value).ok();
+        }
+    }
+}
+
+/// The file mode mask and the priorities of the programs the commands run, set before they start.
+/// Like [`Limits`], the settings of the commands starting with a prefix are set in a table of the
+/// prefix, over the ones of every command. (See `crate::process`)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Process {
+    /// The file mode mask, leaving out the permissions of the files created. (`umask`, in octal
+    /// such as `"022"`, default: inherited)
+    pub umask: Option<u32>,
+    /// The niceness, from `-20` (the most favorable) to `19` (the least). (`nice`, default:
+    /// inherited)
+    ///
+    /// Only a privileged user may set a niceness below the one of the shell.
+    pub nice: Option<i32>,
+    /// The I/O scheduling class and level, on Linux. (`ionice`, one of `"realtime"`,
+    /// `"best-effort"` or `"idle"`, and `ionice_level`, from `0` to `7`, default: inherited)
+    pub ionice: Option<IoPriority>,
+    /// The settings of the commands starting with a prefix, by prefix. (`[process."PREFIX"]`)
+    pub commands: BTreeMap<String, Process>,
+}
+
+/// The I/O scheduling class of a program, with its level from `0` (the highest) to `7` where it
+/// has one. (See [`Process::ionice`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoPriority {
+    /// Served before everything else. Only a privileged user may set it.
+    Realtime(u8),
+    /// Served in turn with the other programs. (The default of programs)
+    BestEffort(u8),
+    /// Served when no other program uses the disk.
+    Idle,
+}
+
+impl fmt::Display for IoPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.level() {
+            Some(level) => write!(f, "{} {}", self.class(), level),
+            None => f.write_str(self.class()),
+        }
+    }
+}
+
+impl IoPriority {
+    /// The priority of a class, with the level for the classes having one (`4` if it is `None`).
+    /// This returns `None` for an unknown class, or a level past `7`.
+    pub fn new(class: &str, level: Option<u8>) -> Option<Self> {
+        let level = level.unwrap_or(4);
+        if level > 7 {
+            return None;
+        }
+        match class {
+            "realtime" => Some(IoPriority::Realtime(level)),
+            "best-effort" => Some(IoPriority::BestEffort(level)),
+            "idle" => Some(IoPriority::Idle),
+            _ => None,
+        }
+    }
+
+    /// The name of the class.
+    pub fn class(self) -> &'static str {
+        match self {
+            IoPriority::Realtime(_) => "realtime",
+            IoPriority::BestEffort(_) => "best-effort",
+            IoPriority::Idle => "idle",
+        }
+    }
+
+    /// The level within the class, if it has levels.
+    pub fn level(self) -> Option<u8> {
+        match self {
+            IoPriority::Realtime(level) | IoPriority::BestEffort(level) => Some(level),
+            IoPriority::Idle => None,
+        }
+    }
+}
+
+impl Process {
+    /// The settings of a command: the ones of the longest prefix it starts with, over the ones of
+    /// every command.
+    pub fn of(&self, command: &str) -> Process {
+        let command = command.trim_start();
+        let specific = self
+            .commands
+            .iter()
+            .filter(|(prefix, _)| command.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, process)| process);
+        Process {
+            umask: specific.and_then(|process| process.umask).or(self.umask),
+            nice: specific.and_then(|process| process.nice).or(self.nice),
+            ionice: specific.and_then(|process| process.ionice).or(self.ionice),
+            commands: BTreeMap::new(),
+        }
+    }
+
+    /// Read the settings of a table, over the ones already set.
+    fn read(&mut self, table: &Table) {
+        if let Some(value) = table.get("umask").and_then(Item::as_str) {
+            self.umask = u32::from_str_radix(value, 8).ok();
+        }
+        if let Some(value) = table.get("nice").and_then(Item::as_integer) {
+            self.nice = i32::try_from(value).ok();
+        }
+        let level = table
+            .get("ionice_level")
+            .and_then(Item::as_integer)
+            .and_then(|level| u8::try_from(level).ok());
+        let class = table.get("ionice").and_then(Item::as_str);
+        if class.is_some() || level.is_some() {
+            let class = class.or(self.ionice.map(IoPriority::class));
+            self.ionice = IoPriority::new(class.unwrap_or("best-effort"), level);
+        }
+    }
+}
+
+/// The environment the programs the commands run get from the shell, set before they start:
+/// the variables passed to them, the ones scrubbed, and the ones set. (See `crate::process`)
+///
+/// Names end with `*` to match every variable starting with the rest, such as `"LC_*"`.
+///
+/// Programs writing to a pipe, as they do when their output is captured, leave their colors out.
+/// The ones of the commands starting with a prefix of `force_color` are told to keep them, with
+/// the variables of [`FORCE_COLOR`], which the shell draws.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChildEnv {
+    /// The variables passed, alone, along with the ones set. (`pass`, default: every variable)
+    pub pass: Vec<String>,
+    /// The variables scrubbed, even when passed. (`scrub`, default: none)
+    pub scrub: Vec<String>,
+    /// The variables set, over the ones of the shell. (`[child_env.set]`)
+    ///
+    /// A variable set by the executor for a command, such as with `NAME=value cmd`, is left as
+    /// it is.
+    pub set: BTreeMap<String, String>,
+    /// The prefixes of the commands whose programs are told to keep their colors, `"*"` for
+    /// every command. (`force_color`, default: none)
+    pub force_color: Vec<String>,
+}
+
+/// The variables telling programs to keep their colors, though they do not write to a terminal,
+/// with their value. Most programs follow `CLICOLOR_FORCE` or `FORCE_COLOR`; cargo has its own.
+pub const FORCE_COLOR: &[(&str, &str)] = &[
+    ("CLICOLOR_FORCE", "1"),
+    ("FORCE_COLOR", "1"),
+    ("CARGO_TERM_COLOR", "always"),
+];
+
+impl ChildEnv {
+    /// Check whether a variable of the shell is passed to the programs.
+    pub fn passes(&self, name: &str) -> bool {
+        let matches = |pattern: &String| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == pattern,
+        };
+        (self.pass.is_empty() || self.pass.iter().any(matches)) && !self.scrub.iter().any(matches)
+    }
+
+    /// The environment of the programs, from the variables of the shell.
+    pub fn apply(
+        &self,
+        variables: impl IntoIterator<Item = (String, String)>,
+    ) -> BTreeMap<String, String> {
+        let mut environment = variables
+            .into_iter()
+            .filter(|(name, _)| self.passes(name))
+            .collect::<BTreeMap<_, _>>();
+        environment.extend(self.set.clone());
+        environment
+    }
+
+    /// The environment of the programs of a command: with the variables of [`FORCE_COLOR`] set
+    /// if it starts with a prefix of `force_color`, unless they are set already.
+    pub fn of(&self, command: &str) -> ChildEnv {
+        let command = command.trim_start();
+        let forced = self
+            .force_color
+            .iter()
+            .any(|prefix| command.starts_with(prefix.strip_suffix('*').unwrap_or(prefix)));
+        let mut environment = self.clone();
+        if forced {
+            for (name, value) in FORCE_COLOR {
+                environment
+                    .set
+                    .entry(name.to_string())
+                    .or_insert_with(|| value.to_string());
+            }
+        }
+        environment
+    }
+}
+
+/// Settings for a profile syncing the command history with other machines. These need the `sync`
+/// feature. (See `crate::sync`)
+#[derive(Debug, Clone)]
+pub struct SyncProfile {
+    /// The storage the history is synced through. (`backend`, one of `"s3"`, `"webdav"` or
+    /// `"ssh"`, default: none)
+    ///
+    /// `None` leaves the profile unusable.
+    pub backend: Option<SyncBackend>,
+    /// The location of the history in the storage. (`url`, default: none)
+    ///
+    /// This is `https://HOST/BUCKET[/PREFIX]` for `s3`, the URL of a collection for `webdav`, and
+    /// `[USER@]HOST:DIR` for `ssh`.
+    pub url: String,
+    /// The region of the bucket of the `s3` backend. (`region`, default: `"us-east-1"`)
+    pub region: String,
+    /// The environment variable holding the access key of the `s3` backend. (`access_key_env`,
+    /// default: `"AWS_ACCESS_KEY_ID"`)
+    pub access_key_env: String,
+    /// The environment variable holding the secret key of the `s3` backend. (`secret_key_env`,
+    /// default: `"AWS_SECRET_ACCESS_KEY"`)
+    pub secret_key_env: String,
+    /// The user of the `webdav` backend. (`user`, default: none)
+    pub user: Option<String>,
+    /// The environment variable holding the password of the `webdav` backend. (`password_env`,
+    /// default: none)
+    pub password_env: Option<String>,
+    /// The environment variable holding the passphrase the history is encrypted with.
+    /// (`passphrase_env`, default: `"SHELLER_SYNC_PASSPHRASE"`)
+    pub passphrase_env: String,
+    /// The file holding the passphrase, read in place of the environment variable.
+    /// (`passphrase_file`, default: none)
+    pub passphrase_file: Option<PathBuf>,
+}
+
+impl Default for SyncProfile {
+    fn default() -> Self {
+        Self {
+            backend: None,
+            url: String::new(),
+            region: "us-east-1".to_string(),
+            access_key_env: "AWS_ACCESS_KEY_ID".to_string(),
+            secret_key_env: "AWS_SECRET_ACCESS_KEY".to_string(),
+            user: None,
+            password_env: None,
+            passphrase_env: "SHELLER_SYNC_PASSPHRASE".to_string(),
+            passphrase_file: None,
+        }
+    }
+}
+
+/// The output shown of the commands of the rc file. (See [`Ui::rc_output`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RcOutput {
+    /// Every command, with its output.
+    All,
+    /// Only the commands that failed, exiting with a code other than 0.
+    Errors,
+    /// Nothing.
+    None,
+}
+
+/// The backend writing commands from a description.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuggestBackend {
+    /// A local program, reading the request on its input and writing the command on its output.
+    Program,
+    /// An OpenAI-compatible chat completions API.
+    Http,
+}
+
+/// The storage a profile syncs the command history through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncBackend {
+    /// A bucket of Amazon S3, or of a compatible storage.
+    S3,
+    /// A WebDAV collection.
+    WebDav,
+    /// A directory of another machine, reached with `ssh`.
+    Ssh,
+}
+
+/// The type of a value in the configuration schema.
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Bool,
+    Integer,
+    String,
+    /// A string, restricted to one of the given values.
+    Choice(&'static [&'static str]),
+    /// A key, or a list of keys. (See [`crate::keymap`])
+    Keys,
+    /// A duration, such as `"1h30m"`. (See `crate::budget`)
+    Duration,
+    /// A file mode, in octal.
+    Octal,
+    /// A list of names.
+    Names,
+    /// A SHA-256, in hexadecimal.
+    Digest,
+    /// An integer, which may be negative.
+    Signed,
+    /// A table of typed keys.
+    Table(&'static [(&'static str, Kind)]),
+}
+
+impl Kind {
+    /// Check if the item matches this kind.
+    fn matches(self, item: &Item) -> bool {
+        match self {
+            Kind::Bool => item.as_bool().is_some(),
+            Kind::Integer => item.as_integer().is_some(),
+            Kind::String => item.as_str().is_some(),
+            Kind::Choice(choices) => item.as_str().is_some_and(|value| choices.contains(&value)),
+            Kind::Keys => keys(item).is_some(),
+            Kind::Duration => item.as_str().and_then(budget::parse).is_some(),
+            Kind::Octal => item
+                .as_str()
+                .is_some_and(|value| u32::from_str_radix(value, 8).is_ok_and(|mode| mode <= 0o777)),
+            Kind::Signed => item.as_integer().is_some(),
+            Kind::Names => names(item).is_some(),
+            Kind::Digest => item.as_str().is_some_and(|value| {
+                value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+            }),
+            Kind::Table(_) => item.as_table().is_some(),
+        }
+    }
+
+    /// The name of the kind, as shown in validation errors.
+    fn name(self) -> String {
+        match self {
+            Kind::Bool => "a boolean".to_string(),
+            Kind::Integer => "an integer".to_string(),
+            Kind::String => "a string".to_string(),
+            Kind::Choice(choices) => format!("one of: {}", choices.join(", ")),
+            Kind::Keys => "a key or a list of keys, such as \"ctrl+l\"".to_string(),
+            Kind::Duration => "a duration, such as \"10m\" or \"1h30m\"".to_string(),
+            Kind::Octal => "a file mode in octal, such as \"022\"".to_string(),
+            Kind::Signed => "an integer".to_string(),
+            Kind::Names => "a list of names, such as [\"LANG\", \"LC_*\"]".to_string(),
+            Kind::Digest => "a SHA-256 in hexadecimal, as printed by sha256sum".to_string(),
+            Kind::Table(_) => "a table".to_string(),
+        }
+    }
+}
+
+/// The schema of the configuration file. Every section is a table of typed keys.
+const SCHEMA: &[(&str, &[(&str, Kind)])] = &[
+    (
+        "ui",
+        &[
+            ("alternate_screen", Kind::Bool),
+            ("theme", Kind::Choice(theme::BUILTIN)),
+            ("background", Kind::Choice(&["auto", "light", "dark"])),
+            (
+                "colors",
+                Kind::Choice(&["auto", "truecolor", "256", "16", "none"]),
+            ),
+            ("screen_reader", Kind::Bool),
+            ("locale", Kind::String),
+            ("shell_integration", Kind::Bool),
+            ("title", Kind::String),
+            ("title_running", Kind::String),
+            ("hints", Kind::Bool),
+            ("autocorrect", Kind::Bool),
+            ("rc_output", Kind::Choice(&["all", "errors", "none"])),
+            ("notify_over_budget", Kind::Bool),
+            ("flood_lines", Kind::Integer),
+            ("mouse", Kind::Bool),
+        ],
+    ),
+    (
+        "history",
+        &[
+            ("limit", Kind::Integer),
+            ("suggestions", Kind::Integer),
+            ("suggest_on_empty", Kind::Bool),
+            ("share", Kind::Bool),
+            ("memory_budget", Kind::Integer),
+            ("record_substitutions", Kind::Bool),
+            ("autosave", Kind::Integer),
+        ],
+    ),
+    (
+        "suggest",
+        &[
+            ("backend", Kind::Choice(&["none", "program", "http"])),
+            ("program", Kind::String),
+            ("url", Kind::String),
+            ("model", Kind::String),
+            ("api_key_env", Kind::String),
+        ],
+    ),
+    ("completion", &[("hosts", Kind::Names)]),
+    (
+        "pick",
+        &[
+            ("finder", Kind::Choice(&["internal", "external"])),
+            ("command", Kind::String),
+        ],
+    ),
+    (
+        "lock",
+        &[
+            ("after", Kind::Integer),
+            ("passphrase_sha256", Kind::Digest),
+        ],
+    ),
+    (
+        "cues",
+        &[
+            (
+                "on",
+                Kind::Choice(&["never", "always", "failure", "success"]),
+            ),
+            ("after", Kind::Integer),
+            ("sound", Kind::Choice(&["bell", "notification"])),
+        ],
+    ),
+    (
+        "jobs",
+        &[("on_exit", Kind::Choice(&["terminate", "detach"]))],
+    ),
+    (
+        "limits",
+        &[
+            ("cpu", Kind::Duration),
+            ("memory", Kind::Integer),
+            ("files", Kind::Integer),
+            (
+                ANY,
+                Kind::Table(&[
+                    ("cpu", Kind::Duration),
+                    ("memory", Kind::Integer),
+                    ("files", Kind::Integer),
+                ]),
+            ),
+        ],
+    ),
+    (
+        "process",
+        &[
+            ("umask", Kind::Octal),
+            ("nice", Kind::Signed),
+            ("ionice", Kind::Choice(&["realtime", "best-effort", "idle"])),
+            ("ionice_level", Kind::Integer),
+            (
+                ANY,
+                Kind::Table(&[
+                    ("umask", Kind::Octal),
+                    ("nice", Kind::Signed),
+                    ("ionice", Kind::Choice(&["realtime", "best-effort", "idle"])),
+                    ("ionice_level", Kind::Integer),
+                ]),
+            ),
+        ],
+    ),
+    (
+        "keys",
+        &[
+            ("help", Kind::Keys),
+            ("clear-screen", Kind::Keys),
+            ("exit", Kind::Keys),
+            ("interrupt", Kind::Keys),
+            ("detach", Kind::Keys),
+            ("cursor-left", Kind::Keys),
+            ("cursor-right", Kind::Keys),
+            ("delete-backward", Kind::Keys),
+            ("delete-to-start", Kind::Keys),
+            ("complete", Kind::Keys),
+            ("submit", Kind::Keys),
+            ("history-previous", Kind::Keys),
+            ("history-next", Kind::Keys),
+            ("previous-prompt", Kind::Keys),
+            ("next-prompt", Kind::Keys),
+            ("scroll-up", Kind::Keys),
+            ("scroll-down", Kind::Keys),
+            ("search-history", Kind::Keys),
+            ("suggest-commands", Kind::Keys),
+            ("annotate-entry", Kind::Keys),
+            ("star-entry", Kind::Keys),
+            ("show-starred", Kind::Keys),
+            ("fix-command", Kind::Keys),
+            ("insert-last-argument", Kind::Keys),
+            ("describe-command", Kind::Keys),
+            ("explain-command", Kind::Keys),
+            ("show-manual", Kind::Keys),
+            ("pick-process", Kind::Keys),
+            ("yank", Kind::Keys),
+            ("show-kills", Kind::Keys),
+            ("copy-output", Kind::Keys),
+            ("copy-mode", Kind::Keys),
+            ("queue-command", Kind::Keys),
+            ("show-queue", Kind::Keys),
+            ("pause-output", Kind::Keys),
+            ("new-tab", Kind::Keys),
+            ("next-tab", Kind::Keys),
+            ("previous-tab", Kind::Keys),
+            ("close-tab", Kind::Keys),
+            ("record-macro", Kind::Keys),
+            ("play-macro", Kind::Keys),
+            ("run", Kind::Table(&[(ANY, Kind::String)])),
+            ("insert", Kind::Table(&[(ANY, Kind::String)])),
+        ],
+    ),
+    ("aliases", &[(ANY, Kind::String)]),
+    ("env", &[(ANY, Kind::String)]),
+    ("directories", &[(ANY, Kind::String)]),
+    ("tasks", &[(ANY, Kind::String)]),
+    ("guards", &[(ANY, Kind::String)]),
+    ("budgets", &[(ANY, Kind::Duration)]),
+    (
+        "child_env",
+        &[
+            ("pass", Kind::Names),
+            ("scrub", Kind::Names),
+            ("force_color", Kind::Names),
+            ("set", Kind::Table(&[(ANY, Kind::String)])),
+        ],
+    ),
+    (
+        "sync",
+        &[(
+            ANY,
+            Kind::Table(&[
+                ("backend", Kind::Choice(&["s3", "webdav", "ssh"])),
+                ("url", Kind::String),
+                ("region", Kind::String),
+                ("access_key_env", Kind::String),
+                ("secret_key_env", Kind::String),
+                ("user", Kind::String),
+                ("password_env", Kind::String),
+                ("passphrase_env", Kind::String),
+                ("passphrase_file", Kind::String),
+            ]),
+        )],
+    ),
+];
+
+/// The key standing for any key of a section in the [`SCHEMA`], for sections of names chosen by
+/// the user.
+const ANY: &str = "*";
+
+///
+/// [`Issue`] is a single problem found while validating the configuration file.
+///
+#[derive(Debug, Clone)]
+pub struct Issue {
+    /// The 1-based line number of the problem, if known.
+    pub line: Option<usize>,
+    /// The dotted path of the offending key, if the problem is tied to one.
+    pub key: Option<String>,
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, "`{}`: ", key)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+///
+/// [`ConfigError`] is returned when the configuration file cannot be read, parsed, or validated.
+/// It carries every [`Issue`] found in the file.
+///
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    /// The path of the configuration file.
+    pub path: PathBuf,
+    /// The problems found in the file.
+    pub issues: Vec<Issue>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration in {}", self.path.display())?;
+        for issue in &self.issues {
+            write!(f, "\n  {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// The default location of the configuration file, if one can be determined.
+    pub fn path() -> Option<PathBuf> {
+        match std::env::var_os(CONFIG_ENV) {
+            Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+            _ => config_dir().map(|dir| dir.join("config.toml")),
+        }
+    }
+
+    /// Load the configuration from the default location. A missing file yields the defaults.
+    pub fn load() -> Result<Self, ConfigError> {
+        match Self::path() {
+            Some(path) if path.exists() => Self::load_from(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Load the configuration from the given file.
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        let source = fs::read_to_string(path).map_err(|e| ConfigError {
+            path: path.to_path_buf(),
+            issues: vec![Issue {
+                line: None,
+                key: None,
+                message: e.to_string(),
+            }],
+        })?;
+
+        Self::parse(&source, path)
+    }
+
+    /// Parse and validate the configuration from its source. The `path` is only used for
+    /// reporting.
+    pub fn parse(source: &str, path: &Path) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        config.merge(source, path)?;
+        Ok(config)
+    }
+
+    /// Parse and validate a configuration from its source, and apply it over this one: the keys it
+    /// sets replace the current ones, and the entries of its tables are added. The `path` is only
+    /// used for reporting.
+    pub fn merge(&mut self, source: &str, path: &Path) -> Result<(), ConfigError> {
+        let error = |issues| ConfigError {
+            path: path.to_path_buf(),
+            issues,
+        };
+
+        let document = ImDocument::parse(source).map_err(|e| {
+            error(vec![Issue {
+                line: e.span().map(|span| line_of(source, span)),
+                key: None,
+                message: e.message().trim().lines().collect::<Vec<_>>().join(": "),
+            }])
+        })?;
+
+        let issues = validate(source, document.as_table());
+        if !issues.is_empty() {
+            return Err(error(issues));
+        }
+
+        let config = self;
+        let root = document.as_table();
+        if let Some(value) = lookup(root, "ui", "alternate_screen").and_then(Item::as_bool) {
+            config.ui.alternate_screen = value;
+        }
+        if let Some(value) = lookup(root, "ui", "screen_reader").and_then(Item::as_bool) {
+            config.ui.screen_reader = value;
+        }
+        if let Some(value) = lookup(root, "ui", "shell_integration").and_then(Item::as_bool) {
+            config.ui.shell_integration = value;
+        }
+        if let Some(value) = lookup(root, "ui", "hints").and_then(Item::as_bool) {
+            config.ui.hints = value;
+        }
+        if let Some(value) = lookup(root, "ui", "autocorrect").and_then(Item::as_bool) {
+            config.ui.autocorrect = value;
+        }
+        if let Some(value) = lookup(root, "ui", "mouse").and_then(Item::as_bool) {
+            config.ui.mouse = value;
+        }
+        if let Some(value) = lookup(root, "ui", "notify_over_budget").and_then(Item::as_bool) {
+            config.ui.notify_over_budget = value;
+        }
+        if let Some(value) = lookup(root, "ui", "flood_lines").and_then(Item::as_integer) {
+            config.ui.flood_lines = usize::try_from(value).unwrap_or_default();
+        }
+        if let Some(value) = lookup(root, "ui", "title").and_then(Item::as_str) {
+            config.ui.title = value.to_string();
+        }
+        if let Some(value) = lookup(root, "ui", "title_running").and_then(Item::as_str) {
+            config.ui.title_running = value.to_string();
+        }
+        if let Some(value) = lookup(root, "ui", "locale").and_then(Item::as_str) {
+            config.ui.locale = Some(value.to_string()).filter(|locale| locale != "auto");
+        }
+        if let Some(value) = lookup(root, "ui", "theme").and_then(Item::as_str) {
+            config.ui.theme = value.to_string();
+        }
+        match lookup(root, "ui", "background").and_then(Item::as_str) {
+            Some("light") => config.ui.background = Some(Appearance::Light),
+            Some("dark") => config.ui.background = Some(Appearance::Dark),
+            _ => {}
+        }
+        match lookup(root, "ui", "colors").and_then(Item::as_str) {
+            Some("truecolor") => config.ui.colors = Some(ColorSupport::TrueColor),
+            Some("256") => config.ui.colors = Some(ColorSupport::Ansi256),
+            Some("16") => config.ui.colors = Some(ColorSupport::Ansi16),
+            Some("none") => config.ui.colors = Some(ColorSupport::NoColor),
+            _ => {}
+        }
+        match lookup(root, "ui", "rc_output").and_then(Item::as_str) {
+            Some("all") => config.ui.rc_output = RcOutput::All,
+            Some("errors") => config.ui.rc_output = RcOutput::Errors,
+            Some("none") => config.ui.rc_output = RcOutput::None,
+            _ => {}
+        }
+        if let Some(value) = lookup(root, "history", "limit").and_then(Item::as_integer) {
+            config.history.limit = usize::try_from(value).ok();
+        }
+        if let Some(value) = lookup(root, "history", "suggestions").and_then(Item::as_integer) {
+            config.history.suggestions = usize::try_from(value).unwrap_or_default();
+        }
+        if let Some(value) = lookup(root, "history", "suggest_on_empty").and_then(Item::as_bool) {
+            config.history.suggest_on_empty = value;
+        }
+        if let Some(value) = lookup(root, "history", "share").and_then(Item::as_bool) {
+            config.history.share = value;
+        }
+        if let Some(value) = lookup(root, "history", "memory_budget").and_then(Item::as_integer) {
+            config.history.memory_budget = usize::try_from(value).ok();
+        }
+        if let Some(value) = lookup(root, "history", "record_substitutions").and_then(Item::as_bool)
+        {
+            config.history.record_substitutions = value;
+        }
+        if let Some(value) = lookup(root, "history", "autosave").and_then(Item::as_integer) {
+            config.history.autosave = u64::try_from(value).unwrap_or_default();
+        }
+        match lookup(root, "suggest", "backend").and_then(Item::as_str) {
+            Some("program") => config.suggest.backend = Some(SuggestBackend::Program),
+            Some("http") => config.suggest.backend = Some(SuggestBackend::Http),
+            _ => {}
+        }
+        if let Some(value) = lookup(root, "suggest", "program").and_then(Item::as_str) {
+            config.suggest.program = value.to_string();
+        }
+        if let Some(value) = lookup(root, "suggest", "url").and_then(Item::as_str) {
+            config.suggest.url = value.to_string();
+        }
+        if let Some(value) = lookup(root, "suggest", "model").and_then(Item::as_str) {
+            config.suggest.model = value.to_string();
+        }
+        if let Some(value) = lookup(root, "suggest", "api_key_env").and_then(Item::as_str) {
+            config.suggest.api_key_env = Some(value.to_string()).filter(|name| !name.is_empty());
+        }
+        if let Some(names) = lookup(root, "completion", "hosts").and_then(names) {
+            config.completion.hosts = names;
+        }
+        match lookup(root, "pick", "finder").and_then(Item::as_str) {
+            Some("internal") => config.pick.finder = Finder::Internal,
+            Some("external") => config.pick.finder = Finder::External,
+            _ => {}
+        }
+        if let Some(value) = lookup(root, "pick", "command").and_then(Item::as_str) {
+            config.pick.command = value.to_string();
+        }
+        if let Some(value) = lookup(root, "lock", "after").and_then(Item::as_integer) {
+            config.lock.after = u64::try_from(value).unwrap_or_default();
+        }
+        if let Some(value) = lookup(root, "lock", "passphrase_sha256").and_then(Item::as_str) {
+            config.lock.passphrase_sha256 = Some(value.to_ascii_lowercase());
+        }
+        match lookup(root, "cues", "on").and_then(Item::as_str) {
+            Some("never") => config.cues.on = CueOn::Never,
+            Some("always") => config.cues.on = CueOn::Always,
+            Some("failure") => config.cues.on = CueOn::Failure,
+            Some("success") => config.cues.on = CueOn::Success,
+            _ => {}
+        }
+        if let Some(value) = lookup(root, "cues", "after").and_then(Item::as_integer) {
+            config.cues.after = u64::try_from(value).unwrap_or_default();
+        }
+        match lookup(root, "cues", "sound").and_then(Item::as_str) {
+            Some("bell") => config.cues.sound = CueSound::Bell,
+            Some("notification") => config.cues.sound = CueSound::Notification,
+            _ => {}
+        }
+        match lookup(root, "jobs", "on_exit").and_then(Item::as_str) {
+            Some("terminate") => config.jobs.on_exit = OnExit::Terminate,
+            Some("detach") => config.jobs.on_exit = OnExit::Detach,
+            _ => {}
+        }
+        if let Some(table) = root.get("limits").and_then(Item::as_table) {
+            config.limits.read(table);
+            for (prefix, item) in table.iter() {
+                if let Some(table) = item.as_table() {
+                    let limits = config.limits.commands.entry(prefix.to_string());
+                    limits.or_default().read(table);
+                }
+            }
+        }
+        if let Some(table) = root.get("process").and_then(Item::as_table) {
+            config.process.read(table);
+            for (prefix, item) in table.iter() {
+                if let Some(table) = item.as_table() {
+                    let process = config.process.commands.entry(prefix.to_string());
+                    process.or_default().read(table);
+                }
+            }
+        }
+        if let Some(names) = lookup(root, "child_env", "pass").and_then(names) {
+            config.child_env.pass = names;
+        }
+        if let Some(names) = lookup(root, "child_env", "scrub").and_then(names) {
+            config.child_env.scrub = names;
+        }
+        if let Some(prefixes) = lookup(root, "child_env", "force_color").and_then(names) {
+            config.child_env.force_color = prefixes;
+        }
+        if let Some(table) = lookup(root, "child_env", "set").and_then(Item::as_table) {
+            config.child_env.set.extend(
+                table.iter().filter_map(|(name, item)| {
+                    Some((name.to_string(), item.as_str()?.to_string()))
+                }),
+            );
+        }
+        if let Some(table) = root.get("keys").and_then(Item::as_table) {
+            for (name, item) in table.iter() {
+                if let (Some(action), Some(keys)) = (Action::from_name(name), keys(item)) {
+                    config.keys.bind(action, keys);
+                }
+            }
+            for (name, run) in [("run", true), ("insert", false)] {
+                let Some(texts) = table.get(name).and_then(Item::as_table) else {
+                    continue;
+                };
+                for (key, item) in texts.iter() {
+                    if let (Ok(key), Some(text)) = (key.parse(), item.as_str()) {
+                        config.keys.bind_text(key, text, run);
+                    }
+                }
+            }
+        }
+        for (section, entries) in [
+            ("aliases", &mut config.aliases),
+            ("env", &mut config.env),
+            ("directories", &mut config.directories),
+            ("tasks", &mut config.tasks),
+            ("guards", &mut config.guards),
+        ] {
+            if let Some(table) = root.get(section).and_then(Item::as_table) {
+                entries.extend(table.iter().filter_map(|(name, item)| {
+                    Some((name.to_string(), item.as_str()?.to_string()))
+                }));
+            }
+        }
+        if let Some(table) = root.get("budgets").and_then(Item::as_table) {
+            config
+                .budgets
+                .extend(table.iter().filter_map(|(prefix, item)| {
+                    Some((prefix.to_string(), budget::parse(item.as_str()?)?))
+                }));
+        }
+        if let Some(table) = root.get("sync").and_then(Item::as_table) {
+            for (name, item) in table.iter() {
+                let Some(table) = item.as_table() else {
+                    continue;
+                };
+                let profile = config.sync.entry(name.to_string()).or_default();
+                let string = |key| table.get(key).and_then(Item::as_str).map(str::to_string);
+                match string("backend").as_deref() {
+                    Some("s3") => profile.backend = Some(SyncBackend::S3),
+                    Some("webdav") => profile.backend = Some(SyncBackend::WebDav),
+                    Some("ssh") => profile.backend = Some(SyncBackend::Ssh),
+                    _ => {}
+                }
+                for (key, value) in [
+                    ("url", &mut profile.url),
+                    ("region", &mut profile.region),
+                    ("access_key_env", &mut profile.access_key_env),
+                    ("secret_key_env", &mut profile.secret_key_env),
+                    ("passphrase_env", &mut profile.passphrase_env),
+                ] {
+                    if let Some(string) = string(key) {
+                        *value = string;
+                    }
+                }
+                if let Some(user) = string("user") {
+                    profile.user = Some(user).filter(|user| !user.is_empty());
+                }
+                if let Some(name) = string("password_env") {
+                    profile.password_env = Some(name).filter(|name| !name.is_empty());
+                }
+                if let Some(path) = string("passphrase_file") {
+                    profile.passphrase_file = Some(PathBuf::from(path));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Persist a single setting to the configuration file, preserving the rest of the file (including
+/// comments and formatting). The file is created if it does not exist yet.
+pub(crate) fn store(
+    section: &str,
+    key: &str,
+    value: impl Into<toml_edit::Value>,
+) -> anyhow::Result<PathBuf> {
+    let path = Config::path()
+        .ok_or_else(|| anyhow::anyhow!("unable to determine the configuration path"))?;
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut document = source.parse::<DocumentMut>()?;
+    let table = document
+        .entry(section)
+        .or_insert_with(toml_edit::table)
+        .as_table_mut()
+        .ok_or_else(|| anyhow::anyhow!("`{}` is not a table", section))?;
+    table[key] = toml_edit::value(value);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, document.to_string())?;
+
+    Ok(path)
+}
+
+/// The directory holding the configuration of the shell. (`$XDG_CONFIG_HOME/sheller`)
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("sheller"))
+}
+
+/// The directory holding the data of the shell, such as the commands run in each directory.
+/// (`$XDG_DATA_HOME/sheller`)
+pub(crate) fn data_dir() -> Option<PathBuf> {
+    data_home().map(|dir| dir.join("sheller"))
+}
+
+/// The directory holding the data of every application. (`$XDG_DATA_HOME`, or
+/// `~/.local/share`)
+pub(crate) fn data_home() -> Option<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+}
+
+/// Look up a key in a section of the document.
+fn lookup<'a>(root: &'a Table, section: &str, key: &str) -> Option<&'a Item> {
+    root.get(section)?.as_table()?.get(key)
+}
+
+/// Parse a list of names.
+fn names(item: &Item) -> Option<Vec<String>> {
+    item.as_array()?
+        .iter()
+        .map(|value| Some(value.as_str()?.to_string()))
+        .collect()
+}
+
+/// Parse a key, or a list of keys.
+fn keys(item: &Item) -> Option<Vec<Key>> {
+    match item.as_array() {
+        Some(array) => array
+            .iter()
+            .map(|value| value.as_str()?.parse().ok())
+            .collect(),
+        None => Some(vec![item.as_str()?.parse().ok()?]),
+    }
+}
+
+/// Validate the document against the [`SCHEMA`], collecting every problem found.
+fn validate(source: &str, root: &Table) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    for (section, item) in root.iter() {
+        let span = root.key(section).and_then(|key| key.span());
+        let issue = |message: String| Issue {
+            line: span.clone().map(|span| line_of(source, span)),
+            key: Some(section.to_string()),
+            message,
+        };
+
+        let Some((_, keys)) = SCHEMA.iter().find(|(name, _)| *name == section) else {
+            issues.push(issue("unknown section".to_string()));
+            continue;
+        };
+        let Some(table) = item.as_table() else {
+            issues.push(issue("expected a table".to_string()));
+            continue;
+        };
+
+        validate_table(source, section, table, keys, &mut issues);
+    }
+
+    issues
+}
+
+/// Validate the keys of a table, at a dotted path, against their schema.
+fn validate_table(
+    source: &str,
+    path: &str,
+    table: &Table,
+    keys: &[(&str, Kind)],
+    issues: &mut Vec<Issue>,
+) {
+    for (key, value) in table.iter() {
+        let span = table.key(key).and_then(|key| key.span());
+        let path = format!("{}.{}", path, key);
+        let issue = |message: String| Issue {
+            line: span.clone().map(|span| line_of(source, span)),
+            key: Some(path.clone()),
+            message,
+        };
+
+        match keys.iter().find(|(name, _)| *name == key || *name == ANY) {
+            None => issues.push(issue("unknown key".to_string())),
+            Some((_, kind)) if !kind.matches(value) => {
+                issues.push(issue(format!("expected {}", kind.name())))
+            }
+            Some((_, kind))
+                if !matches!(kind, Kind::Signed)
+                    && value.as_integer().is_some_and(|value| value < 0) =>
+            {
+                issues.push(issue("expected a non-negative value".to_string()))
+            }
+            Some((_, Kind::Table(keys))) => {
+                if let Some(table) = value.as_table() {
+                    validate_table(source, &path, table, keys, issues);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// The 1-based line number of the start of the span.
+fn line_of(source: &str, span: Range<usize>) -> usize {
+    source
+        .get(..span.start)
+        .map_or(0, |prefix| prefix.matches('\n').count())
+        + 1
+}
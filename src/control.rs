@@ -0,0 +1,278 @@
+//! The remote control socket of a session.
+//!
+//! Next to its own socket, a session listens on a control socket (`<name>.ctl`, see
+//! [`crate::session::control`]) speaking JSON-RPC 2.0, one message per line. External tools
+//! (editor integrations, scripts) use it to drive the shell without attaching to it:
+//!
+//! - `run` (`{"command": "...", "stdin": ["..."]}`) runs a command as if it was typed at the
+//!   prompt, and returns the history entries it added. `stdin` is supplied to commands asking for
+//!   input, and can be left out.
+//! - `history` (`{"limit": 10}`) returns the history, or its last `limit` entries.
+//! - `subscribe` returns `true`, and from then on sends an `output` notification on the
+//!   connection for every history entry added, whoever ran the command.
+//!
+//! A history entry is an object with the `prompt`, `command`, `stdin`, `stdout` and `stderr` of
+//! the command. The calls are handled by the shell in between key presses, in the order they
+//! arrive.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+use serde_json::{json, Value};
+
+use crate::command::CommandOutput;
+use crate::protocol::Entry;
+use crate::renderer::Input;
+
+/// A call made by a client of the control socket.
+pub(crate) enum Request {
+    /// Run a command, supplying `stdin` if it asks for input.
+    Run {
+        /// The command.
+        command: String,
+        /// The input of the command.
+        stdin: Vec<String>,
+    },
+    /// Return the history, or its last `limit` entries.
+    History {
+        /// The number of entries.
+        limit: Option<usize>,
+    },
+    /// Send the history entries added from now on to the client.
+    Subscribe,
+}
+
+impl Request {
+    /// Read a request from the method and the parameters of a call.
+    fn parse(method: &str, params: Value) -> Result<Self, Error> {
+        let params = match params {
+            Value::Null => json!({}),
+            params => params,
+        };
+        let invalid = |e: serde_json::Error| Error::new(Error::INVALID_PARAMS, e);
+
+        match method {
+            "run" => {
+                let command = params
+                    .get("command")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| Error::new(Error::INVALID_PARAMS, "missing `command`"))?;
+                let stdin = match params.get("stdin") {
+                    Some(stdin) => serde_json::from_value(stdin.clone()).map_err(invalid)?,
+                    None => Vec::new(),
+                };
+                Ok(Self::Run {
+                    command: command.to_string(),
+                    stdin,
+                })
+            }
+            "history" => {
+                let limit = match params.get("limit") {
+                    Some(limit) => serde_json::from_value(limit.clone()).map_err(invalid)?,
+                    None => None,
+                };
+                Ok(Self::History { limit })
+            }
+            "subscribe" => Ok(Self::Subscribe),
+            method => Err(Error::new(
+                Error::METHOD_NOT_FOUND,
+                format!("unknown method `{}`", method),
+            )),
+        }
+    }
+}
+
+/// A JSON-RPC error.
+#[derive(Debug)]
+pub(crate) struct Error {
+    /// The error code.
+    code: i64,
+    /// The description of the error.
+    message: String,
+}
+
+impl Error {
+    /// The message is not valid JSON.
+    const PARSE: i64 = -32700;
+    /// The message is not a JSON-RPC request.
+    const INVALID_REQUEST: i64 = -32600;
+    /// The method does not exist.
+    const METHOD_NOT_FOUND: i64 = -32601;
+    /// The parameters of the method are invalid.
+    const INVALID_PARAMS: i64 = -32602;
+    /// The shell cannot run the call right now.
+    pub(crate) const BUSY: i64 = -32000;
+    /// The command failed.
+    pub(crate) const FAILED: i64 = -32001;
+
+    /// Create an error.
+    pub(crate) fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+///
+/// [`Call`] is a request waiting for the shell, along with the way back to the client that made
+/// it.
+///
+pub(crate) struct Call {
+    /// The request.
+    pub(crate) request: Request,
+    /// The id of the request, or `None` for notifications, which are not answered.
+    id: Option<Value>,
+    /// The messages sent to the client.
+    out: mpsc::Sender<String>,
+}
+
+impl Call {
+    /// Answer the call.
+    pub(crate) fn reply(&self, result: Result<Value, Error>) {
+        if let Some(id) = &self.id {
+            send(&self.out, id, result);
+        }
+    }
+
+    /// The client that made the call, to send it notifications.
+    pub(crate) fn subscriber(&self) -> Subscriber {
+        Subscriber(self.out.clone())
+    }
+}
+
+///
+/// [`Subscriber`] is a client receiving the history entries as they are added.
+///
+pub(crate) struct Subscriber(mpsc::Sender<String>);
+
+impl Subscriber {
+    /// Send a history entry to the client. This returns `false` once the client is gone.
+    pub(crate) fn notify(&self, output: &CommandOutput) -> bool {
+        let message = json!({ "jsonrpc": "2.0", "method": "output", "params": entry(output) });
+        self.0.send(message.to_string()).is_ok()
+    }
+}
+
+/// A history entry, as sent to the clients.
+pub(crate) fn entry(output: &CommandOutput) -> Value {
+    json!(Entry::from(output))
+}
+
+/// Send the response to a call.
+fn send(out: &mpsc::Sender<String>, id: &Value, result: Result<Value, Error>) {
+    let message = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": e.code, "message": e.message },
+        }),
+    };
+    let _ = out.send(message.to_string());
+}
+
+///
+/// [`Listener`] accepts the clients of the control socket, and passes their calls to the shell.
+/// The socket is removed when the listener is dropped.
+///
+pub(crate) struct Listener {
+    /// The path of the socket.
+    path: PathBuf,
+}
+
+impl Listener {
+    /// Listen on the control socket, sending the calls to `calls`.
+    pub(crate) fn bind(path: &Path, calls: mpsc::Sender<Input>) -> anyhow::Result<Self> {
+        if path.exists() {
+            // Left behind by a session that did not exit cleanly.
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().filter_map(Result::ok) {
+                let calls = calls.clone();
+                std::thread::spawn(move || serve(stream, calls));
+            }
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Handle the calls of a client, until it disconnects.
+fn serve(stream: UnixStream, calls: mpsc::Sender<Input>) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    let (out, messages) = mpsc::channel::<String>();
+    std::thread::spawn(move || {
+        for message in messages {
+            if writeln!(writer, "{}", message).is_err() {
+                break;
+            }
+        }
+    });
+
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, request) = match read(&line) {
+            Ok(call) => call,
+            Err((id, e)) => {
+                send(&out, &id, Err(e));
+                continue;
+            }
+        };
+
+        let call = Call {
+            request,
+            id,
+            out: out.clone(),
+        };
+        if let Err(mpsc::SendError(Input::Control(call))) = calls.send(Input::Control(call)) {
+            call.reply(Err(Error::new(Error::BUSY, "the shell exited")));
+        }
+    }
+}
+
+/// Read a JSON-RPC request, returning its id and the request. On failure, this returns the id to
+/// answer with (`null` if the id could not be read) and the error.
+fn read(line: &str) -> Result<(Option<Value>, Request), (Value, Error)> {
+    let message = serde_json::from_str::<Value>(line)
+        .map_err(|e| (Value::Null, Error::new(Error::PARSE, e)))?;
+    let id = message.get("id").cloned();
+    let answer = id.clone().unwrap_or_default();
+
+    if message.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err((
+            answer,
+            Error::new(Error::INVALID_REQUEST, "not a JSON-RPC 2.0 request"),
+        ));
+    }
+    let Some(method) = message.get("method").and_then(Value::as_str) else {
+        return Err((
+            answer,
+            Error::new(Error::INVALID_REQUEST, "missing `method`"),
+        ));
+    };
+    let params = message.get("params").cloned().unwrap_or_default();
+
+    let request = Request::parse(method, params).map_err(|e| (answer, e))?;
+    Ok((id, request))
+}
@@ -0,0 +1,315 @@
+//! The line typed at the prompt, and the editing of it.
+//!
+//! A [`LineBuffer`] holds the text of the line along with the cursor. The cursor is a byte offset
+//! into the text, always on the boundary of a character, so that the line can be split at it
+//! whatever is typed. Moving and deleting go a whole grapheme cluster at a time, however many
+//! characters it takes: `e` followed by a combining accent, or an emoji with a skin tone, is one
+//! step of the cursor, as it is one symbol on screen.
+//!
+//! The same editing is used for the command typed at the prompt, and for the input typed to a
+//! running command. Words are the runs of characters other than whitespace, as with `Ctrl+W` in
+//! most shells. Every change can be undone: characters typed or deleted in a row are undone
+//! together, up to [`UNDO`] changes back.
+//!
+//! # Example
+//!
+//! ```rust
+//! use shelgon::editor::LineBuffer;
+//!
+//! let mut line = LineBuffer::from("ls ~/café");
+//! line.move_left();
+//! line.delete_backward();
+//! line.insert('e');
+//!
+//! assert_eq!(line.as_str(), "ls ~/caeé");
+//! assert_eq!(line.before(), "ls ~/cae");
+//! assert_eq!(line.after(), "é");
+//!
+//! line.move_end();
+//! assert_eq!(line.delete_word_backward().as_deref(), Some("~/caeé"));
+//! assert_eq!(line.as_str(), "ls ");
+//!
+//! line.undo();
+//! assert_eq!(line.as_str(), "ls ~/caeé");
+//!
+//! let mut line = LineBuffer::from("e\u{301} 👍🏽");
+//! assert_eq!(line.delete_backward().as_deref(), Some("👍🏽"));
+//! line.move_left();
+//! line.move_left();
+//! assert_eq!(line.after(), "e\u{301} ");
+//! ```
+
+use std::fmt;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The number of changes kept to be undone.
+pub const UNDO: usize = 100;
+
+///
+/// [`LineBuffer`] is a line of text being edited, with a cursor that always lies on the boundary
+/// of a character, between `0` and the length of the text.
+///
+#[derive(Debug, Clone, Default)]
+pub struct LineBuffer {
+    text: String,
+    cursor: usize,
+    /// The text and the cursor before each change, oldest first.
+    undo: Vec<(String, usize)>,
+    /// The last change, continued by the next one of the same kind rather than undone apart.
+    last: Option<Change>,
+}
+
+/// The kinds of changes undone together when made in a row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Insert,
+    Delete,
+}
+
+impl LineBuffer {
+    /// Create an empty line.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The text of the line.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// The position of the cursor, as a byte offset into the text.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Check if the line is empty.
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Check if the cursor is at the end of the line.
+    pub fn at_end(&self) -> bool {
+        self.cursor == self.text.len()
+    }
+
+    /// The text before the cursor.
+    pub fn before(&self) -> &str {
+        &self.text[..self.cursor]
+    }
+
+    /// The text from the cursor on.
+    pub fn after(&self) -> &str {
+        &self.text[self.cursor..]
+    }
+
+    /// Insert a character at the cursor, and move the cursor past it.
+    pub fn insert(&mut self, c: char) {
+        self.save(Some(Change::Insert));
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    /// Insert text at the cursor, and move the cursor past it.
+    pub fn insert_str(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.save(None);
+        self.text.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    /// Insert the part shared by the completions at the cursor, and cut it from each of them, so
+    /// they still complete the line.
+    pub fn splice(&mut self, completions: &mut [String]) {
+        let shared = common_prefix(completions).to_string();
+        self.insert_str(&shared);
+        for completion in completions.iter_mut() {
+            completion.drain(..shared.len());
+        }
+    }
+
+    /// Delete the grapheme cluster before the cursor, if any, and return it.
+    pub fn delete_backward(&mut self) -> Option<String> {
+        let length = self.previous()?.len();
+        self.save(Some(Change::Delete));
+        self.cursor -= length;
+        Some(self.text.drain(self.cursor..self.cursor + length).collect())
+    }
+
+    /// Delete the grapheme cluster under the cursor, if any, and return it.
+    pub fn delete_forward(&mut self) -> Option<String> {
+        let length = self.under()?.len();
+        self.save(Some(Change::Delete));
+        Some(self.text.drain(self.cursor..self.cursor + length).collect())
+    }
+
+    /// Delete the word before the cursor, along with the whitespace after it, if any, and return
+    /// what was deleted.
+    pub fn delete_word_backward(&mut self) -> Option<String> {
+        let start = self.word_start();
+        if start == self.cursor {
+            return None;
+        }
+        self.save(None);
+        let deleted = self.text.drain(start..self.cursor).collect();
+        self.cursor = start;
+        Some(deleted)
+    }
+
+    /// Delete everything before the cursor, and return what was deleted.
+    pub fn delete_to_start(&mut self) -> Option<String> {
+        if self.cursor == 0 {
+            return None;
+        }
+        self.save(None);
+        let deleted = self.text.drain(..self.cursor).collect();
+        self.cursor = 0;
+        Some(deleted)
+    }
+
+    /// Move the cursor one grapheme cluster to the left. Returns whether it moved.
+    pub fn move_left(&mut self) -> bool {
+        match self.previous() {
+            Some(previous) => self.move_to(self.cursor - previous.len()),
+            None => false,
+        }
+    }
+
+    /// Move the cursor one grapheme cluster to the right. Returns whether it moved.
+    pub fn move_right(&mut self) -> bool {
+        match self.under() {
+            Some(under) => self.move_to(self.cursor + under.len()),
+            None => false,
+        }
+    }
+
+    /// Move the cursor to the start of the word before it. Returns whether it moved.
+    pub fn move_word_left(&mut self) -> bool {
+        self.move_to(self.word_start())
+    }
+
+    /// Move the cursor to the end of the word after it. Returns whether it moved.
+    pub fn move_word_right(&mut self) -> bool {
+        let after = self.after();
+        let word = after.trim_start();
+        let end = word.find(char::is_whitespace).unwrap_or(word.len());
+        self.move_to(self.cursor + after.len() - word.len() + end)
+    }
+
+    /// Move the cursor to the start of the line. Returns whether it moved.
+    pub fn move_home(&mut self) -> bool {
+        self.move_to(0)
+    }
+
+    /// Move the cursor to the end of the line. Returns whether it moved.
+    pub fn move_end(&mut self) -> bool {
+        self.move_to(self.text.len())
+    }
+
+    /// Replace the text of the line, with the cursor at its end.
+    pub fn set(&mut self, text: impl Into<String>) {
+        self.save(None);
+        self.text = text.into();
+        self.cursor = self.text.len();
+    }
+
+    /// The grapheme cluster under the cursor, if any: the one drawn as the cursor.
+    pub fn under(&self) -> Option<&str> {
+        self.after().graphemes(true).next()
+    }
+
+    /// The grapheme cluster before the cursor, if any.
+    fn previous(&self) -> Option<&str> {
+        self.before().graphemes(true).next_back()
+    }
+
+    /// Undo the last change. Returns whether there was one.
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop() {
+            Some((text, cursor)) => {
+                self.text = text;
+                self.cursor = cursor;
+                self.last = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The text of the line, dropping the cursor.
+    pub fn into_string(self) -> String {
+        self.text
+    }
+
+    /// The start of the word before the cursor, past the whitespace after it.
+    fn word_start(&self) -> usize {
+        self.before()
+            .trim_end()
+            .char_indices()
+            .rfind(|(_, c)| c.is_whitespace())
+            .map_or(0, |(space, c)| space + c.len_utf8())
+    }
+
+    /// Move the cursor, ending the change in progress. Returns whether it moved.
+    fn move_to(&mut self, cursor: usize) -> bool {
+        self.last = None;
+        let moved = cursor != self.cursor;
+        self.cursor = cursor;
+        moved
+    }
+
+    /// Keep the line as it is before a change, unless the change continues the last one.
+    fn save(&mut self, change: Option<Change>) {
+        if change.is_none() || change != self.last {
+            if self.undo.len() == UNDO {
+                self.undo.remove(0);
+            }
+            self.undo.push((self.text.clone(), self.cursor));
+        }
+        self.last = change;
+    }
+}
+
+impl From<String> for LineBuffer {
+    /// A line holding the text, with the cursor at its end.
+    fn from(text: String) -> Self {
+        let cursor = text.len();
+        Self {
+            text,
+            cursor,
+            ..Self::default()
+        }
+    }
+}
+
+impl From<&str> for LineBuffer {
+    /// A line holding the text, with the cursor at its end.
+    fn from(text: &str) -> Self {
+        Self::from(text.to_string())
+    }
+}
+
+impl fmt::Display for LineBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// The longest prefix shared by the completions, cut at the boundary of a character.
+fn common_prefix(completions: &[String]) -> &str {
+    let Some((first, rest)) = completions.split_first() else {
+        return "";
+    };
+    let shared = rest.iter().fold(first.len(), |shared, completion| {
+        first
+            .char_indices()
+            .zip(completion.chars())
+            .take_while(|((index, a), b)| *index < shared && a == b)
+            .last()
+            .map_or(0, |((index, a), _)| index + a.len_utf8())
+    });
+
+    &first[..shared]
+}
@@ -0,0 +1,200 @@
+//! Explanations of commands, from the help of their program.
+//!
+//! This also provides the manuals read in the pager (see [`crate::pager`]).
+//!
+//! The explain action (`F6` by default, see [`crate::keymap`]) breaks the command being typed, or
+//! the last command run, into its program, flags and arguments. The program is described by the
+//! summary of its help, and every flag by the line of the help defining it. The help is the
+//! `--help` output of the program, or else its man page.
+//!
+//! With the `suggest` feature, a backend that explains commands is asked instead. (See
+//! `crate::suggest`)
+
+use std::{
+    io::Read,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long the program may take to print its help.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The width the man pages are formatted for.
+const MAN_WIDTH: &str = "80";
+
+/// How often the program is checked for completion while waiting for its help.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A part of a command, along with what it means.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Part {
+    /// The program, with the summary of its help.
+    Program(String, Option<String>),
+    /// A flag, with the line of the help defining it.
+    Flag(String, Option<String>),
+    /// Anything else: a subcommand, a file, a value.
+    Argument(String),
+}
+
+/// Explain the parts of a command. This returns `None` if the program has no help.
+pub(crate) fn explain(command: &str) -> Option<Vec<Part>> {
+    let mut words = command.split_whitespace();
+    let program = words.next()?;
+    let help = help(program)?;
+
+    let mut parts = vec![Part::Program(program.to_string(), summary(&help))];
+    let mut options = true;
+    for word in words {
+        match word {
+            "--" => {
+                options = false;
+                parts.push(Part::Argument(word.to_string()));
+            }
+            _ if options && word.starts_with('-') && word.len() > 1 => {
+                parts.extend(
+                    flags(word, &help)
+                        .into_iter()
+                        .map(|flag| Part::Flag(flag.clone(), describe(&help, &flag))),
+                );
+            }
+            _ => parts.push(Part::Argument(word.to_string())),
+        }
+    }
+
+    Some(parts)
+}
+
+/// The help of a program, to explain its flags: its `--help` output, or else its man page.
+fn help(program: &str) -> Option<String> {
+    help_output(program).or_else(|| man_page(program))
+}
+
+/// The manual of a program, to read in full: its man page, or else its `--help` output.
+pub(crate) fn manual(program: &str) -> Option<String> {
+    man_page(program).or_else(|| help_output(program))
+}
+
+/// The `--help` output of a program.
+fn help_output(program: &str) -> Option<String> {
+    run(Command::new(program).arg("--help")).filter(|help| help.contains('-'))
+}
+
+/// The man page of a program, as plain text.
+fn man_page(program: &str) -> Option<String> {
+    run(Command::new("man")
+        .arg(program)
+        .env("MANPAGER", "cat")
+        .env("MANWIDTH", MAN_WIDTH))
+    .map(|page| strip_overstrike(&page))
+}
+
+/// Run a program, returning its output (or its error output, if it printed nothing else). This
+/// gives up on programs that do not exit in time.
+fn run(command: &mut Command) -> Option<String> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    // Read while waiting, so a long help does not fill the pipe and block the program.
+    let read = |mut pipe: Box<dyn Read + Send>| {
+        thread::spawn(move || {
+            let mut output = Vec::new();
+            let _ = pipe.read_to_end(&mut output);
+            String::from_utf8_lossy(&output).into_owned()
+        })
+    };
+    let stdout = read(Box::new(child.stdout.take()?));
+    let stderr = read(Box::new(child.stderr.take()?));
+
+    let deadline = Instant::now() + TIMEOUT;
+    while child.try_wait().ok()?.is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    let stdout = stdout.join().ok()?;
+    let output = match stdout.trim().is_empty() {
+        true => stderr.join().ok()?,
+        false => stdout,
+    };
+    Some(output).filter(|output| !output.trim().is_empty())
+}
+
+/// Remove the overstrikes (`_\bx`, `x\bx`) used by man pages for underlined and bold text.
+fn strip_overstrike(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\u{8}' => {
+                stripped.pop();
+            }
+            c => stripped.push(c),
+        }
+    }
+
+    stripped
+}
+
+/// The summary of a help: the line following the `NAME` header of a man page, or else the first
+/// line that is not the usage.
+fn summary(help: &str) -> Option<String> {
+    let mut lines = help.lines().map(str::trim).filter(|line| !line.is_empty());
+    let first = lines.clone().next()?;
+
+    let summary = match lines.clone().position(|line| line == "NAME") {
+        Some(name) => lines.nth(name + 1),
+        None => lines.find(|line| !line.to_lowercase().starts_with("usage")),
+    };
+    Some(summary.unwrap_or(first).to_string())
+}
+
+/// The flags in a word: `--long=value` is `--long`, and `-abc` is `-a`, `-b` and `-c` unless the
+/// help defines `-abc` itself (as `find -name` does).
+fn flags(word: &str, help: &str) -> Vec<String> {
+    let flag = word.split('=').next().unwrap_or(word);
+    if flag.starts_with("--") || flag.chars().count() == 2 || describe(help, flag).is_some() {
+        return vec![flag.to_string()];
+    }
+
+    flag.chars().skip(1).map(|c| format!("-{}", c)).collect()
+}
+
+/// The description of a flag: the rest of the line of the help defining it, or the next line if
+/// the definition stands alone (as in man pages).
+fn describe(help: &str, flag: &str) -> Option<String> {
+    let lines = help.lines().collect::<Vec<_>>();
+    lines.iter().enumerate().find_map(|(index, line)| {
+        let line = line.trim();
+        if !line.starts_with('-') {
+            return None;
+        }
+
+        // The definition ends where the description starts, after two spaces or a tab.
+        let (definition, description) = match line.find("  ").or_else(|| line.find('\t')) {
+            Some(end) => (&line[..end], line[end..].trim()),
+            None => (line, ""),
+        };
+        let defined = definition
+            .split([',', ' ', '=', '['])
+            .any(|defined| defined == flag);
+        if !defined {
+            return None;
+        }
+
+        match description {
+            "" => lines
+                .get(index + 1)
+                .map(|next| next.trim().to_string())
+                .filter(|next| !next.is_empty() && !next.starts_with('-')),
+            description => Some(description.to_string()),
+        }
+    })
+}
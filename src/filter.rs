@@ -0,0 +1,47 @@
+//! Filtering the entries of the history shown.
+//!
+//! `:filter failed` only shows the entries whose command failed, exiting with a code other than
+//! `0` (or writing to its error output, when the executor cannot tell its exit code), `:filter
+//! starred` the starred ones (as `:starred` does), and `:filter PATTERN` the ones whose command
+//! contains the pattern, such as `:filter git`. The other entries are only hidden: `:filter` alone
+//! shows every entry again. A line under the history tells a filter is active.
+
+use crate::memory::Scrollback;
+
+///
+/// [`Filter`] is which entries of the history are shown.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Filter {
+    /// The entries whose command failed.
+    Failed,
+    /// The starred entries.
+    Starred,
+    /// The entries whose command contains the pattern.
+    Command(String),
+}
+
+impl Filter {
+    /// Parse the arguments of `:filter`, joined. This returns `None` for no arguments, to show
+    /// every entry.
+    pub(crate) fn parse(args: &str) -> Option<Self> {
+        match args.trim() {
+            "" => None,
+            "failed" => Some(Self::Failed),
+            "starred" => Some(Self::Starred),
+            pattern => Some(Self::Command(pattern.to_string())),
+        }
+    }
+
+    /// Check whether an entry of the history is shown, given whether it is starred. The output
+    /// of the entry is never expanded to check.
+    pub(crate) fn matches(&self, history: &Scrollback, index: usize, starred: bool) -> bool {
+        match self {
+            Self::Failed => history.failed(index),
+            Self::Starred => starred,
+            Self::Command(pattern) => history
+                .command(index)
+                .is_some_and(|command| command.contains(pattern.as_str())),
+        }
+    }
+}
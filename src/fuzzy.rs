@@ -0,0 +1,37 @@
+//! Fuzzy matching, as in fzf: a pattern matches a text holding its characters in order, not
+//! necessarily next to each other, ignoring case.
+//!
+//! Matches are ranked by how tight they are: the fewer characters between the ones of the
+//! pattern, and the earlier the first one, the better. `:pick` filters the lines it offers this
+//! way. (See `crate::renderer`)
+
+/// The score of a text for a pattern, lower being better, or `None` if it does not match. The
+/// empty pattern matches every text, with the best score.
+pub(crate) fn score(pattern: &str, text: &str) -> Option<usize> {
+    let text = text.to_lowercase();
+    let mut chars = text.char_indices();
+    let mut first = None;
+    let mut gaps = 0;
+    let mut next = 0;
+    for wanted in pattern.to_lowercase().chars() {
+        let (index, _) = chars.find(|(_, c)| *c == wanted)?;
+        if first.is_some() {
+            gaps += text[next..index].chars().count();
+        }
+        first.get_or_insert(index);
+        next = index + wanted.len_utf8();
+    }
+    Some(gaps * 2 + first.map_or(0, |first| text[..first].chars().count()))
+}
+
+/// The indices of the texts matching the pattern, the best match first. Texts matching as well
+/// keep their order.
+pub(crate) fn filter<T: AsRef<str>>(pattern: &str, texts: &[T]) -> Vec<usize> {
+    let mut matches = texts
+        .iter()
+        .enumerate()
+        .filter_map(|(index, text)| Some((score(pattern, text.as_ref())?, index)))
+        .collect::<Vec<_>>();
+    matches.sort();
+    matches.into_iter().map(|(_, index)| index).collect()
+}
@@ -0,0 +1,172 @@
+//! Hints on the command being typed, from completion specs.
+//!
+//! While a known command is typed, a dim line below the prompt hints at its usage (see `ui.hints`
+//! in [`crate::config::Ui`]):
+//!
+//! - While a flag of the program is typed, the flags it starts are described.
+//! - After a known subcommand, its synopsis is shown.
+//! - Otherwise, the synopsis of the program is shown, or else its most common flags.
+//!
+//! Hints come from completion specs: simple TOML files describing a program, with its flags and
+//! subcommands listed from the most to the least common. Specs for a few programs are built in,
+//! and a spec can be added (or a built-in one replaced) by placing `<program>.toml` in
+//! `$XDG_CONFIG_HOME/sheller/completions`:
+//!
+//! ```toml
+//! synopsis = "ls [OPTION]... [FILE]..."
+//!
+//! [flags]
+//! "-l" = "use a long listing format"
+//! "-a" = "do not ignore entries starting with ."
+//! ```
+//!
+//! Programs with subcommands list them, with their synopsis, in a `[subcommands]` table.
+//!
+//! Programs are never run to find their hint, so hints are cheap enough for every key press.
+
+use std::{collections::HashMap, fs};
+
+use toml_edit::ImDocument;
+
+use crate::config;
+
+/// The built-in specs, by program.
+const BUILTIN: &[(&str, &str)] = &[
+    ("cargo", include_str!("../completions/cargo.toml")),
+    ("git", include_str!("../completions/git.toml")),
+    ("grep", include_str!("../completions/grep.toml")),
+    ("ls", include_str!("../completions/ls.toml")),
+];
+
+/// The number of flags hinted at once.
+const FLAGS: usize = 4;
+
+///
+/// [`Spec`] describes the usage of a program.
+///
+#[derive(Debug, Clone, Default)]
+struct Spec {
+    /// The synopsis of the program.
+    synopsis: Option<String>,
+    /// The flags, with their description, most common first.
+    flags: Vec<(String, String)>,
+    /// The subcommands, with their synopsis, most common first.
+    subcommands: Vec<(String, String)>,
+}
+
+///
+/// [`Specs`] is the set of completion specs, by program.
+///
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Specs {
+    /// The specs, by program.
+    specs: HashMap<String, Spec>,
+}
+
+impl Specs {
+    /// Load the built-in specs, replaced by the user specs.
+    pub(crate) fn load() -> Self {
+        let mut specs = BUILTIN
+            .iter()
+            .filter_map(|(program, source)| Some((program.to_string(), parse(source)?)))
+            .collect::<HashMap<_, _>>();
+
+        let user = config::config_dir()
+            .map(|dir| dir.join("completions"))
+            .and_then(|dir| fs::read_dir(dir).ok());
+        for entry in user.into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("toml") {
+                continue;
+            }
+            let (Some(program), Ok(source)) = (path.file_stem(), fs::read_to_string(&path)) else {
+                continue;
+            };
+            if let Some(spec) = parse(&source) {
+                specs.insert(program.to_string_lossy().into_owned(), spec);
+            }
+        }
+
+        Self { specs }
+    }
+
+    /// The hint on a command line, for the token under the cursor. This returns `None` if the
+    /// program is not known, or if there is nothing to say about the token.
+    pub(crate) fn hint(&self, line: &str, cursor: usize) -> Option<String> {
+        let before = line.get(..cursor).unwrap_or(line);
+        // The hint follows the last command of a pipeline or a list.
+        let command = before
+            .rsplit(['|', ';', '&'])
+            .next()
+            .unwrap_or(before)
+            .trim_start();
+        let mut words = command.split_whitespace().collect::<Vec<_>>();
+        if command.ends_with(char::is_whitespace) {
+            words.push("");
+        }
+        let (&program, arguments) = words.split_first()?;
+        let spec = self.specs.get(program)?;
+
+        let token = arguments.last().copied().unwrap_or_default();
+        if token.starts_with('-') {
+            let flags = describe(
+                spec.flags
+                    .iter()
+                    .filter(|(flag, _)| flag.starts_with(token)),
+            );
+            if flags.is_some() {
+                return flags;
+            }
+        }
+
+        // The flags of subcommands are not described: their synopsis is shown instead.
+        let subcommand = arguments
+            .iter()
+            .take(arguments.len().saturating_sub(1))
+            .find(|word| !word.starts_with('-'))
+            .and_then(|word| spec.subcommands.iter().find(|(name, _)| name == word));
+        if let Some((_, synopsis)) = subcommand {
+            return Some(synopsis.clone());
+        }
+
+        spec.synopsis
+            .clone()
+            .or_else(|| describe(spec.flags.iter()))
+    }
+}
+
+/// The first few flags, with their description, on one line.
+fn describe<'a>(flags: impl Iterator<Item = &'a (String, String)>) -> Option<String> {
+    let flags = flags
+        .take(FLAGS)
+        .map(|(flag, description)| format!("{}  {}", flag, description))
+        .collect::<Vec<_>>();
+
+    Some(flags.join(" · ")).filter(|hint| !hint.is_empty())
+}
+
+/// Parse a spec. Invalid specs are skipped, as are entries which are not strings.
+fn parse(source: &str) -> Option<Spec> {
+    let document = ImDocument::parse(source).ok()?;
+    let table = |name: &str| {
+        document
+            .get(name)
+            .and_then(|item| item.as_table())
+            .map(|table| {
+                table
+                    .iter()
+                    .filter_map(|(key, item)| Some((key.to_string(), item.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    Some(Spec {
+        synopsis: document
+            .get("synopsis")
+            .and_then(|item| item.as_str())
+            .map(str::to_string),
+        flags: table("flags"),
+        subcommands: table("subcommands"),
+    })
+}
@@ -0,0 +1,434 @@
+//! Importing and exporting the history of other shells.
+//!
+//! The commands run in sheller are kept in its journal (`history.db` in the data directory, see
+//! [`crate::config::History`]). The history of other shells can be imported into it, so
+//! migrating to sheller keeps years of history, and the journal can be exported back:
+//!
+//! ```bash
+//! sheller history import --from zsh                 # from ~/.zsh_history
+//! sheller history import --from bash ~/old_history  # from another file
+//! sheller history export --to fish >> ~/.local/share/fish/fish_history
+//! sheller history export --to atuin                 # into the Atuin database
+//! ```
+//!
+//! The formats are:
+//!
+//! - `atuin`: the SQLite database of [Atuin](https://atuin.sh), keeping the directory, the exit
+//!   status and the time of every command. (`~/.local/share/atuin/history.db`)
+//! - `bash`: one command per line, each preceded by a `#TIME` comment when `HISTTIMEFORMAT` is
+//!   set. (`~/.bash_history`)
+//! - `zsh`: one command per line, as `: TIME:DURATION;COMMAND` with `EXTENDED_HISTORY`, and the
+//!   lines of multi-line commands ending with `\`. (`$HISTFILE`, or `~/.zsh_history`)
+//! - `fish`: a YAML-like list of `- cmd: COMMAND` entries, with their `when: TIME`.
+//!   (`~/.local/share/fish/fish_history`)
+//!
+//! Commands without a time are given the modification time of the file. Importing a file twice
+//! adds its commands once, as does exporting to Atuin twice.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use shelgon::history::{self, Format};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let format = "zsh".parse::<Format>()?;
+//! if let Some(path) = format.default_path() {
+//!     let imported = history::import(format, &path)?;
+//!     println!("imported {} commands", imported);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::UNIX_EPOCH,
+};
+
+use rusqlite::{Connection, OpenFlags};
+use sha2::{Digest, Sha256};
+
+use crate::config;
+use crate::integration;
+use crate::journal::{Entry, Store};
+
+/// The byte zsh escapes the bytes it uses internally with, in its history file.
+const ZSH_META: u8 = 0x83;
+
+/// The last byte zsh escapes with [`ZSH_META`].
+const ZSH_MARKER: u8 = 0xa2;
+
+/// The directory Atuin records without one, such as for imported commands.
+const ATUIN_UNKNOWN: &str = "unknown";
+
+///
+/// [`Format`] is a history format of another shell.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The SQLite database of Atuin.
+    Atuin,
+    /// The history file of bash.
+    Bash,
+    /// The history file of zsh.
+    Zsh,
+    /// The history file of fish.
+    Fish,
+}
+
+impl Format {
+    /// Every format.
+    pub const ALL: &'static [Format] = &[Format::Atuin, Format::Bash, Format::Zsh, Format::Fish];
+
+    /// The name of the format, as given on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Atuin => "atuin",
+            Format::Bash => "bash",
+            Format::Zsh => "zsh",
+            Format::Fish => "fish",
+        }
+    }
+
+    /// The usual location of the history in this format, if the home directory is known.
+    pub fn default_path(self) -> Option<PathBuf> {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        match self {
+            Format::Atuin => config::data_home().map(|dir| dir.join("atuin/history.db")),
+            Format::Bash => home.map(|home| home.join(".bash_history")),
+            Format::Zsh => std::env::var_os("HISTFILE")
+                .filter(|file| !file.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| {
+                    std::env::var_os("ZDOTDIR")
+                        .filter(|dir| !dir.is_empty())
+                        .map(PathBuf::from)
+                        .or(home)
+                        .map(|dir| dir.join(".zsh_history"))
+                }),
+            Format::Fish => config::data_home().map(|dir| dir.join("fish/fish_history")),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.name() == name)
+            .ok_or_else(|| {
+                let names = Self::ALL.iter().map(|format| format.name());
+                anyhow::anyhow!(
+                    "unknown history format `{}`, expected one of: {}",
+                    name,
+                    names.collect::<Vec<_>>().join(", ")
+                )
+            })
+    }
+}
+
+/// Import the history at `path` into the journal. This returns the number of commands added.
+pub fn import(format: Format, path: &Path) -> anyhow::Result<usize> {
+    let entries = match format {
+        Format::Atuin => read_atuin(path)?,
+        Format::Bash | Format::Zsh | Format::Fish => {
+            let bytes = fs::read(path)?;
+            let modified = fs::metadata(path)?
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs() as i64)
+                .unwrap_or_default();
+            match format {
+                Format::Bash => parse_bash(&String::from_utf8_lossy(&bytes), modified),
+                Format::Zsh => parse_zsh(&String::from_utf8_lossy(&unmetafy(&bytes)), modified),
+                _ => parse_fish(&String::from_utf8_lossy(&bytes), modified),
+            }
+        }
+    };
+
+    Store::open()?.insert(&entries)
+}
+
+/// Export the journal to the history at `path`: the commands are appended to history files, and
+/// added to Atuin databases (which must exist already). This returns the number of commands
+/// exported.
+pub fn export(format: Format, path: &Path) -> anyhow::Result<usize> {
+    let entries = Store::open()?.entries()?;
+    match format {
+        Format::Atuin => write_atuin(path, &entries),
+        Format::Bash | Format::Zsh | Format::Fish => {
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            write(format, &entries, &mut file)
+        }
+    }
+}
+
+/// Write the journal in a history file format, such as to the standard output. This returns the
+/// number of commands written.
+pub fn write_to(format: Format, out: &mut dyn Write) -> anyhow::Result<usize> {
+    if format == Format::Atuin {
+        anyhow::bail!("the atuin format is a database, and can only be exported to a file");
+    }
+    write(format, &Store::open()?.entries()?, out)
+}
+
+/// Write entries in a history file format.
+fn write(format: Format, entries: &[Entry], out: &mut dyn Write) -> anyhow::Result<usize> {
+    let mut content = Vec::new();
+    for entry in entries {
+        match format {
+            Format::Bash => {
+                content.extend(format!("#{}\n{}\n", entry.time, entry.command).into_bytes())
+            }
+            Format::Zsh => {
+                let line = format!(
+                    ": {}:0;{}\n",
+                    entry.time,
+                    entry.command.replace('\n', "\\\n")
+                );
+                content.extend(metafy(line.as_bytes()));
+            }
+            Format::Fish => {
+                let command = entry.command.replace('\\', "\\\\").replace('\n', "\\n");
+                content
+                    .extend(format!("- cmd: {}\n  when: {}\n", command, entry.time).into_bytes());
+            }
+            Format::Atuin => {}
+        }
+    }
+    out.write_all(&content)?;
+    out.flush()?;
+
+    Ok(entries.len())
+}
+
+/// Parse a bash history file. Timestamps are `#TIME` comments before the commands.
+fn parse_bash(source: &str, modified: i64) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut time = None;
+    for line in source.lines() {
+        if let Some(stamp) = line.strip_prefix('#').and_then(|stamp| stamp.parse().ok()) {
+            time = Some(stamp);
+            continue;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(entry(line.to_string(), time.unwrap_or(modified)));
+    }
+
+    entries
+}
+
+/// Parse a zsh history file, unmetafied. Lines ending with `\` continue on the next line.
+fn parse_zsh(source: &str, modified: i64) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut lines = source.lines();
+    while let Some(line) = lines.next() {
+        let mut command = line.to_string();
+        while command.ends_with('\\') {
+            command.pop();
+            command.push('\n');
+            match lines.next() {
+                Some(next) => command.push_str(next),
+                None => break,
+            }
+        }
+
+        // `: TIME:DURATION;COMMAND` with `EXTENDED_HISTORY`, or the command alone.
+        let extended = command.strip_prefix(": ").and_then(|rest| {
+            let (stamp, command) = rest.split_once(';')?;
+            let (time, _) = stamp.split_once(':')?;
+            Some((time.parse().ok()?, command.to_string()))
+        });
+        let (time, command) = extended.unwrap_or((modified, command));
+        if !command.trim().is_empty() {
+            entries.push(entry(command, time));
+        }
+    }
+
+    entries
+}
+
+/// Parse a fish history file.
+fn parse_fish(source: &str, modified: i64) -> Vec<Entry> {
+    let mut entries = Vec::<Entry>::new();
+    for line in source.lines() {
+        if let Some(command) = line.strip_prefix("- cmd: ") {
+            entries.push(entry(unescape_fish(command), modified));
+        } else if let Some(time) = line.trim_start().strip_prefix("when: ") {
+            if let (Some(last), Ok(time)) = (entries.last_mut(), time.trim().parse()) {
+                last.time = time;
+            }
+        }
+    }
+
+    entries
+}
+
+/// Undo the escapes of commands in a fish history file: `\\` and `\n`.
+fn unescape_fish(command: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('\\')) => {
+                chars.next();
+                unescaped.push('\\');
+            }
+            ('\\', Some('n')) => {
+                chars.next();
+                unescaped.push('\n');
+            }
+            (c, _) => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+/// An entry without a directory or an exit status.
+fn entry(command: String, time: i64) -> Entry {
+    Entry {
+        command,
+        directory: None,
+        exit: None,
+        time,
+    }
+}
+
+/// Undo the escapes of zsh: a [`ZSH_META`] byte stands for the next byte, with its sixth bit
+/// flipped.
+fn unmetafy(bytes: &[u8]) -> Vec<u8> {
+    let mut unmetafied = Vec::with_capacity(bytes.len());
+    let mut bytes = bytes.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            ZSH_META => unmetafied.extend(bytes.next().map(|byte| byte ^ 32)),
+            byte => unmetafied.push(byte),
+        }
+    }
+
+    unmetafied
+}
+
+/// Escape the bytes zsh uses internally, from [`ZSH_META`] to [`ZSH_MARKER`] and `0`.
+fn metafy(bytes: &[u8]) -> Vec<u8> {
+    let mut metafied = Vec::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte == 0 || (ZSH_META..=ZSH_MARKER).contains(&byte) {
+            true => metafied.extend([ZSH_META, byte ^ 32]),
+            false => metafied.push(byte),
+        }
+    }
+
+    metafied
+}
+
+/// Read the commands of an Atuin database, leaving out the deleted ones.
+fn read_atuin(path: &Path) -> anyhow::Result<Vec<Entry>> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut statement = connection.prepare(
+        "SELECT command, cwd, exit, timestamp FROM history
+        WHERE deleted_at IS NULL ORDER BY timestamp",
+    )?;
+    let entries = statement
+        .query_map([], |row| {
+            let directory: String = row.get(1)?;
+            let exit: i64 = row.get(2)?;
+            let timestamp: i64 = row.get(3)?;
+            Ok(Entry {
+                command: row.get(0)?,
+                directory: Some(directory).filter(|dir| !dir.is_empty() && dir != ATUIN_UNKNOWN),
+                // Atuin records `-1` for the commands it did not see exit.
+                exit: Some(exit).filter(|exit| *exit >= 0),
+                // In nanoseconds.
+                time: timestamp / 1_000_000_000,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(entries)
+}
+
+/// Add entries to an Atuin database. Every entry gets an identifier derived from its command and
+/// time, so entries exported twice are only added once.
+fn write_atuin(path: &Path, entries: &[Entry]) -> anyhow::Result<usize> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    let host = format!(
+        "{}:{}",
+        integration::hostname().unwrap_or_else(|| "localhost".to_string()),
+        std::env::var("USER").unwrap_or_default()
+    );
+    let session = identifier(&["sheller", &host]);
+
+    let transaction = connection.unchecked_transaction()?;
+    let mut added = 0;
+    {
+        let mut statement = transaction.prepare(
+            "INSERT OR IGNORE INTO history
+                (id, timestamp, duration, exit, command, cwd, session, hostname, deleted_at)
+            VALUES (?1, ?2, 0, ?3, ?4, ?5, ?6, ?7, NULL)",
+        )?;
+        for entry in entries {
+            added += statement.execute((
+                identifier(&[&entry.command, &entry.time.to_string()]),
+                entry.time.saturating_mul(1_000_000_000),
+                entry.exit.unwrap_or(-1),
+                &entry.command,
+                entry.directory.as_deref().unwrap_or(ATUIN_UNKNOWN),
+                &session,
+                &host,
+            ))?;
+        }
+    }
+    transaction.commit()?;
+
+    Ok(added)
+}
+
+/// A 32 hex digits identifier derived from values, as Atuin identifies its entries: the first
+/// half of their SHA-256, so the same values get the same identifier with every build.
+fn identifier(values: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for value in values {
+        hasher.update(value.as_bytes());
+        hasher.update([0]);
+    }
+    let digest = hasher.finalize();
+    digest[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_do_not_change_between_builds() {
+        // Written once, an identifier must come out the same for every export to come, or the
+        // entries exported again are added twice.
+        assert_eq!(
+            identifier(&["ls", "1700000000"]),
+            "772ac8a528e44db2e4e105fe15ad8504"
+        );
+        assert_ne!(identifier(&["ls", "1"]), identifier(&["ls1", ""]));
+    }
+}
@@ -0,0 +1,156 @@
+//! Hosts, completed after the programs connecting to other machines.
+//!
+//! After `ssh`, `scp` or `sftp` (or the programs listed in `completion.hosts`, such as the command
+//! of an executor connecting over ssh, see [`crate::config::Completion`]), `Tab` completes the
+//! names of the hosts known to ssh: the ones of `~/.ssh/config`, shown with the user, address and
+//! port they stand for, and the ones of `~/.ssh/known_hosts`. A user before the host, `me@`, is
+//! left as it is, and the hosts of `scp` are completed with the `:` the path follows.
+//!
+//! Patterns such as `Host *.internal` are not hosts, and are left out, as are the hashed entries
+//! of `known_hosts`.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// The programs whose hosts are followed by a path, after a `:`.
+const COPY: &[&str] = &["scp", "rsync"];
+
+///
+/// [`Host`] is a host known to ssh.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Host {
+    /// The name the host is connected to with.
+    pub(crate) name: String,
+    /// The user, address and port the name stands for, such as `me@10.0.0.5:2222`, for the hosts
+    /// of `~/.ssh/config` setting any of them.
+    pub(crate) description: Option<String>,
+}
+
+/// The part of a host being typed at the end of a line, after one of the programs, with what
+/// follows the host once completed. This returns `None` if the line is not typing a host.
+pub(crate) fn word<'a>(line: &'a str, programs: &[String]) -> Option<(&'a str, &'static str)> {
+    let (program, arguments) = line.trim_start().split_once(char::is_whitespace)?;
+    if !programs.iter().any(|known| known == program) {
+        return None;
+    }
+    let word = arguments.rsplit(char::is_whitespace).next()?;
+    if word.starts_with('-') || word.contains(':') || word.contains('/') {
+        return None;
+    }
+    let host = word.rsplit_once('@').map_or(word, |(_, host)| host);
+    let suffix = match COPY.contains(&program) {
+        true => ":",
+        false => "",
+    };
+    Some((host, suffix))
+}
+
+/// The hosts known to ssh: the ones of `~/.ssh/config` first, then the ones of
+/// `~/.ssh/known_hosts` not in it.
+pub(crate) fn load() -> Vec<Host> {
+    let Some(dir) = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".ssh")) else {
+        return Vec::new();
+    };
+    let mut hosts = fs::read_to_string(dir.join("config"))
+        .map(|config| configured(&config))
+        .unwrap_or_default();
+    let known = fs::read_to_string(dir.join("known_hosts")).unwrap_or_default();
+    for name in self::known(&known) {
+        if !hosts.iter().any(|host| host.name == name) {
+            hosts.push(Host {
+                name,
+                description: None,
+            });
+        }
+    }
+    hosts
+}
+
+/// The hosts of an ssh configuration, with the user, address and port they stand for.
+fn configured(config: &str) -> Vec<Host> {
+    let mut hosts = Vec::<Host>::new();
+    // The hosts of the `Host` line being read, and their user, address and port.
+    let mut current = Vec::new();
+    let mut settings = [None, None, None];
+    let mut flush = |current: &mut Vec<String>, settings: &mut [Option<String>; 3]| {
+        let [user, address, port] = std::mem::take(settings);
+        let description = match (user, address, port) {
+            (None, None, None) => None,
+            (user, address, port) => {
+                let user = user.map(|user| format!("{}@", user)).unwrap_or_default();
+                let port = port.map(|port| format!(":{}", port)).unwrap_or_default();
+                Some(format!("{}{}{}", user, address.unwrap_or_default(), port))
+            }
+        };
+        for name in current.drain(..) {
+            if !hosts.iter().any(|host: &Host| host.name == name) {
+                hosts.push(Host {
+                    name,
+                    description: description.clone(),
+                });
+            }
+        }
+    };
+
+    for line in config.lines() {
+        let line = line.trim();
+        let (key, value) = line
+            .split_once(|c: char| c.is_whitespace() || c == '=')
+            .map_or((line, ""), |(key, value)| {
+                (
+                    key,
+                    value.trim_start_matches(|c: char| c.is_whitespace() || c == '='),
+                )
+            });
+        match key.to_ascii_lowercase().as_str() {
+            "host" | "match" => {
+                flush(&mut current, &mut settings);
+                if key.eq_ignore_ascii_case("host") {
+                    current.extend(
+                        value
+                            .split_whitespace()
+                            .filter(|name| !name.contains(['*', '?', '!']))
+                            .map(str::to_string),
+                    );
+                }
+            }
+            "user" => settings[0] = Some(value.to_string()),
+            "hostname" => settings[1] = Some(value.to_string()),
+            "port" => settings[2] = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    flush(&mut current, &mut settings);
+    hosts
+}
+
+/// The hosts of a `known_hosts` file, in the order they appear, with the port of `[host]:port`
+/// left out.
+fn known(known_hosts: &str) -> Vec<String> {
+    let mut names = Vec::<String>::new();
+    for line in known_hosts.lines() {
+        let mut fields = line.split_whitespace();
+        // A marker, `@cert-authority` or `@revoked`, comes before the hosts.
+        let hosts = match fields.next() {
+            Some(marker) if marker.starts_with('@') => fields.next(),
+            hosts => hosts,
+        };
+        let Some(hosts) = hosts.filter(|hosts| !hosts.starts_with(['#', '|'])) else {
+            continue;
+        };
+        for host in hosts.split(',') {
+            let host = match host.strip_prefix('[') {
+                Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+                None => host,
+            };
+            if !host.is_empty()
+                && !host.contains(['*', '?', '!'])
+                && !names.iter().any(|name| name == host)
+            {
+                names.push(host.to_string());
+            }
+        }
+    }
+    names
+}
@@ -0,0 +1,140 @@
+//! Translated messages of the shell.
+//!
+//! Every user-facing message of the shell itself (hints, confirmations, errors) is looked up by
+//! key in a [`Catalog`]. Catalogs are simple TOML files mapping keys to messages, with `{name}`
+//! placeholders substituted when the message is shown.
+//!
+//! Catalogs for English, German, Spanish and French are built in. A catalog can be added (or a
+//! built-in one overridden, key by key) by placing `<locale>.toml` in
+//! `$XDG_CONFIG_HOME/sheller/locales`. Missing keys fall back to English.
+//!
+//! The locale is taken from the `ui.locale` configuration key, or from `LC_ALL`, `LC_MESSAGES`
+//! and `LANG` (in that order) when it is `"auto"`.
+//!
+//! # Example
+//!
+//! ```rust
+//! use shelgon::i18n::Catalog;
+//!
+//! let catalog = Catalog::load("de");
+//! assert_eq!(
+//!     catalog.format("unknown-command", &[("name", &"foo")]),
+//!     "unbekannter Befehl `:foo`"
+//! );
+//! ```
+
+use std::{collections::HashMap, fmt::Display, fs};
+
+use toml_edit::ImDocument;
+
+use crate::config;
+
+/// The built-in catalogs, by locale.
+const BUILTIN: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.toml")),
+    ("de", include_str!("../locales/de.toml")),
+    ("es", include_str!("../locales/es.toml")),
+    ("fr", include_str!("../locales/fr.toml")),
+];
+
+/// The locale every catalog falls back to.
+const FALLBACK: &str = "en";
+
+///
+/// [`Catalog`] is the set of messages of the shell in one language.
+///
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    /// The locale of the catalog, such as `de`.
+    locale: String,
+    /// The messages, by key.
+    messages: HashMap<String, String>,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self::load(FALLBACK)
+    }
+}
+
+impl Catalog {
+    /// Load the catalog of a locale (`de`, `de_DE` or `de_DE.UTF-8` are all accepted).
+    ///
+    /// The catalog is made of the English messages, overridden by the built-in catalog of the
+    /// locale, overridden by the user catalog of the locale.
+    pub fn load(locale: &str) -> Self {
+        let locale = language(locale);
+        let mut messages = HashMap::new();
+
+        for (_, source) in BUILTIN
+            .iter()
+            .filter(|(name, _)| *name == FALLBACK || *name == locale)
+        {
+            messages.extend(parse(source));
+        }
+        if let Some(source) = config::config_dir()
+            .map(|dir| dir.join("locales").join(format!("{}.toml", locale)))
+            .and_then(|path| fs::read_to_string(path).ok())
+        {
+            messages.extend(parse(&source));
+        }
+
+        Self { locale, messages }
+    }
+
+    /// Detect the locale from `LC_ALL`, `LC_MESSAGES` and `LANG`, in that order.
+    pub fn detect() -> String {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .map_or_else(|| FALLBACK.to_string(), |value| language(&value))
+    }
+
+    /// The locale of the catalog.
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    /// Look up a message. Unknown keys are returned as is.
+    pub fn get(&self, key: &str) -> String {
+        self.messages
+            .get(key)
+            .cloned()
+            .unwrap_or_else(|| key.to_string())
+    }
+
+    /// Look up a message, substituting the `{name}` placeholders with the arguments.
+    pub fn format(&self, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        args.iter().fold(self.get(key), |message, (name, value)| {
+            message.replace(&format!("{{{}}}", name), &value.to_string())
+        })
+    }
+}
+
+/// The language part of a locale. (`de_DE.UTF-8` -> `de`, `C` and `POSIX` -> `en`)
+fn language(locale: &str) -> String {
+    let language = locale
+        .split(['_', '.', '@', '-'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+
+    match language.as_str() {
+        "" | "c" | "posix" => FALLBACK.to_string(),
+        _ => language,
+    }
+}
+
+/// Parse a catalog. Invalid catalogs, and entries which are not strings, are skipped.
+fn parse(source: &str) -> Vec<(String, String)> {
+    let Ok(document) = ImDocument::parse(source) else {
+        return Vec::new();
+    };
+
+    document
+        .as_table()
+        .iter()
+        .filter_map(|(key, item)| Some((key.to_string(), item.as_str()?.to_string())))
+        .collect()
+}
@@ -0,0 +1,110 @@
+//! Shell integration escape sequences.
+//!
+//! Terminal emulators such as WezTerm, iTerm2 and kitty understand a few escape sequences that
+//! tell them about the structure of a shell session:
+//!
+//! - Semantic prompt markers (OSC 133) delimit the prompt, the command and its output, so the
+//!   terminal can jump between prompts or select the output of a command.
+//! - Working directory reports (OSC 7) let the terminal open new tabs and windows in the current
+//!   directory of the shell.
+//! - Desktop notifications (OSC 9) tell about commands taking longer than their budget, or about
+//!   commands completing, when enabled. (See `crate::budget` and [`crate::config::Cues`]) The
+//!   terminal bell may cue them too.
+//! - The window title (OSC 2) shows the running command, or the working directory. The title of
+//!   the terminal is saved on startup and restored on exit (XTWINOPS 22/23).
+//!
+//! The full screen interface redraws the whole screen, so the prompt markers are only emitted in
+//! screen reader mode, where the session is a plain sequence of lines. The working directory is
+//! reported in both.
+
+use std::path::Path;
+
+/// Start of the prompt. (OSC 133 A)
+pub(crate) const PROMPT_START: &str = "\x1b]133;A\x07";
+/// End of the prompt, start of the command typed by the user. (OSC 133 B)
+pub(crate) const COMMAND_START: &str = "\x1b]133;B\x07";
+/// End of the command, start of its output. (OSC 133 C)
+pub(crate) const OUTPUT_START: &str = "\x1b]133;C\x07";
+/// End of the output of the command. (OSC 133 D)
+pub(crate) const OUTPUT_END: &str = "\x1b]133;D\x07";
+
+/// Ring the terminal bell. (BEL)
+pub(crate) const BELL: &str = "\x07";
+
+/// Save the window title of the terminal on its title stack. (XTWINOPS 22)
+pub(crate) const PUSH_TITLE: &str = "\x1b[22;0t";
+/// Restore the window title saved with [`PUSH_TITLE`]. (XTWINOPS 23)
+pub(crate) const POP_TITLE: &str = "\x1b[23;0t";
+
+/// Set the window title. (OSC 2) Control characters are left out, as they would end the sequence.
+pub(crate) fn title(title: &str) -> String {
+    let title = title
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>();
+    format!("\x1b]2;{}\x07", title)
+}
+
+/// Ask the terminal to show a desktop notification. (OSC 9) Control characters are left out, as
+/// they would end the sequence.
+pub(crate) fn notification(message: &str) -> String {
+    let message = message
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>();
+    format!("\x1b]9;{}\x07", message)
+}
+
+/// A path for display, with the home directory shortened to `~`.
+pub(crate) fn tilde(path: &Path) -> String {
+    let home = std::env::var_os("HOME").map(std::path::PathBuf::from);
+    match home
+        .as_deref()
+        .and_then(|home| path.strip_prefix(home).ok())
+    {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => path.display().to_string(),
+    }
+}
+
+/// Report the working directory. (OSC 7, with a `file://` URL of the directory)
+pub(crate) fn cwd(path: &Path) -> String {
+    format!(
+        "\x1b]7;file://{}{}\x07",
+        hostname().unwrap_or_default(),
+        encode(&path.to_string_lossy())
+    )
+}
+
+/// Percent-encode a path for a `file://` URL.
+fn encode(path: &str) -> String {
+    path.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// The name of the host, which the terminal compares to its own to tell remote directories apart.
+#[cfg(unix)]
+pub(crate) fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer is valid for writes of its length for the duration of the call.
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    if result != 0 {
+        return None;
+    }
+
+    let length = buffer.iter().position(|byte| *byte == 0)?;
+    String::from_utf8(buffer[..length].to_vec()).ok()
+}
+
+/// The name of the host, which the terminal compares to its own to tell remote directories apart.
+#[cfg(not(unix))]
+pub(crate) fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
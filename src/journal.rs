@@ -0,0 +1,682 @@
+//! The journal of the commands run, shared between sessions.
+//!
+//! Every command run is stored in `history.db` in the data directory (`$XDG_DATA_HOME/sheller`),
+//! an SQLite database every running shell reads back, so a command run in one window is
+//! recallable in every other one as soon as it ran. (See `history.share` in
+//! [`crate::config::History`]) Along with the command, the journal keeps the directory it ran in,
+//! its exit status (its exit code, if the executor tells it, or else `0` when it wrote no errors
+//! and `1` otherwise) and when it ran. A command re-run with changes is linked to the one it
+//! fixes, to compare the two later.
+//!
+//! The commands are indexed by text (a trigram index, so any part of a command is found fast), by
+//! directory, by exit status and by time. They are searched:
+//!
+//! - with `Up` and `Down`, recalling the last commands.
+//! - with `Ctrl+R`, searching the commands containing what is typed.
+//! - with `:history [FILTER]... [TEXT]`, listing the commands matching the filters.
+//! - with `:stats [FILTER]... [TEXT]`, counting the commands, and the most run ones.
+//!
+//! The filters are:
+//!
+//! - `--here`, or `--cwd DIR`: the commands run in the working directory, or in `DIR`.
+//! - `--failed`, or `--succeeded`: the commands that failed, or that did not.
+//! - `--since AGE`, or `--until AGE`: the commands run at most, or at least, `AGE` ago, such as
+//!   `90s`, `30m`, `12h`, `7d` or `2w`.
+//! - `--run NAME`: the commands of the named run. (See `crate::runs`)
+//! - `--starred`: the starred commands.
+//! - `--limit N`: the number of commands listed. (default: `20`)
+//!
+//! Sessions write to the database concurrently in write-ahead logging mode, waiting for each
+//! other. The journal of earlier versions, `history.jsonl`, is imported once.
+
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params_from_iter, types::Value, Connection, Transaction, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::migrate;
+
+/// The name of the database, in the data directory.
+const NAME: &str = "history.db";
+
+/// The name of the journal of earlier versions, in the data directory.
+const LEGACY: &str = "history.jsonl";
+
+/// The migrations of the schema of the database, an SQL batch each. (See [`crate::migrate`]) Its
+/// version is kept in `PRAGMA user_version`, which is `0` for a new database.
+const MIGRATIONS: &[&str] = &[
+    // 1 to 2: the named run a command belongs to. (See `crate::runs`)
+    "ALTER TABLE commands ADD COLUMN run TEXT;",
+    // 2 to 3: the note attached to a command. (See `:note`)
+    "ALTER TABLE commands ADD COLUMN note TEXT;",
+    // 3 to 4: whether the command is starred. (See `:star`)
+    "ALTER TABLE commands ADD COLUMN starred INTEGER NOT NULL DEFAULT 0;",
+    // 4 to 5: the command a command re-runs, fixed. (See `:fc`)
+    "ALTER TABLE commands ADD COLUMN fixes INTEGER;",
+];
+
+/// The schema of the database. The text index is kept up to date by triggers.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS commands (
+        id INTEGER PRIMARY KEY,
+        command TEXT NOT NULL,
+        directory TEXT,
+        exit INTEGER,
+        time INTEGER NOT NULL,
+        run TEXT,
+        note TEXT,
+        starred INTEGER NOT NULL DEFAULT 0,
+        fixes INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS commands_command ON commands (command);
+    CREATE INDEX IF NOT EXISTS commands_directory ON commands (directory, time);
+    CREATE INDEX IF NOT EXISTS commands_exit ON commands (exit, time);
+    CREATE INDEX IF NOT EXISTS commands_run ON commands (run, time);
+    CREATE INDEX IF NOT EXISTS commands_time ON commands (time);
+    CREATE VIRTUAL TABLE IF NOT EXISTS commands_text USING fts5 (
+        command,
+        content = 'commands',
+        content_rowid = 'id',
+        tokenize = 'trigram'
+    );
+    CREATE TRIGGER IF NOT EXISTS commands_insert AFTER INSERT ON commands BEGIN
+        INSERT INTO commands_text (rowid, command) VALUES (new.id, new.command);
+    END;
+    CREATE TRIGGER IF NOT EXISTS commands_delete AFTER DELETE ON commands BEGIN
+        INSERT INTO commands_text (commands_text, rowid, command)
+            VALUES ('delete', old.id, old.command);
+    END;
+";
+
+/// How long to wait for another session writing to the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The number of commands listed by default.
+const LIMIT: usize = 20;
+
+///
+/// [`Store`] is the open journal.
+///
+pub(crate) struct Store {
+    /// The connection to the database.
+    connection: Connection,
+}
+
+///
+/// [`Query`] filters the commands of the journal.
+///
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Query {
+    /// A part of the command, ignoring case.
+    pub(crate) text: Option<String>,
+    /// The directory the command ran in.
+    pub(crate) directory: Option<String>,
+    /// Whether the command failed.
+    pub(crate) failed: Option<bool>,
+    /// The earliest time the command ran, in seconds since the Unix epoch.
+    pub(crate) since: Option<i64>,
+    /// The latest time the command ran, in seconds since the Unix epoch.
+    pub(crate) until: Option<i64>,
+    /// The named run the command belongs to. (See `crate::runs`)
+    pub(crate) run: Option<String>,
+    /// Whether only the starred commands are listed.
+    pub(crate) starred: bool,
+    /// The number of commands listed.
+    pub(crate) limit: usize,
+}
+
+///
+/// [`Record`] is a command found in the journal.
+///
+#[derive(Debug, Clone)]
+pub(crate) struct Record {
+    /// The command.
+    pub(crate) command: String,
+    /// The exit status of the command, unless it was still running (or imported).
+    pub(crate) exit: Option<i64>,
+    /// When the command ran, in local time. (`YYYY-MM-DD HH:MM`)
+    pub(crate) time: String,
+    /// The note attached to the command, if any.
+    pub(crate) note: Option<String>,
+    /// Whether the command is starred.
+    pub(crate) starred: bool,
+}
+
+///
+/// [`Stats`] sums up the commands of the journal.
+///
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Stats {
+    /// The number of commands run.
+    pub(crate) total: u64,
+    /// The number of distinct commands.
+    pub(crate) distinct: u64,
+    /// The number of commands that failed.
+    pub(crate) failed: u64,
+    /// The commands run most often, with their count.
+    pub(crate) commands: Vec<(String, u64)>,
+    /// The directories most commands ran in, with their count.
+    pub(crate) directories: Vec<(String, u64)>,
+}
+
+///
+/// [`Entry`] is a command of the journal, as imported from or exported to the history of other
+/// shells (see [`crate::history`]), or synced with other machines. (See `crate::sync`)
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    /// The command.
+    pub(crate) command: String,
+    /// The directory the command ran in, if known.
+    pub(crate) directory: Option<String>,
+    /// The exit status of the command, if known.
+    pub(crate) exit: Option<i64>,
+    /// When the command ran, in seconds since the Unix epoch.
+    pub(crate) time: i64,
+}
+
+/// A command in the journal of earlier versions.
+#[derive(Debug, Deserialize)]
+struct Legacy {
+    /// The command.
+    command: String,
+    /// When the command was run, in seconds since the Unix epoch.
+    time: i64,
+}
+
+impl Store {
+    /// Open the journal, creating it if needed.
+    pub(crate) fn open() -> anyhow::Result<Self> {
+        let dir = config::data_dir()
+            .ok_or_else(|| anyhow::anyhow!("unable to determine the data path"))?;
+        Self::at(&dir)
+    }
+
+    /// Open the journal in a directory, creating it if needed, and upgrading it if it is of an
+    /// older version.
+    fn at(dir: &Path) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        let connection = Connection::open(dir.join(NAME))?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        let transaction = Transaction::new_unchecked(&connection, TransactionBehavior::Immediate)?;
+        let version: i64 =
+            transaction.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version != 0 {
+            migrate::upgrade(NAME, u32::try_from(version)?, MIGRATIONS, |sql| {
+                Ok(transaction.execute_batch(sql)?)
+            })?;
+        }
+        transaction.execute_batch(SCHEMA)?;
+        transaction.pragma_update(None, "user_version", migrate::current(MIGRATIONS))?;
+        transaction.commit()?;
+
+        let store = Self { connection };
+        store.import(&dir.join(LEGACY))?;
+        Ok(store)
+    }
+
+    /// Import the journal of earlier versions, then remove it. Sessions starting at once import it
+    /// in turn, so only the first one finds it.
+    fn import(&self, path: &Path) -> anyhow::Result<()> {
+        let transaction =
+            Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        for legacy in source
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Legacy>(line).ok())
+        {
+            transaction.execute(
+                "INSERT INTO commands (command, time) VALUES (?1, ?2)",
+                (&legacy.command, legacy.time),
+            )?;
+        }
+        fs::remove_file(path)?;
+        // The lock guarding the journal of earlier versions is of no use anymore.
+        let _ = fs::remove_file(path.with_extension("lock"));
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Append a command to the journal, with the directory it ran in, its exit status and the run
+    /// it belongs to. This returns the id of the command in the journal, unless it is empty.
+    pub(crate) fn append(
+        &self,
+        command: &str,
+        directory: Option<&Path>,
+        exit: Option<i64>,
+        run: Option<&str>,
+    ) -> anyhow::Result<Option<i64>> {
+        let command = command.trim();
+        if command.is_empty() {
+            return Ok(None);
+        }
+
+        self.connection.execute(
+            "INSERT INTO commands (command, directory, exit, time, run) VALUES (?1, ?2, ?3, ?4, ?5)",
+            (
+                command,
+                directory.map(|directory| directory.to_string_lossy().into_owned()),
+                exit,
+                now(),
+                run,
+            ),
+        )?;
+        Ok(Some(self.connection.last_insert_rowid()))
+    }
+
+    /// Attach a note to a command of the journal, by its id, replacing the note it had.
+    pub(crate) fn annotate(&self, id: i64, note: &str) -> anyhow::Result<()> {
+        self.connection
+            .execute("UPDATE commands SET note = ?1 WHERE id = ?2", (note, id))?;
+        Ok(())
+    }
+
+    /// Star a command of the journal, by its id, or unstar it.
+    pub(crate) fn star(&self, id: i64, starred: bool) -> anyhow::Result<()> {
+        self.connection.execute(
+            "UPDATE commands SET starred = ?1 WHERE id = ?2",
+            (starred, id),
+        )?;
+        Ok(())
+    }
+
+    /// Link a command of the journal to the one it re-runs with changes, by their ids.
+    pub(crate) fn link(&self, id: i64, fixes: i64) -> anyhow::Result<()> {
+        self.connection
+            .execute("UPDATE commands SET fixes = ?1 WHERE id = ?2", (fixes, id))?;
+        Ok(())
+    }
+
+    /// Add entries to the journal, skipping the ones it had already (the same command, run at the
+    /// same time). This returns the number of entries added.
+    pub(crate) fn insert(&self, entries: &[Entry]) -> anyhow::Result<usize> {
+        let transaction =
+            Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)?;
+        // Entries repeated among the ones added are all kept: only the entries from before count.
+        let last: i64 =
+            transaction.query_row("SELECT COALESCE(MAX(id), 0) FROM commands", [], |row| {
+                row.get(0)
+            })?;
+        let mut added = 0;
+        {
+            let mut statement = transaction.prepare(
+                "INSERT INTO commands (command, directory, exit, time)
+                SELECT ?1, ?2, ?3, ?4
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM commands WHERE time = ?4 AND command = ?1 AND id <= ?5
+                )",
+            )?;
+            for entry in entries {
+                added += statement.execute((
+                    &entry.command,
+                    &entry.directory,
+                    entry.exit,
+                    entry.time,
+                    last,
+                ))?;
+            }
+        }
+        transaction.commit()?;
+
+        Ok(added)
+    }
+
+    /// Every entry of the journal, oldest first.
+    pub(crate) fn entries(&self) -> anyhow::Result<Vec<Entry>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT command, directory, exit, time FROM commands ORDER BY id")?;
+        let entries = statement
+            .query_map([], |row| {
+                Ok(Entry {
+                    command: row.get(0)?,
+                    directory: row.get(1)?,
+                    exit: row.get(2)?,
+                    time: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    /// The number of commands starting with a prefix, and how long ago the last of them ran.
+    pub(crate) fn usage(&self, prefix: &str) -> anyhow::Result<(u64, Option<Duration>)> {
+        // The commands from the prefix up to the prefix followed by the last character, along the
+        // index.
+        let (count, last) = self
+            .connection
+            .prepare_cached(
+                "SELECT COUNT(*), MAX(time) FROM commands WHERE command >= ?1 AND command < ?2",
+            )?
+            .query_row((prefix, format!("{}{}", prefix, char::MAX)), |row| {
+                Ok((row.get(0)?, row.get::<_, Option<i64>>(1)?))
+            })?;
+        let age = last.map(|last| Duration::from_secs(now().saturating_sub(last).max(0) as u64));
+
+        Ok((count, age))
+    }
+
+    /// The last commands of the journal, oldest first.
+    pub(crate) fn recent(&self, limit: usize) -> anyhow::Result<Vec<String>> {
+        let mut statement = self
+            .connection
+            .prepare_cached("SELECT command FROM commands ORDER BY id DESC LIMIT ?1")?;
+        let mut commands = statement
+            .query_map([limit as i64], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        commands.reverse();
+        Ok(commands)
+    }
+
+    /// The distinct commands matching the query, last run first.
+    pub(crate) fn distinct(&self, query: &Query) -> anyhow::Result<Vec<String>> {
+        let (filter, mut values) = filter(query);
+        values.push(Value::Integer(query.limit as i64));
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT command FROM commands WHERE {} GROUP BY command ORDER BY MAX(id) DESC LIMIT ?",
+            filter
+        ))?;
+        let commands = statement
+            .query_map(params_from_iter(values), |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(commands)
+    }
+
+    /// The commands matching the query, last run first.
+    pub(crate) fn search(&self, query: &Query) -> anyhow::Result<Vec<Record>> {
+        let (filter, mut values) = filter(query);
+        values.push(Value::Integer(query.limit as i64));
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT command, exit, strftime('%Y-%m-%d %H:%M', time, 'unixepoch', 'localtime'), note,
+            starred FROM commands WHERE {} ORDER BY id DESC LIMIT ?",
+            filter
+        ))?;
+        let records = statement
+            .query_map(params_from_iter(values), |row| {
+                Ok(Record {
+                    command: row.get(0)?,
+                    exit: row.get(1)?,
+                    time: row.get(2)?,
+                    note: row.get(3)?,
+                    starred: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(records)
+    }
+
+    /// Sum up the commands matching the query, with the most frequent commands and directories
+    /// (as many as the limit of the query).
+    pub(crate) fn stats(&self, query: &Query) -> anyhow::Result<Stats> {
+        let (filter, values) = filter(query);
+        let (total, distinct, failed) = self.connection.query_row(
+            &format!(
+                "SELECT COUNT(*), COUNT(DISTINCT command), COALESCE(SUM(exit != 0), 0)
+                FROM commands WHERE {}",
+                filter
+            ),
+            params_from_iter(values.iter()),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        let counts = |column: &str| -> anyhow::Result<Vec<(String, u64)>> {
+            let mut values = values.clone();
+            values.push(Value::Integer(query.limit as i64));
+            let mut statement = self.connection.prepare(&format!(
+                "SELECT {column}, COUNT(*) FROM commands WHERE {filter} AND {column} IS NOT NULL
+                GROUP BY {column} ORDER BY COUNT(*) DESC, MAX(id) DESC LIMIT ?",
+                column = column,
+                filter = filter
+            ))?;
+            let counts = statement
+                .query_map(params_from_iter(values), |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(counts)
+        };
+
+        Ok(Stats {
+            total,
+            distinct,
+            failed,
+            commands: counts("command")?,
+            directories: counts("directory")?,
+        })
+    }
+}
+
+impl Query {
+    /// Parse the arguments of `:history` and `:stats`, with directories relative to the working
+    /// directory. This returns `None` if they are invalid.
+    pub(crate) fn parse(args: &[&str], directory: Option<&Path>) -> Option<Self> {
+        let mut query = Self {
+            limit: LIMIT,
+            ..Self::default()
+        };
+        let mut text = Vec::new();
+        let mut args = args.iter();
+        while let Some(&arg) = args.next() {
+            match arg {
+                "--here" => query.directory = Some(directory?.to_string_lossy().into_owned()),
+                "--cwd" => {
+                    let dir = Path::new(args.next()?);
+                    let dir = match directory {
+                        Some(directory) => directory.join(dir),
+                        None => dir.to_path_buf(),
+                    };
+                    query.directory = Some(dir.to_string_lossy().into_owned());
+                }
+                "--failed" => query.failed = Some(true),
+                "--succeeded" => query.failed = Some(false),
+                "--since" => query.since = Some(now() - age(args.next()?)?),
+                "--until" => query.until = Some(now() - age(args.next()?)?),
+                "--run" => query.run = Some(args.next()?.to_string()),
+                "--starred" => query.starred = true,
+                "--limit" => query.limit = args.next()?.parse().ok()?,
+                flag if flag.starts_with("--") => return None,
+                word => text.push(word),
+            }
+        }
+        query.text = Some(text.join(" ")).filter(|text| !text.is_empty());
+
+        Some(query)
+    }
+}
+
+/// The SQL condition selecting the commands matching the query, along with its parameters.
+fn filter(query: &Query) -> (String, Vec<Value>) {
+    let mut conditions = vec!["1"];
+    let mut values = Vec::new();
+    if let Some(text) = &query.text {
+        // The trigram index finds the commands with `LIKE` (ignoring case), which takes `%` and `_`
+        // as wildcards: `instr` keeps the commands containing the text as typed.
+        conditions.push(
+            "id IN (SELECT rowid FROM commands_text WHERE command LIKE ?) \
+            AND instr(lower(command), lower(?)) > 0",
+        );
+        values.push(Value::Text(format!("%{}%", text)));
+        values.push(Value::Text(text.clone()));
+    }
+    if let Some(directory) = &query.directory {
+        conditions.push("directory = ?");
+        values.push(Value::Text(directory.clone()));
+    }
+    match query.failed {
+        Some(true) => conditions.push("exit != 0"),
+        Some(false) => conditions.push("exit = 0"),
+        None => {}
+    }
+    if let Some(since) = query.since {
+        conditions.push("time >= ?");
+        values.push(Value::Integer(since));
+    }
+    if let Some(until) = query.until {
+        conditions.push("time <= ?");
+        values.push(Value::Integer(until));
+    }
+    if let Some(run) = &query.run {
+        conditions.push("run = ?");
+        values.push(Value::Text(run.clone()));
+    }
+    if query.starred {
+        conditions.push("starred != 0");
+    }
+
+    (conditions.join(" AND "), values)
+}
+
+/// Parse an age, such as `30m`, in seconds.
+fn age(age: &str) -> Option<i64> {
+    let unit = match age.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    age[..age.len() - 1].parse::<i64>().ok()?.checked_mul(unit)
+}
+
+/// The current time, in seconds since the Unix epoch.
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+///
+/// [`Recall`] walks back through the commands run, from the line being typed.
+///
+#[derive(Debug, Clone)]
+pub(crate) struct Recall {
+    /// The commands, oldest first.
+    commands: Vec<String>,
+    /// The command shown, or `commands.len()` for the line typed.
+    index: usize,
+    /// The line typed before recalling.
+    typed: String,
+}
+
+impl Recall {
+    /// Start recalling from the line typed. Commands run several times in a row are recalled once.
+    pub(crate) fn new(mut commands: Vec<String>, typed: String) -> Self {
+        commands.dedup();
+        Self {
+            index: commands.len(),
+            commands,
+            typed,
+        }
+    }
+
+    /// Go back to the previous command. This returns `None` past the oldest command.
+    pub(crate) fn previous(&mut self) -> Option<&str> {
+        self.index = self.index.checked_sub(1)?;
+        self.commands.get(self.index).map(String::as_str)
+    }
+
+    /// Go forward to the next command, or to the line typed after the newest command. This returns
+    /// `None` if the line typed is shown already.
+    pub(crate) fn next(&mut self) -> Option<&str> {
+        if self.index >= self.commands.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(self.commands.get(self.index).unwrap_or(&self.typed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::testing;
+
+    /// A directory of the test alone, holding the history database of the fixtures of a version.
+    fn fixture(test: &str, version: &str) -> anyhow::Result<PathBuf> {
+        let sql = format!("history-{}.sql", version);
+        let sql = testing::fixture(&format!("journal-{}", test), &sql, "history.sql")?;
+        let dir = sql
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("no directory"))?
+            .to_path_buf();
+        Connection::open(dir.join(NAME))?.execute_batch(&fs::read_to_string(&sql)?)?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn first_version_is_upgraded_to_the_current_one() -> anyhow::Result<()> {
+        let dir = fixture("upgrade", "v1")?;
+        let store = Store::at(&dir)?;
+
+        let version: u32 = store
+            .connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
+        assert_eq!(version, migrate::current(MIGRATIONS));
+        assert_eq!(version, 5);
+
+        let entries = store.entries()?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].command, "cargo tset");
+        assert_eq!(entries[1].exit, Some(101));
+
+        // The columns of every migration are there, and work.
+        let fixed = store.append("cargo test", None, Some(0), Some("ci"))?;
+        let fixed = fixed.ok_or_else(|| anyhow::anyhow!("nothing appended"))?;
+        store.annotate(fixed, "typo")?;
+        store.star(fixed, true)?;
+        store.link(fixed, 2)?;
+        let (run, note, starred, fixes): (String, String, bool, i64) = store.connection.query_row(
+            "SELECT run, note, starred, fixes FROM commands WHERE id = ?1",
+            [fixed],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        assert_eq!(
+            (run.as_str(), note.as_str(), starred, fixes),
+            ("ci", "typo", true, 2)
+        );
+
+        // The text index of the first version still finds the old commands.
+        let found = store.search(&Query {
+            text: Some("tse".to_string()),
+            limit: LIMIT,
+            ..Query::default()
+        })?;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].command, "cargo tset");
+
+        // Opened again, there is nothing left to upgrade.
+        drop(store);
+        assert_eq!(Store::at(&dir)?.entries()?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn newer_version_is_refused() -> anyhow::Result<()> {
+        let dir = fixture("newer", "v1")?;
+        Connection::open(dir.join(NAME))?.pragma_update(None, "user_version", 9)?;
+
+        assert!(Store::at(&dir).is_err());
+        let version: u32 =
+            Connection::open(dir.join(NAME))?
+                .pragma_query_value(None, "user_version", |row| row.get(0))?;
+        assert_eq!(version, 9);
+        Ok(())
+    }
+}
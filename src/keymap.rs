@@ -0,0 +1,702 @@
+//! Keybindings of the shell.
+//!
+//! Every key the shell reacts to is bound to an [`Action`] in a [`Keymap`]. The renderer
+//! dispatches key presses through the keymap, and the help overlay (`F1` or `?`) is generated
+//! from it, so the help always lists the bindings that are actually active.
+//!
+//! Bindings are changed in the `[keys]` section of the configuration file, mapping the name of an
+//! action to a key or a list of keys:
+//!
+//! ```toml
+//! [keys]
+//! clear-screen = "ctrl+k"
+//! exit = ["ctrl+d", "ctrl+x"]
+//! ```
+//!
+//! A count typed with `Alt` and digits before a key repeats its action, as the numeric argument
+//! of readline: `Alt+3 Ctrl+W` deletes three words, and `Alt+5 Up` recalls the fifth command
+//! back. (See [`Action::repeats`]) A character typed after a count is typed that many times.
+//!
+//! Keys can also type text on the command line: the keys of `[keys.insert]` type their text, and
+//! the keys of `[keys.run]` type it and run it at once, as a command used often:
+//!
+//! ```toml
+//! [keys.run]
+//! f5 = "cargo test"
+//!
+//! [keys.insert]
+//! f6 = "git commit -m "
+//! ```
+//!
+//! Keys are written as modifiers (`ctrl`, `alt`, `shift`) followed by the key, joined with `+`.
+//! The key is either a single character, `f1` to `f12`, or one of `enter`, `tab`, `backtab`,
+//! `backspace`, `delete`, `insert`, `esc`, `space`, `left`, `right`, `up`, `down`, `home`, `end`,
+//! `pageup` and `pagedown`.
+//!
+//! # Example
+//!
+//! ```rust
+//! use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+//! use shelgon::keymap::{Action, Keymap};
+//!
+//! let mut keymap = Keymap::default();
+//! keymap.bind(Action::ClearScreen, vec!["ctrl+k".parse().unwrap()]);
+//!
+//! let key = KeyEvent::new(KeyCode::Char('k'), KeyModifiers::CONTROL);
+//! assert_eq!(keymap.action(&key, false), Some(Action::ClearScreen));
+//! assert_eq!(keymap.keys(Action::ClearScreen)[0].to_string(), "Ctrl+K");
+//!
+//! keymap.bind_text("f5".parse().unwrap(), "cargo test", true);
+//! let key = KeyEvent::new(KeyCode::F(5), KeyModifiers::NONE);
+//! assert_eq!(keymap.text(&key, false), Some(("cargo test", true)));
+//! ```
+
+use std::{fmt, str::FromStr};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// The named keys, as written in the configuration file.
+const NAMED: &[(&str, KeyCode)] = &[
+    ("enter", KeyCode::Enter),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("esc", KeyCode::Esc),
+    ("space", KeyCode::Char(' ')),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+];
+
+///
+/// [`Category`] groups related actions in the help overlay.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    /// Actions on the shell itself.
+    Shell,
+    /// Actions editing the command line.
+    Editing,
+    /// Actions recalling previous commands.
+    History,
+    /// Actions recording and replaying keyboard macros.
+    Macros,
+}
+
+impl Category {
+    /// Every category, in the order they are listed in the help.
+    pub const ALL: &'static [Category] = &[
+        Category::Shell,
+        Category::Editing,
+        Category::History,
+        Category::Macros,
+    ];
+
+    /// The key of the title of the category in the message catalog. (See [`crate::i18n`])
+    pub fn message(self) -> &'static str {
+        match self {
+            Category::Shell => "category-shell",
+            Category::Editing => "category-editing",
+            Category::History => "category-history",
+            Category::Macros => "category-macros",
+        }
+    }
+}
+
+///
+/// [`Action`] is something the shell does in response to a key.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Show the keybindings.
+    Help,
+    /// Clear the screen.
+    ClearScreen,
+    /// Exit the shell, or end the input of a running command.
+    Exit,
+    /// Interrupt the running command, killing it, or exit the shell at the prompt.
+    Interrupt,
+    /// Detach from the session. (See [`crate::session`])
+    Detach,
+    /// Show the details of the errors in the banner at the top of the screen.
+    ShowErrors,
+    /// Dismiss the errors in the banner at the top of the screen.
+    DismissErrors,
+    /// Queue the line typed while a command runs, as a command to run after it, rather than as
+    /// its input. (At the prompt, this runs the command)
+    QueueCommand,
+    /// List the commands queued, to reorder or cancel them.
+    ShowQueue,
+    /// Pause the output of a command coming in, or resume it.
+    PauseOutput,
+    /// Open a new tab, with a session of its own on the connection of the executor.
+    NewTab,
+    /// Go to the next tab.
+    NextTab,
+    /// Go to the previous tab.
+    PreviousTab,
+    /// Close the tab, along with its session.
+    CloseTab,
+    /// Move the cursor one character to the left.
+    CursorLeft,
+    /// Move the cursor one character to the right.
+    CursorRight,
+    /// Move the cursor to the start of the word before it.
+    CursorWordLeft,
+    /// Move the cursor to the end of the word after it.
+    CursorWordRight,
+    /// Move the cursor to the start of the line.
+    CursorHome,
+    /// Move the cursor to the end of the line.
+    CursorEnd,
+    /// Delete the character before the cursor.
+    DeleteBackward,
+    /// Delete the character under the cursor.
+    DeleteForward,
+    /// Delete the word before the cursor.
+    DeleteWordBackward,
+    /// Delete everything before the cursor.
+    DeleteToStart,
+    /// Undo the last change to the line.
+    Undo,
+    /// Complete the command.
+    Complete,
+    /// Run the command, or start a new line of input for a running command.
+    Submit,
+    /// Recall the previous command.
+    HistoryPrevious,
+    /// Recall the next command, back to the line being typed.
+    HistoryNext,
+    /// Scroll the history to the entry before the first one shown.
+    PreviousPrompt,
+    /// Scroll the history to the entry after the first one shown.
+    NextPrompt,
+    /// Scroll the history up by a page.
+    ScrollUp,
+    /// Scroll the history down by a page, back to the prompt at the bottom.
+    ScrollDown,
+    /// Search the commands run. (See `crate::journal`)
+    SearchHistory,
+    /// Suggest the commands run most often in the working directory.
+    SuggestCommands,
+    /// Attach a note to the last entry of the history.
+    AnnotateEntry,
+    /// Star the last entry of the history, or unstar it.
+    StarEntry,
+    /// Show only the starred entries of the history, or all of them again.
+    ShowStarred,
+    /// Edit the last command run, to run it again with changes.
+    FixCommand,
+    /// Insert the last argument of the previous command, or of the one before it when repeated.
+    InsertLastArgument,
+    /// Ask for a command doing what the typed line describes. (See [`crate::config::Suggest`])
+    DescribeCommand,
+    /// Explain the command being typed, or the last command run.
+    ExplainCommand,
+    /// Read the manual of the command under the cursor.
+    ShowManual,
+    /// Pick a process from a list, inserting its id at the cursor. (See `crate::ps`)
+    PickProcess,
+    /// Insert the last snippet of the kill ring at the cursor. (See `crate::kills`)
+    Yank,
+    /// List the snippets of the kill ring, to insert one at the cursor.
+    ShowKills,
+    /// Copy the output of the last entry of the history to the kill ring.
+    CopyOutput,
+    /// Select part of a command of the history, such as one stage of a pipeline, to put it on
+    /// the prompt.
+    CopyMode,
+    /// Start recording a keyboard macro, or stop the recording.
+    RecordMacro,
+    /// Replay the last recorded keyboard macro.
+    PlayMacro,
+}
+
+impl Action {
+    /// Every action, in the order they are listed in the help.
+    pub const ALL: &'static [Action] = &[
+        Action::Help,
+        Action::ClearScreen,
+        Action::Exit,
+        Action::Interrupt,
+        Action::Detach,
+        Action::ShowErrors,
+        Action::DismissErrors,
+        Action::QueueCommand,
+        Action::ShowQueue,
+        Action::PauseOutput,
+        Action::NewTab,
+        Action::NextTab,
+        Action::PreviousTab,
+        Action::CloseTab,
+        Action::CursorLeft,
+        Action::CursorRight,
+        Action::CursorWordLeft,
+        Action::CursorWordRight,
+        Action::CursorHome,
+        Action::CursorEnd,
+        Action::DeleteBackward,
+        Action::DeleteForward,
+        Action::DeleteWordBackward,
+        Action::DeleteToStart,
+        Action::Undo,
+        Action::Complete,
+        Action::Submit,
+        Action::HistoryPrevious,
+        Action::HistoryNext,
+        Action::PreviousPrompt,
+        Action::NextPrompt,
+        Action::ScrollUp,
+        Action::ScrollDown,
+        Action::SearchHistory,
+        Action::SuggestCommands,
+        Action::AnnotateEntry,
+        Action::StarEntry,
+        Action::ShowStarred,
+        Action::FixCommand,
+        Action::InsertLastArgument,
+        Action::DescribeCommand,
+        Action::ExplainCommand,
+        Action::ShowManual,
+        Action::PickProcess,
+        Action::Yank,
+        Action::ShowKills,
+        Action::CopyOutput,
+        Action::CopyMode,
+        Action::RecordMacro,
+        Action::PlayMacro,
+    ];
+
+    /// The name of the action, as used in the `[keys]` section of the configuration file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Help => "help",
+            Action::ClearScreen => "clear-screen",
+            Action::Exit => "exit",
+            Action::Interrupt => "interrupt",
+            Action::Detach => "detach",
+            Action::ShowErrors => "show-errors",
+            Action::DismissErrors => "dismiss-errors",
+            Action::QueueCommand => "queue-command",
+            Action::ShowQueue => "show-queue",
+            Action::PauseOutput => "pause-output",
+            Action::NewTab => "new-tab",
+            Action::NextTab => "next-tab",
+            Action::PreviousTab => "previous-tab",
+            Action::CloseTab => "close-tab",
+            Action::CursorLeft => "cursor-left",
+            Action::CursorRight => "cursor-right",
+            Action::CursorWordLeft => "cursor-word-left",
+            Action::CursorWordRight => "cursor-word-right",
+            Action::CursorHome => "cursor-home",
+            Action::CursorEnd => "cursor-end",
+            Action::DeleteBackward => "delete-backward",
+            Action::DeleteForward => "delete-forward",
+            Action::DeleteWordBackward => "delete-word-backward",
+            Action::DeleteToStart => "delete-to-start",
+            Action::Undo => "undo",
+            Action::Complete => "complete",
+            Action::Submit => "submit",
+            Action::HistoryPrevious => "history-previous",
+            Action::HistoryNext => "history-next",
+            Action::PreviousPrompt => "previous-prompt",
+            Action::NextPrompt => "next-prompt",
+            Action::ScrollUp => "scroll-up",
+            Action::ScrollDown => "scroll-down",
+            Action::SearchHistory => "search-history",
+            Action::SuggestCommands => "suggest-commands",
+            Action::AnnotateEntry => "annotate-entry",
+            Action::StarEntry => "star-entry",
+            Action::ShowStarred => "show-starred",
+            Action::FixCommand => "fix-command",
+            Action::InsertLastArgument => "insert-last-argument",
+            Action::DescribeCommand => "describe-command",
+            Action::ExplainCommand => "explain-command",
+            Action::ShowManual => "show-manual",
+            Action::PickProcess => "pick-process",
+            Action::Yank => "yank",
+            Action::ShowKills => "show-kills",
+            Action::CopyOutput => "copy-output",
+            Action::CopyMode => "copy-mode",
+            Action::RecordMacro => "record-macro",
+            Action::PlayMacro => "play-macro",
+        }
+    }
+
+    /// Look up an action by name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name() == name)
+    }
+
+    /// The category the action is listed under in the help.
+    pub fn category(self) -> Category {
+        match self {
+            Action::Help
+            | Action::ClearScreen
+            | Action::Exit
+            | Action::Interrupt
+            | Action::Detach
+            | Action::ShowErrors
+            | Action::DismissErrors
+            | Action::QueueCommand
+            | Action::ShowQueue
+            | Action::PauseOutput
+            | Action::NewTab
+            | Action::NextTab
+            | Action::PreviousTab
+            | Action::CloseTab => Category::Shell,
+            Action::CursorLeft
+            | Action::CursorRight
+            | Action::CursorWordLeft
+            | Action::CursorWordRight
+            | Action::CursorHome
+            | Action::CursorEnd
+            | Action::DeleteBackward
+            | Action::DeleteForward
+            | Action::DeleteWordBackward
+            | Action::DeleteToStart
+            | Action::Undo
+            | Action::Complete
+            | Action::Submit
+            | Action::DescribeCommand
+            | Action::ExplainCommand
+            | Action::ShowManual
+            | Action::PickProcess
+            | Action::Yank
+            | Action::ShowKills
+            | Action::CopyOutput
+            | Action::CopyMode => Category::Editing,
+            Action::HistoryPrevious
+            | Action::HistoryNext
+            | Action::PreviousPrompt
+            | Action::NextPrompt
+            | Action::ScrollUp
+            | Action::ScrollDown
+            | Action::SearchHistory
+            | Action::SuggestCommands
+            | Action::AnnotateEntry
+            | Action::StarEntry
+            | Action::ShowStarred
+            | Action::FixCommand
+            | Action::InsertLastArgument => Category::History,
+            Action::RecordMacro | Action::PlayMacro => Category::Macros,
+        }
+    }
+
+    /// Whether the action is repeated when given a count with `Alt+digit`, such as `Alt+3 Ctrl+W`
+    /// deleting three words. Other actions are done once.
+    pub fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::CursorLeft
+                | Action::CursorRight
+                | Action::CursorWordLeft
+                | Action::CursorWordRight
+                | Action::DeleteBackward
+                | Action::DeleteForward
+                | Action::DeleteWordBackward
+                | Action::Undo
+                | Action::HistoryPrevious
+                | Action::HistoryNext
+                | Action::PreviousPrompt
+                | Action::NextPrompt
+                | Action::ScrollUp
+                | Action::ScrollDown
+                | Action::InsertLastArgument
+                | Action::Yank
+        )
+    }
+
+    /// The key of the description of the action in the message catalog. (See [`crate::i18n`])
+    pub fn message(self) -> String {
+        format!("action-{}", self.name())
+    }
+
+    /// The keys bound to the action by default.
+    fn defaults(self) -> &'static [&'static str] {
+        match self {
+            Action::Help => &["f1", "?"],
+            Action::ClearScreen => &["ctrl+l"],
+            Action::Exit => &["ctrl+d"],
+            Action::Interrupt => &["ctrl+c"],
+            Action::Detach => &["ctrl+q"],
+            Action::ShowErrors => &["f9"],
+            Action::DismissErrors => &["esc"],
+            Action::QueueCommand => &["alt+enter"],
+            Action::ShowQueue => &["alt+q"],
+            Action::PauseOutput => &["ctrl+s"],
+            Action::NewTab => &["alt+t"],
+            Action::NextTab => &["alt+right"],
+            Action::PreviousTab => &["alt+left"],
+            Action::CloseTab => &["alt+x"],
+            Action::CursorLeft => &["left"],
+            Action::CursorRight => &["right"],
+            Action::CursorWordLeft => &["ctrl+left", "alt+b"],
+            Action::CursorWordRight => &["ctrl+right", "alt+f"],
+            Action::CursorHome => &["home", "ctrl+a"],
+            Action::CursorEnd => &["end", "ctrl+e"],
+            Action::DeleteBackward => &["backspace"],
+            Action::DeleteForward => &["delete"],
+            Action::DeleteWordBackward => &["ctrl+w", "alt+backspace"],
+            Action::DeleteToStart => &["ctrl+u"],
+            Action::Undo => &["ctrl+z"],
+            Action::Complete => &["tab"],
+            Action::Submit => &["enter"],
+            Action::HistoryPrevious => &["up"],
+            Action::HistoryNext => &["down"],
+            Action::PreviousPrompt => &["ctrl+up"],
+            Action::NextPrompt => &["ctrl+down"],
+            Action::ScrollUp => &["pageup"],
+            Action::ScrollDown => &["pagedown"],
+            Action::SearchHistory => &["ctrl+r"],
+            Action::SuggestCommands => &["alt+s"],
+            Action::AnnotateEntry => &["alt+n"],
+            Action::StarEntry => &["f7"],
+            Action::ShowStarred => &["f8"],
+            Action::FixCommand => &["alt+e"],
+            Action::InsertLastArgument => &["alt+."],
+            Action::DescribeCommand => &["f5"],
+            Action::ExplainCommand => &["f6"],
+            Action::ShowManual => &["f2"],
+            Action::PickProcess => &["alt+p"],
+            Action::Yank => &["ctrl+y"],
+            Action::ShowKills => &["alt+y"],
+            Action::CopyOutput => &["alt+w"],
+            Action::CopyMode => &["alt+c"],
+            Action::RecordMacro => &["f3"],
+            Action::PlayMacro => &["f4"],
+        }
+    }
+}
+
+///
+/// [`Key`] is a key along with the modifiers held down, such as `Ctrl+L`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    /// The key.
+    pub code: KeyCode,
+    /// The modifiers held down.
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    /// Check if the key types a character, rather than being a shortcut.
+    pub fn is_printable(&self) -> bool {
+        matches!(self.code, KeyCode::Char(_))
+            && !self
+                .modifiers
+                .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+    }
+}
+
+impl From<&KeyEvent> for Key {
+    /// The key of a key press. Shift is dropped from characters and `BackTab`, as it is already
+    /// reflected in the key itself (`?` rather than `Shift+/`).
+    fn from(event: &KeyEvent) -> Self {
+        let mut modifiers = event.modifiers;
+        let code = match event.code {
+            KeyCode::Char(c) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                match modifiers.is_empty() {
+                    true => KeyCode::Char(c),
+                    false => KeyCode::Char(c.to_ascii_lowercase()),
+                }
+            }
+            KeyCode::BackTab => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::BackTab
+            }
+            code => code,
+        };
+
+        Self { code, modifiers }
+    }
+}
+
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (prefix, key) = match value {
+            "+" => ("", "+"),
+            _ => match value.strip_suffix("++") {
+                Some(prefix) => (prefix, "+"),
+                None => value.rsplit_once('+').unwrap_or(("", value)),
+            },
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in prefix.split('+').filter(|modifier| !modifier.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => anyhow::bail!("unknown modifier `{}` in `{}`", modifier, value),
+            };
+        }
+
+        let lower = key.to_lowercase();
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if modifiers.is_empty() => KeyCode::Char(c),
+            (Some(c), None) => KeyCode::Char(c.to_ascii_lowercase()),
+            _ => match NAMED.iter().find(|(name, _)| *name == lower) {
+                Some((_, code)) => *code,
+                None => match lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                    Some(n @ 1..=12) => KeyCode::F(n),
+                    _ => anyhow::bail!("unknown key `{}` in `{}`", key, value),
+                },
+            },
+        };
+        let code = match code {
+            KeyCode::Char(_) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                code
+            }
+            // The terminal reports `Shift+Tab` as `BackTab`.
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::BackTab
+            }
+            code => code,
+        };
+
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl"),
+            (KeyModifiers::ALT, "Alt"),
+            (KeyModifiers::SHIFT, "Shift"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) if self.modifiers.is_empty() => write!(f, "{}", c),
+            KeyCode::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::Esc => write!(f, "Esc"),
+            KeyCode::PageUp => write!(f, "PageUp"),
+            KeyCode::PageDown => write!(f, "PageDown"),
+            KeyCode::BackTab => write!(f, "Shift+Tab"),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+///
+/// [`Keymap`] maps keys to the actions of the shell, or to text typed on the command line.
+///
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    /// The bindings, with every key bound to at most one action.
+    bindings: Vec<(Key, Action)>,
+    /// The keys bound to text, with whether the text is run once typed. A key bound to text is
+    /// bound to no action.
+    texts: Vec<(Key, String, bool)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .iter()
+            .flat_map(|action| {
+                action
+                    .defaults()
+                    .iter()
+                    .filter_map(|key| key.parse().ok())
+                    .map(|key| (key, *action))
+            })
+            .collect();
+
+        Self {
+            bindings,
+            texts: Vec::new(),
+        }
+    }
+}
+
+impl Keymap {
+    /// Bind the keys to the action, replacing its current keys. The keys are unbound from any
+    /// other action.
+    pub fn bind(&mut self, action: Action, keys: Vec<Key>) {
+        self.bindings
+            .retain(|(key, bound)| *bound != action && !keys.contains(key));
+        self.texts.retain(|(key, ..)| !keys.contains(key));
+        self.bindings
+            .extend(keys.into_iter().map(|key| (key, action)));
+    }
+
+    /// Bind a key to text typed on the command line, and run at once if `run`. The key is
+    /// unbound from any action, or other text.
+    pub fn bind_text(&mut self, key: Key, text: impl Into<String>, run: bool) {
+        self.bindings.retain(|(bound, _)| *bound != key);
+        self.texts.retain(|(bound, ..)| *bound != key);
+        self.texts.push((key, text.into(), run));
+    }
+
+    /// The action bound to a key press.
+    ///
+    /// While `editing` (the command line is not empty, or a command is reading its input), keys
+    /// that type a character are typed rather than triggering their action. This allows binding
+    /// `?` to the help without losing the ability to type it.
+    pub fn action(&self, event: &KeyEvent, editing: bool) -> Option<Action> {
+        let key = Key::from(event);
+        if editing && key.is_printable() {
+            return None;
+        }
+
+        self.bindings
+            .iter()
+            .find(|(bound, _)| *bound == key)
+            .map(|(_, action)| *action)
+    }
+
+    /// The keys bound to an action.
+    pub fn keys(&self, action: Action) -> Vec<Key> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    /// The text bound to a key press, with whether it is run once typed. As with
+    /// [`Keymap::action`], keys that type a character are typed while `editing`.
+    pub fn text(&self, event: &KeyEvent, editing: bool) -> Option<(&str, bool)> {
+        let key = Key::from(event);
+        if editing && key.is_printable() {
+            return None;
+        }
+
+        self.texts
+            .iter()
+            .find(|(bound, ..)| *bound == key)
+            .map(|(_, text, run)| (text.as_str(), *run))
+    }
+
+    /// The keys bound to text, in the order they were bound, with the text and whether it is run.
+    pub fn texts(&self) -> impl Iterator<Item = (Key, &str, bool)> {
+        self.texts
+            .iter()
+            .map(|(key, text, run)| (*key, text.as_str(), *run))
+    }
+}
@@ -0,0 +1,61 @@
+//! The kill ring: the snippets of text cut from the line being typed, or copied from the
+//! history, to insert again later.
+//!
+//! `Ctrl+W` and `Ctrl+U` cut the word, or everything, before the cursor into the ring, and
+//! `Alt+W` copies the output of the last entry of the history. `Ctrl+Y` inserts the last snippet
+//! at the cursor, and `Alt+Y` lists them all, the latest first, to choose one. The ring lives as
+//! long as the shell, and is separate from the clipboard of the system.
+
+use std::collections::VecDeque;
+
+/// The number of snippets kept, past which the oldest are dropped.
+const SIZE: usize = 30;
+
+///
+/// [`Kills`] is the kill ring, the latest snippet first.
+///
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Kills {
+    snippets: VecDeque<String>,
+}
+
+impl Kills {
+    /// Add a snippet, as the latest one. A snippet already in the ring is moved to the front,
+    /// rather than added twice, and blank ones are left out.
+    pub(crate) fn push(&mut self, snippet: String) {
+        if snippet.trim().is_empty() {
+            return;
+        }
+        self.snippets.retain(|kept| *kept != snippet);
+        self.snippets.push_front(snippet);
+        self.snippets.truncate(SIZE);
+    }
+
+    /// The snippet at the index, the latest being `0`.
+    pub(crate) fn get(&self, index: usize) -> Option<&str> {
+        self.snippets.get(index).map(String::as_str)
+    }
+
+    /// The snippets, the latest first.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &str> {
+        self.snippets.iter().map(String::as_str)
+    }
+
+    /// The number of snippets.
+    pub(crate) fn len(&self) -> usize {
+        self.snippets.len()
+    }
+}
+
+impl Extend<String> for Kills {
+    fn extend<T: IntoIterator<Item = String>>(&mut self, snippets: T) {
+        for snippet in snippets {
+            self.push(snippet);
+        }
+    }
+}
+
+/// The text of a snippet as inserted on the line, its lines joined with spaces.
+pub(crate) fn inline(snippet: &str) -> String {
+    snippet.lines().collect::<Vec<_>>().join(" ")
+}
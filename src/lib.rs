@@ -49,6 +49,7 @@
 //!             stdin: Vec::new(),
 //!             stdout: vec![input.command],
 //!             stderr: Vec::new(),
+//!             ..Default::default()
 //!         }))
 //!     }
 //! }
@@ -138,11 +139,38 @@
 //! ## Core Modules
 //!
 //! - [`command`]: Core traits and types for command execution
+//! - [`config`]: Configuration file loading and validation
+//! - [`editor`]: The line typed at the prompt, and the editing of it
+//! - [`history`]: Importing and exporting the history of other shells
+//! - [`i18n`]: Translated messages of the shell
+//! - [`keymap`]: Keybindings of the shell
+//! - [`output`]: Rendering the commands run, and their output, in the history
+//! - [`protocol`]: Structured events for embedding the shell in other front-ends
+//! - [`rank`]: Ranking completions and suggestions by how the commands are used
 //! - [`renderer`]: Terminal UI and application state management
+//! - [`session`]: Detachable sessions, served over a unix socket
+//! - `suggest`: Commands written from a description by a language model (with the `suggest`
+//!   feature)
+//! - `sync`: Syncing the command history across machines (with the `sync` feature)
+//! - [`testing`]: Integration tests of executors against the shell, without a terminal
+//! - [`theme`]: Color themes for the terminal UI
+//! - [`transcript`]: The commands of a session with their output, archived in a file
 //!
 //! ## Features
 //!
 //! - `tokio`: Enables async runtime support (enabled by default)
+//! - `tui`: Enables the terminal UI, and everything around it (enabled by default)
+//! - `script`: Enables user scripts (`init.rhai`), written in Rhai (enabled by default)
+//! - `suggest`: Enables writing commands from a description with a language model (opt-in)
+//! - `sync`: Enables syncing the command history across machines, encrypted (opt-in)
+//!
+//! Without `tui`, only [`command`] and [`protocol`] are left, without depending on `ratatui` or
+//! `crossterm`. This suits applications that bring their own front-end, and only want the
+//! executor framework:
+//!
+//! ```toml
+//! shelgon = { version = "0.2", default-features = false, features = ["tokio"] }
+//! ```
 //!
 //! ## Shell Capabilities
 //!
@@ -160,18 +188,113 @@
 //! ## Key Bindings
 //!
 //! - `Ctrl+L`: Clear screen
-//! - `Ctrl+C/Ctrl+D`: Exit shell
+//! - `Ctrl+C/Ctrl+D`: Exit shell (`Ctrl+C` interrupts the running command)
 //! - `Left/Right`: Move cursor
+//! - `Ctrl+Left/Ctrl+Right`: Move cursor by words
 //! - `Tab`: Command completion
 //! - `Enter`: Execute command or add STDIN line
 //! - `Backspace`: Delete character
+//! - `Ctrl+W`: Delete word
+//! - `Ctrl+Z`: Undo
 //!
 //! ## License
 //!
 //! This project is licensed under the MIT License - see the [LICENSE](./LICENSE) file for details.
 
+#[cfg(feature = "tui")]
+mod arithmetic;
+pub mod assignments;
+#[cfg(feature = "tui")]
+mod autocorrect;
+#[cfg(feature = "tui")]
+mod autosave;
+pub mod braces;
+#[cfg(feature = "tui")]
+mod budget;
 pub mod command;
+#[cfg(feature = "tui")]
+pub mod config;
+#[cfg(all(unix, feature = "tui"))]
+mod control;
+#[cfg(feature = "tui")]
+pub mod editor;
+#[cfg(feature = "tui")]
+mod explain;
+#[cfg(feature = "tui")]
+mod filter;
+#[cfg(feature = "tui")]
+mod fuzzy;
+#[cfg(feature = "tui")]
+mod hints;
+#[cfg(feature = "tui")]
+pub mod history;
+#[cfg(feature = "tui")]
+mod hosts;
+#[cfg(feature = "tui")]
+pub mod i18n;
+#[cfg(feature = "tui")]
+mod integration;
+#[cfg(feature = "tui")]
+mod journal;
+#[cfg(feature = "tui")]
+pub mod keymap;
+#[cfg(feature = "tui")]
+mod kills;
+#[cfg(feature = "tui")]
+mod macros;
+#[cfg(feature = "tui")]
+mod memory;
+#[cfg(feature = "tui")]
+mod migrate;
+#[cfg(feature = "tui")]
+pub mod output;
+#[cfg(feature = "tui")]
+mod pager;
+#[cfg(feature = "tui")]
+mod paste;
+#[cfg(all(unix, feature = "tui"))]
+pub mod process;
+#[cfg(feature = "tui")]
+mod project;
+pub mod protocol;
+#[cfg(feature = "tui")]
+mod ps;
+#[cfg(feature = "tui")]
+pub mod rank;
+#[cfg(feature = "tui")]
 pub mod renderer;
+#[cfg(feature = "tui")]
+pub mod runbook;
+#[cfg(feature = "tui")]
+mod runs;
+#[cfg(feature = "script")]
+mod script;
+#[cfg(all(unix, feature = "tui"))]
+pub mod session;
+#[cfg(feature = "tui")]
+mod setup;
+#[cfg(feature = "tui")]
+mod state;
+#[cfg(feature = "tui")]
+mod substitution;
+#[cfg(feature = "suggest")]
+pub mod suggest;
+#[cfg(feature = "sync")]
+pub mod sync;
+pub mod syntax;
+#[cfg(feature = "tui")]
+mod terminal;
+#[cfg(feature = "tui")]
+pub mod testing;
+#[cfg(feature = "tui")]
+pub mod theme;
+#[cfg(feature = "tui")]
+mod tilde;
+#[cfg(feature = "tui")]
+pub mod transcript;
+#[cfg(feature = "tui")]
+mod usage;
 
 pub use command::*;
+#[cfg(feature = "tui")]
 pub use renderer::App;
@@ -0,0 +1,145 @@
+//! Keyboard macros.
+//!
+//! A macro is a sequence of key presses, recorded while the user works (`F3` starts and stops
+//! the recording) and replayed with `F4`. Recorded macros are saved under a name to
+//! `macros.toml` in the configuration directory, with `:macro save NAME`, and replayed with
+//! `:macro play NAME [COUNT]`:
+//!
+//! ```toml
+//! version = 1
+//!
+//! [macros]
+//! status = ["g", "i", "t", "Space", "s", "t", "a", "t", "u", "s", "Enter"]
+//! ```
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use toml_edit::{Array, DocumentMut};
+
+use crate::config;
+use crate::keymap::Key;
+use crate::migrate;
+
+/// The name of the file holding the macros, in the configuration directory.
+const NAME: &str = "macros.toml";
+
+/// The section of the file holding the macros.
+const SECTION: &str = "macros";
+
+/// The format of the file holding the macros. (See [`crate::migrate`])
+const FORMAT: migrate::Toml = migrate::Toml {
+    name: NAME,
+    migrations: &[],
+};
+
+///
+/// [`Recorder`] records the macro being recorded, and keeps the last one recorded.
+///
+#[derive(Debug, Default)]
+pub(crate) struct Recorder {
+    /// The keys recorded so far, while recording.
+    recording: Option<Vec<Key>>,
+    /// The last macro recorded.
+    last: Vec<Key>,
+    /// Whether a macro is being replayed.
+    playing: bool,
+}
+
+impl Recorder {
+    /// Check if a macro is being recorded.
+    pub(crate) fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Start recording, or stop and keep the recorded macro.
+    pub(crate) fn toggle(&mut self) {
+        match self.recording.take() {
+            Some(keys) => self.last = keys,
+            None => self.recording = Some(Vec::new()),
+        }
+    }
+
+    /// Record a key press, if recording. The keys replayed from a macro are not recorded again.
+    pub(crate) fn record(&mut self, key: Key) {
+        if let (Some(keys), false) = (&mut self.recording, self.playing) {
+            keys.push(key);
+        }
+    }
+
+    /// Start replaying a macro. This returns `false` if a macro is already being replayed.
+    pub(crate) fn play(&mut self) -> bool {
+        !std::mem::replace(&mut self.playing, true)
+    }
+
+    /// Stop replaying the macro.
+    pub(crate) fn finish(&mut self) {
+        self.playing = false;
+    }
+
+    /// The last macro recorded.
+    pub(crate) fn last(&self) -> &[Key] {
+        &self.last
+    }
+}
+
+/// The path of the file holding the macros.
+fn path() -> anyhow::Result<PathBuf> {
+    config::config_dir()
+        .map(|dir| dir.join(NAME))
+        .ok_or_else(|| anyhow::anyhow!("unable to determine the configuration path"))
+}
+
+/// Read the file holding the macros, or an empty document if there is none.
+fn read() -> anyhow::Result<DocumentMut> {
+    FORMAT.read(&path()?)
+}
+
+/// The saved macros, by name. Keys that cannot be read are left out.
+pub(crate) fn load() -> anyhow::Result<BTreeMap<String, Vec<Key>>> {
+    let document = read()?;
+    let Some(table) = document.get(SECTION).and_then(|item| item.as_table()) else {
+        return Ok(BTreeMap::new());
+    };
+
+    Ok(table
+        .iter()
+        .filter_map(|(name, item)| {
+            let keys = item
+                .as_array()?
+                .iter()
+                .filter_map(|key| key.as_str()?.parse().ok())
+                .collect();
+            Some((name.to_string(), keys))
+        })
+        .collect())
+}
+
+/// Save a macro, replacing the one with the same name. This preserves the rest of the file.
+pub(crate) fn save(name: &str, keys: &[Key]) -> anyhow::Result<PathBuf> {
+    let path = path()?;
+    let mut document = read()?;
+    let table = document
+        .entry(SECTION)
+        .or_insert_with(toml_edit::table)
+        .as_table_mut()
+        .ok_or_else(|| anyhow::anyhow!("`{}` is not a table", SECTION))?;
+    table[name] = toml_edit::value(keys.iter().map(|key| key.to_string()).collect::<Array>());
+    FORMAT.write(&path, &mut document)?;
+
+    Ok(path)
+}
+
+/// Delete a saved macro. This returns `false` if there is no such macro.
+pub(crate) fn delete(name: &str) -> anyhow::Result<bool> {
+    let mut document = read()?;
+    let removed = document
+        .get_mut(SECTION)
+        .and_then(|item| item.as_table_mut())
+        .and_then(|table| table.remove(name))
+        .is_some();
+    if removed {
+        FORMAT.write(&path()?, &mut document)?;
+    }
+
+    Ok(removed)
+}
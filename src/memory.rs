@@ -0,0 +1,451 @@
+//! Keeping the output held by the history within a memory budget.
+//!
+//! The [`Scrollback`] holds the entries of the history, and accounts for the bytes their text
+//! takes. Past the budget (`[history] memory_budget`, in MiB), the output of the oldest entries
+//! is compressed in memory, oldest first, until the history fits again; the prompt and the command
+//! of every entry are kept as they are, as they are needed all the time. The newest entry is never
+//! compressed.
+//!
+//! When compressing is not enough, the oldest entries are spilled whole to a segment file, in
+//! the data directory, so the memory the history takes stays bounded however long the session
+//! runs. Only where each entry lies in the file is kept in memory: the file is its index. The file
+//! only lives as long as the session, and is never read by another one.
+//!
+//! A compressed or spilled entry is only expanded when it is needed: drawn on the screen, exported
+//! to another front-end, or returned by a test. Expanding it does not keep it expanded, and the
+//! renderer keeps the lines drawn from it anyway. (See `crate::output`)
+
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::command::CommandOutput;
+use crate::config;
+
+/// The number of bytes in a MiB, the unit of the budget.
+const MIB: usize = 1024 * 1024;
+
+/// The budget of the history in bytes, as configured.
+pub(crate) fn budget(config: &config::History) -> Option<usize> {
+    config.memory_budget.map(|mib| mib.saturating_mul(MIB))
+}
+
+///
+/// [`Scrollback`] is the history of the session, within a memory budget.
+///
+#[derive(Default)]
+pub(crate) struct Scrollback {
+    /// The entries, oldest first.
+    entries: Vec<Held>,
+    /// The bytes held by the entries: the text of the plain ones, and the compressed output of
+    /// the others.
+    bytes: usize,
+    /// The bytes the entries may hold before their output is compressed, if limited.
+    budget: Option<usize>,
+    /// The first entry not yet considered for compression. The ones before it are compressed,
+    /// or were not worth it.
+    next: usize,
+    /// The first entry not spilled to disk.
+    spilled: usize,
+    /// The file the entries are spilled to, once one is.
+    segment: Option<Segment>,
+    /// Whether entries may still be spilled: not once the segment file failed to be written.
+    spill: bool,
+}
+
+/// An entry of the history, as it is held.
+enum Held {
+    /// The entry, as it was recorded.
+    Plain(CommandOutput),
+    /// The entry, with its output compressed.
+    Compressed(Compressed),
+    /// The entry, spilled to the segment file.
+    Spilled(Spilled),
+}
+
+/// An entry of the history with its input, output and error output compressed together.
+struct Compressed {
+    /// The prompt that was displayed.
+    prompt: String,
+    /// The command that was executed.
+    command: String,
+    /// Whether the command failed. (See [`CommandOutput::failed`])
+    failed: bool,
+    /// The exit code of the command, if known.
+    exit_code: Option<i32>,
+    /// How long the command took to run.
+    duration: Duration,
+    /// The input, output and error output, encoded with MessagePack and compressed with LZ4.
+    data: Vec<u8>,
+}
+
+/// Where an entry lies in the segment file.
+#[derive(Debug, Clone, Copy)]
+struct Spilled {
+    /// The offset of the entry in the file.
+    offset: u64,
+    /// The length of the entry, encoded with MessagePack and compressed with LZ4.
+    len: usize,
+    /// Whether the command failed. (See [`CommandOutput::failed`])
+    failed: bool,
+}
+
+///
+/// [`Segment`] is the file the entries of the history are spilled to, appended to as they are.
+/// It is removed when dropped, along with the history.
+///
+struct Segment {
+    /// The path of the file.
+    path: PathBuf,
+    /// The file, open for reading and writing.
+    file: File,
+    /// The length of the file.
+    end: u64,
+}
+
+impl Scrollback {
+    /// Create an empty history, holding at most `budget` bytes before compressing.
+    pub(crate) fn new(budget: Option<usize>) -> Self {
+        Self {
+            budget,
+            spill: true,
+            ..Self::default()
+        }
+    }
+
+    /// Create a history of the entries.
+    pub(crate) fn with_entries(budget: Option<usize>, entries: Vec<CommandOutput>) -> Self {
+        let mut scrollback = Self::new(budget);
+        for entry in entries {
+            scrollback.push(entry);
+        }
+        scrollback
+    }
+
+    /// The number of entries.
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The bytes held by the entries.
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// The number of entries with their output compressed.
+    pub(crate) fn compressed(&self) -> usize {
+        let compressed = self.entries.iter().filter(|held| match held {
+            Held::Compressed(_) => true,
+            Held::Plain(_) | Held::Spilled(_) => false,
+        });
+        compressed.count()
+    }
+
+    /// The number of entries spilled to disk.
+    pub(crate) fn spilled(&self) -> usize {
+        let spilled = self.entries.iter().filter(|held| match held {
+            Held::Spilled(_) => true,
+            Held::Plain(_) | Held::Compressed(_) => false,
+        });
+        spilled.count()
+    }
+
+    /// Add an entry, compressing older ones if the history goes past the budget.
+    pub(crate) fn push(&mut self, entry: CommandOutput) {
+        self.bytes += size(&entry);
+        self.entries.push(Held::Plain(entry));
+        self.compress();
+    }
+
+    /// Add lines to the output of the newest entry, as they come in. (See `crate::renderer`)
+    pub(crate) fn extend_last(&mut self, lines: impl IntoIterator<Item = String>) {
+        // The newest entry is never compressed, nor spilled.
+        if let Some(Held::Plain(entry)) = self.entries.last_mut() {
+            for line in lines {
+                self.bytes += line.len();
+                entry.stdout.push(line);
+            }
+        }
+        self.compress();
+    }
+
+    /// Drop the `count` oldest entries.
+    pub(crate) fn drop_oldest(&mut self, count: usize) {
+        let count = count.min(self.entries.len());
+        let dropped = self.entries.drain(..count).map(|held| held.bytes());
+        self.bytes -= dropped.sum::<usize>();
+        self.next = self.next.saturating_sub(count);
+        self.spilled = self.spilled.saturating_sub(count);
+    }
+
+    /// Drop every entry, and the segment file.
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+        self.next = 0;
+        self.spilled = 0;
+        self.segment = None;
+    }
+
+    /// The entry at the index, expanded.
+    pub(crate) fn get(&self, index: usize) -> Option<Cow<'_, CommandOutput>> {
+        self.entries.get(index).map(|held| self.expand(held))
+    }
+
+    /// The entries, oldest first, expanded as they are reached.
+    pub(crate) fn iter(&self) -> impl DoubleEndedIterator<Item = Cow<'_, CommandOutput>> {
+        self.entries.iter().map(|held| self.expand(held))
+    }
+
+    /// The command of the entry, only reading it back if it is spilled.
+    pub(crate) fn command(&self, index: usize) -> Option<Cow<'_, str>> {
+        self.entries.get(index).map(|held| self.command_of(held))
+    }
+
+    /// The commands of the entries, oldest first, only reading the spilled ones back.
+    pub(crate) fn commands(&self) -> impl DoubleEndedIterator<Item = Cow<'_, str>> {
+        self.entries.iter().map(|held| self.command_of(held))
+    }
+
+    /// Check whether the command of the entry failed, without expanding it. (See
+    /// [`CommandOutput::failed`])
+    pub(crate) fn failed(&self, index: usize) -> bool {
+        self.entries.get(index).is_some_and(Held::failed)
+    }
+
+    /// Check whether the command of any of the `count` newest entries failed, without expanding
+    /// any.
+    pub(crate) fn failed_among_last(&self, count: usize) -> bool {
+        self.entries.iter().rev().take(count).any(Held::failed)
+    }
+
+    /// Every entry, expanded.
+    pub(crate) fn into_entries(self) -> Vec<CommandOutput> {
+        let entries = self.entries.iter();
+        entries.map(|held| self.expand(held).into_owned()).collect()
+    }
+
+    /// The command of the entry, read back if it is spilled.
+    fn command_of<'a>(&self, held: &'a Held) -> Cow<'a, str> {
+        match held {
+            Held::Plain(entry) => Cow::Borrowed(entry.command.as_str()),
+            Held::Compressed(compressed) => Cow::Borrowed(compressed.command.as_str()),
+            Held::Spilled(spilled) => Cow::Owned(self.load(spilled).command),
+        }
+    }
+
+    /// The entry, expanded if it is compressed, or read back if it is spilled.
+    fn expand<'a>(&self, held: &'a Held) -> Cow<'a, CommandOutput> {
+        match held {
+            Held::Plain(entry) => Cow::Borrowed(entry),
+            Held::Compressed(compressed) => Cow::Owned(compressed.expand()),
+            Held::Spilled(spilled) => Cow::Owned(self.load(spilled)),
+        }
+    }
+
+    /// Read a spilled entry back.
+    fn load(&self, spilled: &Spilled) -> CommandOutput {
+        let loaded = match &self.segment {
+            Some(segment) => segment.read(spilled.offset, spilled.len),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "no segment file")),
+        };
+        let decoded = loaded
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(lz4_flex::decompress_size_prepended(&data)?))
+            .and_then(|encoded| Ok(rmp_serde::from_slice(&encoded)?));
+        // The segment file belongs to the session: failing to read it back (it was removed, or
+        // the disk failed) shows the entry as lost, rather than bringing the shell down.
+        let (prompt, command, stdin, stdout, stderr, exit_code, duration) =
+            decoded.unwrap_or_else(|e| {
+                tracing::error!("failed to read an entry of the history back: {:#}", e);
+                Default::default()
+            });
+        CommandOutput {
+            prompt,
+            command,
+            stdin,
+            stdout,
+            stderr,
+            exit_code,
+            duration,
+        }
+    }
+
+    /// Compress the output of the oldest entries not compressed yet, and then spill the oldest
+    /// entries not spilled yet, but the newest one, until the history fits in the budget.
+    fn compress(&mut self) {
+        let Some(budget) = self.budget else {
+            return;
+        };
+        while self.bytes > budget && self.next + 1 < self.entries.len() {
+            let held = &mut self.entries[self.next];
+            self.next += 1;
+            let Held::Plain(entry) = held else {
+                continue;
+            };
+            let Some(compressed) = Compressed::new(entry) else {
+                continue;
+            };
+            let before = held.bytes();
+            *held = Held::Compressed(compressed);
+            self.bytes = self.bytes - before + held.bytes();
+        }
+        while self.spill && self.bytes > budget && self.spilled + 1 < self.entries.len() {
+            if let Err(e) = self.spill_oldest() {
+                tracing::warn!("failed to spill the history to disk: {:#}", e);
+                self.spill = false;
+            }
+        }
+    }
+
+    /// Spill the oldest entry not spilled yet to the segment file.
+    fn spill_oldest(&mut self) -> anyhow::Result<()> {
+        let entry = self.expand(&self.entries[self.spilled]);
+        let fields = (
+            &entry.prompt,
+            &entry.command,
+            &entry.stdin,
+            &entry.stdout,
+            &entry.stderr,
+            entry.exit_code,
+            entry.duration,
+        );
+        let data = lz4_flex::compress_prepend_size(&rmp_serde::to_vec(&fields)?);
+        let failed = entry.failed();
+        drop(entry);
+
+        let segment = match &mut self.segment {
+            Some(segment) => segment,
+            None => self.segment.insert(Segment::create()?),
+        };
+        let offset = segment.append(&data)?;
+        self.bytes -= self.entries[self.spilled].bytes();
+        self.entries[self.spilled] = Held::Spilled(Spilled {
+            offset,
+            len: data.len(),
+            failed,
+        });
+        self.spilled += 1;
+        self.next = self.next.max(self.spilled);
+
+        Ok(())
+    }
+}
+
+impl Held {
+    /// The bytes held by the entry in memory.
+    fn bytes(&self) -> usize {
+        match self {
+            Held::Plain(entry) => size(entry),
+            Held::Compressed(compressed) => {
+                compressed.prompt.len() + compressed.command.len() + compressed.data.len()
+            }
+            Held::Spilled(_) => 0,
+        }
+    }
+
+    /// Check whether the command of the entry failed.
+    fn failed(&self) -> bool {
+        match self {
+            Held::Plain(entry) => entry.failed(),
+            Held::Compressed(compressed) => compressed.failed,
+            Held::Spilled(spilled) => spilled.failed,
+        }
+    }
+}
+
+impl Segment {
+    /// Create a segment file for the session, in the data directory.
+    fn create() -> anyhow::Result<Self> {
+        let dir = config::data_dir()
+            .ok_or_else(|| anyhow::anyhow!("no data directory"))?
+            .join("spill");
+        fs::create_dir_all(&dir)?;
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.subsec_nanos());
+        let path = dir.join(format!("{}-{}.seg", std::process::id(), nanos));
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+
+        Ok(Self { path, file, end: 0 })
+    }
+
+    /// Append the data to the file, and return the offset it lies at.
+    fn append(&mut self, data: &[u8]) -> io::Result<u64> {
+        let offset = self.end;
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(data)?;
+        self.end += data.len() as u64;
+        Ok(offset)
+    }
+
+    /// Read `len` bytes back from the offset.
+    fn read(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut data = vec![0; len];
+        file.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl Drop for Segment {
+    fn drop(&mut self) {
+        // The file is of no use once the session is gone; failing to remove it leaves a file in
+        // the data directory, and nothing else.
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl Compressed {
+    /// Compress the output of the entry, unless it does not make it smaller.
+    fn new(entry: &CommandOutput) -> Option<Self> {
+        let streams = (&entry.stdin, &entry.stdout, &entry.stderr);
+        let encoded = rmp_serde::to_vec(&streams).ok()?;
+        let data = lz4_flex::compress_prepend_size(&encoded);
+        let text = size(entry) - entry.prompt.len() - entry.command.len();
+        (data.len() < text).then(|| Self {
+            prompt: entry.prompt.clone(),
+            command: entry.command.clone(),
+            failed: entry.failed(),
+            exit_code: entry.exit_code,
+            duration: entry.duration,
+            data,
+        })
+    }
+
+    /// The entry, with its output decompressed.
+    fn expand(&self) -> CommandOutput {
+        let decoded = lz4_flex::decompress_size_prepended(&self.data)
+            .map_err(anyhow::Error::from)
+            .and_then(|encoded| Ok(rmp_serde::from_slice(&encoded)?));
+        // The data was compressed by the shell itself: failing to expand it is a bug, and the
+        // entry is shown without its output rather than bringing the shell down.
+        let (stdin, stdout, stderr) = decoded.unwrap_or_else(|e| {
+            tracing::error!("failed to expand an entry of the history: {:#}", e);
+            Default::default()
+        });
+        CommandOutput {
+            prompt: self.prompt.clone(),
+            command: self.command.clone(),
+            stdin,
+            stdout,
+            stderr,
+            exit_code: self.exit_code,
+            duration: self.duration,
+        }
+    }
+}
+
+/// The bytes taken by the text of the entry.
+fn size(entry: &CommandOutput) -> usize {
+    let streams = [&entry.stdin, &entry.stdout, &entry.stderr];
+    let lines = streams.into_iter().flatten().map(String::len);
+    entry.prompt.len() + entry.command.len() + lines.sum::<usize>()
+}
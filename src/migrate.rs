@@ -0,0 +1,328 @@
+//! The versions of the files the shell keeps, and the migrations between them.
+//!
+//! Every file the shell writes for itself records the version of its format: the JSON files in the
+//! data directory are wrapped as `{"version": N, "data": ...}`, `macros.toml` has a top-level
+//! `version` key, the history database keeps it in `PRAGMA user_version`, and transcripts in their
+//! header. Files written before versions were recorded are of version [`FIRST`].
+//!
+//! When the format of a file changes, its version is bumped by appending a migration from the
+//! previous version to the list of its migrations. A file of an older version is upgraded as it is
+//! read, running the migrations it missed in order, and written back: the JSON and TOML files keep
+//! the original next to them, as `NAME.vN`. A file of a newer version than the shell knows is
+//! refused, rather than read wrong or overwritten.
+
+use std::{fs, path::Path};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use toml_edit::DocumentMut;
+
+/// The version of the files written before versions were recorded.
+pub(crate) const FIRST: u32 = 1;
+
+/// The key holding the version, in the JSON envelope and at the top of TOML files.
+const VERSION: &str = "version";
+
+/// The key holding the data, in the JSON envelope.
+const DATA: &str = "data";
+
+/// A migration of data from a version of its format to the next one.
+pub(crate) type Migration<V> = fn(&mut V) -> anyhow::Result<()>;
+
+/// The version written by the shell, with the migrations of a format.
+pub(crate) const fn current<S>(migrations: &[S]) -> u32 {
+    FIRST + migrations.len() as u32
+}
+
+/// Check that data of a version can be upgraded to the current one. This returns the number of
+/// migrations it missed.
+pub(crate) fn check<S>(name: &str, version: u32, migrations: &[S]) -> anyhow::Result<usize> {
+    let current = current(migrations);
+    if version > current {
+        anyhow::bail!(
+            "{} is of version {}, newer than this shell reads ({})",
+            name,
+            version,
+            current
+        );
+    }
+    version
+        .checked_sub(FIRST)
+        .map(|done| (current - FIRST - done) as usize)
+        .ok_or_else(|| anyhow::anyhow!("{} is of an unknown version {}", name, version))
+}
+
+/// Upgrade data of a version to the current one, applying the migrations it missed in order. This
+/// returns whether any were applied.
+pub(crate) fn upgrade<S>(
+    name: &str,
+    version: u32,
+    migrations: &[S],
+    mut apply: impl FnMut(&S) -> anyhow::Result<()>,
+) -> anyhow::Result<bool> {
+    let missed = check(name, version, migrations)?;
+
+    let pending = &migrations[migrations.len() - missed..];
+    for (step, migration) in (version..).zip(pending) {
+        apply(migration)
+            .map_err(|e| anyhow::anyhow!("{}: upgrading from version {}: {}", name, step, e))?;
+    }
+    Ok(missed > 0)
+}
+
+///
+/// [`Json`] is the format of a JSON file of the shell, with its migrations.
+///
+pub(crate) struct Json {
+    /// The name of the file, in messages and in the name of the copy of an upgraded file.
+    pub(crate) name: &'static str,
+    /// The migrations of the data, from the version [`FIRST`] on.
+    pub(crate) migrations: &'static [Migration<Value>],
+}
+
+impl Json {
+    /// Read the file, upgrading it if it is of an older version. This returns `None` if the file
+    /// does not exist.
+    pub(crate) fn read<T: DeserializeOwned>(&self, path: &Path) -> anyhow::Result<Option<T>> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let (version, mut data) = match serde_json::from_str(&source)? {
+            Value::Object(mut envelope) if envelope.len() == 2 && envelope.contains_key(DATA) => {
+                let version = envelope
+                    .get(VERSION)
+                    .and_then(Value::as_u64)
+                    .and_then(|version| u32::try_from(version).ok())
+                    .ok_or_else(|| anyhow::anyhow!("{} has no valid version", self.name))?;
+                (version, envelope.remove(DATA).unwrap_or_default())
+            }
+            data => (FIRST, data),
+        };
+
+        if upgrade(self.name, version, self.migrations, |migration| {
+            migration(&mut data)
+        })? {
+            keep(path, self.name, version)?;
+            self.store(path, &data)?;
+        }
+        Ok(Some(serde_json::from_value(data)?))
+    }
+
+    /// Write the file, in the current version.
+    pub(crate) fn write<T: Serialize>(&self, path: &Path, data: &T) -> anyhow::Result<()> {
+        self.store(path, &serde_json::to_value(data)?)
+    }
+
+    /// Write the data, wrapped with the current version.
+    fn store(&self, path: &Path, data: &Value) -> anyhow::Result<()> {
+        let envelope = serde_json::json!({
+            VERSION: current(self.migrations),
+            DATA: data,
+        });
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(&envelope)?)?;
+        Ok(())
+    }
+}
+
+///
+/// [`Toml`] is the format of a TOML file of the shell, with its migrations.
+///
+pub(crate) struct Toml {
+    /// The name of the file, in messages and in the name of the copy of an upgraded file.
+    pub(crate) name: &'static str,
+    /// The migrations of the document, from the version [`FIRST`] on.
+    pub(crate) migrations: &'static [Migration<DocumentMut>],
+}
+
+impl Toml {
+    /// Read the file, upgrading it if it is of an older version. This returns an empty document if
+    /// the file does not exist.
+    pub(crate) fn read(&self, path: &Path) -> anyhow::Result<DocumentMut> {
+        let mut document: DocumentMut = match fs::read_to_string(path) {
+            Ok(source) => source.parse()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(DocumentMut::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let version = match document.get(VERSION) {
+            None => FIRST,
+            Some(item) => item
+                .as_integer()
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or_else(|| anyhow::anyhow!("{} has no valid version", self.name))?,
+        };
+
+        if upgrade(self.name, version, self.migrations, |migration| {
+            migration(&mut document)
+        })? {
+            keep(path, self.name, version)?;
+            self.write(path, &mut document)?;
+        }
+        Ok(document)
+    }
+
+    /// Write the document, in the current version.
+    pub(crate) fn write(&self, path: &Path, document: &mut DocumentMut) -> anyhow::Result<()> {
+        document[VERSION] = toml_edit::value(i64::from(current(self.migrations)));
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, document.to_string())?;
+        Ok(())
+    }
+}
+
+/// Keep a copy of a file of an older version next to it, before it is upgraded.
+fn keep(path: &Path, name: &str, version: u32) -> anyhow::Result<()> {
+    fs::copy(path, path.with_file_name(format!("{}.v{}", name, version)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture;
+
+    /// 1 to 2: the count of a command becomes an object, with room for more.
+    fn counted(data: &mut Value) -> anyhow::Result<()> {
+        for commands in data
+            .as_object_mut()
+            .into_iter()
+            .flat_map(|d| d.values_mut())
+        {
+            for usage in commands
+                .as_object_mut()
+                .into_iter()
+                .flat_map(|c| c.values_mut())
+            {
+                *usage = serde_json::json!({ "count": usage.take() });
+            }
+        }
+        Ok(())
+    }
+
+    /// 1 to 2: every macro is bound to no key, unless it was.
+    fn unbound(document: &mut DocumentMut) -> anyhow::Result<()> {
+        for (_, table) in document.iter_mut() {
+            if let Some(table) = table.as_table_mut() {
+                table.entry("key").or_insert(toml_edit::value(""));
+            }
+        }
+        Ok(())
+    }
+
+    const JSON: Json = Json {
+        name: "directories.json",
+        migrations: &[counted],
+    };
+
+    const TOML: Toml = Toml {
+        name: "macros.toml",
+        migrations: &[unbound],
+    };
+
+    #[test]
+    fn check_counts_the_missed_migrations() -> anyhow::Result<()> {
+        assert_eq!(check("test", 1, &[(), ()])?, 2);
+        assert_eq!(check("test", 3, &[(), ()])?, 0);
+        assert!(check("test", 4, &[(), ()]).is_err());
+        assert!(check("test", 0, &[(), ()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn upgrade_applies_the_missed_migrations_in_order() -> anyhow::Result<()> {
+        let mut applied = Vec::new();
+        let upgraded = upgrade("test", 2, &[1, 2, 3, 4], |step| {
+            applied.push(*step);
+            Ok(())
+        })?;
+        assert!(upgraded);
+        assert_eq!(applied, [2, 3, 4]);
+
+        assert!(!upgrade("test", 5, &[1, 2, 3, 4], |_| Ok(()))?);
+        Ok(())
+    }
+
+    #[test]
+    fn json_of_the_first_version_is_upgraded_and_kept() -> anyhow::Result<()> {
+        let path = fixture(
+            "migrate-json-upgrade",
+            "directories-v1.json",
+            "directories.json",
+        )?;
+        let original = fs::read_to_string(&path)?;
+
+        let data: Value = JSON.read(&path)?.unwrap_or_default();
+        assert_eq!(
+            data,
+            serde_json::json!({"/src": {"cargo build": {"count": 3}}})
+        );
+
+        let written: Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+        assert_eq!(written[VERSION], 2);
+        assert_eq!(written[DATA], data);
+        assert_eq!(
+            fs::read_to_string(path.with_file_name("directories.json.v1"))?,
+            original
+        );
+
+        // Read again, it is already of the current version and is left as it is.
+        fs::remove_file(path.with_file_name("directories.json.v1"))?;
+        let again: Value = JSON.read(&path)?.unwrap_or_default();
+        assert_eq!(again, data);
+        assert!(!path.with_file_name("directories.json.v1").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn json_of_a_newer_version_is_refused() -> anyhow::Result<()> {
+        let path = fixture(
+            "migrate-json-newer",
+            "directories-v9.json",
+            "directories.json",
+        )?;
+        let original = fs::read_to_string(&path)?;
+
+        assert!(JSON.read::<Value>(&path).is_err());
+        assert_eq!(fs::read_to_string(&path)?, original);
+        assert!(!path.with_file_name("directories.json.v9").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn toml_of_the_first_version_is_upgraded_and_kept() -> anyhow::Result<()> {
+        let path = fixture("migrate-toml-upgrade", "macros-v1.toml", "macros.toml")?;
+        let original = fs::read_to_string(&path)?;
+
+        let document = TOML.read(&path)?;
+        assert_eq!(document["greet"]["command"].as_str(), Some("echo hello"));
+        assert_eq!(document["greet"]["key"].as_str(), Some(""));
+
+        let written: DocumentMut = fs::read_to_string(&path)?.parse()?;
+        assert_eq!(written[VERSION].as_integer(), Some(2));
+        assert_eq!(
+            fs::read_to_string(path.with_file_name("macros.toml.v1"))?,
+            original
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn toml_of_a_newer_version_is_refused() -> anyhow::Result<()> {
+        let path = fixture("migrate-toml-newer", "macros-v1.toml", "macros.toml")?;
+        fs::write(&path, "version = 3\n")?;
+
+        assert!(TOML.read(&path).is_err());
+        assert_eq!(fs::read_to_string(&path)?, "version = 3\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,693 @@
+//! Rendering the commands run, and their output, in the history.
+//!
+//! Every entry of the history is drawn by a [`HistoryEntryWidget`]: the prompt and the command,
+//! with how long it took after it and the prompt in red if it exited with a code other than 0,
+//! the command it re-runs with changes, if any (see `:fc`), the input supplied to it, its output,
+//! its error output, and the note attached to it, if any (see `:note`). The output goes through
+//! the first [`OutputRenderer`] that recognizes it, so structured output is shown for what it is:
+//!
+//! - [`Image`]: images sent as a `data:image/...;base64,...` URI, shown as a placeholder, as the
+//!   cells of the screen cannot hold pixels
+//! - [`Json`]: a JSON object or array, pretty-printed with the keys highlighted
+//! - [`Ansi`]: output colored with ANSI escape sequences, as programs write to a terminal, in its
+//!   colors (see `force_color` in [`crate::config::ChildEnv`])
+//! - [`Hex`]: binary output, with control characters or bytes that are not UTF-8, as a hex dump
+//! - [`Table`]: tab-separated columns, aligned, with the first row as the header
+//! - [`Text`]: anything else, line by line
+//!
+//! Other renderers are plugged in with [`crate::renderer::App::with_output_renderer`], and are
+//! tried before the built-in ones.
+//!
+//! Rendering an entry styles every line of its output, and wrapping it measures every character:
+//! too much to do for the whole history on every key press. The shell keeps the wrapped lines of
+//! each entry instead, for the width of the terminal and the theme they were drawn with, and only
+//! draws them again when either changes.
+//!
+//! # Example
+//!
+//! ```rust
+//! use ratatui::text::Line;
+//! use shelgon::output::OutputRenderer;
+//! use shelgon::theme::Theme;
+//!
+//! /// Render the output of `ls -l` with the directories highlighted.
+//! struct Listing;
+//!
+//! impl OutputRenderer for Listing {
+//!     fn render<'a>(&self, stdout: &'a [String], theme: &Theme) -> Option<Vec<Line<'a>>> {
+//!         stdout.first()?.starts_with("total ").then(|| {
+//!             let style = |line: &str| match line.starts_with('d') {
+//!                 true => theme.command,
+//!                 false => theme.stdout,
+//!             };
+//!             stdout.iter().map(|line| Line::styled(line.as_str(), style(line))).collect()
+//!         })
+//!     }
+//! }
+//! ```
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Paragraph, StatefulWidget, Widget, Wrap},
+};
+use unicode_width::UnicodeWidthChar;
+
+use crate::budget;
+use crate::command::CommandOutput;
+use crate::theme::Theme;
+
+/// The number of rows of a hex dump, past which the rest of the output is left out.
+const HEX_ROWS: usize = 64;
+
+/// The number of bytes on every row of a hex dump.
+const HEX_WIDTH: usize = 16;
+
+///
+/// [`OutputRenderer`] draws the output of a command of a kind it recognizes.
+///
+pub trait OutputRenderer {
+    /// Render the output, line by line, or return `None` if it is not of the kind rendered.
+    fn render<'a>(&self, stdout: &'a [String], theme: &Theme) -> Option<Vec<Line<'a>>>;
+}
+
+/// The built-in renderers, in the order they are tried. [`Text`] comes last, as it renders
+/// anything.
+pub fn builtin() -> Vec<Box<dyn OutputRenderer>> {
+    vec![
+        Box::new(Image),
+        Box::new(Json),
+        Box::new(Ansi),
+        Box::new(Hex),
+        Box::new(Table),
+        Box::new(Text),
+    ]
+}
+
+///
+/// [`HistoryEntryWidget`] draws an entry of the history: the command, its input, its output, its
+/// error output, its warnings and its note, starred or not. A comment is drawn alone, dimmed.
+///
+pub struct HistoryEntryWidget<'a> {
+    entry: &'a CommandOutput,
+    theme: &'a Theme,
+    renderers: &'a [Box<dyn OutputRenderer>],
+    note: Option<&'a str>,
+    starred: bool,
+    comment: bool,
+    fixes: Option<&'a str>,
+    warnings: &'a [String],
+}
+
+///
+/// [`HistoryEntryState`] is the part of an entry scrolled past, when it is drawn as a
+/// [`StatefulWidget`].
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HistoryEntryState {
+    /// The number of rows scrolled past.
+    pub offset: u16,
+}
+
+impl<'a> HistoryEntryWidget<'a> {
+    /// Create a widget drawing the entry with the theme, and the output as text.
+    pub fn new(entry: &'a CommandOutput, theme: &'a Theme) -> Self {
+        Self {
+            entry,
+            theme,
+            renderers: &[],
+            note: None,
+            starred: false,
+            comment: false,
+            fixes: None,
+            warnings: &[],
+        }
+    }
+
+    /// Render the output with the first of the renderers recognizing it, or else as text.
+    pub fn renderers(mut self, renderers: &'a [Box<dyn OutputRenderer>]) -> Self {
+        self.renderers = renderers;
+        self
+    }
+
+    /// Draw a note under the entry, in italics.
+    pub fn note(mut self, note: Option<&'a str>) -> Self {
+        self.note = note;
+        self
+    }
+
+    /// Mark the entry as starred, with a star before the prompt.
+    pub fn starred(mut self, starred: bool) -> Self {
+        self.starred = starred;
+        self
+    }
+
+    /// Draw the entry as a comment: the line typed, in place of the prompt and command, dimmed
+    /// and in italics.
+    pub fn comment(mut self, comment: bool) -> Self {
+        self.comment = comment;
+        self
+    }
+
+    /// Draw the command the entry re-runs with changes under its own.
+    pub fn fixes(mut self, fixes: Option<&'a str>) -> Self {
+        self.fixes = fixes;
+        self
+    }
+
+    /// Draw warnings under the output of the entry, such as for a command over its budget.
+    pub fn warnings(mut self, warnings: &'a [String]) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    /// The lines of the entry.
+    pub fn lines(&self) -> Vec<Line<'a>> {
+        let (entry, theme) = (self.entry, self.theme);
+        let star = self.starred.then(|| Span::styled("★ ", theme.prompt));
+        let command = match self.comment {
+            true => Line::from_iter(star.into_iter().chain([Span::styled(
+                entry.command.as_str(),
+                theme.hint.add_modifier(Modifier::ITALIC),
+            )])),
+            false => {
+                let prompt = match entry.exit_code {
+                    Some(0) | None => theme.prompt,
+                    Some(_) => theme.error,
+                };
+                let took = (!entry.duration.is_zero())
+                    .then(|| Span::styled(format!("  {}", took(entry.duration)), theme.hint));
+                Line::from_iter(
+                    star.into_iter()
+                        .chain([
+                            Span::styled(entry.prompt.as_str(), prompt),
+                            Span::raw(" "),
+                            Span::styled(entry.command.as_str(), theme.command),
+                        ])
+                        .chain(took),
+                )
+            }
+        };
+        let stdin = entry
+            .stdin
+            .iter()
+            .map(|line| Line::styled(line.as_str(), theme.stdin));
+        let stdout = self
+            .renderers
+            .iter()
+            .find_map(|renderer| renderer.render(&entry.stdout, theme))
+            .or_else(|| Text.render(&entry.stdout, theme))
+            .unwrap_or_default();
+        let stderr = entry.stderr.iter().map(|line| colored(line, theme.stderr));
+
+        let mut lines = vec![command];
+        lines.extend(
+            self.fixes
+                .map(|fixes| Line::styled(format!("↻ {}", fixes), theme.hint)),
+        );
+        lines.extend(stdin);
+        lines.extend(stdout);
+        lines.extend(stderr);
+        lines.extend(
+            self.warnings
+                .iter()
+                .map(|warning| Line::styled(format!("⚠ {}", warning), theme.error)),
+        );
+        lines.extend(self.note.map(|note| {
+            Line::styled(
+                format!("✎ {}", note),
+                theme.hint.add_modifier(Modifier::ITALIC),
+            )
+        }));
+
+        lines
+    }
+
+    /// The lines of the entry, wrapped to the width, as they are drawn in the shell: lines are
+    /// broken after the last whitespace that fits, or in the middle of a word longer than the
+    /// width.
+    pub fn wrapped(&self, width: u16) -> Vec<Line<'static>> {
+        self.lines()
+            .iter()
+            .flat_map(|line| wrap(line, usize::from(width)))
+            .collect()
+    }
+}
+
+///
+/// [`RenderCache`] holds the wrapped lines of the entries of the history, so they are only
+/// rendered again when the width of the terminal or the theme changes. Entries are told apart by
+/// their id, the number of entries added before them, so the cache outlives the history limit
+/// dropping the oldest entries, or the history being cleared.
+///
+#[derive(Debug, Default)]
+pub(crate) struct RenderCache {
+    /// The width the lines are wrapped to.
+    width: u16,
+    /// The generation of the theme the lines are styled with.
+    generation: u64,
+    /// The wrapped lines of each entry, by id.
+    entries: HashMap<usize, Vec<Line<'static>>>,
+}
+
+impl RenderCache {
+    /// Drop the lines wrapped to another width or styled with another theme, and the ones of
+    /// the entries before `first`, gone from the history.
+    pub(crate) fn prepare(&mut self, width: u16, generation: u64, first: usize) {
+        if (width, generation) != (self.width, self.generation) {
+            self.entries.clear();
+            (self.width, self.generation) = (width, generation);
+        }
+        self.entries.retain(|&id, _| id >= first);
+    }
+
+    /// Drop the lines of an entry, drawn again the next time they are needed.
+    pub(crate) fn forget(&mut self, id: usize) {
+        self.entries.remove(&id);
+    }
+
+    /// The wrapped lines of the entry, drawn by `wrapped` for the width unless they are kept
+    /// already.
+    pub(crate) fn lines(
+        &mut self,
+        id: usize,
+        wrapped: impl FnOnce(u16) -> Vec<Line<'static>>,
+    ) -> &[Line<'static>] {
+        let width = self.width;
+        self.entries.entry(id).or_insert_with(|| wrapped(width))
+    }
+}
+
+/// A line colored with ANSI escape sequences, in the style given where it sets none. The escape
+/// sequences other than the ones setting colors and attributes (SGR) are left out.
+pub(crate) fn colored(line: &str, base: Style) -> Line<'_> {
+    let mut spans = Vec::new();
+    let mut style = base;
+    let mut rest = line;
+    while let Some(start) = rest.find('\x1b') {
+        if start > 0 {
+            spans.push(Span::styled(&rest[..start], style));
+        }
+        let (sequence, after) = escape(&rest[start..]);
+        if let Some(codes) = sequence
+            .strip_prefix("\x1b[")
+            .and_then(|codes| codes.strip_suffix('m'))
+        {
+            style = sgr(codes, style, base);
+        }
+        rest = after;
+    }
+    if !rest.is_empty() {
+        spans.push(Span::styled(rest, style));
+    }
+    Line::from(spans)
+}
+
+/// A line with its ANSI escape sequences left out.
+pub(crate) fn strip(line: &str) -> Cow<'_, str> {
+    match line.contains('\x1b') {
+        true => Cow::Owned(colored(line, Style::default()).to_string()),
+        false => Cow::Borrowed(line),
+    }
+}
+
+/// Split the escape sequence the text starts with off it: a control sequence (`ESC [`, up to its
+/// final character), an operating system command (`ESC ]`, up to `BEL` or `ESC \`), or `ESC` and
+/// the character after it.
+fn escape(text: &str) -> (&str, &str) {
+    let bytes = text.as_bytes();
+    let end = match bytes.get(1) {
+        Some(b'[') => bytes[2..]
+            .iter()
+            .position(|byte| (0x40..=0x7e).contains(byte))
+            .map_or(bytes.len(), |index| index + 3),
+        Some(b']') => match (text.find('\x07'), text[1..].find("\x1b\\")) {
+            (Some(bell), Some(st)) if bell < st + 1 => bell + 1,
+            (_, Some(st)) => st + 3,
+            (Some(bell), None) => bell + 1,
+            (None, None) => bytes.len(),
+        },
+        Some(_) => 1 + text[1..].chars().next().map_or(0, char::len_utf8),
+        None => 1,
+    };
+    text.split_at(end)
+}
+
+/// The style after the codes of a Select Graphic Rendition sequence, such as `1;31` for bold red,
+/// from the style before it. `0`, or no code, goes back to the base style.
+fn sgr(codes: &str, mut style: Style, base: Style) -> Style {
+    /// The colors of the codes `30` to `37`, and `90` to `97` for their bright variants.
+    const COLORS: [Color; 16] = [
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::Gray,
+        Color::DarkGray,
+        Color::LightRed,
+        Color::LightGreen,
+        Color::LightYellow,
+        Color::LightBlue,
+        Color::LightMagenta,
+        Color::LightCyan,
+        Color::White,
+    ];
+    let mut codes = codes
+        .split([';', ':'])
+        .map(|code| code.parse::<u16>().unwrap_or(0));
+    while let Some(code) = codes.next() {
+        style = match code {
+            0 => base,
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            5 => style.add_modifier(Modifier::SLOW_BLINK),
+            7 => style.add_modifier(Modifier::REVERSED),
+            9 => style.add_modifier(Modifier::CROSSED_OUT),
+            22 => style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style.remove_modifier(Modifier::ITALIC),
+            24 => style.remove_modifier(Modifier::UNDERLINED),
+            25 => style.remove_modifier(Modifier::SLOW_BLINK),
+            27 => style.remove_modifier(Modifier::REVERSED),
+            29 => style.remove_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style.fg(COLORS[usize::from(code - 30)]),
+            90..=97 => style.fg(COLORS[usize::from(code - 90 + 8)]),
+            40..=47 => style.bg(COLORS[usize::from(code - 40)]),
+            100..=107 => style.bg(COLORS[usize::from(code - 100 + 8)]),
+            38 | 48 => match (code, sgr_color(&mut codes)) {
+                (38, Some(color)) => style.fg(color),
+                (_, Some(color)) => style.bg(color),
+                (_, None) => style,
+            },
+            39 => Style {
+                fg: base.fg,
+                ..style
+            },
+            49 => Style {
+                bg: base.bg,
+                ..style
+            },
+            _ => style,
+        };
+    }
+    style
+}
+
+/// The color of an extended color code, after `38` or `48`: `5;N` for a color of the 256 color
+/// palette, or `2;R;G;B`.
+fn sgr_color(codes: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    let mut next = || u8::try_from(codes.next()?).ok();
+    match next()? {
+        5 => Some(Color::Indexed(next()?)),
+        2 => Some(Color::Rgb(next()?, next()?, next()?)),
+        _ => None,
+    }
+}
+
+/// How long a command took, as shown after it: in milliseconds under a second, in seconds to
+/// the tenth under a minute, and as `1h2m3s` past that.
+fn took(duration: Duration) -> String {
+    match duration.as_millis() {
+        millis @ 0..=999 => format!("{}ms", millis),
+        1_000..=59_999 => format!("{:.1}s", duration.as_secs_f64()),
+        _ => budget::format(duration),
+    }
+}
+
+/// Wrap a line to the width, as a paragraph wrapped without trimming would. The line keeps its
+/// style on every row it is wrapped to.
+fn wrap(line: &Line, width: usize) -> Vec<Line<'static>> {
+    let chars = line
+        .spans
+        .iter()
+        .flat_map(|span| span.content.chars().map(move |c| (c, span.style)))
+        .collect::<Vec<_>>();
+
+    let mut rows = Vec::new();
+    let mut start = 0;
+    // The width of the row being filled, and where it may be broken: after its last whitespace.
+    let (mut filled, mut brk) = (0, None);
+    let mut index = start;
+    while index < chars.len() {
+        let (c, _) = chars[index];
+        let w = c.width().unwrap_or(0);
+        if filled + w > width && index > start {
+            let end = brk.filter(|&brk| brk > start).unwrap_or(index);
+            rows.push(&chars[start..end]);
+            (start, filled, brk) = (end, 0, None);
+            index = start;
+            continue;
+        }
+        filled += w;
+        index += 1;
+        if c.is_whitespace() {
+            brk = Some(index);
+        }
+    }
+    rows.push(&chars[start..]);
+
+    rows.into_iter()
+        .map(|row| {
+            let mut spans: Vec<Span<'static>> = Vec::new();
+            for &(c, style) in row {
+                match spans.last_mut() {
+                    Some(span) if span.style == style => span.content.to_mut().push(c),
+                    _ => spans.push(Span::styled(Cow::Owned(c.to_string()), style)),
+                }
+            }
+            Line {
+                spans,
+                style: line.style,
+                alignment: line.alignment,
+            }
+        })
+        .collect()
+}
+
+impl Widget for HistoryEntryWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        Paragraph::new(self.lines())
+            .wrap(Wrap { trim: false })
+            .render(area, buf);
+    }
+}
+
+impl StatefulWidget for HistoryEntryWidget<'_> {
+    type State = HistoryEntryState;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
+        Paragraph::new(self.lines())
+            .wrap(Wrap { trim: false })
+            .scroll((state.offset, 0))
+            .render(area, buf);
+    }
+}
+
+///
+/// [`Text`] renders the output line by line, as it is.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Text;
+
+impl OutputRenderer for Text {
+    fn render<'a>(&self, stdout: &'a [String], theme: &Theme) -> Option<Vec<Line<'a>>> {
+        Some(
+            stdout
+                .iter()
+                .map(|line| Line::styled(line.as_str(), theme.stdout))
+                .collect(),
+        )
+    }
+}
+
+///
+/// [`Ansi`] renders output colored with ANSI escape sequences, as programs color what they write
+/// to a terminal, in its colors. The other escape sequences, such as the ones moving the cursor
+/// or opening links, are left out.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ansi;
+
+impl OutputRenderer for Ansi {
+    fn render<'a>(&self, stdout: &'a [String], theme: &Theme) -> Option<Vec<Line<'a>>> {
+        // Other control characters make the output binary, for `Hex` to dump.
+        let binary = |c: char| c.is_control() && !matches!(c, '\t' | '\x1b' | '\x07');
+        let escaped = stdout.iter().any(|line| line.contains('\x1b'));
+        if !escaped || stdout.iter().any(|line| line.contains(binary)) {
+            return None;
+        }
+        Some(
+            stdout
+                .iter()
+                .map(|line| colored(line, theme.stdout))
+                .collect(),
+        )
+    }
+}
+
+///
+/// [`Json`] renders a JSON object or array, pretty-printed, with the keys highlighted.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl OutputRenderer for Json {
+    fn render<'a>(&self, stdout: &'a [String], theme: &Theme) -> Option<Vec<Line<'a>>> {
+        let text = stdout.join("\n");
+        // Only objects and arrays: a lone number or string is better left as it is.
+        if !text.trim_start().starts_with(['{', '[']) {
+            return None;
+        }
+        let value = serde_json::from_str::<serde_json::Value>(&text).ok()?;
+        let pretty = serde_json::to_string_pretty(&value).ok()?;
+
+        let lines = pretty.lines().map(|line| {
+            let indent = line.len() - line.trim_start().len();
+            let (head, rest) = line.split_at(indent);
+            match rest
+                .starts_with('"')
+                .then(|| rest.split_once("\": "))
+                .flatten()
+            {
+                Some((key, value)) => Line::from(vec![
+                    Span::raw(head.to_string()),
+                    Span::styled(format!("{}\":", key), theme.command),
+                    Span::styled(format!(" {}", value), theme.stdout),
+                ]),
+                None => Line::styled(line.to_string(), theme.stdout),
+            }
+        });
+        Some(lines.collect())
+    }
+}
+
+///
+/// [`Table`] renders tab-separated columns, aligned, with the first row as the header. The output
+/// needs at least two rows, all with the same number of columns (two or more).
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Table;
+
+impl OutputRenderer for Table {
+    fn render<'a>(&self, stdout: &'a [String], theme: &Theme) -> Option<Vec<Line<'a>>> {
+        let rows = stdout
+            .iter()
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let columns = rows.first()?.len();
+        if rows.len() < 2 || columns < 2 || rows.iter().any(|row| row.len() != columns) {
+            return None;
+        }
+        let widths = (0..columns)
+            .map(|column| {
+                let cells = rows.iter().map(|row| row[column].chars().count());
+                cells.max().unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+
+        let lines = rows.iter().enumerate().map(|(index, row)| {
+            let style = match index {
+                0 => theme.command,
+                _ => theme.stdout,
+            };
+            let cells = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width));
+            Line::styled(
+                cells.collect::<Vec<_>>().join("  ").trim_end().to_string(),
+                style,
+            )
+        });
+        Some(lines.collect())
+    }
+}
+
+///
+/// [`Hex`] renders binary output as a hex dump: the offset, the bytes, and the printable ones.
+/// Output is taken for binary when it holds control characters (other than tabs), or the
+/// replacement character left by bytes that are not UTF-8.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Hex;
+
+impl OutputRenderer for Hex {
+    fn render<'a>(&self, stdout: &'a [String], theme: &Theme) -> Option<Vec<Line<'a>>> {
+        let binary = |c: char| (c.is_control() && c != '\t') || c == char::REPLACEMENT_CHARACTER;
+        if !stdout.iter().any(|line| line.contains(binary)) {
+            return None;
+        }
+
+        let bytes = stdout.join("\n").into_bytes();
+        let mut lines = bytes
+            .chunks(HEX_WIDTH)
+            .take(HEX_ROWS)
+            .enumerate()
+            .map(|(row, chunk)| {
+                let hex = chunk
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let printable = chunk
+                    .iter()
+                    .map(|&byte| match byte.is_ascii_graphic() || byte == b' ' {
+                        true => char::from(byte),
+                        false => '.',
+                    })
+                    .collect::<String>();
+                Line::from(vec![
+                    Span::styled(format!("{:08x}  ", row * HEX_WIDTH), theme.hint),
+                    Span::styled(
+                        format!("{:<width$}  ", hex, width = HEX_WIDTH * 3 - 1),
+                        theme.stdout,
+                    ),
+                    Span::styled(format!("|{}|", printable), theme.hint),
+                ])
+            })
+            .collect::<Vec<_>>();
+        if bytes.len() > HEX_ROWS * HEX_WIDTH {
+            lines.push(Line::styled("…", theme.hint));
+        }
+        Some(lines)
+    }
+}
+
+///
+/// [`Image`] renders an image sent as a `data:image/...;base64,...` URI as a placeholder, with
+/// the type and the size of the image.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Image;
+
+impl OutputRenderer for Image {
+    fn render<'a>(&self, stdout: &'a [String], theme: &Theme) -> Option<Vec<Line<'a>>> {
+        let [line] = stdout else {
+            return None;
+        };
+        let (kind, data) = line.trim().strip_prefix("data:")?.split_once(";base64,")?;
+        if !kind.starts_with("image/") {
+            return None;
+        }
+
+        let size = data.trim_end_matches('=').len() * 3 / 4;
+        let size = match size {
+            0..=1023 => format!("{} B", size),
+            _ => format!("{:.1} KiB", size as f64 / 1024.0),
+        };
+        Some(vec![Line::styled(
+            format!("[{}, {}]", kind, size),
+            theme.hint,
+        )])
+    }
+}
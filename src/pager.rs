@@ -0,0 +1,204 @@
+//! A pager for reading long text, such as manuals, on top of the shell.
+//!
+//! `F2` (see [`crate::keymap`]) opens the manual of the command under the cursor, and
+//! `:help COMMAND` the manual of any command. The manual is the man page of the command, or else
+//! its `--help` output. (See [`crate::explain::manual`]) The command being typed is left as is.
+//!
+//! The pager is driven like `less`:
+//!
+//! - `Up`/`k` and `Down`/`j` scroll by a line, `PageUp`/`b` and `PageDown`/`Space` by a page.
+//! - `Home`/`g` and `End`/`G` go to the start and the end.
+//! - `/` searches the text (ignoring case), then `n` and `N` go to the next and previous match.
+//! - `q` or `Esc` closes the pager.
+
+use std::cell::Cell;
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    layout::Rect,
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
+    Frame,
+};
+
+use crate::i18n::Catalog;
+use crate::theme::Theme;
+
+///
+/// [`Pager`] is the text being read, and how far it is scrolled.
+///
+pub(crate) struct Pager {
+    /// The title, shown on the border.
+    title: String,
+    /// The lines of the text.
+    lines: Vec<String>,
+    /// The first line shown.
+    offset: usize,
+    /// The number of lines shown at once, as last drawn.
+    height: Cell<usize>,
+    /// The search being typed, after `/`.
+    typing: Option<String>,
+    /// The last search, and whether it matched.
+    search: Option<(String, bool)>,
+}
+
+impl Pager {
+    /// Create a pager, at the start of the text.
+    pub(crate) fn new(title: String, text: &str) -> Self {
+        Self {
+            title,
+            lines: text
+                .lines()
+                .map(|line| line.trim_end().to_string())
+                .collect(),
+            offset: 0,
+            height: Cell::new(1),
+            typing: None,
+            search: None,
+        }
+    }
+
+    /// Handle a key press. This returns `false` once the pager is closed.
+    pub(crate) fn input(&mut self, key: KeyEvent) -> bool {
+        if let Some(typing) = &mut self.typing {
+            match key.code {
+                KeyCode::Char(c) => typing.push(c),
+                KeyCode::Backspace if typing.is_empty() => self.typing = None,
+                KeyCode::Backspace => {
+                    typing.pop();
+                }
+                KeyCode::Enter => {
+                    let query = typing.to_lowercase();
+                    self.typing = None;
+                    if !query.is_empty() {
+                        let found = self.find(&query, self.offset, true);
+                        self.search = Some((query, found));
+                    }
+                }
+                KeyCode::Esc => self.typing = None,
+                _ => {}
+            }
+            return true;
+        }
+
+        let page = self.height.get().max(1);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.scroll_to(self.offset.saturating_sub(1)),
+            KeyCode::Down | KeyCode::Char('j') | KeyCode::Enter => self.scroll_to(self.offset + 1),
+            KeyCode::PageUp | KeyCode::Char('b') => {
+                self.scroll_to(self.offset.saturating_sub(page))
+            }
+            KeyCode::PageDown | KeyCode::Char(' ') => self.scroll_to(self.offset + page),
+            KeyCode::Home | KeyCode::Char('g') => self.scroll_to(0),
+            KeyCode::End | KeyCode::Char('G') => self.scroll_to(usize::MAX),
+            KeyCode::Char('/') => self.typing = Some(String::new()),
+            KeyCode::Char(direction @ ('n' | 'N')) => {
+                if let Some((query, _)) = self.search.take() {
+                    let found = match direction {
+                        'n' => self.find(&query, self.offset + 1, true),
+                        _ => self.find(&query, self.offset, false),
+                    };
+                    self.search = Some((query, found));
+                }
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    /// Scroll to a line, keeping the last page full.
+    fn scroll_to(&mut self, line: usize) {
+        let last = self.lines.len().saturating_sub(self.height.get());
+        self.offset = line.min(last);
+    }
+
+    /// Scroll to the first line matching the query from `start` (after it if `forward`, before it
+    /// otherwise). This returns `false` if there is none.
+    fn find(&mut self, query: &str, start: usize, forward: bool) -> bool {
+        let matches = |index: &usize| self.lines[*index].to_lowercase().contains(query);
+        let found = match forward {
+            true => (start..self.lines.len()).find(matches),
+            false => (0..start.min(self.lines.len())).rev().find(matches),
+        };
+
+        match found {
+            Some(line) => {
+                // Matches near the end are shown on the last page, rather than at the top.
+                self.scroll_to(line);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Draw the pager over `area`.
+    pub(crate) fn render(&self, frame: &mut Frame, area: Rect, theme: &Theme, messages: &Catalog) {
+        let block = Block::bordered()
+            .title(Span::styled(format!(" {} ", self.title), theme.title))
+            .border_style(theme.border);
+        let inner = block.inner(area);
+        // The last line is the status line.
+        let height = usize::from(inner.height.saturating_sub(1));
+        self.height.set(height.max(1));
+
+        let query = self.search.as_ref().map(|(query, _)| query.as_str());
+        let mut lines = self
+            .lines
+            .iter()
+            .skip(self.offset)
+            .take(height)
+            .map(|line| highlight(line, query, theme))
+            .collect::<Vec<_>>();
+        lines.resize(height, Line::raw(""));
+
+        let status = match (&self.typing, &self.search) {
+            (Some(typing), _) => Line::raw(format!("/{}", typing)),
+            (None, Some((query, false))) => Line::styled(
+                messages.format("pager-not-found", &[("query", query)]),
+                theme.error,
+            ),
+            (None, _) => {
+                let first = (self.offset + 1).min(self.lines.len()).to_string();
+                let last = (self.offset + height).min(self.lines.len()).to_string();
+                let total = self.lines.len().to_string();
+                Line::styled(
+                    messages.format(
+                        "pager-status",
+                        &[("first", &first), ("last", &last), ("lines", &total)],
+                    ),
+                    theme.hint,
+                )
+            }
+        };
+        lines.push(status);
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+}
+
+/// A line of the text, with the matches of the search highlighted.
+fn highlight<'a>(line: &'a str, query: Option<&str>, theme: &Theme) -> Line<'a> {
+    let Some(query) = query else {
+        return Line::raw(line);
+    };
+    // Lowercasing can change the length of some characters, in which case the matches cannot be
+    // located in the line: it is shown as is.
+    let lowercase = line.to_lowercase();
+    let mut spans = Vec::new();
+    let mut start = 0;
+    for (index, _) in lowercase.match_indices(query) {
+        let end = index + query.len();
+        let (Some(before), Some(matched)) = (line.get(start..index), line.get(index..end)) else {
+            return Line::raw(line);
+        };
+        spans.push(Span::raw(before));
+        spans.push(Span::styled(matched, theme.selection));
+        start = end;
+    }
+    spans.push(Span::raw(line.get(start..).unwrap_or_default()));
+
+    Line::from(spans)
+}
@@ -0,0 +1,86 @@
+//! Pasted text, made ready for the line being typed.
+//!
+//! Terminals send what is pasted, or dropped from a file manager, as a whole (bracketed paste),
+//! rather than as keys: a newline in it does not run the command. What is pasted goes through
+//! [`normalize`] first:
+//!
+//! - `file://` URLs, as some terminals drop files, become the paths they stand for, unescaped,
+//!   such as `file:///home/me/My%20Files/a.txt` for `'/home/me/My Files/a.txt'`.
+//! - A path to an existing file holding spaces, or other characters a shell splits words on, is
+//!   quoted, in the syntax of the executor. (See [`crate::syntax`])
+//! - The lines of the text are joined with spaces, the line being typed holding one line.
+
+use std::path::Path;
+
+use crate::syntax::Syntax;
+
+/// Make pasted text ready for the line being typed.
+pub(crate) fn normalize(text: &str, syntax: &dyn Syntax) -> String {
+    let lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    if !lines.is_empty() && lines.iter().all(|line| line.starts_with("file://")) {
+        let paths = lines.iter().filter_map(|line| path_of(line));
+        return paths
+            .map(|path| quote(&path, syntax))
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+    if let [line] = lines.as_slice() {
+        let path = line.starts_with('/') || line.starts_with("~/");
+        if path && exists(line) {
+            return quote(line, syntax);
+        }
+    }
+    text.trim_end_matches(['\r', '\n'])
+        .lines()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The path of a `file://` URL, unescaped. The host, if any, is left out: files dropped are on
+/// the machine the terminal runs on.
+fn path_of(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("file://")?;
+    let path = &rest[rest.find('/')?..];
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| after.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &after[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = after;
+            }
+        }
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Check whether a file exists at a path, which may start with `~/`.
+fn exists(path: &str) -> bool {
+    match path.strip_prefix("~/") {
+        Some(rest) => {
+            std::env::var_os("HOME").is_some_and(|home| Path::new(&home).join(rest).exists())
+        }
+        None => Path::new(path).exists(),
+    }
+}
+
+/// Quote a path if it needs to. A path starting with `~/` keeps it out of the quotes, to be
+/// expanded.
+fn quote(path: &str, syntax: &dyn Syntax) -> String {
+    match path.strip_prefix("~/") {
+        Some(rest) => format!("~/{}", syntax.quote(rest)),
+        None => syntax.quote(path),
+    }
+}
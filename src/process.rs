@@ -0,0 +1,541 @@
+//! Job control, for executors running programs of their own.
+//!
+//! [`run`] spawns a program in a process group of its own, and hands it the controlling terminal
+//! while it runs in the foreground: `Ctrl+C` then interrupts the program alone, not the shell,
+//! and `Ctrl+Z` stops it, returning a [`Job`] to keep in the jobs table of the executor and
+//! [`resume`] later. The terminal is taken back when the program exits or stops, along with the
+//! modes the shell set on it, such as the raw mode of the full screen interface. Jobs still
+//! stopped when the shell exits are hung up by the system, their process group being orphaned.
+//!
+//! While the program runs, the terminal is in its usual line mode, with its signal keys on, and
+//! flow control: `Ctrl+S` stops the output of the program, which waits on it, until `Ctrl+Q`.
+//! Without a controlling terminal, such as in an embedded shell, the program is only spawned in
+//! its own process group.
+//!
+//! [`spawn`] runs a program in the background instead, in a process group of its own too, and
+//! [`reap`] collects the background jobs that exited. The executor of `--system` spawns the
+//! commands it streams the output of in a process group of their own too, waiting for them
+//! itself. (See `command::system`) Every job is kept track of until it exits: when the shell
+//! exits, however it does, the jobs left are terminated, or detached and left running, following
+//! `jobs.on_exit`. (See [`crate::config::Jobs`])
+//!
+//! The programs are spawned with the resource limits of the command the shell is running, from
+//! the `[limits]` section of the configuration. (See [`crate::config::Limits`]) A program run in
+//! the foreground that is stopped by one of them is told about under the entry of the command.
+//! Their file mode mask, niceness and I/O priority are set too, from the `[process]` section, or
+//! from `:umask`, `:nice` and `:ionice` for the session. (See [`crate::config::Process`]) Their
+//! environment is the one of the shell, filtered and overridden by the `[child_env]` section,
+//! which `:env` shows. (See [`crate::config::ChildEnv`])
+//!
+//! ```rust
+//! use std::process::Command;
+//!
+//! use shelgon::process::{self, Foreground};
+//!
+//! match process::run(Command::new("sh").args(["-c", "exit 3"]))? {
+//!     Foreground::Exited(status) => assert_eq!(status.code(), Some(3)),
+//!     Foreground::Stopped(job) => println!("[{}] stopped", job.pid()),
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    io,
+    os::unix::process::{CommandExt, ExitStatusExt},
+    process::{Command, ExitStatus},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::config::{ChildEnv, IoPriority, Limits, Process};
+
+/// How long the jobs left are given to exit when the shell terminates them, before being killed.
+const GRACE: Duration = Duration::from_secs(1);
+
+/// How often the jobs left are checked for exiting while they are given time to.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The process groups of the jobs not known to have exited yet.
+static JOBS: Mutex<Vec<libc::pid_t>> = Mutex::new(Vec::new());
+
+/// The resource limits of the programs spawned: the ones of the command the shell is running.
+static LIMITS: Mutex<Limits> = Mutex::new(Limits {
+    cpu: None,
+    memory: None,
+    files: None,
+    commands: BTreeMap::new(),
+});
+
+/// The file mode mask and the priorities of the programs spawned: the ones of the command the
+/// shell is running.
+static SETTINGS: Mutex<Process> = Mutex::new(Process {
+    umask: None,
+    nice: None,
+    ionice: None,
+    commands: BTreeMap::new(),
+});
+
+/// The environment of the programs spawned, from the one of the shell.
+static ENVIRONMENT: Mutex<ChildEnv> = Mutex::new(ChildEnv {
+    pass: Vec::new(),
+    scrub: Vec::new(),
+    set: BTreeMap::new(),
+    force_color: Vec::new(),
+});
+
+/// Whether the terminal was handed to a program since [`handed`] was last called.
+static HANDED: AtomicBool = AtomicBool::new(false);
+
+/// The resource limit that stopped the last program run in the foreground, if any.
+static EXCEEDED: Mutex<Option<Exceeded>> = Mutex::new(None);
+
+/// The type of the resources of `setrlimit`.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+/// The type of the resources of `setrlimit`.
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type Resource = libc::c_int;
+
+///
+/// [`Exceeded`] is a resource limit a program was stopped by.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Exceeded {
+    /// The processor time, of the duration. The program was sent `SIGXCPU`.
+    Cpu(Duration),
+    /// The memory, of the MiB. The program was killed by a signal, likely failing to allocate.
+    Memory(u64),
+}
+
+///
+/// [`Job`] is a program run in a process group of its own, in the background or stopped with
+/// `Ctrl+Z`.
+///
+#[derive(Debug)]
+pub struct Job {
+    /// The id of the process, which is the id of its process group too.
+    pid: libc::pid_t,
+    /// The resource limits it was spawned with.
+    limits: Limits,
+}
+
+impl Job {
+    /// The id of the process of the job.
+    pub fn pid(&self) -> u32 {
+        self.pid.unsigned_abs()
+    }
+}
+
+///
+/// [`Foreground`] is how a program run in the foreground came back to the shell.
+///
+#[derive(Debug)]
+pub enum Foreground {
+    /// The program exited, or was killed by a signal.
+    Exited(ExitStatus),
+    /// The program was stopped, such as with `Ctrl+Z`, and waits to be resumed.
+    Stopped(Job),
+}
+
+/// Run a program in the foreground, in a process group of its own, until it exits or stops.
+pub fn run(command: &mut Command) -> io::Result<Foreground> {
+    let job = spawn(command)?;
+    foreground(job, false)
+}
+
+/// Collect the background jobs that exited, with their status. Jobs still running or stopped are
+/// left alone.
+pub fn reap() -> Vec<(Job, ExitStatus)> {
+    let mut jobs = JOBS.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut exited = Vec::new();
+    jobs.retain(|&pid| {
+        let mut status = 0;
+        // SAFETY: the status is valid for writes for the duration of the call.
+        match unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } {
+            0 => true,
+            -1 => false,
+            _ => {
+                let job = Job {
+                    pid,
+                    limits: Limits::default(),
+                };
+                exited.push((job, ExitStatus::from_raw(status)));
+                false
+            }
+        }
+    });
+    exited
+}
+
+/// Terminate the jobs left, stopped ones included: they are asked to (`SIGTERM`), and killed if
+/// they did not exit after a moment. Every one of them is waited for, leaving no zombie.
+pub fn terminate() {
+    let jobs = std::mem::take(&mut *JOBS.lock().unwrap_or_else(PoisonError::into_inner));
+    for &pid in &jobs {
+        signal(pid, libc::SIGTERM);
+        signal(pid, libc::SIGCONT);
+    }
+
+    let deadline = Instant::now() + GRACE;
+    let mut left = jobs;
+    while !left.is_empty() && Instant::now() < deadline {
+        left.retain(|&pid| !exited(pid, libc::WNOHANG));
+        thread::sleep(POLL_INTERVAL);
+    }
+    for pid in left {
+        signal(pid, libc::SIGKILL);
+        exited(pid, 0);
+    }
+}
+
+/// Detach the jobs left, to keep running once the shell exited. Stopped jobs are continued, and
+/// the ones that exited already are waited for, leaving no zombie.
+pub fn detach() {
+    let jobs = std::mem::take(&mut *JOBS.lock().unwrap_or_else(PoisonError::into_inner));
+    for pid in jobs {
+        if !exited(pid, libc::WNOHANG) {
+            signal(pid, libc::SIGCONT);
+        }
+    }
+}
+
+/// Run a program in the background, in a process group of its own. The job is waited for with
+/// [`reap`].
+pub fn spawn(command: &mut Command) -> io::Result<Job> {
+    let limits = prepare(command);
+    let child = command.spawn()?;
+    track(child.id(), limits)
+}
+
+/// Set a program up to run in a process group of its own, with the resource limits, the file mode
+/// mask, the priorities and the environment of the programs spawned now. This returns the limits,
+/// for the program to be kept track of with [`track`] once spawned, by whatever spawns it.
+pub(crate) fn prepare(command: &mut Command) -> Limits {
+    let limits = LIMITS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    let cpu = limits.cpu.map(|cpu| cpu.as_secs().max(1));
+    let memory = limits
+        .memory
+        .map(|memory| memory.saturating_mul(1024 * 1024));
+    let files = limits.files;
+    let settings = SETTINGS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    let (umask, nice) = (settings.umask, settings.nice);
+    let ionice = settings.ionice.map(ioprio);
+    set_environment(command);
+    // SAFETY: only async-signal-safe functions are called between `fork` and `exec`.
+    unsafe {
+        command.pre_exec(move || {
+            if libc::setpgid(0, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // The signals the shell ignores would stay ignored by the program.
+            for signal in [
+                libc::SIGINT,
+                libc::SIGQUIT,
+                libc::SIGTSTP,
+                libc::SIGTTIN,
+                libc::SIGTTOU,
+            ] {
+                libc::signal(signal, libc::SIG_DFL);
+            }
+            // Past the processor time, `SIGXCPU` is sent, and `SIGKILL` a second later.
+            if let Some(cpu) = cpu {
+                restrict(libc::RLIMIT_CPU, cpu, cpu.saturating_add(1))?;
+            }
+            if let Some(memory) = memory {
+                restrict(libc::RLIMIT_AS, memory, memory)?;
+            }
+            if let Some(files) = files {
+                restrict(libc::RLIMIT_NOFILE, files, files)?;
+            }
+            if let Some(umask) = umask {
+                libc::umask(umask as libc::mode_t);
+            }
+            // A niceness or an I/O priority not permitted is left as it is, as `nice` would.
+            if let Some(nice) = nice {
+                libc::setpriority(libc::PRIO_PROCESS, 0, nice);
+            }
+            if let Some(ionice) = ionice {
+                set_ioprio(ionice);
+            }
+            Ok(())
+        });
+    }
+    limits
+}
+
+/// Keep track of a program spawned once set up with [`prepare`], as a job, until it exits.
+pub(crate) fn track(id: u32, limits: Limits) -> io::Result<Job> {
+    let pid = libc::pid_t::try_from(id)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "process id out of range"))?;
+    // Set in the shell too, so the group exists before the terminal is handed to it, whichever
+    // of the two processes runs first.
+    // SAFETY: `setpgid` has no memory safety requirements.
+    unsafe { libc::setpgid(pid, pid) };
+    JOBS.lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(pid);
+
+    Ok(Job { pid, limits })
+}
+
+/// Set the resource limits, the file mode mask, the priorities and the environment of the
+/// programs spawned from now on.
+pub(crate) fn configure(limits: Limits, settings: Process, environment: ChildEnv) {
+    *LIMITS.lock().unwrap_or_else(PoisonError::into_inner) = limits;
+    *SETTINGS.lock().unwrap_or_else(PoisonError::into_inner) = settings;
+    *ENVIRONMENT.lock().unwrap_or_else(PoisonError::into_inner) = environment;
+}
+
+/// Scrub the variables of the shell not passed to the program, and set the ones of the
+/// configuration. The variables the executor set on the command itself are left as they are.
+fn set_environment(command: &mut Command) {
+    let explicit = command
+        .get_envs()
+        .map(|(name, _)| name.to_os_string())
+        .collect::<BTreeSet<_>>();
+    for (name, value) in environment() {
+        match value {
+            _ if explicit.contains(&name) => {}
+            Some(value) => {
+                command.env(name, value);
+            }
+            None => {
+                command.env_remove(name);
+            }
+        }
+    }
+}
+
+/// The changes to the environment of the shell for the programs spawned now: the variables
+/// scrubbed, with no value, and the ones set.
+fn environment() -> Vec<(OsString, Option<OsString>)> {
+    let environment = ENVIRONMENT.lock().unwrap_or_else(PoisonError::into_inner);
+    let scrubbed = std::env::vars_os()
+        .map(|(name, _)| name)
+        .filter(|name| !environment.passes(&name.to_string_lossy()))
+        .map(|name| (name, None));
+    let set = environment
+        .set
+        .iter()
+        .map(|(name, value)| (OsString::from(name), Some(OsString::from(value))));
+    scrubbed.chain(set).collect()
+}
+
+/// The I/O priority as `ioprio_set` takes it: the class in the high bits, and the level.
+fn ioprio(priority: IoPriority) -> libc::c_int {
+    let class = match priority {
+        IoPriority::Realtime(_) => 1,
+        IoPriority::BestEffort(_) => 2,
+        IoPriority::Idle => 3,
+    };
+    class << 13 | libc::c_int::from(priority.level().unwrap_or_default())
+}
+
+/// Set the I/O priority of this process.
+#[cfg(target_os = "linux")]
+fn set_ioprio(ioprio: libc::c_int) {
+    /// `ioprio_set` sets the priority of a process, of the id given.
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    // SAFETY: `ioprio_set` takes integers only, and has no memory safety requirements.
+    unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) };
+}
+
+/// Set the I/O priority of this process: there is none outside of Linux.
+#[cfg(not(target_os = "linux"))]
+fn set_ioprio(_ioprio: libc::c_int) {}
+
+/// Stop keeping track of a job its executor waited for itself, noting the resource limit that
+/// stopped it, if any. A job killed by the shell has no status.
+#[cfg(feature = "tokio")]
+pub(crate) fn finished(job: Job, status: Option<ExitStatus>) {
+    JOBS.lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .retain(|&pid| pid != job.pid);
+    if let Some(status) = status {
+        *EXCEEDED.lock().unwrap_or_else(PoisonError::into_inner) =
+            exceeded_by(&job, status.into_raw());
+    }
+}
+
+/// Whether the terminal was handed to a program since this was last called: the shell then draws
+/// the screen anew, whatever the program left on it.
+pub(crate) fn handed() -> bool {
+    HANDED.swap(false, Ordering::Relaxed)
+}
+
+/// The resource limit that stopped the last program run in the foreground since this was last
+/// called, if any.
+pub(crate) fn exceeded() -> Option<Exceeded> {
+    EXCEEDED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take()
+}
+
+/// Lower a resource limit of this process. Limits are never raised past the hard limit already
+/// set, which only a privileged process may do.
+fn restrict(resource: Resource, soft: u64, hard: u64) -> io::Result<()> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: the limit is valid for writes for the duration of the call.
+    if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    limit.rlim_max = limit.rlim_max.min(hard as libc::rlim_t);
+    limit.rlim_cur = limit.rlim_max.min(soft as libc::rlim_t);
+    // SAFETY: the limit is valid for reads for the duration of the call.
+    match unsafe { libc::setrlimit(resource, &limit) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// The resource limit of a job that stopped it, judging from its exit status.
+fn exceeded_by(job: &Job, status: libc::c_int) -> Option<Exceeded> {
+    if !libc::WIFSIGNALED(status) {
+        return None;
+    }
+    match (libc::WTERMSIG(status), job.limits.cpu, job.limits.memory) {
+        (libc::SIGXCPU, Some(cpu), _) => Some(Exceeded::Cpu(cpu)),
+        (libc::SIGABRT | libc::SIGSEGV | libc::SIGBUS | libc::SIGKILL, _, Some(memory)) => {
+            Some(Exceeded::Memory(memory))
+        }
+        _ => None,
+    }
+}
+
+/// Resume a stopped job in the foreground, until it exits or stops again.
+pub fn resume(job: Job) -> io::Result<Foreground> {
+    foreground(job, true)
+}
+
+/// Resume a stopped job in the background. The job is waited for with [`reap`].
+pub fn background(job: &Job) -> io::Result<()> {
+    match signal(job.pid, libc::SIGCONT) {
+        true => Ok(()),
+        false => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Send a signal to the process group of a job. This returns whether it was sent.
+fn signal(pid: libc::pid_t, signal: libc::c_int) -> bool {
+    // SAFETY: `kill` has no memory safety requirements.
+    unsafe { libc::kill(-pid, signal) == 0 }
+}
+
+/// Wait for a process to exit, with the options of `waitpid`. This returns whether it exited, or
+/// is gone already.
+fn exited(pid: libc::pid_t, options: libc::c_int) -> bool {
+    let mut status = 0;
+    // SAFETY: the status is valid for writes for the duration of the call.
+    unsafe { libc::waitpid(pid, &mut status, options) != 0 }
+}
+
+/// Hand the terminal to a job, continuing it first if `continued`, and wait for it to exit or
+/// stop before taking the terminal back.
+fn foreground(job: Job, continued: bool) -> io::Result<Foreground> {
+    let terminal = Terminal::hand(job.pid);
+    if continued {
+        background(&job)?;
+    }
+    let status = wait(job.pid);
+    drop(terminal);
+
+    let status = status?;
+    match libc::WIFSTOPPED(status) {
+        true => Ok(Foreground::Stopped(job)),
+        false => {
+            JOBS.lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .retain(|&pid| pid != job.pid);
+            *EXCEEDED.lock().unwrap_or_else(PoisonError::into_inner) = exceeded_by(&job, status);
+            Ok(Foreground::Exited(ExitStatus::from_raw(status)))
+        }
+    }
+}
+
+/// Wait for a process to exit or stop, returning its raw status.
+fn wait(pid: libc::pid_t) -> io::Result<libc::c_int> {
+    let mut status = 0;
+    loop {
+        // SAFETY: the status is valid for writes for the duration of the call.
+        match unsafe { libc::waitpid(pid, &mut status, libc::WUNTRACED) } {
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            -1 => return Err(io::Error::last_os_error()),
+            _ => return Ok(status),
+        }
+    }
+}
+
+///
+/// [`Terminal`] is the controlling terminal, handed to a process group in the foreground, and
+/// taken back by the shell when dropped.
+///
+struct Terminal {
+    /// The modes of the terminal set by the shell, restored when taken back.
+    modes: libc::termios,
+}
+
+impl Terminal {
+    /// Hand the terminal to the process group, in line mode. This returns `None` if the shell has
+    /// no controlling terminal.
+    fn hand(group: libc::pid_t) -> Option<Self> {
+        // SAFETY: `isatty` has no memory safety requirements.
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+            return None;
+        }
+        // SAFETY: `termios` is plain data, for which zeroes are valid, and is only read once
+        // `tcgetattr` filled it.
+        let mut modes = unsafe { std::mem::zeroed::<libc::termios>() };
+        // SAFETY: the modes are valid for writes for the duration of the call.
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut modes) } != 0 {
+            return None;
+        }
+
+        let mut line = modes;
+        line.c_iflag |= libc::ICRNL | libc::BRKINT | libc::IXON;
+        line.c_oflag |= libc::OPOST | libc::ONLCR;
+        line.c_lflag |= libc::ISIG | libc::ICANON | libc::ECHO | libc::ECHOE | libc::IEXTEN;
+        // SAFETY: the modes are valid for reads for the duration of the call.
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &line) };
+        give(group);
+        HANDED.store(true, Ordering::Relaxed);
+
+        Some(Self { modes })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // SAFETY: `getpgrp` has no memory safety requirements.
+        give(unsafe { libc::getpgrp() });
+        // SAFETY: the modes are valid for reads for the duration of the call.
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.modes) };
+    }
+}
+
+/// Make a process group the foreground one of the terminal. The shell ignores `SIGTTOU` while
+/// doing so, as it may not be in the foreground itself.
+fn give(group: libc::pid_t) {
+    // SAFETY: `signal` and `tcsetpgrp` have no memory safety requirements, and the previous
+    // handler is put back as it was.
+    unsafe {
+        let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+        libc::tcsetpgrp(libc::STDIN_FILENO, group);
+        libc::signal(libc::SIGTTOU, previous);
+    }
+}
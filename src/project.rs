@@ -0,0 +1,122 @@
+//! Project configuration files.
+//!
+//! A project can ship a `.sheller.toml`, in the working directory or at the root of its git
+//! repository, with settings merged over the configuration of the user: the same sections as
+//! `config.toml` (see [`crate::config`]), along with the aliases, environment variables, tasks and
+//! guards of the project.
+//!
+//! A project file runs commands on behalf of whoever wrote it, so it is only applied once trusted:
+//! the first time a file is seen (or once it changed), the shell asks whether to trust it. The
+//! answers are kept in `projects.json` in the data directory (`$XDG_DATA_HOME/sheller`).
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::config;
+use crate::migrate;
+
+/// The name of the project configuration file.
+pub(crate) const NAME: &str = ".sheller.toml";
+
+/// The name of the file holding the answers to the trust prompts, in the data directory.
+const TRUST: &str = "projects.json";
+
+/// The format of the file holding the answers. (See [`crate::migrate`])
+const FORMAT: migrate::Json = migrate::Json {
+    name: TRUST,
+    migrations: &[forget_hashes],
+};
+
+/// The answer to the trust prompt for a project file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Answer {
+    /// The SHA-256 of the file when the answer was given, in hexadecimal. The question is asked
+    /// again once the file changes.
+    hash: String,
+    /// Whether the file is trusted.
+    trusted: bool,
+}
+
+/// The answers to the trust prompts, by project file.
+type Answers = BTreeMap<String, Answer>;
+
+/// The project file for a working directory: `.sheller.toml` in the directory, or else at the
+/// root of the git repository holding it.
+pub(crate) fn find(directory: &Path) -> Option<PathBuf> {
+    let file = directory.join(NAME);
+    if file.is_file() {
+        return Some(file);
+    }
+
+    directory
+        .ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .map(|root| root.join(NAME))
+        .filter(|file| file.is_file())
+}
+
+/// Check whether a project file, with the given content, is trusted. This returns `None` if the
+/// user was never asked, or if the file changed since.
+pub(crate) fn trusted(file: &Path, content: &str) -> Option<bool> {
+    let answers = read().ok()?;
+    let answer = answers.get(file.to_string_lossy().as_ref())?;
+
+    Some(answer.trusted).filter(|_| answer.hash == hash(content))
+}
+
+/// Remember whether a project file, with the given content, is trusted.
+pub(crate) fn remember(file: &Path, content: &str, trusted: bool) -> anyhow::Result<()> {
+    let path = path()?;
+    let mut answers = read()?;
+    answers.insert(
+        file.to_string_lossy().into_owned(),
+        Answer {
+            hash: hash(content),
+            trusted,
+        },
+    );
+
+    FORMAT.write(&path, &answers)
+}
+
+/// The path of the file holding the answers.
+fn path() -> anyhow::Result<PathBuf> {
+    config::data_dir()
+        .map(|dir| dir.join(TRUST))
+        .ok_or_else(|| anyhow::anyhow!("unable to determine the data path"))
+}
+
+/// Read the answers, or none if the file does not exist yet.
+fn read() -> anyhow::Result<Answers> {
+    Ok(FORMAT.read(&path()?)?.unwrap_or_default())
+}
+
+/// The SHA-256 of the content of a project file, in hexadecimal.
+fn hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Version 2: the hashes of the files are SHA-256 digests, rather than the 64 bits of the hasher
+/// of the standard library. Those cannot be told from the files anymore, so the questions are
+/// asked again.
+fn forget_hashes(answers: &mut Value) -> anyhow::Result<()> {
+    for answer in answers
+        .as_object_mut()
+        .into_iter()
+        .flat_map(|answers| answers.values_mut())
+    {
+        if let Some(answer) = answer.as_object_mut() {
+            answer.insert("hash".to_string(), Value::String(String::new()));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,347 @@
+//! Structured events for embedding the shell in other front-ends.
+//!
+//! Besides the full screen interface, the shell can be driven by another front-end (a GUI, a
+//! web page running xterm.js, an editor panel) through [`crate::renderer::App::embed`]. The
+//! shell then draws nothing: it reads [`UiInput`]s and writes [`UiEvent`]s describing what
+//! changed, leaving the presentation to the front-end.
+//!
+//! The messages are encoded in JSON (one per line) or in MessagePack (see [`Format`]), with a
+//! `type` field telling them apart:
+//!
+//! ```text
+//! --> {"type": "text", "text": "ls"}
+//! <-- {"type": "state-changed", "view": {"state": "idle", "command": "ls", "cursor": 2, ...}}
+//! --> {"type": "key", "key": "enter"}
+//! <-- {"type": "history-appended", "entry": {"prompt": "$", "command": "ls", ...}}
+//! <-- {"type": "state-changed", "view": {"state": "idle", "command": "", "cursor": 0, ...}}
+//! ```
+//!
+//! # Example
+//!
+//! ```rust
+//! use shelgon::protocol::{Format, Reader, UiInput};
+//!
+//! let input = br#"{"type": "run", "command": "ls"}"#;
+//! let mut reader = Reader::new(&input[..], Format::Json);
+//! assert_eq!(
+//!     reader.read().unwrap(),
+//!     Some(UiInput::Run { command: "ls".to_string(), stdin: Vec::new() }),
+//! );
+//! assert_eq!(reader.read().unwrap(), None);
+//! ```
+
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::command::CommandOutput;
+
+/// The encoding of the messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// JSON, one message per line.
+    #[default]
+    Json,
+    /// MessagePack, one message after the other.
+    MsgPack,
+}
+
+///
+/// [`Entry`] is an entry of the history: a command and its output.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// The prompt that was displayed.
+    pub prompt: String,
+    /// The command.
+    pub command: String,
+    /// The input that was supplied to the command.
+    pub stdin: Vec<String>,
+    /// The output of the command.
+    pub stdout: Vec<String>,
+    /// The error output of the command.
+    pub stderr: Vec<String>,
+    /// The note attached to the entry, if any. (See `:note`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Whether the entry is starred. (See `:star`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub starred: bool,
+    /// The exit code of the command, if the executor told it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// How long the command took to run, if measured.
+    #[serde(default, skip_serializing_if = "Duration::is_zero")]
+    pub duration: Duration,
+}
+
+impl From<&CommandOutput> for Entry {
+    fn from(output: &CommandOutput) -> Self {
+        Self {
+            prompt: output.prompt.clone(),
+            command: output.command.clone(),
+            stdin: output.stdin.clone(),
+            stdout: output.stdout.clone(),
+            stderr: output.stderr.clone(),
+            note: None,
+            starred: false,
+            exit_code: output.exit_code,
+            duration: output.duration,
+        }
+    }
+}
+
+impl From<Entry> for CommandOutput {
+    fn from(entry: Entry) -> Self {
+        Self {
+            prompt: entry.prompt,
+            command: entry.command,
+            stdin: entry.stdin,
+            stdout: entry.stdout,
+            stderr: entry.stderr,
+            exit_code: entry.exit_code,
+            duration: entry.duration,
+        }
+    }
+}
+
+///
+/// [`View`] is what the front-end shows below the history.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "kebab-case")]
+pub enum View {
+    /// The shell waits for a command.
+    Idle {
+        /// The prompt.
+        prompt: String,
+        /// The command typed so far.
+        command: String,
+        /// The position of the cursor in the command, in bytes.
+        cursor: usize,
+    },
+    /// The command asks for input.
+    Running {
+        /// The prompt.
+        prompt: String,
+        /// The command.
+        command: String,
+        /// The input typed so far, line by line.
+        stdin: Vec<String>,
+    },
+}
+
+///
+/// [`UiEvent`] is a change of the shell, sent to the front-end.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum UiEvent {
+    /// A progress message of the executor initialization.
+    Startup {
+        /// The message.
+        message: String,
+    },
+    /// An error reported by the shell itself, such as an invalid configuration file.
+    Error {
+        /// The description of the error.
+        message: String,
+    },
+    /// An entry was added to the history.
+    HistoryAppended {
+        /// The entry.
+        entry: Entry,
+    },
+    /// The history was cleared.
+    HistoryCleared,
+    /// A note was attached to an entry of the history.
+    EntryAnnotated {
+        /// The entry, as the number of entries added to the history before it.
+        id: usize,
+        /// The note.
+        note: String,
+    },
+    /// An entry of the history was starred, or unstarred.
+    EntryStarred {
+        /// The entry, as the number of entries added to the history before it.
+        id: usize,
+        /// Whether the entry is starred now.
+        starred: bool,
+    },
+    /// The command being typed, or the state of the shell, changed.
+    StateChanged {
+        /// What is shown below the history.
+        view: View,
+    },
+    /// Completions are shown below the command, as the text following the command.
+    CompletionShown {
+        /// The completions.
+        completions: Vec<String>,
+    },
+    /// The shell exited.
+    Exited {
+        /// The message the shell exited with.
+        message: String,
+    },
+}
+
+///
+/// [`UiInput`] is an input from the front-end.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum UiInput {
+    /// A key press, such as `"enter"` or `"ctrl+l"`, handled through the keybindings. (See
+    /// [`crate::keymap::Key`])
+    Key {
+        /// The key.
+        key: String,
+    },
+    /// Text typed at the cursor.
+    Text {
+        /// The text.
+        text: String,
+    },
+    /// Run a command, supplying `stdin` if it asks for input. What the user was typing is kept.
+    Run {
+        /// The command.
+        command: String,
+        /// The input of the command.
+        #[serde(default)]
+        stdin: Vec<String>,
+    },
+}
+
+///
+/// [`Reader`] reads the [`UiInput`]s sent by the front-end.
+///
+pub struct Reader<R> {
+    /// Where the input is read from.
+    input: R,
+    /// The encoding of the input.
+    format: Format,
+}
+
+impl<R: BufRead> Reader<R> {
+    /// Create a reader.
+    pub fn new(input: R, format: Format) -> Self {
+        Self { input, format }
+    }
+
+    /// Read the next input, or `None` once the front-end closed its end.
+    pub fn read(&mut self) -> anyhow::Result<Option<UiInput>> {
+        match self.format {
+            Format::Json => loop {
+                let mut line = String::new();
+                if self.input.read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
+                if !line.trim().is_empty() {
+                    return Ok(Some(serde_json::from_str(&line)?));
+                }
+            },
+            Format::MsgPack => match self.input.fill_buf()?.is_empty() {
+                true => Ok(None),
+                false => Ok(Some(rmp_serde::from_read(&mut self.input)?)),
+            },
+        }
+    }
+}
+
+///
+/// [`Writer`] writes the [`UiEvent`]s sent to the front-end.
+///
+pub struct Writer<W> {
+    /// Where the events are written.
+    output: W,
+    /// The encoding of the output.
+    format: Format,
+}
+
+impl<W: Write> Writer<W> {
+    /// Create a writer.
+    pub fn new(output: W, format: Format) -> Self {
+        Self { output, format }
+    }
+
+    /// Write an event.
+    pub fn send(&mut self, event: &UiEvent) -> anyhow::Result<()> {
+        match self.format {
+            Format::Json => {
+                serde_json::to_writer(&mut self.output, event)?;
+                self.output.write_all(b"\n")?;
+            }
+            Format::MsgPack => rmp_serde::encode::write_named(&mut self.output, event)?,
+        }
+        self.output.flush()?;
+
+        Ok(())
+    }
+}
+
+///
+/// [`Publisher`] turns the changes of the shell into [`UiEvent`]s. Changes to the history are
+/// queued as they happen, while the view is compared with the one last sent.
+///
+#[cfg(feature = "tui")]
+pub(crate) struct Publisher {
+    /// Where the events are written.
+    writer: Writer<Box<dyn Write>>,
+    /// The events waiting to be sent.
+    pending: Vec<UiEvent>,
+    /// The view last sent.
+    view: Option<View>,
+    /// The completions last sent.
+    completions: Option<Vec<String>>,
+}
+
+#[cfg(feature = "tui")]
+impl Publisher {
+    /// Create a publisher.
+    pub(crate) fn new(output: Box<dyn Write>, format: Format) -> Self {
+        Self {
+            writer: Writer::new(output, format),
+            pending: Vec::new(),
+            view: None,
+            completions: None,
+        }
+    }
+
+    /// Queue an event, sent with the next [`Publisher::update`].
+    pub(crate) fn push(&mut self, event: UiEvent) {
+        self.pending.push(event);
+    }
+
+    /// Send an event right away.
+    pub(crate) fn send(&mut self, event: &UiEvent) -> anyhow::Result<()> {
+        self.writer.send(event)
+    }
+
+    /// Send the queued events, then the view and the completions if they changed.
+    pub(crate) fn update(
+        &mut self,
+        view: View,
+        completions: Option<Vec<String>>,
+    ) -> anyhow::Result<()> {
+        for event in std::mem::take(&mut self.pending) {
+            self.writer.send(&event)?;
+        }
+
+        if self.view.as_ref() != Some(&view) {
+            self.writer
+                .send(&UiEvent::StateChanged { view: view.clone() })?;
+            self.view = Some(view);
+        }
+        if completions != self.completions {
+            if let Some(completions) = &completions {
+                self.writer.send(&UiEvent::CompletionShown {
+                    completions: completions.clone(),
+                })?;
+            }
+            self.completions = completions;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+//! The processes of the user, completed after `kill` and picked from a list.
+//!
+//! After `kill`, `Tab` completes the ids of the processes, shown with their name, and after
+//! `pkill`, `killall`, `pgrep` or `pidof`, their names, shown with their ids. `Alt+P` (the
+//! `pick-process` action) lists the processes with their processor and memory usage, the busiest
+//! first: typing filters them by name, and `Enter` inserts the id of the one selected at the
+//! cursor.
+//!
+//! The processes are listed by `ps`, on unix, so nothing is listed where it is missing.
+
+use std::process::Command;
+
+/// The programs taking the names of processes, rather than their ids.
+const BY_NAME: &[&str] = &["pkill", "killall", "pgrep", "pidof"];
+
+///
+/// [`Process`] is a process of the user.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Process {
+    /// The id of the process.
+    pub(crate) pid: u32,
+    /// The share of a processor it used, in percent, over its lifetime.
+    pub(crate) cpu: f32,
+    /// The share of the memory of the machine it holds, in percent.
+    pub(crate) memory: f32,
+    /// The name of its program.
+    pub(crate) name: String,
+}
+
+/// The processes of the user, the busiest first.
+pub(crate) fn list() -> Vec<Process> {
+    let output = Command::new("ps")
+        .args(["-xo", "pid=,pcpu=,pmem=,comm="])
+        .output();
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        _ => return Vec::new(),
+    };
+    let mut processes = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse)
+        .collect::<Vec<_>>();
+    processes.sort_by(|a, b| b.cpu.total_cmp(&a.cpu));
+    processes
+}
+
+/// Parse a line of `ps`: the id, the processor and memory usage, and the name, which may hold
+/// spaces.
+fn parse(line: &str) -> Option<Process> {
+    let mut rest = line.trim_start();
+    let mut field = || {
+        let (field, after) = rest.split_once(char::is_whitespace)?;
+        rest = after.trim_start();
+        Some(field)
+    };
+    let pid = field()?.parse().ok()?;
+    let cpu = field()?.parse().ok()?;
+    let memory = field()?.parse().ok()?;
+    let name = rest.trim_end();
+    // The name is the path of the program on some systems.
+    let name = match name.starts_with('/') {
+        true => name.rsplit('/').next().unwrap_or(name),
+        false => name,
+    };
+    Some(Process {
+        pid,
+        cpu,
+        memory,
+        name: name.to_string(),
+    })
+}
+
+/// The part of a process being typed at the end of a line, after `kill` or one of the programs
+/// taking names, with whether it is a name. This returns `None` if the line is not typing a
+/// process.
+pub(crate) fn word(line: &str) -> Option<(&str, bool)> {
+    let (program, arguments) = line.trim_start().split_once(char::is_whitespace)?;
+    let by_name = BY_NAME.contains(&program);
+    if program != "kill" && !by_name {
+        return None;
+    }
+    let word = arguments.rsplit(char::is_whitespace).next()?;
+    (!word.starts_with('-')).then_some((word, by_name))
+}
@@ -0,0 +1,105 @@
+//! Ranking completions and suggestions by how the commands are used.
+//!
+//! The completions shown on `Tab` and the commands suggested for the working directory are
+//! ordered by how often, and how recently, the commands they lead to were run, as recorded in the
+//! journal. (See `crate::journal`) By default, [`Frecency`] ranks them the way `zoxide` ranks
+//! directories: the number of runs, weighted by how long ago the last one was. Candidates ranked
+//! the same keep their order.
+//!
+//! Another [`Ranker`] is plugged in with [`crate::renderer::App::with_ranker`].
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use shelgon::rank::{self, Candidate, Frecency, Ranker};
+//!
+//! /// Rank the commands run most often first, however long ago.
+//! struct Frequency;
+//!
+//! impl Ranker for Frequency {
+//!     fn score(&self, candidate: &Candidate<'_>) -> f64 {
+//!         candidate.count as f64
+//!     }
+//! }
+//!
+//! let hour = Duration::from_secs(60 * 60);
+//! let candidates = [
+//!     Candidate { text: "git stash", count: 30, age: Some(hour * 24 * 30) },
+//!     Candidate { text: "git status", count: 5, age: Some(hour / 2) },
+//!     Candidate { text: "git stage", count: 0, age: None },
+//! ];
+//!
+//! assert_eq!(rank::rank(&Frecency, &candidates), [1, 0, 2]);
+//! assert_eq!(rank::rank(&Frequency, &candidates), [0, 1, 2]);
+//! ```
+
+use std::time::Duration;
+
+/// The weights of the runs, by how long ago the last one was: within an hour, a day and a week.
+const WEIGHTS: [(Duration, f64); 3] = [
+    (Duration::from_secs(60 * 60), 4.0),
+    (Duration::from_secs(24 * 60 * 60), 2.0),
+    (Duration::from_secs(7 * 24 * 60 * 60), 0.5),
+];
+
+/// The weight of the runs of a command last run more than a week ago.
+const OLD: f64 = 0.25;
+
+///
+/// [`Candidate`] is a completion or a suggestion to rank, along with how the command it leads to
+/// was used.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate<'a> {
+    /// The command the candidate leads to, as it would be typed.
+    pub text: &'a str,
+    /// The number of commands run starting with the text.
+    pub count: u64,
+    /// How long ago the last of them was run, if known.
+    pub age: Option<Duration>,
+}
+
+///
+/// [`Ranker`] scores the candidates shown: the higher the score, the earlier the candidate.
+///
+pub trait Ranker {
+    /// The score of a candidate.
+    fn score(&self, candidate: &Candidate<'_>) -> f64;
+}
+
+///
+/// [`Frecency`] scores candidates by the number of runs, weighted by how long ago the last one
+/// was: 4 within an hour, 2 within a day, 0.5 within a week, and 0.25 otherwise.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Frecency;
+
+impl Ranker for Frecency {
+    fn score(&self, candidate: &Candidate<'_>) -> f64 {
+        let weight = match candidate.age {
+            Some(age) => WEIGHTS
+                .iter()
+                .find(|(within, _)| age < *within)
+                .map_or(OLD, |(_, weight)| *weight),
+            // Commands run without a time, such as in this session with the journal off.
+            None => 1.0,
+        };
+
+        candidate.count as f64 * weight
+    }
+}
+
+/// The order of the candidates, best first, as their indexes. Candidates scored the same keep
+/// their order.
+pub fn rank(ranker: &dyn Ranker, candidates: &[Candidate<'_>]) -> Vec<usize> {
+    let scores = candidates
+        .iter()
+        .map(|candidate| ranker.score(candidate))
+        .collect::<Vec<_>>();
+    let mut order = (0..candidates.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+
+    order
+}
@@ -22,14 +22,53 @@
 //!
 //! # Key Bindings
 //!
-//! The following key combinations are supported:
+//! The following key combinations are supported by default, and can be rebound (see
+//! [`crate::keymap`]):
 //!
+//! - `F1/?`: Show the active keybindings
 //! - `Ctrl+L`: Clear screen
 //! - `Ctrl+C/Ctrl+D`: Exit shell (or terminate current command if running)
 //! - `Left/Right`: Move cursor
-//! - `Tab`: Trigger command completion
+//! - `Ctrl+Left/Ctrl+Right`: Move the cursor by words (or `Alt+B/Alt+F`)
+//! - `Home/End`: Move the cursor to the start or the end of the line (or `Ctrl+A/Ctrl+E`)
+//! - `Up/Down`: Recall the previous and next commands, run in any session (see
+//!   `crate::journal`)
+//! - `Ctrl+R`: Search the commands run
+//! - `PageUp/PageDown`: Scroll through the history (or the mouse wheel). On an empty line `Ctrl+U`
+//!   scrolls up by half a page, and `Ctrl+D` back down while scrolled up.
+//! - `Tab`: Trigger command completion, or complete the suggestions shown
+//! - `Alt+S`: Suggest the commands run most often in the working directory (see
+//!   `crate::usage`)
+//! - `Alt+N`: Attach a note to the last entry of the history (`:note TEXT`)
+//! - `Alt+E`: Edit the last command, to run it again with changes (`:fc`). `^OLD^NEW` runs it
+//!   again right away, with the first `OLD` replaced by `NEW`.
+//! - `Alt+.`: Insert the last argument of the previous command, or of older ones when repeated.
+//!   `!$` and `!*` in a command stand for the last argument and all the arguments of the previous
+//!   command, but within single quotes.
+//! - `F2`: Read the manual of the command under the cursor (see `crate::pager`)
+//! - `F6`: Explain the command being typed, or the last command run (see `crate::explain`)
+//! - `F5`: Write a command doing what the typed line describes, with the `suggest` feature (see
+//!   `crate::suggest`). `Enter` does the same for lines starting with `#`.
 //! - `Enter`: Execute command or add new STDIN line
 //! - `Backspace`: Delete character
+//! - `Delete`: Delete the character under the cursor
+//! - `Ctrl+W`: Delete the word before the cursor (or `Alt+Backspace`)
+//! - `Ctrl+Z`: Undo the last change to the line (see [`crate::editor`])
+//! - `F3`: Start or stop recording a macro (see `crate::macros`)
+//! - `F4`: Replay the last recorded macro
+//! - `F7`: Star the last entry of the history, `F8` to show only the starred entries (`:star`,
+//!   `:starred`)
+//!
+//! # Shell Integration
+//!
+//! Unless `ui.shell_integration` is disabled, the working directory (see
+//! [`command::Execute::cwd`]) is reported to the terminal whenever it changes (OSC 7), so new
+//! tabs open in the same directory. In screen reader mode, the prompt, the command and its output
+//! are also delimited with semantic prompt markers (OSC 133), letting the terminal jump between
+//! prompts.
+//!
+//! The window title shows the working directory, or the running command. (See `ui.title` and
+//! `ui.title_running` in [`crate::config::Ui`]) The original title is restored on exit.
 //!
 //! # Example
 //!
@@ -65,6 +104,7 @@
 //!             stdin: Vec::new(),
 //!             stdout: vec!["Hello, world!".to_string()],
 //!             stderr: Vec::new(),
+//!             ..Default::default()
 //!         }))
 //!     }
 //! }
@@ -82,36 +122,155 @@
 //!
 //!
 
-use std::io;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 #[cfg(feature = "tokio")]
-use std::sync::Arc;
+use std::{future::Future, pin::Pin, sync::Arc};
 
 use crossterm::{
-    event::{KeyCode, KeyModifiers},
+    event::{
+        DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event, KeyCode, KeyEvent, KeyModifiers, MouseEventKind,
+    },
     terminal::{EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
-    prelude::CrosstermBackend,
-    style::{Style, Stylize},
+    backend::TestBackend,
+    layout::{Alignment, Rect},
+    prelude::{Backend, CrosstermBackend},
+    style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{Paragraph, Wrap},
-    Frame, Terminal,
+    widgets::{Block, Clear, Paragraph, Wrap},
+    Frame, Terminal, TerminalOptions, Viewport,
 };
+use sha2::{Digest, Sha256};
 #[cfg(feature = "tokio")]
 use tokio::runtime::Runtime;
+use unicode_segmentation::UnicodeSegmentation;
 
+use crate::arithmetic;
+use crate::assignments;
+use crate::autocorrect;
+use crate::autosave;
+use crate::budget;
 use crate::command::{self};
+use crate::config::{self, Config, ConfigError, CueOn, CueSound, Issue, RcOutput};
+#[cfg(unix)]
+use crate::control::{self, Call, Request, Subscriber};
+use crate::editor::LineBuffer;
+use crate::explain::{self, Part};
+use crate::filter::Filter;
+use crate::fuzzy;
+use crate::hints::Specs;
+use crate::hosts;
+use crate::i18n::Catalog;
+use crate::integration;
+use crate::journal::{Query, Recall, Store};
+use crate::keymap::{Action, Category, Key, Keymap};
+use crate::kills::{self, Kills};
+use crate::macros::{self, Recorder};
+use crate::memory::{self, Scrollback};
+use crate::output::{self, HistoryEntryWidget, OutputRenderer, RenderCache};
+use crate::pager::Pager;
+use crate::paste;
+#[cfg(unix)]
+use crate::process;
+use crate::project;
+use crate::protocol::{Entry, Format, Publisher, Reader, UiEvent, UiInput, View};
+use crate::ps;
+use crate::rank::{self, Candidate, Frecency, Ranker};
+use crate::runbook::Runbook;
+use crate::runs::{Run, Runs};
+#[cfg(feature = "script")]
+use crate::script::{self, Effect, Script};
+#[cfg(unix)]
+use crate::session::{self, Server};
+use crate::setup::{Outcome, Wizard};
+use crate::state::{self, State, Transition};
+use crate::substitution;
+#[cfg(feature = "suggest")]
+use crate::suggest;
+#[cfg(feature = "sync")]
+use crate::sync;
+use crate::syntax::Expansion;
+use crate::terminal::{self, TermCaps};
+use crate::testing::Transcript;
+use crate::theme::{self, Appearance, ColorSupport, Theme};
+use crate::tilde;
+use crate::transcript;
+use crate::usage;
 
 ///
 /// [`App`] is the main application.
 ///
 /// commands. This is the main struct that is used to create a shell. This is responsible for
-/// managing state, rendering the shell, and executing the commands.
+/// setting up the terminal, initializing the executor, and running the shell.
 ///
 pub struct App<T: command::Execute> {
+    /// How the executor is obtained once the terminal UI is up.
+    startup: Startup<T>,
+    /// The configuration of the shell.
+    config: Config,
+    /// The error encountered while loading the configuration file, shown on startup.
+    config_error: Option<ConfigError>,
+    /// Whether there is no configuration file yet, in which case the setup wizard is shown on
+    /// startup.
+    first_run: bool,
+    #[cfg(feature = "tokio")]
+    /// The runtime that is passed to the `Execute` trait. This is used to facilitate executing
+    /// on [`std::future::Future`]s, creating [`tokio::task::JoinHandle`]s, etc.
+    runtime: Arc<Runtime>,
+    /// The ranking of the completions and suggestions.
+    ranker: Box<dyn Ranker>,
+    /// The renderers of the output of commands, tried before the built-in ones.
+    renderers: Vec<Box<dyn OutputRenderer>>,
+    /// The entries the history starts with.
+    history: Vec<command::CommandOutput>,
+    /// The notes attached to the entries the history starts with, by index.
+    notes: BTreeMap<usize, String>,
+    /// The entries the history starts with that are starred, by index.
+    starred: BTreeSet<usize>,
+    /// The runbook whose steps are offered, if any.
+    runbook: Option<Runbook>,
+    #[cfg(feature = "suggest")]
+    /// The backend writing commands from a description, in place of the configured one.
+    suggester: Option<Box<dyn suggest::Backend>>,
+}
+
+/// The executor and its context, or the means to create them.
+enum Startup<T: command::Execute> {
+    /// The executor was supplied by the user, and is ready to be used.
+    Ready(T, T::Context),
+    /// The executor is created inside the terminal UI, behind the startup screen.
+    Deferred(Init<T>),
+}
+
+/// Deferred initialization of the executor, driven by [`App::initialize`].
+#[cfg(feature = "tokio")]
+type Init<T> = Box<
+    dyn FnOnce(
+        command::Progress,
+    ) -> Pin<
+        Box<dyn Future<Output = anyhow::Result<(T, <T as command::Execute>::Context)>>>,
+    >,
+>;
+
+/// Deferred initialization of the executor, driven by [`App::initialize`].
+#[cfg(not(feature = "tokio"))]
+type Init<T> = Box<dyn FnOnce() -> anyhow::Result<(T, <T as command::Execute>::Context)>>;
+
+///
+/// [`Shell`] is the running REPL. This is created by [`App::execute`] once the executor is ready,
+/// and is responsible for managing state, rendering the shell, and executing the commands.
+///
+struct Shell<T: command::Execute> {
     /// The executor that is used to execute the commands.
     executor: T,
-    /// The context that is maintained by the [`App`] struct. This is specific to your
+    /// The context that is maintained by the [`Shell`] struct. This is specific to your
     context: T::Context,
     /// The state of the shell. This is different from the context. This is used to maintain
     /// information about the renderer.
@@ -120,20 +279,474 @@ pub struct App<T: command::Execute> {
     /// The runtime that is passed to the `Execute` trait. This is used to facilitate executing
     /// on [`std::future::Future`]s, creating [`tokio::task::JoinHandle`]s, etc.
     runtime: Arc<Runtime>,
-    /// The history of the commands that are executed.
-    history: Vec<command::CommandOutput>,
+    /// The history of the commands that are executed, within the memory budget. (See
+    /// `crate::memory`)
+    history: Scrollback,
+    /// The number of entries ever added to the history. (Unlike the length of the history, this
+    /// is not affected by the history limit)
+    recorded: usize,
+    /// The configuration of the shell.
+    config: Config,
+    /// The active color theme.
+    theme: Theme,
+    /// The number of times the theme changed, telling the lines styled with another theme apart
+    /// in the cache.
+    theme_generation: u64,
+    /// The wrapped lines of the entries of the history, kept between frames. (See
+    /// [`crate::output`])
+    cache: RefCell<RenderCache>,
+    /// The terminal background, which decides the variant of the theme.
+    appearance: Appearance,
+    /// The colors supported by the terminal, which the theme is adapted to.
+    colors: ColorSupport,
+    /// The overlays drawn on top of the shell, bottom first. The top one receives all the input
+    /// while it is open.
+    overlays: Vec<Overlay>,
+    /// The overlays opened while the screen is locked, in order, opened once it is unlocked.
+    held: Vec<Overlay>,
+    /// The internal errors that did not stop the shell, not dismissed yet: their message, shown
+    /// in a banner at the top of the screen, and the errors that caused them. (See
+    /// [`Shell::fail`])
+    errors: Vec<(String, Vec<String>)>,
+    /// What becomes of the jobs of the executor when the shell exits.
+    _leftovers: Leftovers,
+    /// The messages of the shell, in the configured language.
+    messages: Catalog,
+    /// The working directory last reported to the terminal.
+    cwd: Option<PathBuf>,
+    /// The window title last set.
+    title: String,
+    /// What sits between the shell and the terminal, deciding how escape sequences are sent.
+    caps: TermCaps,
+    /// Where the escape sequences that are not part of the drawing are written.
+    out: Box<dyn Write>,
+    /// The keyboard macros being recorded and replayed.
+    macros: Recorder,
+    /// The named runs of commands. (See `crate::runs`)
+    runs: Runs,
+    /// The notes attached to the entries of the history, by id. (See [`Shell::annotate`])
+    notes: BTreeMap<usize, String>,
+    /// The starred entries of the history, by id. (See [`Shell::star`])
+    starred: BTreeSet<usize>,
+    /// Which entries of the history are shown, if not all of them. (See [`crate::filter`])
+    filter: Option<Filter>,
+    /// The entries of the history re-running another one with changes, by id, with the id of the
+    /// one they fix. (See [`Shell::fix`])
+    fixes: BTreeMap<usize, usize>,
+    /// The warnings about the entries of the history, by id, such as for a command that took
+    /// longer than its budget. (See [`crate::budget`])
+    warnings: BTreeMap<usize, Vec<String>>,
+    /// The entry whose command is being fixed at the prompt, with its id in the journal if it was
+    /// kept there.
+    fixing: Option<(usize, Option<i64>)>,
+    /// The last entry added by a command kept in the journal, with the id of the command there.
+    journaled: Option<(usize, i64)>,
+    /// The builtins of the executor. (See [`command::Execute::builtins`])
+    builtins: Vec<command::Builtin<T::Context>>,
+    /// The number of files being sourced, within each other. (See [`Shell::source`])
+    sourcing: usize,
+    /// The output shown of the commands of the rc file, while it runs. (See [`Shell::start`])
+    rc: Option<RcOutput>,
+    /// The configuration of the user, which project files are merged over.
+    user_config: Config,
+    /// The project file of the working directory, applied or not. (See `crate::project`)
+    project: Option<PathBuf>,
+    /// The environment variables set by the project file, with their previous values.
+    saved_env: Vec<(String, Option<std::ffi::OsString>)>,
+    /// The file mode mask and the priorities of the programs, set for the session with `:umask`,
+    /// `:nice` and `:ionice`, over the ones of the configuration. (See [`crate::process`])
+    process: config::Process,
+    /// The completion specs the hints below the prompt come from. (See `crate::hints`)
+    specs: Specs,
+    /// The snippets cut from the line, or copied from the history. (See `crate::kills`)
+    kills: Kills,
+    /// The runbook whose steps are offered, if any. (See [`crate::runbook`])
+    runbook: Option<Runbook>,
+    /// The commands queued while another one runs, run in order once it is done. (See
+    /// [`Shell::run_queued`])
+    queue: VecDeque<String>,
+    /// The output of the last command still coming in, if any. (See [`Shell::ingest_flood`])
+    flood: Option<Flood>,
+    /// The output of the command running, as it is streamed, if it is. (See
+    /// [`Shell::ingest_stream`])
+    streaming: Option<Streaming>,
+    /// The size of the terminal, as the commands run are told, updated as it is resized.
+    size: command::Size,
+    /// The rows the history is scrolled up by, from the bottom: `0` follows the output as it
+    /// comes in. (See [`Shell::scroll_by`])
+    scroll: usize,
+    /// The height of the history scrolled up, as last drawn, with the rows drawn below it: the
+    /// prompt and the lines around it.
+    view: Cell<(usize, usize)>,
+    /// The prompt of a remote executor, asked for once per command. (See
+    /// [`command::Execute::remote`])
+    cached_prompt: RefCell<Option<String>>,
+    /// Whether the command at the prompt is echoed, submitted to a remote executor but not run
+    /// yet, with the prompt predicted for after it, if any. The command runs once it is drawn.
+    echoed: Option<Option<String>>,
+    /// The tabs in the background, in order, leaving out the one shown. (See [`Session`])
+    tabs: Vec<Session<T>>,
+    /// The position of the tab shown among every tab, from 0.
+    tab: usize,
+    /// The descriptions of the completions shown, by the line they complete to, such as the
+    /// address of a host. (See `crate::hosts`)
+    descriptions: BTreeMap<String, String>,
+    /// The commands run in the session, recalled when the journal is not shared. (See
+    /// `crate::journal`)
+    commands: Vec<String>,
+    /// The commands being recalled with `Up` and `Down`, until another key is pressed.
+    recall: Option<Recall>,
+    /// The commands whose last argument is inserted with `Alt+.`, with the number of grapheme
+    /// clusters of the one inserted last, until another key is pressed.
+    yank: Option<(Recall, usize)>,
+    /// The count typed with `Alt+digit` for the next action, if any. (See
+    /// [`Action::repeats`])
+    count: Option<usize>,
+    /// The journal shared between sessions, unless `history.share` is off. (See
+    /// `crate::journal`)
+    store: Option<Store>,
+    /// The file the state of the session is saved to, unless `history.autosave` is off, with
+    /// when it was last saved. (See `crate::autosave`)
+    autosave: Option<(PathBuf, Instant)>,
+    /// Whether the history changed since the state of the session was last saved.
+    unsaved: bool,
+    /// The environment variables when the shell started, which the ones saved are compared to.
+    environment: BTreeMap<String, String>,
+    /// When the last input came, to lock the screen once idle. (See [`crate::config::Lock`])
+    active: Instant,
+    /// The ranking of the completions and suggestions. (See [`crate::rank`])
+    ranker: Box<dyn Ranker>,
+    /// The renderers of the output of commands, in the order they are tried. (See
+    /// [`crate::output`])
+    renderers: Vec<Box<dyn OutputRenderer>>,
+    /// Whether the shell is kept apart from the files of the user: the user script, the rc file,
+    /// the project files, the journal and the usage counts are left alone. (See
+    /// [`crate::testing`])
+    isolated: bool,
+    #[cfg(unix)]
+    /// The clients of the control socket receiving the new history entries.
+    subscribers: Vec<Subscriber>,
+    /// The events sent to the front-end embedding the shell. (See [`App::embed`])
+    publisher: Option<Publisher>,
+    #[cfg(feature = "script")]
+    /// The user script. (See [`crate::script`])
+    script: Option<Script>,
+    #[cfg(feature = "suggest")]
+    /// The backend writing commands from a description. (See [`crate::suggest`])
+    suggester: Option<Box<dyn suggest::Backend>>,
+}
+
+///
+/// [`Session`] is a tab in the background: a session opened on the connection of the executor,
+/// with its own context and history. The tab shown has these in the [`Shell`], swapped with the
+/// ones of another tab when going to it. (See [`command::Execute::open_session`])
+///
+struct Session<T: command::Execute> {
+    /// The context of the session.
+    context: T::Context,
+    /// The state of the tab.
+    state: State,
+    /// The history of the tab.
+    history: Scrollback,
+    /// The wrapped lines of the entries of the history.
+    cache: RefCell<RenderCache>,
+    /// The number of entries of the history recorded in the journal.
+    recorded: usize,
+    /// The runs grouping the entries of the history.
+    runs: Runs,
+    /// The notes attached to the entries, by index.
+    notes: BTreeMap<usize, String>,
+    /// The entries starred, by index.
+    starred: BTreeSet<usize>,
+    /// The filter of the history, if any.
+    filter: Option<Filter>,
+    /// The entries fixed, by index, with the index of the fixed command.
+    fixes: BTreeMap<usize, usize>,
+    /// The warnings under the entries, by index.
+    warnings: BTreeMap<usize, Vec<String>>,
+    /// The entry being fixed, if any.
+    fixing: Option<(usize, Option<i64>)>,
+    /// The last entry recorded in the journal, with its id.
+    journaled: Option<(usize, i64)>,
+    /// The commands queued.
+    queue: VecDeque<String>,
+    /// The output still coming in, if any.
+    flood: Option<Flood>,
+    /// The output of the command running, as it is streamed, if it is.
+    streaming: Option<Streaming>,
+    /// The rows the history is scrolled up by, from the bottom.
+    scroll: usize,
+}
+
+///
+/// [`Shown`] is an entry of the history shown, with the rows it takes. (See
+/// [`Shell::shown_entries`])
+///
+struct Shown {
+    /// The rows the entry takes, with the header of its run, if drawn above it.
+    rows: usize,
+    /// Its wrapped lines among the rows wanted.
+    lines: Vec<Line<'static>>,
+}
+
+///
+/// [`Flood`] is the output of a command longer than the shell adds to the history per frame:
+/// the lines still to come, added to the newest entry over the next frames. (See
+/// [`config::Ui::flood_lines`])
+///
+struct Flood {
+    /// The lines still to come.
+    pending: VecDeque<String>,
+    /// The number of lines of the output.
+    total: usize,
+    /// Whether the lines stopped coming in, until resumed.
+    paused: bool,
 }
 
-/// The state of the shell.
-enum State {
-    /// The shell is idle. This is the default state of the shell.
-    /// This is when the user is typing the command. This state holds the incomplete command, the
-    /// cursor location, and the completions.
-    Idle(String, usize, Option<Vec<String>>),
-    /// The shell is running. This is when the command is being executed. This state holds the
-    /// stdin that is being supplied to the command. And the contextual information about the
-    /// command.
-    Running(command::Prepare, Vec<String>),
+///
+/// [`Streaming`] is the output of the command running, as it comes in through its
+/// [`command::OutputStream`].
+///
+struct Streaming {
+    /// The output come in so far.
+    output: command::CommandOutput,
+    /// The lines still to come.
+    chunks: std::sync::mpsc::Receiver<command::Chunk>,
+    /// The last lines come in, output and error output alike. (See [`STREAM_TAIL`])
+    tail: VecDeque<command::Chunk>,
+    /// When the command started.
+    started: Instant,
+    /// Cancelled once the output is not waited for anymore.
+    cancel: command::Cancel,
+    /// Whether the lines are left waiting in the channel, the command waiting with them.
+    paused: bool,
+    /// The bytes taken by the lines of the output kept.
+    bytes: usize,
+    /// The bytes the lines of the output may take, past which the rest are dropped. (See
+    /// [`STREAM_BUDGET`])
+    budget: usize,
+    /// The number of lines dropped past the budget.
+    dropped: usize,
+}
+
+impl Streaming {
+    /// Take in a chunk of the output. Past the budget, the lines are dropped and counted, so a
+    /// command printing without end does not take ever more memory; its exit code is still kept.
+    fn take(&mut self, chunk: command::Chunk) {
+        if let command::Chunk::Stdout(line) | command::Chunk::Stderr(line) = &chunk {
+            let bytes = line.len() + std::mem::size_of::<String>();
+            if self.dropped > 0 || self.bytes + bytes > self.budget {
+                self.dropped += 1;
+                return;
+            }
+            self.bytes += bytes;
+        }
+        self.output.push(chunk);
+    }
+}
+
+// The command is told to stop however the shell stops waiting for its output: interrupted, with
+// its tab closed, or with the shell exiting.
+impl Drop for Streaming {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+/// The input of the shell.
+pub(crate) enum Input {
+    /// An event of the terminal.
+    Event(Event),
+    #[cfg(unix)]
+    /// A call on the control socket of the session. (See [`crate::control`])
+    Control(Call),
+    /// No input came for a while. (See [`TICK`])
+    Tick,
+}
+
+/// A modal overlay drawn on top of the shell. Overlays stack: one opened while another is open
+/// (such as the help, over the pager) is drawn over it, and closes back to it.
+enum Overlay {
+    /// The theme picker. This holds the index of the selected theme in [`theme::BUILTIN`], which
+    /// is previewed while the picker is open, and the theme to restore if the picker is cancelled.
+    Themes(usize, Box<Theme>),
+    /// The list of the active keybindings, closed by any key.
+    Help,
+    /// The explanation of a command, part by part, closed by any key. The parts without a meaning
+    /// span the whole width.
+    Explanation(String, Vec<(String, String)>),
+    /// The pager, reading a manual.
+    Pager(Box<Pager>),
+    #[cfg(feature = "suggest")]
+    /// A command written from a description, put on the prompt if confirmed.
+    Suggestion(String),
+    /// The correction of the command being run, asked below the prompt. (See
+    /// `crate::autocorrect`)
+    Correction(String),
+    /// The confirmation of a guarded command, with the reason of the guard, asked below the
+    /// prompt.
+    Guard(String),
+    /// The trust of a project file, asked below the prompt. (See `crate::project`)
+    Trust(PathBuf),
+    /// The restoring of the autosave of a session that crashed, asked below the prompt. (See
+    /// `crate::autosave`)
+    Recover(PathBuf, Box<transcript::Transcript>),
+    /// The current step of the runbook, asked below the prompt. (See [`crate::runbook`])
+    Runbook,
+    /// The output still coming in, to pause or drop, asked below the prompt. (See
+    /// [`Shell::ingest_flood`])
+    Flood,
+    /// The history search. This holds the text searched, the index of the selected match, and
+    /// the matches, last run first. (See `crate::journal`)
+    Search(String, usize, Vec<String>),
+    /// The fuzzy picker of `:pick`. This holds the text the lines are filtered by, the index of
+    /// the selected line among the ones matching, the lines of the output picked from, and the
+    /// command the line chosen is put in. (See `crate::fuzzy`)
+    Pick(String, usize, Vec<String>, String),
+    /// The snippets of the kill ring, to insert one at the cursor. This holds the index of the
+    /// selected snippet. (See `crate::kills`)
+    Kills(usize),
+    /// The copy mode, selecting part of a command of the history to put on the prompt. This holds
+    /// the index of the entry, and the end of the selection that stays put and the one moved, in
+    /// bytes. (See [`stages`])
+    Copy(usize, usize, usize),
+    /// The commands queued, to reorder or cancel them. This holds the index of the selected
+    /// command. (See [`Shell::run_queued`])
+    Queue(usize),
+    /// The process picker. This holds the text the names are filtered by, the index of the
+    /// selected process among the ones shown, and every process, the busiest first. (See
+    /// `crate::ps`)
+    Processes(String, usize, Vec<ps::Process>),
+    /// The lock screen, hiding the shell until it is unlocked. This holds the passphrase typed so
+    /// far, and whether the last one typed was wrong. (See [`crate::config::Lock`])
+    Lock(String, bool),
+    /// The details of the errors in the banner, closed by any key. (See [`Shell::fail`])
+    Errors,
+}
+
+impl Overlay {
+    /// Check if the overlay is drawn in a box over the shell, dimming what is under it, rather
+    /// than asked below the prompt.
+    fn boxed(&self) -> bool {
+        !matches!(
+            self,
+            Overlay::Correction(_)
+                | Overlay::Guard(_)
+                | Overlay::Trust(_)
+                | Overlay::Recover(..)
+                | Overlay::Runbook
+                | Overlay::Flood
+        )
+    }
+}
+
+/// The number of rounds of changes requested by the user script applied at once. (See
+/// [`Shell::scripted`])
+#[cfg(feature = "script")]
+const SCRIPT_ROUNDS: usize = 8;
+
+/// The names of the shell builtins, run as `:NAME`. (See [`Shell::builtin`])
+const BUILTINS: &[&str] = &[
+    "begin",
+    "end",
+    "env",
+    "fc",
+    "filter",
+    "help",
+    "history",
+    "ionice",
+    "macro",
+    "nice",
+    "note",
+    "pick",
+    "run",
+    "source",
+    "star",
+    "starred",
+    "stats",
+    "sync",
+    "task",
+    "theme",
+    "transcript",
+    "umask",
+];
+
+/// The number of commands recalled with `Up`, at most.
+const RECALL: usize = 1000;
+
+/// How long the shell waits for input while the output of a command is streamed, before taking
+/// in the lines come meanwhile. (See [`Shell::ingest_stream`])
+const STREAM_POLL: Duration = Duration::from_millis(50);
+
+/// The number of lines of the output streamed taken in per frame, at most.
+const STREAM_LINES: usize = 10_000;
+
+/// The bytes the output streamed may take, lines included, unless the memory budget of the history
+/// is lower. (See [`config::History::memory_budget`])
+const STREAM_BUDGET: usize = 64 * 1024 * 1024;
+
+/// The number of the last lines of the output streamed kept to be drawn below the command, in the
+/// order they came in.
+const STREAM_TAIL: usize = 256;
+
+/// The rows the history scrolls by for each step of the mouse wheel.
+const WHEEL: usize = 3;
+
+/// The largest count given to an action with `Alt+digit`.
+const MAX_COUNT: usize = 1000;
+
+/// The number of matches listed by the history search.
+const SEARCH: usize = 10;
+
+/// How long the shell waits for input before checking whether to lock the screen, when it may.
+/// (See [`crate::config::Lock`])
+const TICK: Duration = Duration::from_secs(1);
+
+/// The smallest terminal the shell is drawn on, in columns and rows: the prompt with the lines
+/// below it, and room for the overlays. A smaller terminal shows a placeholder instead. (See
+/// [`render_too_small`])
+const MIN_SIZE: (u16, u16) = (20, 5);
+
+/// The name the messages about project files are recorded under. (See `crate::project`)
+const PROJECT: &str = "[project]";
+
+/// The name the restoring of an autosave is recorded under. (See `crate::autosave`)
+const AUTOSAVE: &str = "[autosave]";
+
+/// The terminal of the shell, handed to the external picker. (See [`config::Pick`])
+#[cfg(unix)]
+const TTY: &str = "/dev/tty";
+#[cfg(not(unix))]
+const TTY: &str = "CON";
+
+/// The name the messages about runbooks are recorded under. (See [`crate::runbook`])
+const RUNBOOK: &str = "[runbook]";
+
+/// The name of the file of commands run at startup, in the configuration directory.
+const RC: &str = "rc";
+
+/// The number of files sourced within each other at most, so a file sourcing itself stops.
+const SOURCE_DEPTH: usize = 8;
+
+/// The markers in front of every line printed in screen reader mode.
+mod marker {
+    /// A progress message of the executor initialization.
+    pub const STARTUP: &str = "[startup]";
+    /// The prompt, waiting for a command.
+    pub const PROMPT: &str = "[prompt]";
+    /// A question of the shell, such as whether to run the next step of a runbook.
+    pub const QUESTION: &str = "[question]";
+    /// A line of input supplied to the command.
+    pub const STDIN: &str = "[stdin]";
+    /// A line of output of the command.
+    pub const STDOUT: &str = "[stdout]";
+    /// A line of error output of the command.
+    pub const STDERR: &str = "[stderr]";
+    /// An error reported by the shell itself.
+    pub const ERROR: &str = "[error]";
+    /// A warning about the command, such as it taking longer than its budget, or being stopped by a
+    /// resource limit.
+    pub const WARNING: &str = "[warning]";
 }
 
 ///
@@ -152,16 +765,22 @@ enum Next {
 
 impl<T: command::Execute> App<T> {
     /// Create a new instance of the [`App`] struct.
+    ///
+    /// The executor is not created here. It is initialized through [`command::New`] once the
+    /// terminal UI is up, while a startup screen shows the progress.
     pub fn new(#[cfg(feature = "tokio")] rt: Runtime) -> anyhow::Result<Self>
     where
-        T: command::New,
+        T: command::New + 'static,
     {
-        let (executor, context) = T::new()?;
-        Ok(Self::new_with_executor(
+        #[cfg(feature = "tokio")]
+        let init: Init<T> = Box::new(|progress| Box::pin(T::new_async(progress)));
+        #[cfg(not(feature = "tokio"))]
+        let init: Init<T> = Box::new(T::new);
+
+        Ok(Self::with_startup(
             #[cfg(feature = "tokio")]
             rt,
-            executor,
-            context,
+            Startup::Deferred(init),
         ))
     }
 
@@ -171,189 +790,110 @@ impl<T: command::Execute> App<T> {
         executor: T,
         context: T::Context,
     ) -> Self {
+        Self::with_startup(
+            #[cfg(feature = "tokio")]
+            rt,
+            Startup::Ready(executor, context),
+        )
+    }
+
+    /// Create a new instance of the [`App`] struct, loading the configuration from the default
+    /// location. (See [`Config::load`])
+    fn with_startup(#[cfg(feature = "tokio")] rt: Runtime, startup: Startup<T>) -> Self {
+        let (config, config_error) = match Config::load() {
+            Ok(config) => (config, None),
+            Err(e) => (Config::default(), Some(e)),
+        };
+
         Self {
-            executor,
-            context,
-            state: State::Idle(String::new(), 0, None),
+            startup,
+            config,
+            config_error,
+            first_run: Wizard::required(),
             #[cfg(feature = "tokio")]
             runtime: Arc::new(rt),
+            ranker: Box::new(Frecency),
+            renderers: Vec::new(),
             history: Vec::new(),
+            notes: BTreeMap::new(),
+            starred: BTreeSet::new(),
+            runbook: None,
+            #[cfg(feature = "suggest")]
+            suggester: None,
         }
     }
 
-    /// Render the shell.
-    fn render(&self, frame: &mut Frame) {
-        let prompt = self.executor.prompt(&self.context);
-        let area = frame.area();
-        let mut text_content = self
-            .history
-            .iter()
-            .flat_map(render_history)
-            .collect::<Vec<_>>();
-
-        match &self.state {
-            State::Idle(ref cmd, cursor, comp) => {
-                let (left_cmd, right_cmd) = cmd.split_at(*cursor);
-                let left_cmd = Span::styled(left_cmd, Style::default().bold());
-                let (cursor, right_cmd) = match right_cmd {
-                    "" => {
-                        let cursor =
-                            Span::styled(" ", Style::default().bg(ratatui::style::Color::White));
-                        let right_cmd = Span::raw("");
-                        (cursor, right_cmd)
-                    }
-                    right_cmd => {
-                        let cursor = Span::styled(
-                            //
-                            // # Safety: `right_cmd` will never be empty.
-                            //
-                            #[allow(clippy::expect_used)]
-                            right_cmd
-                                .chars()
-                                .next()
-                                .expect("match statement failed")
-                                .to_string(),
-                            Style::default()
-                                .bg(ratatui::style::Color::White)
-                                .fg(ratatui::style::Color::Black),
-                        );
-
-                        let right_cmd =
-                            Span::styled(right_cmd[1..].to_string(), Style::default().bold());
-                        (cursor, right_cmd)
-                    }
-                };
-
-                text_content.push(Line::from(vec![
-                    Span::styled(prompt.clone(), Style::default().blue()),
-                    Span::raw(" "),
-                    Span::styled(left_cmd.to_string(), Style::default().bold()),
-                    cursor,
-                    right_cmd,
-                ]));
+    /// Use the given configuration instead of the one loaded from the configuration file. This
+    /// also disables the setup wizard.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self.config_error = None;
+        self.first_run = false;
+        self
+    }
 
-                if let Some(comp) = comp {
-                    let completions = comp
-                        .iter()
-                        .map(|cmp| cmd.to_string() + cmp)
-                        .map(|line| {
-                            Span::styled(
-                                line,
-                                Style::default().bg(ratatui::style::Color::Rgb(200, 200, 200)),
-                            )
-                        })
-                        .map(Line::from)
-                        .collect::<Vec<_>>();
-                    text_content.extend(completions);
-                }
+    /// Adjust the configuration loaded from the configuration file.
+    pub fn configure(mut self, configure: impl FnOnce(&mut Config)) -> Self {
+        configure(&mut self.config);
+        self
+    }
 
-                let text_para = Paragraph::new(text_content).wrap(Wrap { trim: true });
-                frame.render_widget(text_para, area);
-            }
-            State::Running(ref prep, stdin) => {
-                text_content.push(Line::from(vec![
-                    Span::styled(prompt.clone(), Style::default().blue()),
-                    Span::raw(" "),
-                    Span::styled(prep.command.clone(), Style::default().bold()),
-                ]));
-                let stdin = stdin
-                    .iter()
-                    .map(Span::raw)
-                    .map(Line::from)
-                    .collect::<Vec<_>>();
-                text_content.extend(stdin);
+    /// Rank the completions and suggestions with the given ranker, instead of [`Frecency`]. (See
+    /// [`crate::rank`])
+    pub fn with_ranker(mut self, ranker: impl Ranker + 'static) -> Self {
+        self.ranker = Box::new(ranker);
+        self
+    }
 
-                let history_para = Paragraph::new(text_content).wrap(Wrap { trim: true });
-                frame.render_widget(history_para, area);
-            }
-        }
+    /// Render the output of commands with the given renderer, when it recognizes it. Renderers
+    /// added this way are tried in the order they are added, before the built-in ones. (See
+    /// [`crate::output`])
+    pub fn with_output_renderer(mut self, renderer: impl OutputRenderer + 'static) -> Self {
+        self.renderers.push(Box::new(renderer));
+        self
     }
 
-    /// Handle the input from the user.
-    fn input(&mut self, event: crossterm::event::Event) -> anyhow::Result<Next> {
-        if let crossterm::event::Event::Key(ke) = event {
-            match (ke.code, ke.modifiers) {
-                (KeyCode::Char('l'), KeyModifiers::CONTROL) => {
-                    self.history.clear();
-                    return Ok(Next::Continue);
-                }
+    /// Start with the entries in the history, as if the commands had been run in the session.
+    /// They are only shown: they are neither recalled nor recorded in the journal.
+    pub fn with_history(mut self, entries: Vec<command::CommandOutput>) -> Self {
+        self.history = entries;
+        self
+    }
 
-                (KeyCode::Char('d') | KeyCode::Char('c'), KeyModifiers::CONTROL) => {
-                    if let State::Running(..) = &self.state {
-                        self.continue_execution()?;
-                    } else {
-                        return Ok(Next::Exit("".to_string()));
-                    }
-                }
-                (KeyCode::Left, KeyModifiers::NONE) => self.move_cursor_left(),
-                (KeyCode::Right, KeyModifiers::NONE) => self.move_cursor_right(),
-                (KeyCode::Tab, KeyModifiers::NONE) => {
-                    if let State::Idle(ref mut cmd, ref mut cursor, ref mut comp @ None) =
-                        self.state
-                    {
-                        if *cursor == cmd.len() {
-                            let (fixed, variable) = self.executor.completion(&self.context, cmd)?;
-                            cmd.push_str(&fixed);
-                            *cursor = cmd.len();
-                            *comp = Some(variable);
-                        }
-                    }
-                }
-                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => match self.state {
-                    State::Idle(ref mut cmd, ref mut cursor, ref mut comp) => {
-                        cmd.insert(*cursor, c);
-                        *cursor += 1;
-
-                        match comp.as_mut() {
-                            None => {}
-                            Some(cmp) => {
-                                *cmp = cmp
-                                    .iter()
-                                    .filter_map(|i| {
-                                        if i.starts_with(&cmd[..*cursor]) {
-                                            Some(i[*cursor..].to_string())
-                                        } else {
-                                            None
-                                        }
-                                    })
-                                    .collect::<Vec<_>>();
-                            }
-                        }
-                    }
-                    State::Running(ref mut _pre, ref mut stdin) => {
-                        stdin.last_mut().map(|i| i.push(c)).unwrap_or_else(|| {
-                            stdin.push(c.to_string());
-                        });
-                    }
-                },
-                (KeyCode::Backspace, KeyModifiers::NONE) => {
-                    self.cursor_backspace();
+    /// Start with the entries in the history, along with their notes and whether they are
+    /// starred, such as the ones of a transcript. (See [`App::with_history`])
+    pub fn with_entries(mut self, entries: Vec<Entry>) -> Self {
+        self.notes.clear();
+        self.starred.clear();
+        self.history = entries
+            .into_iter()
+            .enumerate()
+            .map(|(index, mut entry)| {
+                if let Some(note) = entry.note.take() {
+                    self.notes.insert(index, note);
                 }
-                (KeyCode::Enter, KeyModifiers::NONE) => match self.state {
-                    State::Idle(..) => {
-                        return self.execute_command();
-                    }
-                    State::Running(ref mut _pre, ref mut stdin) => {
-                        stdin.push(String::new());
-                    }
-                },
-                (KeyCode::Up, KeyModifiers::NONE) => {
-                    let last = self.history.last().map(|x| x.command.clone());
-                    if let Some(last) = last {
-                        match self.state {
-                            State::Idle(ref mut cmd, ref mut cursor, _) => {
-                                *cmd = last;
-                                *cursor = cmd.len();
-                            }
-                            State::Running(..) => {}
-                        }
-                    }
+                if entry.starred {
+                    self.starred.insert(index);
                 }
-                _ => {}
-            }
-        }
+                command::CommandOutput::from(entry)
+            })
+            .collect();
+        self
+    }
 
-        Ok(Default::default())
+    /// Offer the steps of the runbook, one at a time, once the shell starts, and write the
+    /// results next to it once they are done. (See [`crate::runbook`])
+    pub fn with_runbook(mut self, runbook: Runbook) -> Self {
+        self.runbook = Some(runbook);
+        self
+    }
+
+    /// Write commands from a description with the given backend, instead of the one of the
+    /// `[suggest]` section of the configuration. (See [`crate::suggest`])
+    #[cfg(feature = "suggest")]
+    pub fn with_suggester(mut self, backend: impl suggest::Backend + 'static) -> Self {
+        self.suggester = Some(Box::new(backend));
+        self
     }
 
     /// Execute the shell.
@@ -362,179 +902,5842 @@ impl<T: command::Execute> App<T> {
     /// created and the input is handled. This also converts the shell into raw mode and enables
     /// the alternate screen.
     ///
+    /// In screen reader mode (`ui.screen_reader`), the terminal is left as is, and the session is
+    /// a plain sequence of lines instead. (See `App::run_plain`)
+    ///
     /// This method returns an `anyhow::Result<()>` which is used to handle the errors that are
     /// encountered during the execution of the shell.
     ///
-    pub fn execute(mut self) -> anyhow::Result<String> {
+    pub fn execute(self) -> anyhow::Result<String> {
+        let titled = !self.config.ui.title.is_empty() || !self.config.ui.title_running.is_empty();
+        if titled {
+            write!(io::stdout(), "{}", integration::PUSH_TITLE)?;
+        }
+
+        let response = match self.config.ui.screen_reader {
+            true => self.run_plain(),
+            false => self.run_fullscreen(),
+        };
+
+        if titled {
+            write!(io::stdout(), "{}", integration::POP_TITLE)?;
+            io::stdout().flush()?;
+        }
+
+        response
+    }
+
+    /// Set up the terminal for the full screen interface, run the shell, and restore the terminal.
+    fn run_fullscreen(self) -> anyhow::Result<String> {
+        let alternate_screen = self.config.ui.alternate_screen;
+        let mouse = self.config.ui.mouse;
         crossterm::terminal::enable_raw_mode()?;
 
         let mut stdout = io::stdout();
-        crossterm::execute!(stdout, EnterAlternateScreen)?;
+        if alternate_screen {
+            crossterm::execute!(stdout, EnterAlternateScreen)?;
+        }
+        crossterm::execute!(stdout, EnableBracketedPaste)?;
+        if mouse {
+            crossterm::execute!(stdout, EnableMouseCapture)?;
+        }
         let backend = CrosstermBackend::new(stdout);
         let mut terminal = Terminal::new(backend)?;
 
-        let response: anyhow::Result<String> = loop {
-            let draw = terminal.draw(|f| self.render(f));
-
-            if let Err(e) = draw {
-                break Err(e.into());
-            }
-
-            let event = crossterm::event::read();
-            let next = match event {
-                Ok(event) => self.input(event),
-                Err(e) => break Err(e.into()),
-            };
-
-            match next {
-                Ok(Next::Continue) => continue,
-                Ok(Next::Exit(msg)) => break Ok(msg),
-                Ok(Next::Clear) => {
-                    terminal.clear()?;
-                    continue;
-                }
-                Err(e) => break Err(e),
-            }
-        };
+        let response = self.run(
+            &mut terminal,
+            &mut |wait| match wait {
+                Some(wait) if !crossterm::event::poll(wait)? => Ok(Input::Tick),
+                _ => crossterm::event::read().map(Input::Event),
+            },
+            Box::new(io::stdout()),
+        );
 
         crossterm::terminal::disable_raw_mode()?;
-        crossterm::execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        crossterm::execute!(terminal.backend_mut(), DisableBracketedPaste)?;
+        if mouse {
+            crossterm::execute!(terminal.backend_mut(), DisableMouseCapture)?;
+        }
+        if alternate_screen {
+            crossterm::execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        }
         terminal.show_cursor()?;
 
         response
     }
 
-    // helpers
+    /// Run the shell as a detachable session, in the background. The full screen interface is
+    /// served on the socket of the session, and drawn by the clients attaching to it. The shell
+    /// can also be driven through the control socket of the session. (See [`crate::session`])
+    #[cfg(unix)]
+    pub fn serve(self, name: &str) -> anyhow::Result<String> {
+        let server = Server::bind(&session::socket(name)?, &session::control(name)?)?;
 
-    /// Move the cursor to the left by one.
-    fn move_cursor_left(&mut self) {
-        match self.state {
-            State::Idle(_, 0, _) | State::Running(..) => {}
-            State::Idle(_, ref mut cursor, ref mut comp) => {
-                *cursor -= 1;
-                *comp = None;
-            }
-        }
+        // The size is that of the first client, which sends it as soon as it attaches.
+        let backend = CrosstermBackend::new(server.client());
+        let viewport = Viewport::Fixed(Rect::new(0, 0, 80, 24));
+        let mut terminal = Terminal::with_options(backend, TerminalOptions { viewport })?;
+
+        self.run(
+            &mut terminal,
+            &mut |wait| server.next(wait),
+            Box::new(server.client()),
+        )
     }
 
-    /// Move the cursor to the right by one.
-    fn move_cursor_right(&mut self) {
-        match self.state {
-            State::Idle(ref cmd, cursor, _) if cursor == cmd.len() => {}
-            State::Idle(_, ref mut cursor, _) => {
-                *cursor += 1;
-            }
-            State::Running(..) => {}
-        }
+    /// Initialize the executor (if required) and run the shell until it exits.
+    ///
+    /// The shell reacts to the input returned by `events`, and writes the escape sequences that
+    /// are not part of the drawing (titles, working directory reports) to `out`.
+    fn run<B: Backend>(
+        self,
+        terminal: &mut Terminal<B>,
+        events: &mut impl FnMut(Option<Duration>) -> io::Result<Input>,
+        out: Box<dyn Write>,
+    ) -> anyhow::Result<String> {
+        let mut shell = self.launch(terminal, events, out)?;
+        shell.start();
+        let response = shell.drive(terminal, events)?;
+        shell.stop_autosave();
+        Ok(response)
     }
 
-    /// Move the cursor back by one.
-    fn cursor_backspace(&mut self) {
-        match self.state {
-            State::Idle(ref mut _cmd, 0, _) => {}
-            State::Idle(ref mut cmd, ref mut cursor, ref mut comp) => {
-                cmd.remove(*cursor - 1);
-                *cursor -= 1;
-                *comp = None;
-            }
-            State::Running(ref mut _pre, ref mut stdin) => {
-                stdin.last_mut().map(|i| i.pop());
-                if stdin.last().map_or(true, |i| i.is_empty()) {
-                    stdin.pop();
-                }
+    /// Run the shell on a test terminal until it exits, or until the events run out, and sum up
+    /// where it ended. The shell is isolated from the files of the user, and speaks English unless
+    /// configured otherwise. (See [`crate::testing`])
+    pub(crate) fn rehearse(
+        mut self,
+        terminal: &mut Terminal<TestBackend>,
+        events: impl IntoIterator<Item = Event>,
+    ) -> anyhow::Result<Transcript> {
+        self.first_run = false;
+        self.config_error = None;
+        self.config.ui.background.get_or_insert(Appearance::Dark);
+        self.config
+            .ui
+            .locale
+            .get_or_insert_with(|| "en".to_string());
+
+        let mut events = events.into_iter();
+        // The output coming in, or streamed, is all in before the next event.
+        let mut next = |wait| match wait {
+            Some(Duration::ZERO | STREAM_POLL) => Ok(Input::Tick),
+            _ => events
+                .next()
+                .map(Input::Event)
+                .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof)),
+        };
+        let mut shell = self.launch(terminal, &mut next, Box::new(io::sink()))?;
+        shell.isolated = true;
+        shell.start();
+        let exit = match shell.drive(terminal, &mut next) {
+            Ok(message) => Some(message),
+            Err(e)
+                if e.downcast_ref::<io::Error>()
+                    .is_some_and(|e| e.kind() == io::ErrorKind::UnexpectedEof) =>
+            {
+                None
             }
-        }
+            Err(e) => return Err(e),
+        };
+
+        let buffer = terminal.backend().buffer();
+        let screen = (0..buffer.area.height)
+            .map(|y| {
+                let row = (0..buffer.area.width).map(|x| buffer[(x, y)].symbol());
+                row.collect::<String>().trim_end().to_string()
+            })
+            .collect();
+        Ok(Transcript {
+            exit,
+            prompt: shell.prompt(),
+            line: shell.state.line().clone(),
+            history: shell.history.into_entries(),
+            screen,
+        })
     }
 
-    /// Continue the execution of the command.
-    fn continue_execution(&mut self) -> anyhow::Result<Next> {
-        let (prepare, stdin) = match self.state {
-            State::Running(ref prep, ref stdin) => (prep.clone(), stdin.clone()),
-            State::Idle(..) => return Ok(Next::Continue),
-        };
+    /// Initialize the executor (if required), and create the shell.
+    fn launch<B: Backend>(
+        mut self,
+        terminal: &mut Terminal<B>,
+        events: &mut impl FnMut(Option<Duration>) -> io::Result<Input>,
+        out: Box<dyn Write>,
+    ) -> anyhow::Result<Shell<T>> {
+        // The background is queried once, before any key is read. (See `terminal::background`)
+        let caps = TermCaps::detect();
+        let background = self
+            .config
+            .ui
+            .background
+            .or_else(|| terminal::background(&caps));
+        let colors = self.config.ui.colors.unwrap_or_else(terminal::colors);
+        if self.first_run {
+            self.setup(terminal, events, background.unwrap_or_default(), colors)?;
+        }
 
-        self._final_execution(&prepare.command, Some(stdin))
+        let appearance = self.config.ui.background.or(background).unwrap_or_default();
+        let theme = Theme::builtin(&self.config.ui.theme, appearance)
+            .unwrap_or_default()
+            .adapted(colors);
+        let messages = self.messages();
+        if let Some(error) = &self.config_error {
+            Self::report_config_error(terminal, events, &theme, &messages, error)?;
+        }
+
+        let shell = self.build(messages, caps, out, |messages, log| {
+            terminal.draw(|f| render_startup(f, &theme, messages, log))?;
+            Ok(())
+        })?;
+        Ok(Shell {
+            theme,
+            appearance,
+            colors,
+            ..shell
+        })
     }
 
-    /// Execute the command.
-    fn execute_command(&mut self) -> anyhow::Result<Next> {
-        let (cmd, _) = match self.state {
-            State::Idle(ref cmd, cursor, _) => (cmd.clone(), cursor),
-            State::Running(..) => return Ok(Next::Continue),
-        };
+    /// Obtain the executor and its context, reporting the progress of the initialization, and
+    /// create the shell with what the app was given: its history, its renderers, the runbook and
+    /// the suggester.
+    fn build(
+        self,
+        messages: Catalog,
+        caps: TermCaps,
+        out: Box<dyn Write>,
+        mut report: impl FnMut(&Catalog, &[String]) -> anyhow::Result<()>,
+    ) -> anyhow::Result<Shell<T>> {
+        let (executor, context) = Self::initialize(
+            self.startup,
+            #[cfg(feature = "tokio")]
+            &self.runtime,
+            |log| report(&messages, log),
+        )?;
+        Ok(Shell {
+            ranker: self.ranker,
+            renderers: self
+                .renderers
+                .into_iter()
+                .chain(output::builtin())
+                .collect(),
+            recorded: self.history.len(),
+            history: Scrollback::with_entries(memory::budget(&self.config.history), self.history),
+            notes: self.notes,
+            starred: self.starred,
+            runbook: self.runbook,
+            #[cfg(feature = "suggest")]
+            suggester: self.suggester,
+            ..Shell::new(
+                executor,
+                context,
+                #[cfg(feature = "tokio")]
+                self.runtime,
+                self.config,
+                messages,
+                caps,
+                out,
+            )
+        })
+    }
 
-        let prepare = self.executor.prepare(&cmd);
-        self.state = State::Running(prepare.clone(), Vec::new());
+    /// Run the shell in screen reader mode.
+    ///
+    /// There is no full screen UI and no styling: commands are read line by line (with the line
+    /// editing of the terminal itself), and everything is printed once, in order, with a textual
+    /// marker in front of every line telling what it is. This keeps the cursor where screen
+    /// readers expect it.
+    fn run_plain(self) -> anyhow::Result<String> {
+        let mut stdout = io::stdout();
+        let caps = TermCaps::detect();
+        let messages = self.messages();
+        if let Some(error) = &self.config_error {
+            writeln!(stdout, "{} {}", marker::ERROR, error)?;
+            writeln!(
+                stdout,
+                "{} {}",
+                marker::ERROR,
+                messages.get("config-error-fallback")
+            )?;
+        }
 
-        match prepare.stdin_required {
-            true => Ok(Next::Continue),
-            false => self._final_execution(&cmd, None),
+        let mut reported = 0;
+        let mut shell = self.build(messages, caps, Box::new(io::stdout()), |_, log| {
+            for message in &log[reported..] {
+                writeln!(io::stdout(), "{} {}", marker::STARTUP, message)?;
+            }
+            reported = log.len();
+            Ok(())
+        })?;
+
+        // The size of the terminal is told as it is, the lines printed being wrapped by it.
+        if let Ok((columns, rows)) = crossterm::terminal::size() {
+            shell.size.set(columns, rows);
         }
+        let stdin = io::stdin();
+        shell.start();
+        let response = loop {
+            let recorded = shell.recorded;
+            let next = shell.scripted()?;
+            shell.print_entries(&mut stdout, recorded)?;
+            if let Next::Exit(msg) = next {
+                break msg;
+            }
+
+            shell.autosave();
+            shell.report_cwd()?;
+            shell.report_title()?;
+            shell.advance_runbook();
+            let recorded = shell.recorded;
+            let step = matches!(shell.overlays.last(), Some(Overlay::Runbook));
+            let mut next = match step.then(|| shell.question()).flatten() {
+                // The step of the runbook is offered, answered with a line: its first character
+                // is the key of the answer, and an empty one runs the step.
+                Some(question) => {
+                    shell.overlays.pop();
+                    write!(stdout, "{} {} ", marker::QUESTION, question)?;
+                    stdout.flush()?;
+
+                    let mut line = String::new();
+                    if stdin.read_line(&mut line)? == 0 {
+                        writeln!(stdout)?;
+                        break String::new();
+                    }
+                    let code = line
+                        .trim()
+                        .chars()
+                        .next()
+                        .map_or(KeyCode::Enter, KeyCode::Char);
+                    let key = Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+                    shell.integrate(integration::OUTPUT_START)?;
+                    shell.overlay_input(Overlay::Runbook, key)?
+                }
+                None => {
+                    let prompt = shell.prompt();
+                    shell.integrate(integration::PROMPT_START)?;
+                    write!(stdout, "{} {} ", marker::PROMPT, prompt)?;
+                    shell.integrate(integration::COMMAND_START)?;
+                    stdout.flush()?;
+
+                    let mut line = String::new();
+                    if stdin.read_line(&mut line)? == 0 {
+                        writeln!(stdout)?;
+                        break String::new();
+                    }
+                    let cmd = line.trim_end_matches(['\n', '\r']);
+                    shell.integrate(integration::OUTPUT_START)?;
+
+                    shell.transition(state::Event::Edit(LineBuffer::from(cmd)));
+                    shell.execute_command()?
+                }
+            };
+            if let State::Running(_, ref mut input, _) = shell.state {
+                writeln!(
+                    stdout,
+                    "{} {}",
+                    marker::STDIN,
+                    shell.messages.get("stdin-end")
+                )?;
+                loop {
+                    write!(stdout, "{} ", marker::STDIN)?;
+                    stdout.flush()?;
+                    let mut line = String::new();
+                    if stdin.read_line(&mut line)? == 0 {
+                        writeln!(stdout)?;
+                        break;
+                    }
+                    input.push(line.trim_end_matches(['\n', '\r']).to_string());
+                }
+                next = shell.continue_execution()?;
+            }
+            if !std::mem::take(&mut shell.overlays).is_empty() {
+                writeln!(
+                    stdout,
+                    "{} {}",
+                    marker::ERROR,
+                    shell.messages.get("overlay-unavailable")
+                )?;
+            }
+
+            shell.print_entries(&mut stdout, recorded)?;
+            shell.integrate(integration::OUTPUT_END)?;
+
+            if let Next::Exit(msg) = next {
+                break msg;
+            }
+        };
+        shell.stop_autosave();
+        Ok(response)
     }
 
-    /// Execute the command and return the next action.
-    fn _final_execution(&mut self, cmd: &str, stdin: Option<Vec<String>>) -> anyhow::Result<Next> {
-        let prompt = self.executor.prompt(&self.context);
-        let output = self.executor.execute(
-            &mut self.context,
-            command::CommandInput {
-                prompt,
-                command: cmd.to_string(),
-                stdin,
-                #[cfg(feature = "tokio")]
-                runtime: self.runtime.clone(),
+    /// Run the shell without drawing anything, for another front-end (a GUI, a web terminal) to
+    /// present. The front-end sends [`UiInput`]s to `input`, and receives [`UiEvent`]s on
+    /// `output`, both encoded in `format`. (See [`crate::protocol`])
+    ///
+    /// The shell exits when the front-end closes `input`, or when the executor exits.
+    pub fn embed(
+        self,
+        input: impl io::BufRead,
+        output: Box<dyn Write>,
+        format: Format,
+    ) -> anyhow::Result<String> {
+        let mut publisher = Publisher::new(output, format);
+        let messages = self.messages();
+        if let Some(error) = &self.config_error {
+            publisher.send(&UiEvent::Error {
+                message: error.to_string(),
+            })?;
+        }
+
+        let mut reported = 0;
+        let shell = self.build(
+            messages,
+            TermCaps::default(),
+            Box::new(io::sink()),
+            |_, log| {
+                for message in &log[reported..] {
+                    publisher.send(&UiEvent::Startup {
+                        message: message.clone(),
+                    })?;
+                }
+                reported = log.len();
+                Ok(())
             },
         )?;
-        self.state = State::Idle(String::new(), 0, None);
+        let mut shell = Shell {
+            publisher: Some(publisher),
+            ..shell
+        };
 
-        match output {
-            command::OutputAction::Command(command_output) => self.history.push(command_output),
-            command::OutputAction::Exit => {
-                return Ok(Next::Exit("".to_string()));
+        let mut reader = Reader::new(input, format);
+        shell.start();
+        loop {
+            let mut next = shell.scripted()?;
+            if !matches!(next, Next::Exit(_)) {
+                shell.publish()?;
+                let Some(input) = reader.read()? else {
+                    break Ok(String::new());
+                };
+                next = shell.embedded_input(input)?;
             }
-            command::OutputAction::Clear => {
-                self.history.clear();
-                return Ok(Next::Clear);
+
+            if let Next::Exit(message) = next {
+                shell.publish()?;
+                if let Some(publisher) = &mut shell.publisher {
+                    publisher.send(&UiEvent::Exited {
+                        message: message.clone(),
+                    })?;
+                }
+                break Ok(message);
             }
         }
-
-        Ok(Next::Continue)
     }
-}
 
-/// Render the history of the commands.
-fn render_history(history: &command::CommandOutput) -> Vec<Line> {
-    let command = Line::from(vec![
-        Span::styled(history.prompt.clone(), Style::default().blue()),
-        Span::raw(" "),
-        Span::styled(history.command.clone(), Style::default().bold()),
-    ]);
-    let stdin = history
-        .stdin
-        .iter()
-        .cloned()
-        .map(Span::raw)
-        .map(Line::from)
-        .collect::<Vec<_>>();
-    let stdout = history
-        .stdout
-        .iter()
-        .cloned()
-        .map(Span::raw)
-        .map(Line::from)
-        .collect::<Vec<_>>();
-    let stderr = history
-        .stderr
-        .iter()
-        .cloned()
-        .map(|i| Span::styled(i, Style::default().red()))
-        .map(Line::from)
-        .collect::<Vec<_>>();
+    /// Run the setup wizard, writing its answers to a new configuration file and applying them.
+    /// Failing to write the file is reported like an invalid configuration file.
+    fn setup<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        events: &mut impl FnMut(Option<Duration>) -> io::Result<Input>,
+        appearance: Appearance,
+        colors: ColorSupport,
+    ) -> anyhow::Result<()> {
+        let messages = self.messages();
+        let mut wizard = Wizard::new(&self.config);
 
-    let mut lines = vec![command];
-    lines.extend(stdin);
-    lines.extend(stdout);
-    lines.extend(stderr);
+        loop {
+            let theme = Theme::builtin(wizard.theme(), appearance)
+                .unwrap_or_default()
+                .adapted(colors);
+            terminal.draw(|f| {
+                if !render_too_small(f, &theme, &messages) {
+                    wizard.render(f, &theme, &messages);
+                }
+            })?;
 
-    lines
+            let Input::Event(Event::Key(key)) = events(None)? else {
+                continue;
+            };
+            match wizard.input(key, &mut self.config) {
+                Ok(Outcome::Continue) => {}
+                Ok(Outcome::Done) => return Ok(()),
+                Err(e) => {
+                    self.config_error = Some(ConfigError {
+                        path: Config::path().unwrap_or_default(),
+                        issues: vec![Issue {
+                            line: None,
+                            key: None,
+                            message: e.to_string(),
+                        }],
+                    });
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Load the messages in the configured language, or in the language of the environment.
+    fn messages(&self) -> Catalog {
+        match &self.config.ui.locale {
+            Some(locale) => Catalog::load(locale),
+            None => Catalog::load(&Catalog::detect()),
+        }
+    }
+
+    /// Show the configuration error screen, and wait for the user to acknowledge it.
+    fn report_config_error<B: Backend>(
+        terminal: &mut Terminal<B>,
+        events: &mut impl FnMut(Option<Duration>) -> io::Result<Input>,
+        theme: &Theme,
+        messages: &Catalog,
+        error: &ConfigError,
+    ) -> anyhow::Result<()> {
+        loop {
+            terminal.draw(|f| {
+                if !render_too_small(f, theme, messages) {
+                    render_config_error(f, theme, messages, error);
+                }
+            })?;
+            if let Input::Event(Event::Key(_)) = events(None)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Obtain the executor and its context, running the deferred initialization if required.
+    ///
+    /// `report` is called with the progress reported so far every time the initialization
+    /// yields with new messages.
+    #[cfg(feature = "tokio")]
+    fn initialize(
+        startup: Startup<T>,
+        runtime: &Runtime,
+        mut report: impl FnMut(&[String]) -> anyhow::Result<()>,
+    ) -> anyhow::Result<(T, T::Context)> {
+        let init = match startup {
+            Startup::Ready(executor, context) => return Ok((executor, context)),
+            Startup::Deferred(init) => init,
+        };
+
+        let (progress, messages) = command::Progress::channel();
+        let mut log = Vec::new();
+        report(&log)?;
+
+        let mut init = init(progress);
+        runtime.block_on(std::future::poll_fn(|cx| {
+            let poll = init.as_mut().poll(cx);
+            let seen = log.len();
+            log.extend(messages.try_iter());
+            if poll.is_pending() && log.len() != seen {
+                if let Err(e) = report(&log) {
+                    return std::task::Poll::Ready(Err(e));
+                }
+            }
+            poll
+        }))
+    }
+
+    /// Obtain the executor and its context, running the deferred initialization if required.
+    #[cfg(not(feature = "tokio"))]
+    fn initialize(
+        startup: Startup<T>,
+        mut report: impl FnMut(&[String]) -> anyhow::Result<()>,
+    ) -> anyhow::Result<(T, T::Context)> {
+        match startup {
+            Startup::Ready(executor, context) => Ok((executor, context)),
+            Startup::Deferred(init) => {
+                report(&[])?;
+                init()
+            }
+        }
+    }
+}
+
+impl<T: command::Execute> Shell<T> {
+    /// Create the shell, idle with an empty history and the default theme.
+    fn new(
+        executor: T,
+        context: T::Context,
+        #[cfg(feature = "tokio")] runtime: Arc<Runtime>,
+        config: Config,
+        messages: Catalog,
+        caps: TermCaps,
+        out: Box<dyn Write>,
+    ) -> Self {
+        Self {
+            builtins: executor.builtins(),
+            executor,
+            context,
+            state: State::default(),
+            #[cfg(feature = "tokio")]
+            runtime,
+            history: Scrollback::new(memory::budget(&config.history)),
+            recorded: 0,
+            user_config: config.clone(),
+            _leftovers: Leftovers(config.jobs.on_exit),
+            config,
+            theme: Theme::default(),
+            theme_generation: 0,
+            cache: RefCell::default(),
+            appearance: Appearance::default(),
+            colors: ColorSupport::default(),
+            overlays: Vec::new(),
+            held: Vec::new(),
+            errors: Vec::new(),
+            messages,
+            cwd: None,
+            title: String::new(),
+            caps,
+            out,
+            macros: Recorder::default(),
+            runs: Runs::default(),
+            notes: BTreeMap::new(),
+            starred: BTreeSet::new(),
+            filter: None,
+            fixes: BTreeMap::new(),
+            warnings: BTreeMap::new(),
+            fixing: None,
+            journaled: None,
+            specs: Specs::default(),
+            kills: Kills::default(),
+            runbook: None,
+            queue: VecDeque::new(),
+            flood: None,
+            streaming: None,
+            size: command::Size::default(),
+            scroll: 0,
+            view: Cell::default(),
+            cached_prompt: RefCell::default(),
+            echoed: None,
+            tabs: Vec::new(),
+            tab: 0,
+            descriptions: BTreeMap::new(),
+            commands: Vec::new(),
+            recall: None,
+            yank: None,
+            count: None,
+            store: None,
+            autosave: None,
+            unsaved: false,
+            environment: BTreeMap::new(),
+            active: Instant::now(),
+            ranker: Box::new(Frecency),
+            renderers: output::builtin(),
+            isolated: false,
+            sourcing: 0,
+            rc: None,
+            project: None,
+            saved_env: Vec::new(),
+            process: config::Process::default(),
+            #[cfg(unix)]
+            subscribers: Vec::new(),
+            publisher: None,
+            #[cfg(feature = "script")]
+            script: None,
+            #[cfg(feature = "suggest")]
+            suggester: None,
+        }
+    }
+
+    /// The prompt, as customized by the user script.
+    fn prompt(&self) -> String {
+        let prompt = match self.executor.remote() {
+            true => (self.cached_prompt.borrow_mut())
+                .get_or_insert_with(|| self.executor.prompt(&self.context))
+                .clone(),
+            false => self.executor.prompt(&self.context),
+        };
+        #[cfg(feature = "script")]
+        if let Some(script) = &self.script {
+            return script.prompt(prompt);
+        }
+
+        prompt
+    }
+
+    /// Load the user script, tell it the shell is ready, and run the rc file. A script, or a
+    /// suggestion backend, that fails to load is reported in the history.
+    ///
+    /// The rc file (`rc` in the configuration directory) holds commands run before the first
+    /// prompt, as with `:source`, showing their output as set by `ui.rc_output`. Commands of the
+    /// rc file cannot exit the shell.
+    fn start(&mut self) {
+        if self.config.ui.hints {
+            self.specs = Specs::load();
+        }
+        if self.config.history.share && self.store.is_none() && !self.isolated {
+            match Store::open() {
+                Ok(store) => self.store = Some(store),
+                Err(e) => self.fail(e),
+            }
+        }
+        #[cfg(feature = "suggest")]
+        if self.suggester.is_none() {
+            match suggest::from_config(&self.config.suggest) {
+                Ok(backend) => self.suggester = backend,
+                Err(e) => self.record("[suggest]".to_string(), Vec::new(), vec![e.to_string()]),
+            }
+        }
+        #[cfg(feature = "script")]
+        match Script::load().filter(|_| !self.isolated) {
+            Some(Ok(script)) => {
+                script.start();
+                self.script = Some(script);
+            }
+            Some(Err(e)) => self.record(script::NAME.to_string(), Vec::new(), vec![e.to_string()]),
+            None => {}
+        }
+        self.start_autosave();
+        self.update_project();
+        if let Some(content) = config::config_dir()
+            .filter(|_| !self.isolated)
+            .map(|dir| dir.join(RC))
+            .and_then(|path| std::fs::read_to_string(path).ok())
+        {
+            self.rc = Some(self.config.ui.rc_output);
+            let _ = self.run_lines(&content, true);
+            self.rc = None;
+        }
+        if self.config.history.suggest_on_empty {
+            self.suggest();
+        }
+    }
+
+    /// Run the shell on a terminal until it exits, reacting to the input returned by `events`.
+    fn drive<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        events: &mut impl FnMut(Option<Duration>) -> io::Result<Input>,
+    ) -> anyhow::Result<String> {
+        let size = terminal.size()?;
+        self.size.set(size.width, size.height);
+        loop {
+            match self.scripted()? {
+                Next::Continue => {}
+                Next::Exit(msg) => break Ok(msg),
+                Next::Clear => terminal.clear()?,
+            }
+            match self.run_queued()? {
+                Next::Continue => {}
+                Next::Exit(msg) => break Ok(msg),
+                Next::Clear => terminal.clear()?,
+            }
+            self.ingest_flood();
+            self.ingest_stream();
+            self.advance_runbook();
+            self.autosave();
+            self.report_cwd()?;
+            self.report_title()?;
+            terminal.draw(|f| self.render(f))?;
+
+            // The input is only waited for as long as the lock screen allows, and not at all while
+            // the output of a command is still coming in, or briefly while it is streamed.
+            let wait = match &self.flood {
+                Some(flood) if !flood.paused => Some(Duration::ZERO),
+                _ if self.streaming.as_ref().is_some_and(|s| !s.paused) => Some(STREAM_POLL),
+                _ => (self.config.lock.after > 0).then_some(TICK),
+            };
+            // The command echoed is run once drawn, the keys typed meanwhile waiting their turn.
+            let next = match self.echoed.take() {
+                Some(_) => self.execute_guarded()?,
+                None => match events(wait)? {
+                    Input::Event(event) => {
+                        if let Event::Resize(width, height) = event {
+                            terminal.resize(Rect::new(0, 0, width, height))?;
+                            self.size.set(width, height);
+                        }
+                        self.active = Instant::now();
+                        self.input(event)?
+                    }
+                    #[cfg(unix)]
+                    Input::Control(call) => self.control(call)?,
+                    Input::Tick => {
+                        self.lock_if_idle();
+                        Next::Continue
+                    }
+                },
+            };
+            match next {
+                Next::Continue => continue,
+                Next::Exit(msg) => break Ok(msg),
+                Next::Clear => {
+                    terminal.clear()?;
+                    continue;
+                }
+            }
+        }
+    }
+
+    /// Apply the changes requested by the user script, and record what it printed. Commands run
+    /// by the script can call its hooks again, so this goes on for a few rounds at most.
+    fn scripted(&mut self) -> anyhow::Result<Next> {
+        #[cfg(feature = "script")]
+        for _ in 0..SCRIPT_ROUNDS {
+            let Some((effects, output, errors)) = self.script.as_ref().map(Script::take) else {
+                break;
+            };
+            if effects.is_empty() && output.is_empty() && errors.is_empty() {
+                break;
+            }
+            if !output.is_empty() {
+                self.record(script::NAME.to_string(), output, Vec::new());
+            }
+            for error in errors {
+                self.fail(anyhow::anyhow!("{}: {}", script::NAME, error));
+            }
+
+            for effect in effects {
+                let next = match effect {
+                    Effect::Run(command) => match self.inject(&command, &[]) {
+                        Some(next) => next?,
+                        None => Next::Continue,
+                    },
+                    Effect::Action(action) => self.perform(action)?,
+                    Effect::Bind(key, action) => {
+                        let mut keys = self.config.keys.keys(action);
+                        if !keys.contains(&key) {
+                            keys.push(key);
+                        }
+                        self.config.keys.bind(action, keys.clone());
+                        self.user_config.keys.bind(action, keys);
+                        Next::Continue
+                    }
+                };
+                if !matches!(next, Next::Continue) {
+                    return Ok(next);
+                }
+            }
+        }
+
+        Ok(Next::Continue)
+    }
+
+    /// Print the output of the entries added to the history since `recorded` entries were
+    /// recorded, in screen reader mode, along with the internal errors reported since, which have
+    /// no banner to stay in.
+    fn print_entries(&mut self, out: &mut impl Write, recorded: usize) -> io::Result<()> {
+        let added = self.recorded - recorded;
+        let skipped = self.history.len().saturating_sub(added);
+        let first = self.recorded - self.history.len();
+        for (index, entry) in self.history.iter().enumerate().skip(skipped) {
+            for line in &entry.stdout {
+                writeln!(out, "{} {}", marker::STDOUT, output::strip(line))?;
+            }
+            for line in &entry.stderr {
+                writeln!(out, "{} {}", marker::STDERR, output::strip(line))?;
+            }
+            for warning in self.warnings.get(&(first + index)).into_iter().flatten() {
+                writeln!(out, "{} {}", marker::WARNING, warning)?;
+            }
+        }
+
+        for (message, causes) in std::mem::take(&mut self.errors) {
+            writeln!(out, "{} {}", marker::ERROR, message)?;
+            for cause in causes {
+                writeln!(out, "{}   {}", marker::ERROR, cause)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The wrapped lines of the history among `rows`, counted up from its bottom, oldest first.
+    fn render_history(&self, width: u16, rows: Range<usize>) -> Vec<Line<'static>> {
+        let entries = self.shown_entries(width, rows);
+        entries
+            .into_iter()
+            .rev()
+            .flat_map(|shown| shown.lines)
+            .collect()
+    }
+
+    /// The entries of the history shown, the newest first, up to the end of `rows`, counted up
+    /// from the bottom of the history. Only their lines among `rows` are taken from the cache, so
+    /// that an entry longer than the screen is not copied whole. The header of a run is drawn
+    /// above the first entry of it shown.
+    fn shown_entries(&self, width: u16, rows: Range<usize>) -> Vec<Shown> {
+        let first = self.recorded - self.history.len();
+        let mut cache = self.cache.borrow_mut();
+        cache.prepare(width, self.theme_generation, first);
+
+        let mut entries: Vec<Shown> = Vec::new();
+        let mut gathered = 0;
+        // The run of the entries gathered last, its header drawn once an earlier entry is not in
+        // it.
+        let mut header = None;
+        for index in (0..self.history.len()).rev() {
+            if gathered >= rows.end {
+                break;
+            }
+            if let Some(filter) = &self.filter {
+                if !filter.matches(
+                    &self.history,
+                    index,
+                    self.starred.contains(&(first + index)),
+                ) {
+                    continue;
+                }
+            }
+            let run = self.runs.containing(first + index);
+            if header.map(|(position, _)| position) != run.map(|(position, _)| position) {
+                gathered += self.run_header(header.take(), &mut entries, gathered, &rows);
+            }
+            if let Some((position, run)) = run {
+                header = Some((position, run));
+                // The entries of a folded run are left out, save for its header.
+                if run.folded {
+                    entries.push(Shown {
+                        rows: 0,
+                        lines: Vec::new(),
+                    });
+                    continue;
+                }
+            }
+            // Compressed entries are only expanded when their lines are not kept already.
+            let wrapped = |width| match self.history.get(index) {
+                Some(entry) => {
+                    let fixes = self
+                        .fixes
+                        .get(&(first + index))
+                        .and_then(|fixes| self.history.command(fixes.checked_sub(first)?));
+                    let comment = self.is_comment(&entry.command)
+                        && entry.stdout.is_empty()
+                        && entry.stderr.is_empty();
+                    HistoryEntryWidget::new(&entry, &self.theme)
+                        .renderers(&self.renderers)
+                        .comment(comment)
+                        .note(self.notes.get(&(first + index)).map(String::as_str))
+                        .starred(self.starred.contains(&(first + index)))
+                        .fixes(fixes.as_deref())
+                        .warnings(
+                            self.warnings
+                                .get(&(first + index))
+                                .map_or(&[], Vec::as_slice),
+                        )
+                        .wrapped(width)
+                }
+                None => Vec::new(),
+            };
+            let lines = cache.lines(first + index, wrapped);
+            // The lines of the entry among the rows wanted: its line `i` is on the row
+            // `end - 1 - i`.
+            let end = gathered + lines.len();
+            let taken = |row: usize| end.saturating_sub(row).min(lines.len());
+            entries.push(Shown {
+                rows: lines.len(),
+                lines: lines[taken(rows.end)..taken(rows.start)].to_vec(),
+            });
+            gathered = end;
+        }
+        self.run_header(header, &mut entries, gathered, &rows);
+
+        entries
+    }
+
+    /// Draw the header of a run above the last entry gathered, the first of the run shown, on
+    /// the row `gathered` counted up from the bottom, if among `rows`. Returns the number of rows
+    /// added.
+    fn run_header(
+        &self,
+        run: Option<(usize, &Run)>,
+        entries: &mut [Shown],
+        gathered: usize,
+        rows: &Range<usize>,
+    ) -> usize {
+        let (Some((_, run)), Some(shown)) = (run, entries.last_mut()) else {
+            return 0;
+        };
+        shown.rows += 1;
+        if rows.contains(&gathered) {
+            let marker = if run.folded { '▸' } else { '▾' };
+            let title = self
+                .messages
+                .format("run-header", &[("name", &run.name), ("count", &run.len())]);
+            shown.lines.insert(
+                0,
+                Line::styled(format!("{} {}", marker, title), self.theme.hint),
+            );
+        }
+        1
+    }
+
+    /// Render the shell.
+    fn render(&self, frame: &mut Frame) {
+        if render_too_small(frame, &self.theme, &self.messages) {
+            return;
+        }
+        let prompt = self.prompt();
+        let mut area = frame.area();
+        if let Some((error, _)) = self.errors.last().filter(|_| area.height > 1) {
+            let banner = Rect { height: 1, ..area };
+            area = Rect {
+                y: area.y + 1,
+                height: area.height - 1,
+                ..area
+            };
+            self.render_banner(frame, banner, error);
+        }
+        if !self.tabs.is_empty() && area.height > 1 {
+            let bar = Rect { height: 1, ..area };
+            area = Rect {
+                y: area.y + 1,
+                height: area.height - 1,
+                ..area
+            };
+            self.render_tabs(frame, bar);
+        }
+        // Scrolled up, the bottom line tells how far the prompt is.
+        if self.scroll > 0 && area.height > 1 {
+            area.height -= 1;
+            let bar = Rect {
+                y: area.y + area.height,
+                height: 1,
+                ..area
+            };
+            let key = self.key_label(Action::ScrollDown);
+            let text = self
+                .messages
+                .format("scrolled", &[("rows", &self.scroll), ("key", &key)]);
+            let line = Line::styled(text, self.theme.hint);
+            frame.render_widget(Paragraph::new(line).alignment(Alignment::Right), bar);
+        }
+        // The lines below the history: the prompt and the lines around it.
+        let mut text_content = Vec::new();
+        if self.macros.is_recording() {
+            let key = self.key_label(Action::RecordMacro);
+            text_content.push(Line::styled(
+                self.messages.format("macro-recording", &[("key", &key)]),
+                self.theme.hint,
+            ));
+        }
+        if let Some(run) = self.runs.open() {
+            text_content.push(Line::styled(
+                self.messages.format("run-open", &[("name", &run.name)]),
+                self.theme.hint,
+            ));
+        }
+        if let Some(filter) = &self.filter {
+            let indicator = match filter {
+                Filter::Failed => self.messages.get("filter-failed"),
+                Filter::Starred => {
+                    let key = self.key_label(Action::ShowStarred);
+                    self.messages.format("starred-only", &[("key", &key)])
+                }
+                Filter::Command(pattern) => self
+                    .messages
+                    .format("filter-command", &[("pattern", pattern)]),
+            };
+            text_content.push(Line::styled(indicator, self.theme.hint));
+        }
+
+        match &self.state {
+            State::Idle(ref line, _) if self.echoed.is_some() => {
+                text_content.push(Line::from(vec![
+                    Span::styled(prompt.clone(), self.theme.prompt),
+                    Span::raw(" "),
+                    Span::styled(line.to_string(), self.theme.hint),
+                ]));
+                if let Some(Some(predicted)) = &self.echoed {
+                    let style = self.theme.prompt.add_modifier(Modifier::UNDERLINED);
+                    text_content.push(Line::styled(predicted.clone(), style));
+                }
+
+                self.render_scrolled(frame, text_content, area);
+            }
+            State::Idle(ref line, comp) => {
+                let cmd = line.as_str();
+                let hint = match self
+                    .registered(cmd)
+                    .and_then(|builtin| builtin.usage.as_ref())
+                {
+                    Some(usage) if self.config.ui.hints => Some(usage.clone()),
+                    _ => self.specs.hint(cmd, line.cursor()),
+                };
+                let mut spans = vec![
+                    Span::styled(prompt.clone(), self.theme.prompt),
+                    Span::raw(" "),
+                ];
+                let comment = match cmd.starts_with('#') && self.describes() {
+                    true => None,
+                    false => self.executor.syntax().comment_start(cmd),
+                };
+                spans.extend(render_line(line, self.theme.command, &self.theme, comment));
+                text_content.push(Line::from(spans));
+
+                if let Some(question) = self.question() {
+                    text_content.push(Line::styled(question, self.theme.hint));
+                } else if let Some(comp) = comp.as_ref().filter(|comp| !comp.is_empty()) {
+                    let lines = comp
+                        .iter()
+                        .map(|cmp| cmd.to_string() + cmp.as_str())
+                        .collect::<Vec<_>>();
+                    // The descriptions are lined up in a column after the longest completion.
+                    let width = lines.iter().map(|line| line.chars().count()).max();
+                    let completions = lines
+                        .into_iter()
+                        .map(|line| {
+                            let description = self.descriptions.get(&line).map(|description| {
+                                let pad = width.unwrap_or_default() - line.chars().count() + 2;
+                                let text = format!("{}{}", " ".repeat(pad), description);
+                                Span::styled(text, self.theme.hint)
+                            });
+                            let mut spans = vec![Span::styled(line, self.theme.completion)];
+                            spans.extend(description);
+                            Line::from(spans)
+                        })
+                        .collect::<Vec<_>>();
+                    text_content.extend(completions);
+                } else if let Some(hint) = hint {
+                    // The hint stays on one line, cut at the edge of the screen.
+                    let width = usize::from(area.width);
+                    let hint = match hint.chars().count() > width {
+                        true => {
+                            let cut = hint.chars().take(width.saturating_sub(1));
+                            cut.chain(['…']).collect()
+                        }
+                        false => hint,
+                    };
+                    text_content.push(Line::styled(hint, self.theme.hint));
+                }
+
+                self.render_scrolled(frame, text_content, area);
+            }
+            State::Running(ref prep, stdin, line) => {
+                text_content.push(Line::from(vec![
+                    Span::styled(prompt.clone(), self.theme.prompt),
+                    Span::raw(" "),
+                    Span::styled(prep.command.clone(), self.theme.command),
+                ]));
+                // The last lines streamed that fit above the line being typed.
+                if let Some(streaming) = &self.streaming {
+                    let taken = text_content.len()
+                        + stdin.len()
+                        + 1
+                        + self.queue.len()
+                        + usize::from(streaming.paused);
+                    let room = usize::from(area.height).saturating_sub(taken);
+                    let skipped = streaming.tail.len().saturating_sub(room);
+                    text_content.extend(streaming.tail.iter().skip(skipped).filter_map(|chunk| {
+                        match chunk {
+                            command::Chunk::Stdout(line) => {
+                                Some(output::colored(line, self.theme.stdout))
+                            }
+                            command::Chunk::Stderr(line) => {
+                                Some(output::colored(line, self.theme.stderr))
+                            }
+                            command::Chunk::Exit(_) => None,
+                        }
+                    }));
+                    if streaming.paused {
+                        let key = self.key_label(Action::PauseOutput);
+                        let text = self.messages.format("stream-paused", &[("key", &key)]);
+                        text_content.push(Line::styled(text, self.theme.hint));
+                    }
+                }
+                let stdin = stdin
+                    .iter()
+                    .map(|line| Span::styled(line, self.theme.stdin))
+                    .map(Line::from)
+                    .collect::<Vec<_>>();
+                text_content.extend(stdin);
+                text_content.push(Line::from(render_line(
+                    line,
+                    self.theme.stdin,
+                    &self.theme,
+                    None,
+                )));
+                text_content.extend(self.queue.iter().map(|command| {
+                    Line::styled(
+                        self.messages.format("queue-entry", &[("command", command)]),
+                        self.theme.hint,
+                    )
+                }));
+
+                self.render_scrolled(frame, text_content, area);
+            }
+        }
+
+        for overlay in &self.overlays {
+            if overlay.boxed() {
+                let area = frame.area();
+                frame
+                    .buffer_mut()
+                    .set_style(area, Style::new().add_modifier(Modifier::DIM));
+            }
+            self.render_overlay(frame, overlay);
+        }
+    }
+
+    /// Render the history, followed by the lines below it, scrolled up by [`Shell::scroll`] rows
+    /// from the bottom. Only the lines of the history on screen are taken, already wrapped, from
+    /// the cache: the lines below it, few, are wrapped as they are drawn.
+    fn render_scrolled(&self, frame: &mut Frame, below: Vec<Line>, area: Rect) {
+        let paragraph = Paragraph::new(below).wrap(Wrap { trim: false });
+        let below = paragraph.line_count(area.width);
+        let height = usize::from(area.height);
+        // Scrolled up, the history leaves its bottom line to tell how far the prompt is.
+        let scrolled = height - usize::from(self.scroll == 0 && height > 1);
+        self.view.set((scrolled, below));
+
+        // The rows on screen, counted up from the bottom of the history.
+        let rows = self.scroll.saturating_sub(below)..(self.scroll + height).saturating_sub(below);
+        let history = self.render_history(area.width, rows);
+        let shown = u16::try_from(history.len()).unwrap_or(area.height);
+        frame.render_widget(
+            Paragraph::new(history),
+            Rect {
+                height: shown,
+                ..area
+            },
+        );
+
+        // The lines below the history are drawn after it, their first rows hidden when the
+        // history is scrolled up by fewer rows than they take.
+        let hidden = below.saturating_sub(self.scroll + height);
+        let area = Rect {
+            y: area.y + shown,
+            height: area.height - shown,
+            ..area
+        };
+        let hidden = u16::try_from(hidden).unwrap_or(u16::MAX);
+        frame.render_widget(paragraph.scroll((hidden, 0)), area);
+    }
+
+    /// Render the tabs, on a line of their own: each by its position, with the name of its
+    /// working directory, if any.
+    fn render_tabs(&self, frame: &mut Frame, area: Rect) {
+        let contexts = self.tabs.iter().map(|session| &session.context);
+        let mut contexts = contexts.collect::<Vec<_>>();
+        contexts.insert(self.tab, &self.context);
+        let spans = contexts.into_iter().enumerate().map(|(index, context)| {
+            let cwd = self.executor.cwd(context);
+            let name = cwd.as_deref().and_then(Path::file_name);
+            let label = match name {
+                Some(name) => format!(" {}: {} ", index + 1, name.to_string_lossy()),
+                None => format!(" {} ", index + 1),
+            };
+            match index == self.tab {
+                true => Span::styled(label, self.theme.selection),
+                false => Span::styled(label, self.theme.hint),
+            }
+        });
+        frame.render_widget(Paragraph::new(Line::from(spans.collect::<Vec<_>>())), area);
+    }
+
+    /// Render the banner of the last error reported, with the keys to show the details of the
+    /// errors and to dismiss them.
+    fn render_banner(&self, frame: &mut Frame, area: Rect, error: &str) {
+        let details = self.key_label(Action::ShowErrors);
+        let dismiss = self.key_label(Action::DismissErrors);
+        let banner = |error: &str| match self.errors.len() {
+            1 => self.messages.format(
+                "error-banner",
+                &[
+                    ("error", &error),
+                    ("details", &details),
+                    ("dismiss", &dismiss),
+                ],
+            ),
+            count => self.messages.format(
+                "error-banner-many",
+                &[
+                    ("error", &error),
+                    ("more", &(count - 1)),
+                    ("details", &details),
+                    ("dismiss", &dismiss),
+                ],
+            ),
+        };
+        // The error is cut rather than the keys, for the banner to stay on one line.
+        let error = error.lines().next().unwrap_or_default();
+        let excess = banner(error)
+            .chars()
+            .count()
+            .saturating_sub(usize::from(area.width));
+        let banner = match excess {
+            0 => banner(error),
+            excess => {
+                let kept = error.chars().count().saturating_sub(excess + 1);
+                banner(&error.chars().take(kept).chain(['…']).collect::<String>())
+            }
+        };
+        let style = self.theme.error.add_modifier(Modifier::REVERSED);
+        frame.buffer_mut().set_style(area, style);
+        frame.render_widget(Paragraph::new(banner).style(style), area);
+    }
+
+    /// Render the overlay on top of the shell.
+    fn render_overlay(&self, frame: &mut Frame, overlay: &Overlay) {
+        match overlay {
+            Overlay::Themes(selected, _) => {
+                let lines = theme::BUILTIN
+                    .iter()
+                    .enumerate()
+                    .map(|(index, name)| match index == *selected {
+                        true => Line::styled(format!(" {} ", name), self.theme.selection),
+                        false => Line::raw(format!(" {} ", name)),
+                    })
+                    .collect::<Vec<_>>();
+                let area = centered(frame.area(), 30, lines.len() as u16 + 2);
+                let block = Block::bordered()
+                    .title(Span::styled(
+                        format!(" {} ", self.messages.get("themes-title")),
+                        self.theme.title,
+                    ))
+                    .border_style(self.theme.border);
+
+                frame.render_widget(Clear, area);
+                frame.render_widget(Paragraph::new(lines).block(block), area);
+            }
+            Overlay::Help => {
+                let lines = render_keymap(&self.config.keys, &self.theme, &self.messages);
+                let width = lines.iter().map(Line::width).max().unwrap_or_default() as u16;
+                let area = centered(frame.area(), width + 4, lines.len() as u16 + 2);
+                let block = Block::bordered()
+                    .title(Span::styled(
+                        format!(" {} ", self.messages.get("help-title")),
+                        self.theme.title,
+                    ))
+                    .border_style(self.theme.border);
+
+                frame.render_widget(Clear, area);
+                frame.render_widget(Paragraph::new(lines).block(block), area);
+            }
+            Overlay::Pager(pager) => {
+                let area = frame.area();
+                let area = centered(
+                    area,
+                    area.width.saturating_sub(4),
+                    area.height.saturating_sub(2),
+                );
+                pager.render(frame, area, &self.theme, &self.messages);
+            }
+            Overlay::Explanation(command, parts) => {
+                let width = parts.iter().map(|(part, _)| part.chars().count()).max();
+                let lines = parts
+                    .iter()
+                    .map(|(part, meaning)| match part.as_str() {
+                        "" => Line::styled(meaning.clone(), self.theme.stdout),
+                        part => Line::from(vec![
+                            Span::styled(
+                                format!("{:width$}  ", part, width = width.unwrap_or_default()),
+                                self.theme.command,
+                            ),
+                            Span::styled(meaning.clone(), self.theme.stdout),
+                        ]),
+                    })
+                    .collect::<Vec<_>>();
+                let area = frame.area();
+                let width = lines.iter().map(Line::width).max().unwrap_or_default() as u16;
+                let area = centered(area, (width + 4).min(area.width), lines.len() as u16 + 2);
+                let block = Block::bordered()
+                    .title(Span::styled(format!(" {} ", command), self.theme.title))
+                    .border_style(self.theme.border);
+
+                frame.render_widget(Clear, area);
+                frame.render_widget(
+                    Paragraph::new(lines)
+                        .wrap(Wrap { trim: false })
+                        .block(block),
+                    area,
+                );
+            }
+            Overlay::Search(query, selected, matches) => {
+                let area = frame.area();
+                let width = area.width.saturating_sub(4).min(100);
+                let mut lines = vec![Line::from(vec![
+                    Span::styled("> ", self.theme.prompt),
+                    Span::styled(query.clone(), self.theme.command),
+                    Span::styled(" ", self.theme.cursor),
+                ])];
+                lines.extend(matches.iter().enumerate().map(|(index, command)| {
+                    match index == *selected {
+                        true => Line::styled(format!(" {} ", command), self.theme.selection),
+                        false => Line::raw(format!(" {} ", command)),
+                    }
+                }));
+                if matches.is_empty() {
+                    lines.push(Line::styled(
+                        self.messages.get("search-none"),
+                        self.theme.hint,
+                    ));
+                }
+                lines.push(Line::styled(
+                    self.messages.get("search-hint"),
+                    self.theme.hint,
+                ));
+                let area = centered(area, width, lines.len() as u16 + 2);
+                let block = Block::bordered()
+                    .title(Span::styled(
+                        format!(" {} ", self.messages.get("search-title")),
+                        self.theme.title,
+                    ))
+                    .border_style(self.theme.border);
+
+                frame.render_widget(Clear, area);
+                frame.render_widget(Paragraph::new(lines).block(block), area);
+            }
+            Overlay::Queue(selected) => {
+                let area = frame.area();
+                let width = area.width.saturating_sub(4).min(100);
+                let rows = usize::from(area.height.saturating_sub(5)).max(1);
+                let first = selected.saturating_sub(rows - 1);
+                let mut lines = self
+                    .queue
+                    .iter()
+                    .enumerate()
+                    .skip(first)
+                    .take(rows)
+                    .map(|(index, command)| match index == *selected {
+                        true => Line::styled(format!(" {} ", command), self.theme.selection),
+                        false => Line::styled(format!(" {} ", command), self.theme.command),
+                    })
+                    .collect::<Vec<_>>();
+                lines.push(Line::styled(
+                    self.messages.get("queue-hint"),
+                    self.theme.hint,
+                ));
+                let area = centered(area, width, lines.len() as u16 + 2);
+                let block = Block::bordered()
+                    .title(Span::styled(
+                        format!(" {} ", self.messages.get("queue-title")),
+                        self.theme.title,
+                    ))
+                    .border_style(self.theme.border);
+
+                frame.render_widget(Clear, area);
+                frame.render_widget(Paragraph::new(lines).block(block), area);
+            }
+            Overlay::Kills(selected) => {
+                let area = frame.area();
+                let width = area.width.saturating_sub(4).min(100);
+                let rows = usize::from(area.height.saturating_sub(5)).max(1);
+                let first = selected.saturating_sub(rows - 1);
+                // A snippet holds on one line, its first one, cut at the edge of the box.
+                let fits = usize::from(width.saturating_sub(4));
+                let mut lines = self
+                    .kills
+                    .iter()
+                    .enumerate()
+                    .skip(first)
+                    .take(rows)
+                    .map(|(index, snippet)| {
+                        let mut shown = snippet.lines().next().unwrap_or_default().to_string();
+                        if shown.chars().count() > fits || snippet.lines().nth(1).is_some() {
+                            shown = shown.chars().take(fits.saturating_sub(1)).collect();
+                            shown.push('…');
+                        }
+                        match index == *selected {
+                            true => Line::styled(format!(" {} ", shown), self.theme.selection),
+                            false => Line::raw(format!(" {} ", shown)),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                lines.push(Line::styled(
+                    self.messages.get("kills-hint"),
+                    self.theme.hint,
+                ));
+                let area = centered(area, width, lines.len() as u16 + 2);
+                let block = Block::bordered()
+                    .title(Span::styled(
+                        format!(" {} ", self.messages.get("kills-title")),
+                        self.theme.title,
+                    ))
+                    .border_style(self.theme.border);
+
+                frame.render_widget(Clear, area);
+                frame.render_widget(Paragraph::new(lines).block(block), area);
+            }
+            Overlay::Copy(entry, anchor, cursor) => {
+                let area = frame.area();
+                let width = area.width.saturating_sub(4).min(100);
+                let command = self.history.command(*entry).unwrap_or_default();
+                let (start, end) = (*anchor.min(cursor), *anchor.max(cursor));
+                // With nothing selected, the character under the cursor is shown as the cursor.
+                let (end, style) = match start == end {
+                    true => {
+                        let under = command[end..].chars().next();
+                        (end + under.map_or(0, char::len_utf8), self.theme.cursor)
+                    }
+                    false => (end, self.theme.selection),
+                };
+                let selected = match &command[start..end] {
+                    "" => " ",
+                    selected => selected,
+                };
+                let lines = vec![
+                    Line::from(vec![
+                        Span::styled(&command[..start], self.theme.command),
+                        Span::styled(selected, style),
+                        Span::styled(&command[end..], self.theme.command),
+                    ]),
+                    Line::styled(self.messages.get("copy-hint"), self.theme.hint),
+                ];
+                let inner = usize::from(width.saturating_sub(2)).max(1);
+                let rows = lines
+                    .iter()
+                    .map(|line| line.width().max(1).div_ceil(inner))
+                    .sum::<usize>();
+                let area = centered(area, width, rows as u16 + 2);
+                let block = Block::bordered()
+                    .title(Span::styled(
+                        format!(" {} ", self.messages.get("copy-title")),
+                        self.theme.title,
+                    ))
+                    .border_style(self.theme.border);
+
+                frame.render_widget(Clear, area);
+                frame.render_widget(
+                    Paragraph::new(lines)
+                        .wrap(Wrap { trim: false })
+                        .block(block),
+                    area,
+                );
+            }
+            Overlay::Pick(query, selected, picked, _) => {
+                let area = frame.area();
+                let width = area.width.saturating_sub(4).min(100);
+                let shown = fuzzy::filter(query, picked);
+                // The rows fit the screen, scrolling along with the selection.
+                let rows = usize::from(area.height.saturating_sub(6)).max(1);
+                let first = selected.saturating_sub(rows - 1);
+                let mut lines = vec![Line::from(vec![
+                    Span::styled("> ", self.theme.prompt),
+                    Span::styled(query.clone(), self.theme.command),
+                    Span::styled(" ", self.theme.cursor),
+                ])];
+                lines.extend(shown.iter().enumerate().skip(first).take(rows).map(
+                    |(index, &line)| match index == *selected {
+                        true => Line::styled(format!(" {} ", picked[line]), self.theme.selection),
+                        false => Line::raw(format!(" {} ", picked[line])),
+                    },
+                ));
+                if shown.is_empty() {
+                    lines.push(Line::styled(
+                        self.messages.get("pick-none"),
+                        self.theme.hint,
+                    ));
+                }
+                lines.push(Line::styled(
+                    self.messages.get("search-hint"),
+                    self.theme.hint,
+                ));
+                let area = centered(area, width, lines.len() as u16 + 2);
+                let block = Block::bordered()
+                    .title(Span::styled(
+                        format!(" {} ", self.messages.get("pick-title")),
+                        self.theme.title,
+                    ))
+                    .border_style(self.theme.border);
+
+                frame.render_widget(Clear, area);
+                frame.render_widget(Paragraph::new(lines).block(block), area);
+            }
+            Overlay::Processes(query, selected, processes) => {
+                let area = frame.area();
+                let width = area.width.saturating_sub(4).min(80);
+                let shown = filter_processes(query, processes);
+                // The rows fit the screen, scrolling along with the selection.
+                let rows = usize::from(area.height.saturating_sub(7)).max(1);
+                let first = selected.saturating_sub(rows - 1);
+                let mut lines = vec![
+                    Line::from(vec![
+                        Span::styled("> ", self.theme.prompt),
+                        Span::styled(query.clone(), self.theme.command),
+                        Span::styled(" ", self.theme.cursor),
+                    ]),
+                    Line::styled(
+                        format!(
+                            " {:>7} {:>6} {:>6}  {}",
+                            "PID",
+                            "CPU%",
+                            "MEM%",
+                            self.messages.get("processes-name")
+                        ),
+                        self.theme.hint,
+                    ),
+                ];
+                lines.extend(shown.iter().enumerate().skip(first).take(rows).map(
+                    |(index, process)| {
+                        let row = format!(
+                            " {:>7} {:>6.1} {:>6.1}  {} ",
+                            process.pid, process.cpu, process.memory, process.name
+                        );
+                        match index == *selected {
+                            true => Line::styled(row, self.theme.selection),
+                            false => Line::raw(row),
+                        }
+                    },
+                ));
+                if shown.is_empty() {
+                    lines.push(Line::styled(
+                        self.messages.get("processes-none"),
+                        self.theme.hint,
+                    ));
+                }
+                lines.push(Line::styled(
+                    self.messages.get("processes-hint"),
+                    self.theme.hint,
+                ));
+                let area = centered(area, width, lines.len() as u16 + 2);
+                let block = Block::bordered()
+                    .title(Span::styled(
+                        format!(" {} ", self.messages.get("processes-title")),
+                        self.theme.title,
+                    ))
+                    .border_style(self.theme.border);
+
+                frame.render_widget(Clear, area);
+                frame.render_widget(Paragraph::new(lines).block(block), area);
+            }
+            Overlay::Lock(typed, failed) => {
+                let mut lines = vec![Line::styled(
+                    self.messages.get("lock-title"),
+                    self.theme.title,
+                )];
+                match &self.config.lock.passphrase_sha256 {
+                    Some(_) => {
+                        lines.push(Line::styled(
+                            self.messages.get("lock-passphrase"),
+                            self.theme.hint,
+                        ));
+                        let masked = "•".repeat(typed.chars().count());
+                        lines.push(Line::from(vec![
+                            Span::styled(masked, self.theme.command),
+                            Span::styled(" ", self.theme.cursor),
+                        ]));
+                        if *failed {
+                            lines.push(Line::styled(
+                                self.messages.get("lock-wrong"),
+                                self.theme.error,
+                            ));
+                        }
+                    }
+                    None => lines.push(Line::styled(
+                        self.messages.get("lock-any-key"),
+                        self.theme.hint,
+                    )),
+                }
+                // The whole screen is blanked, so nothing of the history shows around the box.
+                let area = frame.area();
+                frame.render_widget(Clear, area);
+                let width = lines.iter().map(Line::width).max().unwrap_or_default() as u16;
+                let area = centered(area, width + 4, lines.len() as u16 + 2);
+                let block = Block::bordered().border_style(self.theme.border);
+                frame.render_widget(Paragraph::new(lines).centered().block(block), area);
+            }
+            Overlay::Errors => {
+                let area = frame.area();
+                let width = area.width.saturating_sub(4).min(100);
+                let mut lines = Vec::new();
+                for (index, (message, causes)) in self.errors.iter().enumerate() {
+                    if index > 0 {
+                        lines.push(Line::raw(""));
+                    }
+                    lines.extend(
+                        message
+                            .lines()
+                            .map(|line| Line::styled(line.to_string(), self.theme.error)),
+                    );
+                    lines.extend(
+                        causes
+                            .iter()
+                            .map(|cause| Line::styled(format!("  {}", cause), self.theme.stdout)),
+                    );
+                }
+                let area = centered(area, width, lines.len() as u16 + 2);
+                let block = Block::bordered()
+                    .title(Span::styled(
+                        format!(" {} ", self.messages.get("errors-title")),
+                        self.theme.title,
+                    ))
+                    .border_style(self.theme.border);
+
+                frame.render_widget(Clear, area);
+                frame.render_widget(
+                    Paragraph::new(lines)
+                        .wrap(Wrap { trim: false })
+                        .block(block),
+                    area,
+                );
+            }
+            // Asked below the prompt. (See `Shell::render`)
+            Overlay::Correction(_)
+            | Overlay::Guard(_)
+            | Overlay::Trust(_)
+            | Overlay::Recover(..)
+            | Overlay::Runbook
+            | Overlay::Flood => {}
+            #[cfg(feature = "suggest")]
+            Overlay::Suggestion(command) => {
+                let lines = vec![
+                    Line::styled(command.clone(), self.theme.command),
+                    Line::raw(""),
+                    Line::styled(self.messages.get("suggest-hint"), self.theme.hint),
+                ];
+                let width = lines.iter().map(Line::width).max().unwrap_or_default() as u16;
+                let area = centered(frame.area(), width + 4, lines.len() as u16 + 2);
+                let block = Block::bordered()
+                    .title(Span::styled(
+                        format!(" {} ", self.messages.get("suggest-title")),
+                        self.theme.title,
+                    ))
+                    .border_style(self.theme.border);
+
+                frame.render_widget(Clear, area);
+                frame.render_widget(
+                    Paragraph::new(lines)
+                        .wrap(Wrap { trim: false })
+                        .block(block),
+                    area,
+                );
+            }
+        }
+    }
+
+    /// Handle the input from the user.
+    fn input(&mut self, event: Event) -> anyhow::Result<Next> {
+        if let Event::Key(ke) = &event {
+            let action = self.config.keys.action(ke, self.editing());
+            let controls = matches!(action, Some(Action::RecordMacro | Action::PlayMacro));
+            let locked = matches!(self.overlays.last(), Some(Overlay::Lock(..)));
+            if (!self.overlays.is_empty() || !controls) && !locked {
+                self.macros.record(Key::from(ke));
+            }
+        }
+
+        if let Some(overlay) = self.overlays.pop() {
+            self.count = None;
+            return self.overlay_input(overlay, event);
+        }
+
+        let ke = match event {
+            Event::Key(ke) => ke,
+            Event::Mouse(mouse) => {
+                match mouse.kind {
+                    MouseEventKind::ScrollUp => self.scroll_by(true, WHEEL),
+                    MouseEventKind::ScrollDown => self.scroll_by(false, WHEEL),
+                    _ => {}
+                }
+                return Ok(Default::default());
+            }
+            Event::Paste(text) => {
+                let text = paste::normalize(&text, self.executor.syntax());
+                self.edit(|line| {
+                    line.insert_str(&text);
+                    !text.is_empty()
+                });
+                return Ok(Default::default());
+            }
+            _ => return Ok(Default::default()),
+        };
+
+        // A key bound to text types it, as if pasted, and runs it if bound in `[keys.run]`.
+        if let Some((text, run)) = self.config.keys.text(&ke, self.editing()) {
+            let text = text.to_string();
+            self.count = None;
+            self.recall = None;
+            self.yank = None;
+            self.edit(|line| {
+                line.insert_str(&text);
+                !text.is_empty()
+            });
+            return match run {
+                true => self.perform(Action::Submit),
+                false => Ok(Default::default()),
+            };
+        }
+
+        let action = self.config.keys.action(&ke, self.editing());
+        // `Alt+digit`, unless bound, adds a digit to the count of the next action.
+        if let (None, KeyCode::Char(c), KeyModifiers::ALT) = (action, ke.code, ke.modifiers) {
+            if let Some(digit) = c.to_digit(10) {
+                let count = self.count.unwrap_or_default() * 10 + digit as usize;
+                self.count = Some(count.min(MAX_COUNT));
+                return Ok(Default::default());
+            }
+        }
+        let count = self.count.take().unwrap_or(1);
+
+        if !matches!(action, Some(Action::HistoryPrevious | Action::HistoryNext)) {
+            self.recall = None;
+        }
+        if action != Some(Action::InsertLastArgument) {
+            self.yank = None;
+        }
+        match action {
+            Some(action) if action.repeats() => {
+                for _ in 1..count {
+                    self.perform(action)?;
+                }
+                return self.perform(action);
+            }
+            Some(action) => return self.perform(action),
+            None => {
+                if let (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) =
+                    (ke.code, ke.modifiers)
+                {
+                    for _ in 0..count.max(1) {
+                        self.insert_char(c);
+                    }
+                }
+            }
+        }
+
+        Ok(Default::default())
+    }
+
+    /// The commands recalled with `Up` and `Alt+.`, oldest first. The journal is read every time,
+    /// picking up the commands run in the other sessions since.
+    fn recent(&self) -> Vec<String> {
+        match &self.store {
+            Some(store) => store
+                .recent(RECALL)
+                .unwrap_or_else(|_| self.commands.clone()),
+            None => self.commands.clone(),
+        }
+    }
+
+    /// Scroll the history to the start of the entry before the first one shown, or after it,
+    /// passing over the entries hidden by the filter.
+    fn jump_prompt(&mut self, back: bool) {
+        let (height, below) = self.view.get();
+        let (width, _) = self.size.get();
+        // The rows from the start of each entry to the bottom, the newest entry first.
+        let starts = self
+            .shown_entries(width, usize::MAX..usize::MAX)
+            .into_iter()
+            .scan(below, |rows, shown| {
+                *rows += shown.rows;
+                Some(*rows)
+            })
+            .collect::<Vec<_>>();
+        let top = self.scroll + height;
+        let target = match back {
+            true => starts.iter().find(|&&start| start > top),
+            false => starts.iter().rev().find(|&&start| start < top),
+        };
+        if let Some(target) = target {
+            let limit = starts.last().map_or(0, |rows| rows.saturating_sub(height));
+            self.scroll = target.saturating_sub(height).min(limit);
+        }
+    }
+
+    /// Scroll the history up by a number of rows, as far as its first line, or down, as far as
+    /// the prompt.
+    fn scroll_by(&mut self, up: bool, rows: usize) {
+        if !up {
+            self.scroll = self.scroll.saturating_sub(rows);
+            return;
+        }
+        let (height, below) = self.view.get();
+        let (width, _) = self.size.get();
+        let entries = self.shown_entries(width, usize::MAX..usize::MAX);
+        let total = below + entries.iter().map(|shown| shown.rows).sum::<usize>();
+        self.scroll = (self.scroll + rows).min(total.saturating_sub(height));
+    }
+
+    /// The rows a page of the history takes, as scrolled by `PageUp` and `PageDown`: the height
+    /// of the history, but for a line kept on screen.
+    fn page(&self) -> usize {
+        self.view.get().0.saturating_sub(1).max(1)
+    }
+
+    /// Check if the user is typing, in which case printable keys are typed rather than bound to
+    /// their action. (See [`Keymap::action`])
+    fn editing(&self) -> bool {
+        match &self.state {
+            State::Idle(line, ..) => !line.is_empty(),
+            State::Running(..) => true,
+        }
+    }
+
+    /// Perform an action of the keymap.
+    fn perform(&mut self, action: Action) -> anyhow::Result<Next> {
+        match action {
+            Action::Help => self.open(Overlay::Help),
+            Action::ClearScreen => {
+                self.clear_history();
+                return Ok(Next::Continue);
+            }
+            // A command streaming its output reads no input to end.
+            Action::Exit if self.streaming.is_some() => {}
+            Action::Exit => {
+                if let State::Running(..) = &self.state {
+                    self.continue_execution()?;
+                } else if self.scroll > 0 {
+                    // As in a pager, scrolled up, the history scrolls down by half a page.
+                    self.scroll_by(false, self.page() / 2 + 1);
+                } else {
+                    return Ok(Next::Exit("".to_string()));
+                }
+            }
+            Action::Interrupt => match self.state {
+                State::Running(..) => self.interrupt(),
+                State::Idle(..) => return self.perform(Action::Exit),
+            },
+            // Handled by the client attached to the session. (See `session::attach`)
+            Action::Detach => {}
+            Action::ShowErrors if !self.errors.is_empty() => self.open(Overlay::Errors),
+            Action::ShowErrors => {}
+            Action::DismissErrors => self.errors.clear(),
+            Action::QueueCommand => match self.state {
+                State::Running(_, _, ref mut line) => {
+                    let command = std::mem::take(line).into_string();
+                    if !command.trim().is_empty() {
+                        self.queue.push_back(command);
+                    }
+                }
+                State::Idle(..) => return self.perform(Action::Submit),
+            },
+            Action::PauseOutput => self.pause_output(),
+            Action::NewTab => self.new_tab(),
+            Action::NextTab if !self.tabs.is_empty() => {
+                self.switch_tab((self.tab + 1) % (self.tabs.len() + 1))
+            }
+            Action::PreviousTab if !self.tabs.is_empty() => {
+                self.switch_tab((self.tab + self.tabs.len()) % (self.tabs.len() + 1))
+            }
+            Action::NextTab | Action::PreviousTab => {}
+            Action::CloseTab => self.close_tab(),
+            Action::ShowQueue => {
+                if !self.queue.is_empty() {
+                    self.open(Overlay::Queue(0));
+                }
+            }
+            Action::CursorLeft => self.edit(LineBuffer::move_left),
+            Action::CursorRight => self.edit(LineBuffer::move_right),
+            Action::CursorWordLeft => self.edit(LineBuffer::move_word_left),
+            Action::CursorWordRight => self.edit(LineBuffer::move_word_right),
+            Action::CursorHome => self.edit(LineBuffer::move_home),
+            Action::CursorEnd => self.edit(LineBuffer::move_end),
+            Action::Complete => {
+                if let State::Idle(ref mut line, ref mut comp) = self.state {
+                    if line.at_end() {
+                        match comp {
+                            // Complete the part shared by the completions or suggestions shown.
+                            Some(shown) if !shown.is_empty() => line.splice(shown),
+                            _ => {
+                                let hosts = complete_hosts(
+                                    &self.config.completion.hosts,
+                                    line.as_str(),
+                                    &self.messages,
+                                );
+                                let hosts = hosts.or_else(|| complete_processes(line.as_str()));
+                                let (fixed, variable) = match hosts {
+                                    Some((fixed, variable, descriptions)) => {
+                                        self.descriptions = descriptions;
+                                        (fixed, variable)
+                                    }
+                                    None => complete(
+                                        &self.executor,
+                                        &self.context,
+                                        &self.builtins,
+                                        &self.config.directories,
+                                        line.as_str(),
+                                    )?,
+                                };
+                                line.insert_str(&fixed);
+                                *comp = Some(rank_completions(
+                                    self.ranker.as_ref(),
+                                    self.store.as_ref(),
+                                    &self.commands,
+                                    line.as_str(),
+                                    variable,
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+            Action::DeleteBackward => self.delete_backward(),
+            Action::DeleteForward => self.edit(|line| line.delete_forward().is_some()),
+            Action::DeleteWordBackward => {
+                let mut deleted = None;
+                self.edit(|line| {
+                    deleted = line.delete_word_backward();
+                    deleted.is_some()
+                });
+                self.kills.extend(deleted);
+            }
+            Action::DeleteToStart => {
+                let mut deleted = None;
+                self.edit(|line| {
+                    deleted = line.delete_to_start();
+                    deleted.is_some()
+                });
+                // With nothing to delete, the history scrolls up by half a page, as in a pager.
+                if deleted.is_none() {
+                    self.scroll_by(true, self.page() / 2 + 1);
+                }
+                self.kills.extend(deleted);
+            }
+            Action::Undo => self.edit(LineBuffer::undo),
+            Action::Submit => match self.state {
+                State::Idle(ref line, ..) if line.as_str().starts_with('#') && self.describes() => {
+                    return self.describe();
+                }
+                State::Idle(ref line, ..) => {
+                    if let Some(correction) = self.correction(line.as_str()) {
+                        self.open(Overlay::Correction(correction));
+                        return Ok(Next::Continue);
+                    }
+                    // A front-end embedding the shell draws the command itself.
+                    if self.executor.remote() && self.publisher.is_none() {
+                        let predicted = self.executor.predict_prompt(&self.context, line.as_str());
+                        self.echoed = Some(predicted);
+                        return Ok(Next::Continue);
+                    }
+                    return self.execute_guarded();
+                }
+                State::Running(_, ref mut stdin, ref mut line) => {
+                    stdin.push(std::mem::take(line).into_string());
+                }
+            },
+            Action::HistoryPrevious => {
+                if let Some(line) = self.state.prompt_line().filter(|_| self.recall.is_none()) {
+                    self.recall = Some(Recall::new(self.recent(), line.to_string()));
+                }
+                if let (State::Idle(ref mut line, _), Some(recall)) =
+                    (&mut self.state, &mut self.recall)
+                {
+                    if let Some(previous) = recall.previous() {
+                        line.set(previous);
+                    }
+                }
+            }
+            Action::HistoryNext => {
+                if let (State::Idle(ref mut line, _), Some(recall)) =
+                    (&mut self.state, &mut self.recall)
+                {
+                    if let Some(next) = recall.next() {
+                        line.set(next);
+                    }
+                }
+            }
+            Action::InsertLastArgument => {
+                if self.state.prompt_line().is_some() && self.yank.is_none() {
+                    self.yank = Some((Recall::new(self.recent(), String::new()), 0));
+                }
+                if let (State::Idle(ref mut line, _), Some((recall, inserted))) =
+                    (&mut self.state, &mut self.yank)
+                {
+                    // Repeated, the argument inserted last is replaced by the one before it.
+                    while let Some(command) = recall.previous() {
+                        let Some(argument) = last_argument(command) else {
+                            continue;
+                        };
+                        for _ in 0..*inserted {
+                            line.delete_backward();
+                        }
+                        line.insert_str(argument);
+                        *inserted = argument.graphemes(true).count();
+                        break;
+                    }
+                }
+            }
+            Action::PreviousPrompt => self.jump_prompt(true),
+            Action::NextPrompt => self.jump_prompt(false),
+            Action::ScrollUp => self.scroll_by(true, self.page()),
+            Action::ScrollDown => self.scroll_by(false, self.page()),
+            Action::SearchHistory => {
+                if let Some(line) = self.state.prompt_line() {
+                    let query = line.to_string();
+                    let matches = self.search_history(&query);
+                    self.open(Overlay::Search(query, 0, matches));
+                }
+            }
+            Action::Yank => {
+                if let Some(snippet) = self.kills.get(0).map(kills::inline) {
+                    self.edit(|line| {
+                        line.insert_str(&snippet);
+                        true
+                    });
+                }
+            }
+            Action::ShowKills => {
+                if self.kills.len() > 0 {
+                    self.open(Overlay::Kills(0));
+                }
+            }
+            Action::CopyOutput => {
+                let output = self.history.iter().next_back().map(|entry| {
+                    let lines = entry.stdout.iter().map(|line| output::strip(line));
+                    let lines = lines.map(|line| line.trim_end().to_string());
+                    lines.collect::<Vec<_>>().join("\n")
+                });
+                self.kills.extend(output);
+            }
+            Action::CopyMode => {
+                if let State::Idle(..) = self.state {
+                    let last = self.history.len().checked_sub(1);
+                    let command = last.and_then(|last| self.history.command(last));
+                    if let (Some(last), Some(command)) = (last, command) {
+                        let end = command.len();
+                        self.open(Overlay::Copy(last, 0, end));
+                    }
+                }
+            }
+            Action::PickProcess => {
+                if let State::Idle(..) = self.state {
+                    self.open(Overlay::Processes(String::new(), 0, ps::list()));
+                }
+            }
+            Action::SuggestCommands => self.suggest(),
+            Action::AnnotateEntry => {
+                self.transition(state::Event::Edit(LineBuffer::from(":note ")))
+            }
+            Action::StarEntry => {
+                self.star();
+            }
+            Action::ShowStarred => self.show_starred(),
+            Action::FixCommand => {
+                let _ = self.fix(None);
+            }
+            Action::DescribeCommand => return self.describe(),
+            Action::ExplainCommand => self.explain(),
+            Action::ShowManual => {
+                if let Some(line) = self.state.prompt_line() {
+                    if let Some(program) =
+                        program_at(line.as_str(), line.cursor()).map(str::to_string)
+                    {
+                        if let Err(error) = self.open_manual(&program) {
+                            self.record(format!(":help {}", program), Vec::new(), vec![error]);
+                        }
+                    }
+                }
+            }
+            Action::RecordMacro => self.macros.toggle(),
+            Action::PlayMacro => {
+                let keys = self.macros.last().to_vec();
+                return self.play(&keys, 1);
+            }
+        }
+
+        Ok(Default::default())
+    }
+
+    /// The directory commands run in: the working directory of the executor, or else the one of
+    /// the shell.
+    fn directory(&self) -> Option<PathBuf> {
+        self.executor
+            .cwd(&self.context)
+            .or_else(|| std::env::current_dir().ok())
+    }
+
+    /// Show the commands run most often in the working directory that continue the command typed
+    /// so far, as completions.
+    fn suggest(&mut self) {
+        let limit = self.config.history.suggestions;
+        let Some(directory) = self.directory().filter(|_| limit > 0 && !self.isolated) else {
+            return;
+        };
+        let State::Idle(ref line, ref mut comp) = self.state else {
+            return;
+        };
+        if !line.at_end() {
+            return;
+        }
+
+        // Read more than needed, as some are left out by what is typed.
+        let frequent = usage::frequent(&directory, usage::MAX_COMMANDS, self.ranker.as_ref())
+            .unwrap_or_default();
+        let suggestions = frequent
+            .iter()
+            .filter_map(|command| command.strip_prefix(line.as_str()))
+            .filter(|rest| !rest.is_empty())
+            .take(limit)
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if !suggestions.is_empty() {
+            *comp = Some(suggestions);
+        }
+    }
+
+    /// The distinct commands run containing the text, last run first, for the history search.
+    fn search_history(&self, text: &str) -> Vec<String> {
+        let Some(store) = &self.store else {
+            let mut matches = Vec::<String>::new();
+            let text = text.to_lowercase();
+            for command in self.commands.iter().rev() {
+                if matches.len() == SEARCH {
+                    break;
+                }
+                if command.to_lowercase().contains(&text) && !matches.contains(command) {
+                    matches.push(command.clone());
+                }
+            }
+            return matches;
+        };
+
+        let query = Query {
+            text: Some(text.to_string()).filter(|text| !text.is_empty()),
+            limit: SEARCH,
+            ..Query::default()
+        };
+        store.distinct(&query).unwrap_or_default()
+    }
+
+    /// List the commands of the journal matching the arguments of `:history`, oldest first.
+    fn list_history(&self, args: &[&str]) -> (Vec<String>, Vec<String>) {
+        let Some(store) = &self.store else {
+            return (Vec::new(), vec![self.messages.get("history-unavailable")]);
+        };
+        let Some(query) = Query::parse(args, self.directory().as_deref()) else {
+            return (Vec::new(), vec![self.messages.get("history-usage")]);
+        };
+
+        match store.search(&query) {
+            Ok(records) if records.is_empty() => {
+                (vec![self.messages.get("history-none")], Vec::new())
+            }
+            Ok(records) => (
+                records
+                    .iter()
+                    .rev()
+                    .flat_map(|record| {
+                        let key = match record.exit {
+                            Some(exit) if exit != 0 => "history-entry-failed",
+                            _ => "history-entry",
+                        };
+                        let mut entry = self
+                            .messages
+                            .format(key, &[("time", &record.time), ("command", &record.command)]);
+                        if record.starred {
+                            entry.insert_str(0, "★ ");
+                        }
+                        let note = record
+                            .note
+                            .as_ref()
+                            .map(|note| self.messages.format("history-note", &[("note", note)]));
+                        std::iter::once(entry).chain(note)
+                    })
+                    .collect(),
+                Vec::new(),
+            ),
+            Err(e) => (
+                Vec::new(),
+                vec![self
+                    .messages
+                    .format("history-failed", &[("error", &e.to_string())])],
+            ),
+        }
+    }
+
+    /// Sum up the commands of the journal matching the arguments of `:stats`.
+    fn history_stats(&self, args: &[&str]) -> (Vec<String>, Vec<String>) {
+        let Some(store) = &self.store else {
+            return (Vec::new(), vec![self.messages.get("history-unavailable")]);
+        };
+        let Some(query) = Query::parse(args, self.directory().as_deref()) else {
+            return (Vec::new(), vec![self.messages.get("stats-usage")]);
+        };
+
+        let stats = match store.stats(&query) {
+            Ok(stats) => stats,
+            Err(e) => {
+                return (
+                    Vec::new(),
+                    vec![self
+                        .messages
+                        .format("history-failed", &[("error", &e.to_string())])],
+                )
+            }
+        };
+        let mut stdout = vec![self.messages.format(
+            "stats-summary",
+            &[
+                ("total", &stats.total),
+                ("distinct", &stats.distinct),
+                ("failed", &stats.failed),
+            ],
+        )];
+        for (title, counts) in [
+            ("stats-commands", &stats.commands),
+            ("stats-directories", &stats.directories),
+        ] {
+            if counts.is_empty() {
+                continue;
+            }
+            stdout.push(String::new());
+            stdout.push(self.messages.get(title));
+            let width = counts
+                .iter()
+                .map(|(_, count)| count.to_string().len())
+                .max()
+                .unwrap_or_default();
+            stdout.extend(
+                counts
+                    .iter()
+                    .map(|(name, count)| format!("  {:>width$}  {}", count, name, width = width)),
+            );
+        }
+        stdout.push(String::new());
+        stdout.push(self.messages.format(
+            "stats-memory",
+            &[
+                ("entries", &self.history.len()),
+                ("size", &self.history.bytes().div_ceil(1024)),
+                ("compressed", &self.history.compressed()),
+                ("spilled", &self.history.spilled()),
+            ],
+        ));
+
+        (stdout, Vec::new())
+    }
+
+    /// Sync the journal with other machines, through the profile named by `:sync`, or else every
+    /// profile of the configuration. (See [`crate::sync`])
+    #[cfg(feature = "sync")]
+    fn sync_history(&self, name: Option<&str>) -> (Vec<String>, Vec<String>) {
+        let profiles = match name {
+            Some(name) => match self.config.sync.get_key_value(name) {
+                Some(profile) => vec![profile],
+                None => {
+                    return (
+                        Vec::new(),
+                        vec![self.messages.format("sync-unknown", &[("profile", &name)])],
+                    )
+                }
+            },
+            None => self.config.sync.iter().collect(),
+        };
+        if profiles.is_empty() {
+            return (Vec::new(), vec![self.messages.get("sync-none")]);
+        }
+
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        for (name, profile) in profiles {
+            match sync::sync_profile(profile) {
+                Ok(report) => stdout.push(self.messages.format(
+                    "sync-done",
+                    &[
+                        ("profile", name),
+                        ("received", &report.received),
+                        ("machines", &report.machines),
+                        ("sent", &report.sent),
+                    ],
+                )),
+                Err(e) => stderr.push(
+                    self.messages
+                        .format("sync-failed", &[("profile", name), ("error", &e)]),
+                ),
+            }
+        }
+
+        (stdout, stderr)
+    }
+
+    /// Without the `sync` feature, the journal stays on this machine.
+    #[cfg(not(feature = "sync"))]
+    fn sync_history(&self, _: Option<&str>) -> (Vec<String>, Vec<String>) {
+        (Vec::new(), vec![self.messages.get("sync-unavailable")])
+    }
+
+    /// The question asked below the prompt by the overlay, if it asks one.
+    fn question(&self) -> Option<String> {
+        match self.overlays.last()? {
+            Overlay::Correction(command) => Some(
+                self.messages
+                    .format("autocorrect-prompt", &[("command", command)]),
+            ),
+            Overlay::Guard(reason) => {
+                Some(self.messages.format("guard-prompt", &[("reason", reason)]))
+            }
+            Overlay::Trust(file) => Some(
+                self.messages
+                    .format("project-trust", &[("path", &file.display())]),
+            ),
+            Overlay::Flood => {
+                let flood = self.flood.as_ref()?;
+                let shown = flood.total - flood.pending.len();
+                let key = match flood.paused {
+                    true => "flood-paused",
+                    false => "flood-prompt",
+                };
+                let pause = self.key_label(Action::PauseOutput);
+                Some(self.messages.format(
+                    key,
+                    &[("shown", &shown), ("total", &flood.total), ("key", &pause)],
+                ))
+            }
+            Overlay::Runbook => {
+                let runbook = self.runbook.as_ref()?;
+                let (index, line, command) = runbook.current()?;
+                Some(match runbook.sourced {
+                    Some(_) => self.messages.format(
+                        "source-confirm",
+                        &[
+                            ("line", &line),
+                            ("path", &runbook.path().display()),
+                            ("command", &command),
+                        ],
+                    ),
+                    None => self.messages.format(
+                        "runbook-prompt",
+                        &[
+                            ("index", &index),
+                            ("count", &runbook.len()),
+                            ("command", &command),
+                        ],
+                    ),
+                })
+            }
+            Overlay::Recover(_, transcript) => Some(self.messages.format(
+                "autosave-prompt",
+                &[
+                    ("cwd", &transcript.meta.cwd.as_deref().unwrap_or("?")),
+                    ("count", &transcript.entries.len()),
+                ],
+            )),
+            _ => None,
+        }
+    }
+
+    /// Execute the command, once confirmed if a guard of the configuration matches it.
+    fn execute_guarded(&mut self) -> anyhow::Result<Next> {
+        let Some(line) = self.state.prompt_line() else {
+            return Ok(Next::Continue);
+        };
+        let command = self.expand(line.as_str());
+        let guard = self
+            .config
+            .guards
+            .iter()
+            .find(|(prefix, _)| command.trim_start().starts_with(prefix.as_str()));
+
+        match guard {
+            Some((_, reason)) => {
+                self.open(Overlay::Guard(reason.clone()));
+                Ok(Next::Continue)
+            }
+            None => self.execute_command(),
+        }
+    }
+
+    /// Expand the alias in the first word of a command, if any.
+    fn expand(&self, cmd: &str) -> String {
+        let start = cmd.len() - cmd.trim_start().len();
+        let Some(word) = cmd[start..].split_whitespace().next() else {
+            return cmd.to_string();
+        };
+
+        match self.config.aliases.get(word) {
+            Some(alias) => format!("{}{}", alias, &cmd[start + word.len()..]),
+            None => cmd.to_string(),
+        }
+    }
+
+    /// Look for the project file of the working directory, when it changed. A trusted file is
+    /// applied, and the user is asked about a file never seen before. (See `crate::project`)
+    fn update_project(&mut self) {
+        if self.isolated {
+            return;
+        }
+        let file = self
+            .directory()
+            .and_then(|directory| project::find(&directory));
+        if file == self.project {
+            return;
+        }
+        self.leave_project();
+        self.project.clone_from(&file);
+        let Some(file) = file else {
+            return;
+        };
+
+        match std::fs::read_to_string(&file) {
+            Ok(content) => match project::trusted(&file, &content) {
+                Some(true) => self.apply_project(&file, &content),
+                Some(false) => {}
+                // Embedding front-ends cannot be asked: the file is left alone.
+                None if self.publisher.is_some() => {}
+                None => self.open(Overlay::Trust(file)),
+            },
+            Err(e) => {
+                let error = self.messages.format(
+                    "project-failed",
+                    &[("path", &file.display()), ("error", &e)],
+                );
+                self.record(PROJECT.to_string(), Vec::new(), vec![error]);
+            }
+        }
+    }
+
+    /// Remember the answer to the trust prompt of a project file, and apply the file if trusted.
+    fn trust_project(&mut self, file: &Path, trusted: bool) {
+        let content = match std::fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(e) => {
+                let error = self.messages.format(
+                    "project-failed",
+                    &[("path", &file.display()), ("error", &e)],
+                );
+                self.record(PROJECT.to_string(), Vec::new(), vec![error]);
+                return;
+            }
+        };
+        if let Err(e) = project::remember(file, &content, trusted) {
+            self.fail(e);
+        }
+        if trusted {
+            self.apply_project(file, &content);
+        }
+    }
+
+    /// Go on with the runbook once the shell is waiting for a command: the step run (or the
+    /// command run after putting it on the prompt, noted as edited if it changed) is noted with
+    /// its output, and the next step is offered, or the results written once every step is done.
+    /// A step whose command was not run in the end, such as a guarded one, is skipped. A script
+    /// stepped through by `:source` stops at a command failing, unless told to go on. (See
+    /// [`crate::runbook`])
+    fn advance_runbook(&mut self) {
+        if !self.overlays.is_empty() || !matches!(self.state, State::Idle(..)) {
+            return;
+        }
+        let Some(mut runbook) = self.runbook.take() else {
+            return;
+        };
+        let mut failed = None;
+        match (runbook.started, runbook.current()) {
+            (Some((start, true)), _) if self.recorded == start => {}
+            (Some((start, editing)), Some((_, line, step))) => {
+                let oldest = self.recorded - self.history.len();
+                let entries = (start.max(oldest)..self.recorded)
+                    .filter_map(|id| self.history.get(id - oldest))
+                    .collect::<Vec<_>>();
+                let output = entries
+                    .iter()
+                    .flat_map(|entry| entry.stdin.iter().chain(&entry.stdout).chain(&entry.stderr))
+                    .map(|line| output::strip(line).into_owned())
+                    .collect();
+                let command = entries.first().map(|entry| entry.command.clone());
+                if editing && command.as_deref().is_some_and(|command| command != step) {
+                    let note = self.messages.format("step-edited", &[("command", &step)]);
+                    self.notes.insert(start, note);
+                }
+                if self.failed_since(start) {
+                    failed = Some(line);
+                }
+                match command {
+                    Some(command) => runbook.complete(command, output),
+                    None => runbook.skip(),
+                }
+            }
+            _ => {}
+        }
+        let waiting = runbook.started.is_some();
+        let stop = failed.filter(|_| matches!(runbook.sourced, Some((_, false))));
+        let done = runbook.current().is_none();
+        self.runbook = Some(runbook);
+        match (waiting, stop, done) {
+            (true, ..) => {}
+            (false, Some(line), _) => self.finish_script(Some(line)),
+            (false, None, true) => self.finish_runbook(),
+            (false, None, false) => self.open(Overlay::Runbook),
+        }
+    }
+
+    /// Open a new tab, with a session of its own opened by the executor from the one of the tab
+    /// shown, and go to it. (See [`command::Execute::open_session`])
+    fn new_tab(&mut self) {
+        let context = match self.executor.open_session(&self.context) {
+            Ok(Some(context)) => context,
+            Ok(None) => return self.fail(anyhow::anyhow!(self.messages.get("tab-unsupported"))),
+            Err(e) => return self.fail(e),
+        };
+        self.tabs.push(Session {
+            context,
+            state: State::default(),
+            history: Scrollback::new(memory::budget(&self.config.history)),
+            cache: RefCell::default(),
+            recorded: 0,
+            runs: Runs::default(),
+            notes: BTreeMap::new(),
+            starred: BTreeSet::new(),
+            filter: None,
+            fixes: BTreeMap::new(),
+            warnings: BTreeMap::new(),
+            fixing: None,
+            journaled: None,
+            queue: VecDeque::new(),
+            flood: None,
+            streaming: None,
+            scroll: 0,
+        });
+        self.switch_tab(self.tabs.len());
+    }
+
+    /// Go to a tab, by its position among every tab, putting the one shown in the background.
+    fn switch_tab(&mut self, tab: usize) {
+        if tab == self.tab || tab > self.tabs.len() {
+            return;
+        }
+        // The tabs in the background leave out the one shown, shifting the ones after it.
+        let mut session = self.tabs.remove(tab - usize::from(tab > self.tab));
+        std::mem::swap(&mut self.context, &mut session.context);
+        std::mem::swap(&mut self.state, &mut session.state);
+        std::mem::swap(&mut self.history, &mut session.history);
+        std::mem::swap(&mut self.cache, &mut session.cache);
+        std::mem::swap(&mut self.recorded, &mut session.recorded);
+        std::mem::swap(&mut self.runs, &mut session.runs);
+        std::mem::swap(&mut self.notes, &mut session.notes);
+        std::mem::swap(&mut self.starred, &mut session.starred);
+        std::mem::swap(&mut self.filter, &mut session.filter);
+        std::mem::swap(&mut self.fixes, &mut session.fixes);
+        std::mem::swap(&mut self.warnings, &mut session.warnings);
+        std::mem::swap(&mut self.fixing, &mut session.fixing);
+        std::mem::swap(&mut self.journaled, &mut session.journaled);
+        std::mem::swap(&mut self.queue, &mut session.queue);
+        std::mem::swap(&mut self.flood, &mut session.flood);
+        std::mem::swap(&mut self.streaming, &mut session.streaming);
+        std::mem::swap(&mut self.scroll, &mut session.scroll);
+        self.tabs
+            .insert(self.tab - usize::from(self.tab > tab), session);
+        self.tab = tab;
+        self.overlays.clear();
+        self.held.clear();
+        self.cached_prompt.get_mut().take();
+    }
+
+    /// Close the tab shown, going to the next one (or the previous one, for the last tab), and
+    /// have the executor close its session. The last tab left is not closed.
+    fn close_tab(&mut self) {
+        if self.tabs.is_empty() {
+            return;
+        }
+        let closed = self.tab;
+        let next = match closed == self.tabs.len() {
+            true => closed - 1,
+            false => closed + 1,
+        };
+        self.switch_tab(next);
+        let session = self.tabs.remove(closed - usize::from(closed > next));
+        self.tab = next - usize::from(closed < next);
+        self.executor.close_session(session.context);
+    }
+
+    /// Run the commands queued while another one ran, in order, as if typed, once the shell is
+    /// waiting for a command. This stops at a command reading its input, or asking a question
+    /// (such as a guard), to go on once it is done.
+    fn run_queued(&mut self) -> anyhow::Result<Next> {
+        loop {
+            let waiting = self.overlays.is_empty()
+                && self.state.prompt_line().is_some_and(LineBuffer::is_empty);
+            let Some(command) = self.queue.pop_front().filter(|_| waiting) else {
+                return Ok(Next::Continue);
+            };
+            self.transition(state::Event::Edit(LineBuffer::from(command)));
+            match self.execute_guarded()? {
+                Next::Continue => {}
+                next => return Ok(next),
+            }
+        }
+    }
+
+    /// Skip the step of the runbook offered, noting it in the history. (See [`crate::runbook`])
+    fn skip_step(&mut self) {
+        let Some(runbook) = self.runbook.as_mut() else {
+            return;
+        };
+        let Some((_, line, command)) = runbook.current() else {
+            return;
+        };
+        let skipped = self
+            .messages
+            .format("step-skipped", &[("line", &line), ("command", &command)]);
+        let name = match &runbook.sourced {
+            Some((source, _)) => source.clone(),
+            None => RUNBOOK.to_string(),
+        };
+        runbook.skip();
+        self.record(name, vec![skipped], Vec::new());
+    }
+
+    /// Leave a script stepped through by `:source`, stopped at the line of a command failing, if
+    /// any. (See [`Shell::source`])
+    fn finish_script(&mut self, stopped: Option<usize>) {
+        let Some(runbook) = self.runbook.take() else {
+            return;
+        };
+        let Some((source, _)) = &runbook.sourced else {
+            return;
+        };
+        let path = runbook.path().display();
+        let (stdout, stderr) = match stopped {
+            Some(line) => (
+                Vec::new(),
+                vec![self
+                    .messages
+                    .format("source-stopped", &[("path", &path), ("line", &line)])],
+            ),
+            None => (
+                vec![self.messages.format(
+                    "source-confirmed",
+                    &[
+                        ("path", &path),
+                        ("ran", &runbook.ran()),
+                        ("skipped", &runbook.skipped()),
+                        ("count", &runbook.len()),
+                    ],
+                )],
+                Vec::new(),
+            ),
+        };
+        self.record(source.clone(), stdout, stderr);
+    }
+
+    /// Write the results of the runbook next to it, and leave it. A script stepped through by
+    /// `:source` is left without writing anything. (See [`crate::runbook`])
+    fn finish_runbook(&mut self) {
+        if self
+            .runbook
+            .as_ref()
+            .is_some_and(|runbook| runbook.sourced.is_some())
+        {
+            return self.finish_script(None);
+        }
+        let Some(runbook) = self.runbook.take() else {
+            return;
+        };
+        match runbook.write() {
+            Ok(path) => {
+                let done = self.messages.format(
+                    "runbook-done",
+                    &[
+                        ("ran", &runbook.ran()),
+                        ("count", &runbook.len()),
+                        ("path", &path.display()),
+                    ],
+                );
+                self.record(RUNBOOK.to_string(), vec![done], Vec::new());
+            }
+            Err(e) => {
+                let error = self.messages.format(
+                    "runbook-failed",
+                    &[("path", &runbook.results_path().display()), ("error", &e)],
+                );
+                self.record(RUNBOOK.to_string(), Vec::new(), vec![error]);
+            }
+        }
+    }
+
+    /// Merge a project file over the configuration of the user, and set its environment
+    /// variables. Invalid files are reported in the history, and left alone. The lock screen is
+    /// the user's own: a project cannot turn it off, or set its passphrase.
+    fn apply_project(&mut self, file: &Path, content: &str) {
+        let mut config = self.user_config.clone();
+        if let Err(e) = config.merge(content, file) {
+            self.record(PROJECT.to_string(), Vec::new(), vec![e.to_string()]);
+            return;
+        }
+        config.lock = self.user_config.lock.clone();
+
+        for (name, value) in &config.env {
+            self.saved_env.push((name.clone(), std::env::var_os(name)));
+            std::env::set_var(name, value);
+        }
+        self.config = config;
+        let loaded = self
+            .messages
+            .format("project-loaded", &[("path", &file.display())]);
+        self.record(PROJECT.to_string(), vec![loaded], Vec::new());
+    }
+
+    /// Go back to the configuration of the user, and restore the environment variables set by the
+    /// project file.
+    fn leave_project(&mut self) {
+        self.config = self.user_config.clone();
+        for (name, value) in self.saved_env.drain(..).rev() {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+    }
+
+    /// The correction of a command whose first word is mistyped, if any. Builtins are corrected
+    /// to builtins, and other commands to the commands and builtins of the executor. Commands run
+    /// by embedding front-ends are never corrected, as they cannot be asked.
+    fn correction(&self, cmd: &str) -> Option<String> {
+        if !self.config.ui.autocorrect || self.publisher.is_some() || self.is_comment(cmd) {
+            return None;
+        }
+
+        match cmd.trim_start().starts_with(':') {
+            true => {
+                let builtins = BUILTINS.iter().map(|name| format!(":{}", name));
+                autocorrect::correct(cmd, &builtins.collect::<Vec<_>>())
+            }
+            false => {
+                let mut commands = self.executor.commands(&self.context);
+                commands.extend(self.builtins.iter().map(|builtin| builtin.name.clone()));
+                autocorrect::correct(cmd, &commands)
+            }
+        }
+    }
+
+    /// Check whether a line is a comment, starting with the comment marker of the syntax of the
+    /// executor. Comments are recorded in the history, and not run. (See [`crate::syntax`])
+    fn is_comment(&self, line: &str) -> bool {
+        let line = line.trim_start();
+        self.executor
+            .syntax()
+            .comment()
+            .is_some_and(|marker| line.starts_with(marker))
+    }
+
+    /// The builtin of the executor running a command, if any.
+    fn registered(&self, cmd: &str) -> Option<&command::Builtin<T::Context>> {
+        let program = cmd.split_whitespace().next()?;
+        self.builtins.iter().find(|builtin| builtin.name == program)
+    }
+
+    /// Check if a backend writes commands from a description, in which case lines starting with
+    /// `#` are descriptions.
+    fn describes(&self) -> bool {
+        #[cfg(feature = "suggest")]
+        return self.suggester.is_some();
+        #[cfg(not(feature = "suggest"))]
+        return false;
+    }
+
+    /// Ask the backend for a command doing what the typed line describes, and show it for
+    /// confirmation. Failures are reported in the history, keeping the line.
+    fn describe(&mut self) -> anyhow::Result<Next> {
+        let Some(line) = self.state.prompt_line().map(LineBuffer::to_string) else {
+            return Ok(Next::Continue);
+        };
+        let description = line.trim_start_matches('#').trim();
+        if description.is_empty() {
+            return Ok(Next::Continue);
+        }
+
+        #[cfg(feature = "suggest")]
+        if let Some(backend) = &self.suggester {
+            let request = suggest::Request {
+                description: description.to_string(),
+                cwd: self.directory(),
+                history: self
+                    .history
+                    .commands()
+                    .skip(self.history.len().saturating_sub(suggest::RECENT))
+                    .map(std::borrow::Cow::into_owned)
+                    .collect(),
+            };
+            match backend.suggest(&request) {
+                Ok(reply) => match suggest::command(&reply) {
+                    Some(command) => self.open(Overlay::Suggestion(command)),
+                    None => {
+                        let error = self.messages.get("suggest-empty");
+                        self.record(line, Vec::new(), vec![error]);
+                    }
+                },
+                Err(e) => {
+                    let error = self
+                        .messages
+                        .format("suggest-failed", &[("error", &e.to_string())]);
+                    self.record(line, Vec::new(), vec![error]);
+                }
+            }
+            return Ok(Next::Continue);
+        }
+
+        let error = self.messages.get("suggest-disabled");
+        self.record(line, Vec::new(), vec![error]);
+        Ok(Next::Continue)
+    }
+
+    /// Open the manual of a program in the pager, or return the error to report. The manual of a
+    /// builtin of the executor is its usage and help.
+    fn open_manual(&mut self, program: &str) -> Result<(), String> {
+        let builtin = self
+            .builtins
+            .iter()
+            .find(|builtin| builtin.name == program && !builtin.help.is_empty())
+            .map(|builtin| match &builtin.usage {
+                Some(usage) => format!("{}\n\n{}", usage, builtin.help),
+                None => builtin.help.clone(),
+            });
+        let manual = builtin
+            .or_else(|| explain::manual(program))
+            .ok_or_else(|| {
+                self.messages
+                    .format("manual-none", &[("program", &program)])
+            })?;
+        self.open(Overlay::Pager(Box::new(Pager::new(
+            program.to_string(),
+            &manual,
+        ))));
+        Ok(())
+    }
+
+    /// Explain the command being typed, or else the last command run, in an overlay.
+    fn explain(&mut self) {
+        let command = match &self.state {
+            State::Idle(line, ..) if !line.as_str().trim().is_empty() => line.to_string(),
+            State::Idle(..) => match self.history.commands().next_back() {
+                Some(command) => command.to_string(),
+                None => return,
+            },
+            State::Running(prepare, ..) => prepare.command.clone(),
+        };
+
+        #[cfg(feature = "suggest")]
+        if let Some(explained) = self.suggester.as_ref().and_then(|b| b.explain(&command)) {
+            let lines = match explained {
+                Ok(text) => text
+                    .lines()
+                    .map(|line| (String::new(), line.to_string()))
+                    .collect(),
+                Err(e) => {
+                    let error = self
+                        .messages
+                        .format("explain-failed", &[("error", &e.to_string())]);
+                    vec![(String::new(), error)]
+                }
+            };
+            self.open(Overlay::Explanation(command, lines));
+            return;
+        }
+
+        let lines = match explain::explain(&command) {
+            Some(parts) => parts
+                .into_iter()
+                .map(|part| match part {
+                    Part::Program(program, summary) => {
+                        let unknown = || self.messages.get("explain-program");
+                        (program, summary.unwrap_or_else(unknown))
+                    }
+                    Part::Flag(flag, description) => {
+                        let unknown = || self.messages.get("explain-unknown-flag");
+                        (flag, description.unwrap_or_else(unknown))
+                    }
+                    Part::Argument(argument) => (argument, self.messages.get("explain-argument")),
+                })
+                .collect(),
+            None => {
+                let program = command.split_whitespace().next().unwrap_or_default();
+                let error = self
+                    .messages
+                    .format("explain-no-help", &[("program", &program)]);
+                vec![(String::new(), error)]
+            }
+        };
+        self.open(Overlay::Explanation(command, lines));
+    }
+
+    /// Replay the keys of a macro `count` times, stopping early if the shell exits.
+    fn play(&mut self, keys: &[Key], count: usize) -> anyhow::Result<Next> {
+        if !self.macros.play() {
+            // Macros do not replay each other, which could go on forever.
+            return Ok(Next::Continue);
+        }
+
+        let mut next = Next::Continue;
+        let result = 'replay: {
+            for key in keys.iter().cycle().take(keys.len() * count) {
+                match self.input(Event::Key(KeyEvent::new(key.code, key.modifiers))) {
+                    Ok(Next::Continue) => {}
+                    Ok(Next::Clear) => next = Next::Clear,
+                    other => break 'replay other,
+                }
+            }
+            Ok(next)
+        };
+        self.macros.finish();
+
+        result
+    }
+
+    /// The first key bound to an action, as shown to the user.
+    fn key_label(&self, action: Action) -> String {
+        self.config
+            .keys
+            .keys(action)
+            .first()
+            .map(Key::to_string)
+            .unwrap_or_default()
+    }
+
+    /// The file a path given to a builtin names: `~` is the home directory, `~NAME` a named
+    /// directory (see [`crate::tilde`]), and relative paths are relative to the working directory
+    /// of the executor.
+    fn resolve(&self, path: &str) -> PathBuf {
+        let file = match tilde::word(path, &self.config.directories) {
+            Some(path) => PathBuf::from(path),
+            None => PathBuf::from(path),
+        };
+        match self.directory() {
+            Some(directory) => directory.join(file),
+            None => file,
+        }
+    }
+
+    /// The history of the session as a transcript, with the working directory. (See
+    /// [`crate::transcript`])
+    fn transcript(&self) -> transcript::Transcript {
+        let oldest = self.recorded - self.history.len();
+        let mut transcript = transcript::Transcript::new(
+            self.history
+                .iter()
+                .enumerate()
+                .map(|(index, entry)| Entry {
+                    note: self.notes.get(&(oldest + index)).cloned(),
+                    starred: self.starred.contains(&(oldest + index)),
+                    ..Entry::from(&*entry)
+                })
+                .collect(),
+        );
+        transcript.meta.cwd = self.directory().map(|dir| dir.display().to_string());
+        transcript
+    }
+
+    /// Start saving the state of the session, unless `history.autosave` is off, and offer to
+    /// restore the autosave of a session that crashed. Autosaves are only offered in the full
+    /// screen interface, where the question is asked. (See `crate::autosave`)
+    fn start_autosave(&mut self) {
+        if self.isolated || self.publisher.is_some() || self.config.history.autosave == 0 {
+            return;
+        }
+        if !self.config.ui.screen_reader {
+            if let Some(file) = autosave::orphan() {
+                let read = std::fs::File::open(&file)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|file| transcript::Transcript::read(io::BufReader::new(file)));
+                match read {
+                    Ok(transcript) => self.open(Overlay::Recover(file, Box::new(transcript))),
+                    Err(e) => {
+                        self.fail(e.context(format!("unable to read `{}`", file.display())));
+                        self.discard(&file);
+                    }
+                }
+            }
+        }
+        self.environment = autosave::environment();
+        self.autosave = autosave::path().map(|file| (file, Instant::now()));
+    }
+
+    /// Save the state of the session if the history changed, and the last save is older than
+    /// `history.autosave`. Failing to save turns the autosave off, reported once.
+    fn autosave(&mut self) {
+        let interval = Duration::from_secs(self.config.history.autosave);
+        let Some((file, saved)) = &self.autosave else {
+            return;
+        };
+        if !self.unsaved || saved.elapsed() < interval {
+            return;
+        }
+
+        let mut transcript = self.transcript();
+        transcript.meta.env = autosave::changes(&self.environment);
+        match autosave::save(file, &transcript) {
+            Ok(()) => {
+                self.autosave = Some((file.clone(), Instant::now()));
+                self.unsaved = false;
+            }
+            Err(e) => {
+                self.autosave = None;
+                self.fail(e.context("unable to save the session, autosave is off"));
+            }
+        }
+    }
+
+    /// Lock the screen if no input came for `lock.after` minutes, unless it is locked already.
+    /// (See [`crate::config::Lock`])
+    fn lock_if_idle(&mut self) {
+        let after = Duration::from_secs(self.config.lock.after.saturating_mul(60));
+        let locked = self
+            .overlays
+            .iter()
+            .any(|overlay| matches!(overlay, Overlay::Lock(..)));
+        if self.config.lock.after > 0 && self.active.elapsed() >= after && !locked {
+            self.open(Overlay::Lock(String::new(), false));
+        }
+    }
+
+    /// Stop saving the state of the session, as it exits normally, and remove the last save.
+    fn stop_autosave(&mut self) {
+        if let Some((file, _)) = self.autosave.take() {
+            self.discard(&file);
+        }
+    }
+
+    /// Restore the autosave of a session that crashed: add its entries to the history, and set
+    /// its working directory and environment variables back. The autosave is removed.
+    fn recover(&mut self, file: &Path, transcript: transcript::Transcript) {
+        let count = transcript.entries.len();
+        for mut entry in transcript.entries {
+            let note = entry.note.take();
+            let starred = entry.starred;
+            self.push_history(command::CommandOutput::from(entry));
+            let id = self.recorded - 1;
+            if let Some(note) = note {
+                self.notes.insert(id, note);
+            }
+            if starred {
+                self.starred.insert(id);
+            }
+        }
+        if let Some(cwd) = transcript.meta.cwd {
+            if let Err(e) = std::env::set_current_dir(&cwd) {
+                self.fail(
+                    anyhow::Error::from(e).context(format!("unable to go back to `{}`", cwd)),
+                );
+            }
+        }
+        for (name, value) in transcript.meta.env {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+        self.discard(file);
+
+        let restored = self
+            .messages
+            .format("autosave-restored", &[("count", &count)]);
+        self.record(AUTOSAVE.to_string(), vec![restored], Vec::new());
+    }
+
+    /// Remove an autosave.
+    fn discard(&mut self, file: &Path) {
+        match std::fs::remove_file(file) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                self.fail(
+                    anyhow::Error::from(e)
+                        .context(format!("unable to remove `{}`", file.display())),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    /// Run the `:transcript save FILE` builtin: write the history of the session to a
+    /// transcript, returning the output and the errors. (See [`crate::transcript`])
+    fn save_transcript(&self, path: &str) -> (Vec<String>, Vec<String>) {
+        let transcript = self.transcript();
+        let file = self.resolve(path);
+        let written = std::fs::File::create(&file)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| transcript.write(io::BufWriter::new(file)));
+        match written {
+            Ok(()) => (
+                vec![self.messages.format(
+                    "transcript-saved",
+                    &[
+                        ("count", &transcript.entries.len()),
+                        ("path", &file.display()),
+                    ],
+                )],
+                Vec::new(),
+            ),
+            Err(e) => (
+                Vec::new(),
+                vec![self.messages.format(
+                    "transcript-failed",
+                    &[("path", &path), ("error", &format!("{:#}", e))],
+                )],
+            ),
+        }
+    }
+
+    /// Attach a note to the last entry of the history, and to the command of the journal it came
+    /// from. This returns `false` if the history is empty.
+    fn annotate(&mut self, note: &str) -> bool {
+        if self.history.len() == 0 {
+            return false;
+        }
+        let id = self.recorded - 1;
+        self.notes.insert(id, note.to_string());
+        self.cache.borrow_mut().forget(id);
+
+        if let (Some(store), Some((entry, row))) = (&self.store, self.journaled) {
+            if entry == id {
+                if let Err(e) = store.annotate(row, note) {
+                    self.fail(e);
+                }
+            }
+        }
+        if let Some(publisher) = &mut self.publisher {
+            publisher.push(UiEvent::EntryAnnotated {
+                id,
+                note: note.to_string(),
+            });
+        }
+        true
+    }
+
+    /// Star the last entry of the history, or unstar it if it is starred, along with the command
+    /// of the journal it came from. This returns `false` if the history is empty.
+    fn star(&mut self) -> bool {
+        if self.history.len() == 0 {
+            return false;
+        }
+        let id = self.recorded - 1;
+        let starred = self.starred.insert(id) || !self.starred.remove(&id);
+        self.cache.borrow_mut().forget(id);
+
+        if let (Some(store), Some((entry, row))) = (&self.store, self.journaled) {
+            if entry == id {
+                if let Err(e) = store.star(row, starred) {
+                    self.fail(e);
+                }
+            }
+        }
+        if let Some(publisher) = &mut self.publisher {
+            publisher.push(UiEvent::EntryStarred { id, starred });
+        }
+        true
+    }
+
+    /// Put the command of the last entry of the history on the prompt, to run it again with
+    /// changes, linked to the entry. A substitution `^OLD^NEW` is applied to it first. (See
+    /// [`Shell::substitute`]) This returns the error to show if there is no command to fix.
+    fn fix(&mut self, substitution: Option<&str>) -> Result<(), String> {
+        if self.state.prompt_line().is_none() {
+            return Ok(());
+        }
+        let command = match substitution {
+            Some(substitution) => self.substitute(substitution)?,
+            None => self
+                .history
+                .len()
+                .checked_sub(1)
+                .and_then(|index| self.history.command(index))
+                .ok_or_else(|| self.messages.get("fix-nothing"))?
+                .into_owned(),
+        };
+        self.fixing = Some(self.last_entry());
+        self.transition(state::Event::Edit(LineBuffer::from(command)));
+        Ok(())
+    }
+
+    /// The last command of the history, with the first `OLD` replaced by `NEW`, given a quick
+    /// substitution `^OLD^NEW`. This returns the error to show if it cannot be applied.
+    fn substitute(&self, substitution: &str) -> Result<String, String> {
+        let index = self.history.len().checked_sub(1);
+        let Some(command) = index.and_then(|index| self.history.command(index)) else {
+            return Err(self.messages.get("fix-nothing"));
+        };
+        let Some((old, new)) = substitution
+            .strip_prefix('^')
+            .map(|rest| rest.trim_end_matches('^'))
+            .and_then(|rest| rest.split_once('^').or(Some((rest, ""))))
+            .filter(|(old, _)| !old.is_empty())
+        else {
+            return Err(self.messages.get("fix-usage"));
+        };
+        if !command.contains(old) {
+            return Err(self.messages.format("fix-no-match", &[("old", &old)]));
+        }
+        Ok(command.replacen(old, new, 1))
+    }
+
+    /// The id of the last entry of the history, with the id of the command of the journal it came
+    /// from, if it was kept there.
+    fn last_entry(&self) -> (usize, Option<i64>) {
+        let id = self.recorded - 1;
+        let row = self.journaled.filter(|(entry, _)| *entry == id);
+        (id, row.map(|(_, row)| row))
+    }
+
+    /// Show only the starred entries of the history, or every entry again if they are.
+    fn show_starred(&mut self) {
+        self.filter = match self.filter {
+            Some(Filter::Starred) => None,
+            _ => Some(Filter::Starred),
+        };
+    }
+
+    /// Run the `:begin` builtin: start a run, with the entries added after its own.
+    fn begin_run(&mut self, cmd: &str, name: &str) {
+        if let Some(open) = self.runs.open() {
+            let error = self
+                .messages
+                .format("run-still-open", &[("name", &open.name)]);
+            self.record(cmd.to_string(), Vec::new(), vec![error]);
+            return;
+        }
+        let started = self.messages.format("run-started", &[("name", &name)]);
+        self.record(cmd.to_string(), vec![started], Vec::new());
+        self.runs.begin(name, self.recorded);
+    }
+
+    /// Run the `:run` builtin: list, fold, unfold or export the runs.
+    fn manage_runs(
+        &mut self,
+        subcommand: Option<&str>,
+        name: Option<&str>,
+        file: Option<&str>,
+    ) -> (Vec<String>, Vec<String>) {
+        match (subcommand, name, file) {
+            (None | Some("list"), None, None) => {
+                let runs = self.runs.iter().map(|run| {
+                    let key = match run.end {
+                        Some(_) => "run-entry",
+                        None => "run-entry-open",
+                    };
+                    self.messages
+                        .format(key, &[("name", &run.name), ("count", &run.len())])
+                });
+                match runs.collect::<Vec<_>>() {
+                    runs if runs.is_empty() => (vec![self.messages.get("run-none")], Vec::new()),
+                    runs => (runs, Vec::new()),
+                }
+            }
+            (Some(action @ ("fold" | "unfold")), name, None) => match self.runs.find_mut(name) {
+                Some(run) => {
+                    run.folded = action == "fold";
+                    let key = match run.folded {
+                        true => "run-folded",
+                        false => "run-unfolded",
+                    };
+                    let done = self.messages.format(key, &[("name", &run.name)]);
+                    (vec![done], Vec::new())
+                }
+                None => match name {
+                    Some(name) => (
+                        Vec::new(),
+                        vec![self.messages.format("run-unknown", &[("name", &name)])],
+                    ),
+                    None => (Vec::new(), vec![self.messages.get("run-none")]),
+                },
+            },
+            (Some("export"), Some(name), file) => {
+                let Some(run) = self.runs.find(Some(name)) else {
+                    let unknown = self.messages.format("run-unknown", &[("name", &name)]);
+                    return (Vec::new(), vec![unknown]);
+                };
+                let oldest = self.recorded - self.history.len();
+                let entries = (run.first.max(oldest)..run.end.unwrap_or(self.recorded))
+                    .filter_map(|id| Some((id, self.history.get(id - oldest)?)));
+                let comment = self.executor.syntax().comment();
+                let markdown = run.markdown(entries, &self.notes, &self.starred, comment);
+
+                let Some(file) = file else {
+                    return (markdown.lines().map(str::to_string).collect(), Vec::new());
+                };
+                let path = self.resolve(file);
+                match std::fs::write(&path, markdown) {
+                    Ok(()) => (
+                        vec![self.messages.format(
+                            "run-exported",
+                            &[("name", &name), ("path", &path.display())],
+                        )],
+                        Vec::new(),
+                    ),
+                    Err(e) => (
+                        Vec::new(),
+                        vec![self
+                            .messages
+                            .format("run-export-failed", &[("path", &file), ("error", &e)])],
+                    ),
+                }
+            }
+            _ => (Vec::new(), vec![self.messages.get("run-usage")]),
+        }
+    }
+
+    /// Run the `:source` builtin: run every line of a file, in order, as if typed. Empty lines and
+    /// lines starting with `#` are skipped. This stops at the first command failing (returning an
+    /// error, or exiting with a code other than 0) unless `keep_going` is set.
+    fn source(&mut self, cmd: &str, path: &str, keep_going: bool) -> anyhow::Result<Next> {
+        let file = self.resolve(path);
+        if self.sourcing >= SOURCE_DEPTH {
+            let error = self.messages.get("source-nested");
+            self.record(cmd.to_string(), Vec::new(), vec![error]);
+            return Ok(Next::Continue);
+        }
+        let content = match std::fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(e) => {
+                let error = self
+                    .messages
+                    .format("source-failed", &[("path", &path), ("error", &e)]);
+                self.record(cmd.to_string(), Vec::new(), vec![error]);
+                return Ok(Next::Continue);
+            }
+        };
+
+        let (next, stopped) = self.run_lines(&content, keep_going);
+
+        if !matches!(next, Next::Exit(_)) {
+            let (stdout, stderr) = match stopped {
+                Some(line) => (
+                    Vec::new(),
+                    vec![self
+                        .messages
+                        .format("source-stopped", &[("path", &path), ("line", &line)])],
+                ),
+                None => (
+                    vec![self.messages.format("source-done", &[("path", &path)])],
+                    Vec::new(),
+                ),
+            };
+            self.record(cmd.to_string(), stdout, stderr);
+        }
+
+        Ok(next)
+    }
+
+    /// Run the `:source --confirm-each` builtin: step through the lines of a file as through a
+    /// runbook, every command offered to be run, edited or skipped before it is run. (See
+    /// [`Shell::source`] and [`crate::runbook`])
+    fn source_confirmed(
+        &mut self,
+        cmd: &str,
+        path: &str,
+        keep_going: bool,
+    ) -> anyhow::Result<Next> {
+        let file = self.resolve(path);
+        if self.runbook.is_some() {
+            let error = self.messages.get("runbook-busy");
+            self.record(cmd.to_string(), Vec::new(), vec![error]);
+            return Ok(Next::Continue);
+        }
+        match std::fs::read_to_string(&file) {
+            Ok(content) => {
+                self.runbook = Some(Runbook::script(file, &content, cmd, keep_going));
+            }
+            Err(e) => {
+                let error = self
+                    .messages
+                    .format("source-failed", &[("path", &path), ("error", &e)]);
+                self.record(cmd.to_string(), Vec::new(), vec![error]);
+            }
+        }
+        Ok(Next::Continue)
+    }
+
+    /// Run every line of a file, in order, as if typed, skipping empty lines and comments. This
+    /// returns how the shell should go on, and the number of the line that failed, if it stopped
+    /// there. (See [`Shell::source`])
+    fn run_lines(&mut self, content: &str, keep_going: bool) -> (Next, Option<usize>) {
+        self.sourcing += 1;
+        let mut next = Next::Continue;
+        let mut stopped = None;
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let recorded = self.recorded;
+            let failed = match self.inject(line, &[]) {
+                Some(Ok(Next::Exit(message))) => {
+                    next = Next::Exit(message);
+                    break;
+                }
+                Some(Ok(Next::Clear)) => {
+                    next = Next::Clear;
+                    false
+                }
+                Some(Ok(Next::Continue)) => self.failed_since(recorded),
+                Some(Err(e)) => {
+                    self.record(line.to_string(), Vec::new(), vec![e.to_string()]);
+                    true
+                }
+                None => true,
+            };
+            if failed && !keep_going {
+                stopped = Some(index + 1);
+                break;
+            }
+        }
+        self.sourcing -= 1;
+
+        (next, stopped)
+    }
+
+    /// Run the `:macro` builtin, returning its output.
+    fn manage_macros(
+        &mut self,
+        subcommand: Option<&str>,
+        name: Option<&str>,
+    ) -> (Vec<String>, Vec<String>) {
+        let failed = |messages: &Catalog, e: anyhow::Error| {
+            let error = messages.format("macro-failed", &[("error", &e.to_string())]);
+            (Vec::new(), vec![error])
+        };
+
+        match (subcommand, name) {
+            (None | Some("list"), None) => match macros::load() {
+                Ok(saved) if saved.is_empty() => {
+                    (vec![self.messages.get("macro-none")], Vec::new())
+                }
+                Ok(saved) => (
+                    saved
+                        .iter()
+                        .map(|(name, keys)| {
+                            let count = keys.len().to_string();
+                            self.messages
+                                .format("macro-entry", &[("name", name), ("count", &count)])
+                        })
+                        .collect(),
+                    Vec::new(),
+                ),
+                Err(e) => failed(&self.messages, e),
+            },
+            (Some("save"), Some(name)) => match self.macros.last() {
+                [] => {
+                    let key = self.key_label(Action::RecordMacro);
+                    (
+                        Vec::new(),
+                        vec![self.messages.format("macro-empty", &[("key", &key)])],
+                    )
+                }
+                keys => match macros::save(name, keys) {
+                    Ok(path) => {
+                        let path = path.display().to_string();
+                        let saved = self
+                            .messages
+                            .format("macro-saved", &[("name", &name), ("path", &path)]);
+                        (vec![saved], Vec::new())
+                    }
+                    Err(e) => failed(&self.messages, e),
+                },
+            },
+            (Some("delete"), Some(name)) => match macros::delete(name) {
+                Ok(true) => {
+                    let deleted = self.messages.format("macro-deleted", &[("name", &name)]);
+                    (vec![deleted], Vec::new())
+                }
+                Ok(false) => {
+                    let unknown = self.messages.format("macro-unknown", &[("name", &name)]);
+                    (Vec::new(), vec![unknown])
+                }
+                Err(e) => failed(&self.messages, e),
+            },
+            _ => (Vec::new(), vec![self.messages.get("macro-usage")]),
+        }
+    }
+
+    /// Look up a saved macro for `:macro play NAME [COUNT]`, returning its keys and the number
+    /// of times to replay it, or the error to report.
+    fn saved_macro(
+        &self,
+        name: Option<&str>,
+        count: Option<&str>,
+    ) -> Result<(Vec<Key>, usize), String> {
+        let (Some(name), Ok(count)) = (name, count.map_or(Ok(1), str::parse::<usize>)) else {
+            return Err(self.messages.get("macro-usage"));
+        };
+
+        match macros::load() {
+            Ok(mut saved) => saved
+                .remove(name)
+                .map(|keys| (keys, count))
+                .ok_or_else(|| self.messages.format("macro-unknown", &[("name", &name)])),
+            Err(e) => Err(self
+                .messages
+                .format("macro-failed", &[("error", &e.to_string())])),
+        }
+    }
+
+    /// Handle a call on the control socket, answering it once it is done.
+    #[cfg(unix)]
+    fn control(&mut self, call: Call) -> anyhow::Result<Next> {
+        match &call.request {
+            Request::Run { command, stdin } => {
+                let recorded = self.recorded;
+                let Some(next) = self.inject(command, stdin) else {
+                    call.reply(Err(control::Error::new(
+                        control::Error::BUSY,
+                        "a command is waiting for input",
+                    )));
+                    return Ok(Next::Continue);
+                };
+
+                match next {
+                    Ok(next) => {
+                        let added = self.recorded - recorded;
+                        let entries = self
+                            .history
+                            .iter()
+                            .skip(self.history.len().saturating_sub(added))
+                            .map(|entry| control::entry(&entry))
+                            .collect();
+                        call.reply(Ok(serde_json::Value::Array(entries)));
+                        Ok(next)
+                    }
+                    Err(e) => {
+                        call.reply(Err(control::Error::new(control::Error::FAILED, &e)));
+                        Err(e)
+                    }
+                }
+            }
+            Request::History { limit } => {
+                let skip = limit.map_or(0, |limit| self.history.len().saturating_sub(limit));
+                let entries = self.history.iter().skip(skip);
+                let entries = entries.map(|entry| control::entry(&entry)).collect();
+                call.reply(Ok(serde_json::Value::Array(entries)));
+                Ok(Next::Continue)
+            }
+            Request::Subscribe => {
+                self.subscribers.push(call.subscriber());
+                call.reply(Ok(serde_json::Value::Bool(true)));
+                Ok(Next::Continue)
+            }
+        }
+    }
+
+    /// Handle the input while an overlay is open, taken off the top of the stack. The overlay is
+    /// put back unless the input closes it. The help opens over any other overlay.
+    fn overlay_input(&mut self, overlay: Overlay, event: Event) -> anyhow::Result<Next> {
+        let Event::Key(ke) = event else {
+            self.open(overlay);
+            return Ok(Next::Continue);
+        };
+        if self.config.keys.action(&ke, true) == Some(Action::Help)
+            && !matches!(overlay, Overlay::Help | Overlay::Lock(..))
+        {
+            self.open(overlay);
+            self.open(Overlay::Help);
+            return Ok(Next::Continue);
+        }
+
+        match overlay {
+            Overlay::Themes(selected, previous) => match ke.code {
+                KeyCode::Up | KeyCode::Char('k') => {
+                    let selected = selected.checked_sub(1).unwrap_or(theme::BUILTIN.len() - 1);
+                    self.preview_theme(selected, previous);
+                }
+                KeyCode::Down | KeyCode::Char('j') | KeyCode::Tab => {
+                    let selected = (selected + 1) % theme::BUILTIN.len();
+                    self.preview_theme(selected, previous);
+                }
+                KeyCode::Enter => {
+                    let command = format!(":theme {}", self.theme.name);
+                    let (stdout, stderr) = self.set_theme(&self.theme.name.clone());
+                    self.record(command, stdout, stderr);
+                }
+                KeyCode::Esc => self.apply_theme(*previous),
+                _ => self.open(Overlay::Themes(selected, previous)),
+            },
+            Overlay::Help | Overlay::Explanation(..) | Overlay::Errors => {}
+            Overlay::Pager(mut pager) => {
+                if pager.input(ke) {
+                    self.open(Overlay::Pager(pager));
+                }
+            }
+            #[cfg(feature = "suggest")]
+            Overlay::Suggestion(command) => match ke.code {
+                KeyCode::Enter => {
+                    self.transition(state::Event::Edit(LineBuffer::from(command)));
+                }
+                KeyCode::Esc => {}
+                _ => self.open(Overlay::Suggestion(command)),
+            },
+            Overlay::Correction(command) => match ke.code {
+                KeyCode::Char('y') | KeyCode::Enter => {
+                    self.transition(state::Event::Edit(LineBuffer::from(command)));
+                    return self.execute_guarded();
+                }
+                KeyCode::Char('n') => return self.execute_guarded(),
+                KeyCode::Char('e') => {
+                    self.transition(state::Event::Edit(LineBuffer::from(command)));
+                }
+                KeyCode::Esc => {}
+                _ => self.open(Overlay::Correction(command)),
+            },
+            Overlay::Flood
+                if ke.code == KeyCode::Char('p')
+                    || self.config.keys.action(&ke, true) == Some(Action::PauseOutput) =>
+            {
+                self.pause_output();
+                self.open(Overlay::Flood);
+            }
+            Overlay::Flood => match ke.code {
+                KeyCode::Esc => self.drop_flood(),
+                _ => self.open(Overlay::Flood),
+            },
+            Overlay::Runbook => {
+                let recorded = self.recorded;
+                let Some(runbook) = self.runbook.as_mut() else {
+                    return Ok(Next::Continue);
+                };
+                let Some((_, _, command)) = runbook.current() else {
+                    return Ok(Next::Continue);
+                };
+                let command = LineBuffer::from(command.to_string());
+                match ke.code {
+                    KeyCode::Char('y') | KeyCode::Enter => {
+                        runbook.started = Some((recorded, false));
+                        self.transition(state::Event::Edit(command));
+                        return self.execute_guarded();
+                    }
+                    KeyCode::Char('e') => {
+                        runbook.started = Some((recorded, true));
+                        self.transition(state::Event::Edit(command));
+                    }
+                    KeyCode::Char('n' | 's') => self.skip_step(),
+                    KeyCode::Char('q') | KeyCode::Esc => self.finish_runbook(),
+                    _ => self.open(Overlay::Runbook),
+                }
+            }
+            Overlay::Guard(reason) => match ke.code {
+                KeyCode::Char('y') => return self.execute_command(),
+                KeyCode::Char('n') | KeyCode::Esc => {}
+                _ => self.open(Overlay::Guard(reason)),
+            },
+            Overlay::Trust(file) => match ke.code {
+                KeyCode::Char(answer @ ('y' | 'n')) => self.trust_project(&file, answer == 'y'),
+                KeyCode::Esc => {}
+                _ => self.open(Overlay::Trust(file)),
+            },
+            Overlay::Lock(mut typed, failed) => {
+                let hash = self.config.lock.passphrase_sha256.clone();
+                match (hash, ke.code) {
+                    (None, _) => self.unlock(),
+                    (Some(hash), KeyCode::Enter) if unlocks(&typed, &hash) => self.unlock(),
+                    (Some(_), KeyCode::Enter) => self.open(Overlay::Lock(String::new(), true)),
+                    (Some(_), KeyCode::Esc) => self.open(Overlay::Lock(String::new(), failed)),
+                    (Some(_), KeyCode::Backspace) => {
+                        typed.pop();
+                        self.open(Overlay::Lock(typed, failed));
+                    }
+                    (Some(_), KeyCode::Char(c)) => {
+                        typed.push(c);
+                        self.open(Overlay::Lock(typed, failed));
+                    }
+                    (Some(_), _) => self.open(Overlay::Lock(typed, failed)),
+                }
+            }
+            // Left unanswered, the autosave is offered again on the next launch.
+            Overlay::Recover(file, transcript) => match ke.code {
+                KeyCode::Char('y') => self.recover(&file, *transcript),
+                KeyCode::Char('n') => self.discard(&file),
+                KeyCode::Esc => {}
+                _ => self.open(Overlay::Recover(file, transcript)),
+            },
+            Overlay::Queue(selected) => {
+                let last = self.queue.len().saturating_sub(1);
+                let shift = ke.modifiers.contains(KeyModifiers::SHIFT);
+                let selected = match ke.code {
+                    KeyCode::Esc | KeyCode::Enter => return Ok(Next::Continue),
+                    KeyCode::Up if shift && selected > 0 => {
+                        self.queue.swap(selected, selected - 1);
+                        selected - 1
+                    }
+                    KeyCode::Down if shift && selected < last => {
+                        self.queue.swap(selected, selected + 1);
+                        selected + 1
+                    }
+                    KeyCode::Up => selected.saturating_sub(1),
+                    KeyCode::Down => (selected + 1).min(last),
+                    KeyCode::Delete | KeyCode::Backspace => {
+                        self.queue.remove(selected);
+                        selected.min(self.queue.len().saturating_sub(1))
+                    }
+                    _ => selected,
+                };
+                if !self.queue.is_empty() {
+                    self.open(Overlay::Queue(selected));
+                }
+            }
+            Overlay::Kills(selected) => match ke.code {
+                KeyCode::Enter => {
+                    if let Some(snippet) = self.kills.get(selected).map(kills::inline) {
+                        self.edit(|line| {
+                            line.insert_str(&snippet);
+                            true
+                        });
+                    }
+                }
+                KeyCode::Esc => {}
+                KeyCode::Up => self.open(Overlay::Kills(selected.saturating_sub(1))),
+                KeyCode::Down => {
+                    let last = self.kills.len().saturating_sub(1);
+                    self.open(Overlay::Kills((selected + 1).min(last)));
+                }
+                _ => self.open(Overlay::Kills(selected)),
+            },
+            Overlay::Copy(entry, anchor, cursor) => {
+                let command = self.history.command(entry).unwrap_or_default();
+                let (start, end) = (anchor.min(cursor), anchor.max(cursor));
+                let shift = ke.modifiers.contains(KeyModifiers::SHIFT);
+                // Moving without `Shift` leaves nothing selected, the selection starting anew.
+                let moved = |cursor: usize| match shift {
+                    true => Overlay::Copy(entry, anchor, cursor),
+                    false => Overlay::Copy(entry, cursor, cursor),
+                };
+                let overlay = match ke.code {
+                    KeyCode::Enter => {
+                        let selected = match start == end {
+                            true => command.trim(),
+                            false => command[start..end].trim(),
+                        };
+                        let selected = LineBuffer::from(selected.to_string());
+                        self.transition(state::Event::Edit(selected));
+                        return Ok(Next::Continue);
+                    }
+                    KeyCode::Esc => return Ok(Next::Continue),
+                    KeyCode::Up | KeyCode::Down => {
+                        let entry = match ke.code {
+                            KeyCode::Up => entry.saturating_sub(1),
+                            _ => (entry + 1).min(self.history.len().saturating_sub(1)),
+                        };
+                        let end = self
+                            .history
+                            .command(entry)
+                            .map_or(0, |command| command.len());
+                        Overlay::Copy(entry, 0, end)
+                    }
+                    KeyCode::Left => {
+                        let before = command[..cursor].chars().next_back();
+                        moved(cursor - before.map_or(0, char::len_utf8))
+                    }
+                    KeyCode::Right => {
+                        let after = command[cursor..].chars().next();
+                        moved(cursor + after.map_or(0, char::len_utf8))
+                    }
+                    KeyCode::Home => moved(0),
+                    KeyCode::End => moved(command.len()),
+                    KeyCode::Tab | KeyCode::BackTab => {
+                        let stages = stages(&command);
+                        let stage = match ke.code {
+                            KeyCode::Tab => stages.iter().find(|stage| stage.0 >= end),
+                            _ => stages.iter().rev().find(|stage| stage.1 <= start),
+                        };
+                        match stage.or(match ke.code {
+                            KeyCode::Tab => stages.first(),
+                            _ => stages.last(),
+                        }) {
+                            Some(&(start, end)) => Overlay::Copy(entry, start, end),
+                            None => Overlay::Copy(entry, anchor, cursor),
+                        }
+                    }
+                    _ => Overlay::Copy(entry, anchor, cursor),
+                };
+                self.open(overlay);
+            }
+            Overlay::Pick(mut query, selected, lines, template) => {
+                let shown = fuzzy::filter(&query, &lines);
+                match ke.code {
+                    KeyCode::Enter => {
+                        if let Some(line) = shown.get(selected).map(|&index| &lines[index]) {
+                            let command = fill(&template, line);
+                            self.transition(state::Event::Edit(LineBuffer::from(command)));
+                        }
+                    }
+                    KeyCode::Esc => {}
+                    KeyCode::Up => {
+                        let selected = selected.saturating_sub(1);
+                        self.open(Overlay::Pick(query, selected, lines, template));
+                    }
+                    KeyCode::Down => {
+                        let selected = (selected + 1).min(shown.len().saturating_sub(1));
+                        self.open(Overlay::Pick(query, selected, lines, template));
+                    }
+                    KeyCode::Backspace => {
+                        query.pop();
+                        self.open(Overlay::Pick(query, 0, lines, template));
+                    }
+                    KeyCode::Char(c) if !ke.modifiers.contains(KeyModifiers::CONTROL) => {
+                        query.push(c);
+                        self.open(Overlay::Pick(query, 0, lines, template));
+                    }
+                    _ => self.open(Overlay::Pick(query, selected, lines, template)),
+                }
+            }
+            Overlay::Processes(mut query, selected, processes) => {
+                let shown = filter_processes(&query, &processes).len();
+                match ke.code {
+                    KeyCode::Enter => {
+                        let pid = filter_processes(&query, &processes)
+                            .get(selected)
+                            .map(|process| process.pid);
+                        if let Some(pid) = pid {
+                            self.edit(|line| {
+                                let spaced = line.before().is_empty()
+                                    || line.before().ends_with(char::is_whitespace);
+                                match spaced {
+                                    true => line.insert_str(&pid.to_string()),
+                                    false => line.insert_str(&format!(" {}", pid)),
+                                }
+                                true
+                            });
+                        }
+                    }
+                    KeyCode::Esc => {}
+                    KeyCode::Up => {
+                        let selected = selected.saturating_sub(1);
+                        self.open(Overlay::Processes(query, selected, processes));
+                    }
+                    KeyCode::Down => {
+                        let selected = (selected + 1).min(shown.saturating_sub(1));
+                        self.open(Overlay::Processes(query, selected, processes));
+                    }
+                    KeyCode::Backspace => {
+                        query.pop();
+                        self.open(Overlay::Processes(query, 0, processes));
+                    }
+                    KeyCode::Char(c) if !ke.modifiers.contains(KeyModifiers::CONTROL) => {
+                        query.push(c);
+                        self.open(Overlay::Processes(query, 0, processes));
+                    }
+                    _ => self.open(Overlay::Processes(query, selected, processes)),
+                }
+            }
+            Overlay::Search(mut query, selected, matches) => {
+                let older = self.config.keys.action(&ke, true) == Some(Action::SearchHistory);
+                match ke.code {
+                    KeyCode::Enter => {
+                        if let Some(command) = matches.get(selected).cloned() {
+                            self.transition(state::Event::Edit(LineBuffer::from(command)));
+                        }
+                    }
+                    KeyCode::Esc => {}
+                    _ if older || ke.code == KeyCode::Up => {
+                        let selected = (selected + 1).min(matches.len().saturating_sub(1));
+                        self.open(Overlay::Search(query, selected, matches));
+                    }
+                    KeyCode::Down => {
+                        let selected = selected.saturating_sub(1);
+                        self.open(Overlay::Search(query, selected, matches));
+                    }
+                    KeyCode::Backspace => {
+                        query.pop();
+                        let matches = self.search_history(&query);
+                        self.open(Overlay::Search(query, 0, matches));
+                    }
+                    KeyCode::Char(c) if !ke.modifiers.contains(KeyModifiers::CONTROL) => {
+                        query.push(c);
+                        let matches = self.search_history(&query);
+                        self.open(Overlay::Search(query, 0, matches));
+                    }
+                    _ => self.open(Overlay::Search(query, selected, matches)),
+                }
+            }
+        }
+
+        Ok(Next::Continue)
+    }
+
+    // helpers
+
+    /// Open an overlay, on top of the ones already open. While the screen is locked, the overlay
+    /// is held until it is unlocked, so no key reaches it through the lock.
+    fn open(&mut self, overlay: Overlay) {
+        let locked = matches!(self.overlays.last(), Some(Overlay::Lock(..)));
+        match locked && !matches!(overlay, Overlay::Lock(..)) {
+            true => self.held.push(overlay),
+            false => self.overlays.push(overlay),
+        }
+    }
+
+    /// Open the overlays held while the screen was locked, once it is unlocked.
+    fn unlock(&mut self) {
+        let held = std::mem::take(&mut self.held);
+        for overlay in held {
+            self.open(overlay);
+        }
+    }
+
+    /// Switch to the theme, styling the history with it anew.
+    fn apply_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.theme_generation += 1;
+    }
+
+    /// Preview the selected theme of the theme picker.
+    fn preview_theme(&mut self, selected: usize, previous: Box<Theme>) {
+        if let Some(theme) = theme::BUILTIN
+            .get(selected)
+            .and_then(|name| self.load_theme(name))
+        {
+            self.apply_theme(theme);
+        }
+        self.open(Overlay::Themes(selected, previous));
+    }
+
+    /// Report the working directory of the executor to the terminal, if it changed since the last
+    /// report. (OSC 7)
+    fn report_cwd(&mut self) -> io::Result<()> {
+        if !self.config.ui.shell_integration {
+            return Ok(());
+        }
+
+        let cwd = self.executor.cwd(&self.context);
+        if cwd != self.cwd {
+            if let Some(path) = &cwd {
+                write!(self.out, "{}", self.caps.osc(&integration::cwd(path)))?;
+                self.out.flush()?;
+            }
+            self.cwd = cwd;
+        }
+
+        Ok(())
+    }
+
+    /// Update the window title to match the state of the shell, if it changed. (OSC 2)
+    fn report_title(&mut self) -> io::Result<()> {
+        let (template, command) = match &self.state {
+            State::Idle(..) => (&self.config.ui.title, ""),
+            State::Running(prepare, ..) => {
+                (&self.config.ui.title_running, prepare.command.as_str())
+            }
+        };
+        if template.is_empty() {
+            return Ok(());
+        }
+
+        let cwd = self
+            .executor
+            .cwd(&self.context)
+            .map(|path| integration::tilde(&path))
+            .unwrap_or_default();
+        let title = template
+            .replace("{cwd}", &cwd)
+            .replace("{command}", command);
+        if title != self.title {
+            write!(self.out, "{}", self.caps.osc(&integration::title(&title)))?;
+            self.out.flush()?;
+            self.title = title;
+        }
+
+        Ok(())
+    }
+
+    /// Emit a semantic prompt marker, if shell integration is enabled. (OSC 133)
+    fn integrate(&mut self, marker: &str) -> io::Result<()> {
+        match self.config.ui.shell_integration {
+            true => write!(self.out, "{}", self.caps.osc(marker)),
+            false => Ok(()),
+        }
+    }
+
+    /// Look up a built-in theme, in the variant matching the terminal.
+    fn load_theme(&self, name: &str) -> Option<Theme> {
+        Theme::builtin(name, self.appearance).map(|theme| theme.adapted(self.colors))
+    }
+
+    /// Apply the theme, and persist the choice to the configuration file. This returns the
+    /// output and the errors to be recorded in the history.
+    fn set_theme(&mut self, name: &str) -> (Vec<String>, Vec<String>) {
+        let Some(theme) = self.load_theme(name) else {
+            return (
+                Vec::new(),
+                vec![self.messages.format(
+                    "theme-unknown",
+                    &[("name", &name), ("themes", &theme::BUILTIN.join(", "))],
+                )],
+            );
+        };
+        self.apply_theme(theme);
+        self.config.ui.theme = name.to_string();
+        self.user_config.ui.theme = name.to_string();
+
+        match config::store("ui", "theme", name) {
+            Ok(path) => (
+                vec![self
+                    .messages
+                    .format("theme-saved", &[("name", &name), ("path", &path.display())])],
+                Vec::new(),
+            ),
+            Err(e) => (
+                vec![self.messages.format("theme-set", &[("name", &name)])],
+                vec![self.messages.format("theme-save-failed", &[("error", &e)])],
+            ),
+        }
+    }
+
+    /// Run a shell builtin (a command starting with `:`), recording its output in the history.
+    fn builtin(&mut self, cmd: &str) -> anyhow::Result<Next> {
+        let mut args = cmd.trim_start_matches(':').split_whitespace();
+        let (stdout, stderr) = match (args.next(), args.next()) {
+            (Some("theme"), None) => {
+                let selected = theme::BUILTIN
+                    .iter()
+                    .position(|name| *name == self.theme.name)
+                    .unwrap_or_default();
+                self.open(Overlay::Themes(selected, Box::new(self.theme.clone())));
+                self.transition(state::Event::Done);
+                return Ok(Next::Continue);
+            }
+            (Some("theme"), Some(name)) => self.set_theme(name),
+            (Some("help"), None) => {
+                self.open(Overlay::Help);
+                self.transition(state::Event::Done);
+                return Ok(Next::Continue);
+            }
+            (Some("help"), Some(program)) => match self.open_manual(program) {
+                Ok(()) => {
+                    self.transition(state::Event::Done);
+                    return Ok(Next::Continue);
+                }
+                Err(error) => (Vec::new(), vec![error]),
+            },
+            (Some("macro"), Some("play")) => match self.saved_macro(args.next(), args.next()) {
+                Ok((keys, count)) => {
+                    self.transition(state::Event::Done);
+                    return self.play(&keys, count);
+                }
+                Err(error) => (Vec::new(), vec![error]),
+            },
+            (Some("macro"), subcommand) => self.manage_macros(subcommand, args.next()),
+            (Some("begin"), Some(name)) if args.clone().next().is_none() => {
+                self.transition(state::Event::Done);
+                self.begin_run(cmd, name);
+                return Ok(Next::Continue);
+            }
+            (Some("end"), None) => match self.runs.end(self.recorded) {
+                Some(run) => (
+                    vec![self
+                        .messages
+                        .format("run-ended", &[("name", &run.name), ("count", &run.len())])],
+                    Vec::new(),
+                ),
+                None => (Vec::new(), vec![self.messages.get("run-none-open")]),
+            },
+            (Some("run"), subcommand) => self.manage_runs(subcommand, args.next(), args.next()),
+            (Some("begin" | "end"), _) => (Vec::new(), vec![self.messages.get("run-usage")]),
+            (Some("note"), Some(_)) => {
+                let note = cmd.trim().trim_start_matches(':');
+                let note = note.strip_prefix("note").unwrap_or(note).trim();
+                match self.annotate(note) {
+                    true => {
+                        self.transition(state::Event::Done);
+                        return Ok(Next::Continue);
+                    }
+                    false => (Vec::new(), vec![self.messages.get("note-nothing")]),
+                }
+            }
+            (Some("note"), None) => (Vec::new(), vec![self.messages.get("note-usage")]),
+            (Some("star"), None) => match self.star() {
+                true => {
+                    self.transition(state::Event::Done);
+                    return Ok(Next::Continue);
+                }
+                false => (Vec::new(), vec![self.messages.get("star-nothing")]),
+            },
+            (Some("starred"), None) => {
+                self.show_starred();
+                self.transition(state::Event::Done);
+                return Ok(Next::Continue);
+            }
+            (Some("filter"), _) => {
+                let args = cmd.trim().trim_start_matches(':');
+                self.filter = Filter::parse(args.strip_prefix("filter").unwrap_or(args));
+                self.transition(state::Event::Done);
+                return Ok(Next::Continue);
+            }
+            (Some("star" | "starred"), Some(_)) => {
+                (Vec::new(), vec![self.messages.get("star-usage")])
+            }
+            (Some("source"), Some(first)) => {
+                let mut path = None;
+                let (mut keep_going, mut confirm, mut valid) = (false, false, true);
+                for arg in std::iter::once(first).chain(args.by_ref()) {
+                    match arg {
+                        "--continue" => keep_going = true,
+                        "--confirm-each" => confirm = true,
+                        arg => valid &= path.replace(arg).is_none(),
+                    }
+                }
+                match path.filter(|_| valid) {
+                    None => (Vec::new(), vec![self.messages.get("source-usage")]),
+                    Some(path) => {
+                        self.transition(state::Event::Done);
+                        return match confirm {
+                            true => self.source_confirmed(cmd, path, keep_going),
+                            false => self.source(cmd, path, keep_going),
+                        };
+                    }
+                }
+            }
+            (Some("source"), None) => (Vec::new(), vec![self.messages.get("source-usage")]),
+            (Some("fc"), _) => {
+                let substitution = cmd.trim().trim_start_matches(':');
+                let substitution = substitution.strip_prefix("fc").unwrap_or(substitution);
+                match self.fix(Some(substitution.trim()).filter(|s| !s.is_empty())) {
+                    Ok(()) => return Ok(Next::Continue),
+                    Err(error) => (Vec::new(), vec![error]),
+                }
+            }
+            (Some(name @ ("history" | "stats")), first) => {
+                let args = first.into_iter().chain(args).collect::<Vec<_>>();
+                match name {
+                    "history" => self.list_history(&args),
+                    _ => self.history_stats(&args),
+                }
+            }
+            (Some("pick"), _) => {
+                let template = cmd.trim().trim_start_matches(':');
+                let template = template.strip_prefix("pick").unwrap_or(template).trim();
+                let lines = self.history.iter().next_back().map(|entry| {
+                    let lines = entry.stdout.iter().map(|line| output::strip(line));
+                    let lines = lines.map(|line| line.trim_end().to_string());
+                    lines.filter(|line| !line.is_empty()).collect::<Vec<_>>()
+                });
+                match lines.filter(|lines| !lines.is_empty()) {
+                    Some(lines) => return Ok(self.pick(lines, template.to_string())),
+                    None => (Vec::new(), vec![self.messages.get("pick-nothing")]),
+                }
+            }
+            (Some("sync"), name) => self.sync_history(name),
+            (Some("env"), text) if args.clone().next().is_none() => self.child_environment(text),
+            (Some("env"), _) => (Vec::new(), vec![self.messages.get("env-usage")]),
+            (Some(name @ ("umask" | "nice" | "ionice")), first) => {
+                let args = first.into_iter().chain(args).collect::<Vec<_>>();
+                self.set_process(name, &args)
+            }
+            (Some("transcript"), Some("save")) => match (args.next(), args.next()) {
+                (Some(path), None) => self.save_transcript(path),
+                _ => (Vec::new(), vec![self.messages.get("transcript-usage")]),
+            },
+            (Some("transcript"), _) => (Vec::new(), vec![self.messages.get("transcript-usage")]),
+            (Some("task"), None) => match self.config.tasks.is_empty() {
+                true => (vec![self.messages.get("task-none")], Vec::new()),
+                false => (
+                    self.config
+                        .tasks
+                        .iter()
+                        .map(|(name, command)| {
+                            self.messages
+                                .format("task-entry", &[("name", name), ("command", command)])
+                        })
+                        .collect(),
+                    Vec::new(),
+                ),
+            },
+            (Some("task"), Some(name)) => match self.config.tasks.get(name).cloned() {
+                Some(command) => {
+                    self.transition(state::Event::Edit(LineBuffer::from(command)));
+                    return self.execute_guarded();
+                }
+                None => (
+                    Vec::new(),
+                    vec![self.messages.format("task-unknown", &[("name", &name)])],
+                ),
+            },
+            (name, _) => (
+                Vec::new(),
+                vec![self
+                    .messages
+                    .format("unknown-command", &[("name", &name.unwrap_or_default())])],
+            ),
+        };
+
+        self.transition(state::Event::Done);
+        self.record(cmd.to_string(), stdout, stderr);
+        Ok(Next::Continue)
+    }
+
+    /// Set the file mode mask (`umask`), the niceness (`nice`) or the I/O priority (`ionice`) of
+    /// the programs of the session, or show it without arguments. `inherit` goes back to the
+    /// configuration.
+    fn set_process(&mut self, name: &str, args: &[&str]) -> (Vec<String>, Vec<String>) {
+        let process = &mut self.process;
+        let set = match (name, args) {
+            (_, []) => true,
+            ("umask", ["inherit"]) => {
+                process.umask = None;
+                true
+            }
+            ("nice", ["inherit"]) => {
+                process.nice = None;
+                true
+            }
+            ("ionice", ["inherit"]) => {
+                process.ionice = None;
+                true
+            }
+            ("umask", [mode]) => match u32::from_str_radix(mode, 8) {
+                Ok(mode) if mode <= 0o777 => {
+                    process.umask = Some(mode);
+                    true
+                }
+                _ => false,
+            },
+            ("nice", [nice]) => match nice.parse() {
+                Ok(nice) if (-20..=19).contains(&nice) => {
+                    process.nice = Some(nice);
+                    true
+                }
+                _ => false,
+            },
+            ("ionice", [class, level @ ..]) if level.len() <= 1 => {
+                let level = match level.first().map(|level| level.parse()) {
+                    Some(Err(_)) => return self.process_usage(name),
+                    level => level.and_then(Result::ok),
+                };
+                match config::IoPriority::new(class, level) {
+                    Some(priority) => {
+                        process.ionice = Some(priority);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            _ => false,
+        };
+        if !set {
+            return self.process_usage(name);
+        }
+
+        let settings = self.process_settings("");
+        let value = match name {
+            "umask" => settings.umask.map(|umask| format!("{:03o}", umask)),
+            "nice" => settings.nice.map(|nice| nice.to_string()),
+            _ => settings.ionice.map(|ionice| ionice.to_string()),
+        };
+        let value = value.unwrap_or_else(|| self.messages.get("process-inherited"));
+        let line = self
+            .messages
+            .format("process-setting", &[("name", &name), ("value", &value)]);
+        (vec![line], Vec::new())
+    }
+
+    /// Pick one of the lines, to put on the prompt in the command: with the external picker if
+    /// set, or else in the picker of the shell. (See [`config::Pick`])
+    fn pick(&mut self, lines: Vec<String>, template: String) -> Next {
+        self.transition(state::Event::Done);
+        if self.config.pick.finder == config::Finder::External && self.publisher.is_none() {
+            match self.find_externally(&lines) {
+                Ok(Some(line)) => {
+                    let command = fill(&template, &line);
+                    self.transition(state::Event::Edit(LineBuffer::from(command)));
+                    return Next::Clear;
+                }
+                Ok(None) => return Next::Clear,
+                Err(e) => {
+                    let command = &self.config.pick.command;
+                    self.fail(
+                        anyhow::Error::new(e).context(format!("failed to run `{}`", command)),
+                    );
+                }
+            }
+        }
+        self.open(Overlay::Pick(String::new(), 0, lines, template));
+        Next::Continue
+    }
+
+    /// Run the external picker over the lines, handing it the terminal, and return the line
+    /// chosen, if any. This fails if there is no terminal to hand, or the picker cannot run.
+    fn find_externally(&self, lines: &[String]) -> io::Result<Option<String>> {
+        let mut words = self.config.pick.command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no command"))?;
+        // The picker draws on the terminal of the shell, and reads the keys from it.
+        std::fs::File::open(TTY)?;
+
+        let raw = crossterm::terminal::is_raw_mode_enabled()?;
+        if raw {
+            crossterm::terminal::disable_raw_mode()?;
+        }
+        let chosen = (|| {
+            let mut child = std::process::Command::new(program)
+                .args(words)
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .spawn()?;
+            if let Some(mut stdin) = child.stdin.take() {
+                // The picker may exit before reading every line.
+                let _ = stdin.write_all(lines.join("\n").as_bytes());
+            }
+            let output = child.wait_with_output()?;
+            let chosen = String::from_utf8_lossy(&output.stdout);
+            let chosen = chosen.trim_end_matches(['\r', '\n']);
+            Ok(Some(chosen.to_string())
+                .filter(|chosen| output.status.success() && !chosen.is_empty()))
+        })();
+        if raw {
+            crossterm::terminal::enable_raw_mode()?;
+        }
+        chosen
+    }
+
+    /// List the environment the programs of the commands get, with the variables whose name
+    /// contains the text if given. (See [`config::ChildEnv`])
+    fn child_environment(&self, text: Option<&str>) -> (Vec<String>, Vec<String>) {
+        let variables = self.config.child_env.apply(std::env::vars());
+        let lines = variables
+            .iter()
+            .filter(|(name, _)| text.map_or(true, |text| name.contains(text)))
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        (lines, Vec::new())
+    }
+
+    /// The usage of `:umask`, `:nice` or `:ionice`.
+    fn process_usage(&self, name: &str) -> (Vec<String>, Vec<String>) {
+        (
+            Vec::new(),
+            vec![self.messages.get(&format!("{}-usage", name))],
+        )
+    }
+
+    /// The file mode mask and the priorities of the programs of a command: the ones of the
+    /// configuration for the command, over the ones set for the session, over the ones of the
+    /// configuration for every command.
+    fn process_settings(&self, cmd: &str) -> config::Process {
+        let configured = &self.config.process;
+        let session = config::Process {
+            umask: self.process.umask.or(configured.umask),
+            nice: self.process.nice.or(configured.nice),
+            ionice: self.process.ionice.or(configured.ionice),
+            commands: configured.commands.clone(),
+        };
+        session.of(cmd)
+    }
+
+    /// Record the output of a builtin in the history.
+    fn record(&mut self, command: String, stdout: Vec<String>, stderr: Vec<String>) {
+        let prompt = self.prompt();
+        self.push_history(command::CommandOutput {
+            prompt,
+            command,
+            stdin: Vec::new(),
+            stdout,
+            stderr,
+            ..Default::default()
+        });
+    }
+
+    /// Add the next lines of the output coming in to the newest entry, `ui.flood_lines` at most,
+    /// unless it is paused. Commands printing faster than the screen is drawn, such as `yes`, so
+    /// leave the shell responsive, and the lines can be dropped before they take up memory.
+    fn ingest_flood(&mut self) {
+        let Some(flood) = self.flood.as_mut().filter(|flood| !flood.paused) else {
+            return;
+        };
+        let count = self.config.ui.flood_lines.max(1).min(flood.pending.len());
+        self.history.extend_last(flood.pending.drain(..count));
+        self.scroll = 0;
+        self.cache.get_mut().forget(self.recorded - 1);
+        if flood.pending.is_empty() {
+            self.flood = None;
+            self.overlays
+                .retain(|overlay| !matches!(overlay, Overlay::Flood));
+            self.held
+                .retain(|overlay| !matches!(overlay, Overlay::Flood));
+        }
+    }
+
+    /// Take in the lines of the output streamed come in since the last frame, and add the output
+    /// to the history once the command is done.
+    fn ingest_stream(&mut self) {
+        let Some(streaming) = self
+            .streaming
+            .as_mut()
+            .filter(|streaming| !streaming.paused)
+        else {
+            return;
+        };
+        for _ in 0..STREAM_LINES {
+            match streaming.chunks.try_recv() {
+                Ok(chunk) => {
+                    if streaming.tail.len() == STREAM_TAIL {
+                        streaming.tail.pop_front();
+                    }
+                    streaming.tail.push_back(chunk.clone());
+                    streaming.take(chunk);
+                    self.scroll = 0;
+                }
+                Err(std::sync::mpsc::TryRecvError::Empty) => return,
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                    self.finish_stream(false);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Stop waiting for the output streamed, adding what came in to the history. If `interrupted`,
+    /// the command is told to stop, its output noted as cut short.
+    fn finish_stream(&mut self, interrupted: bool) {
+        let Some(streaming) = self.streaming.take() else {
+            return;
+        };
+        self.transition(state::Event::Done);
+        self.record_stream(streaming, interrupted);
+    }
+
+    /// Add the output streamed to the history, noting the lines dropped past its budget under it.
+    /// If `interrupted`, the output is noted as cut short.
+    fn record_stream(&mut self, mut streaming: Streaming, interrupted: bool) {
+        let mut output = std::mem::take(&mut streaming.output);
+        let elapsed = streaming.started.elapsed();
+        let dropped = streaming.dropped;
+        // Cancelled, and sending fails from now on, telling the executor to stop the command.
+        drop(streaming);
+        if interrupted {
+            output.stderr.push(self.messages.get("interrupted"));
+        }
+        let cmd = output.command.clone();
+        let recorded = self.recorded;
+        self.finish_output(output, &cmd, elapsed);
+        if dropped > 0 && self.recorded > recorded {
+            let message = self
+                .messages
+                .format("flood-dropped", &[("count", &dropped)]);
+            self.warnings
+                .entry(self.recorded - 1)
+                .or_default()
+                .push(message);
+        }
+    }
+
+    /// Interrupt the running command: the one streaming its output is told to stop, and the one
+    /// reading its input is dropped, without running. Either is noted as interrupted in the
+    /// history.
+    fn interrupt(&mut self) {
+        if self.streaming.is_some() {
+            return self.finish_stream(true);
+        }
+        let State::Running(ref prepare, ref stdin, ref line) = self.state else {
+            return;
+        };
+        let output = command::CommandOutput {
+            prompt: self.prompt(),
+            command: prepare.command.clone(),
+            stdin: typed_stdin(stdin, line),
+            stderr: vec![self.messages.get("interrupted")],
+            ..Default::default()
+        };
+        self.transition(state::Event::Done);
+        self.push_history(output);
+    }
+
+    /// Pause the output coming in, or resume it, if any. The output streamed is left unread, so
+    /// the command waits until it is resumed.
+    fn pause_output(&mut self) {
+        if let Some(flood) = &mut self.flood {
+            flood.paused = !flood.paused;
+        } else if let Some(streaming) = &mut self.streaming {
+            streaming.paused = !streaming.paused;
+        }
+    }
+
+    /// Add the rest of the output coming in at once, before another entry is added.
+    fn finish_flood(&mut self) {
+        if let Some(flood) = self.flood.take() {
+            self.history.extend_last(flood.pending);
+            self.cache.get_mut().forget(self.recorded - 1);
+            self.overlays
+                .retain(|overlay| !matches!(overlay, Overlay::Flood));
+            self.held
+                .retain(|overlay| !matches!(overlay, Overlay::Flood));
+        }
+    }
+
+    /// Drop the rest of the output coming in, noted under its entry.
+    fn drop_flood(&mut self) {
+        if let Some(flood) = self.flood.take() {
+            let count = flood.pending.len();
+            let message = self.messages.format("flood-dropped", &[("count", &count)]);
+            self.warnings
+                .entry(self.recorded - 1)
+                .or_default()
+                .push(message);
+            self.cache.get_mut().forget(self.recorded - 1);
+        }
+    }
+
+    /// Add an entry to the history, dropping the oldest entries beyond the configured limit. Past
+    /// `ui.flood_lines` lines of output, the rest comes in over the next frames. (See
+    /// [`Shell::ingest_flood`])
+    fn push_history(&mut self, mut output: command::CommandOutput) {
+        match (self.rc, output.failed()) {
+            (Some(RcOutput::None), _) | (Some(RcOutput::Errors), false) => return,
+            _ => {}
+        }
+        self.finish_flood();
+        #[cfg(unix)]
+        self.subscribers
+            .retain(|subscriber| subscriber.notify(&output));
+        if let Some(publisher) = &mut self.publisher {
+            publisher.push(UiEvent::HistoryAppended {
+                entry: Entry::from(&output),
+            });
+        }
+        #[cfg(feature = "script")]
+        if let Some(script) = &self.script {
+            script.output(Entry::from(&output), self.config.history.limit);
+        }
+        // Without frames drawn, the output is added at once.
+        let limit = self.config.ui.flood_lines;
+        let frames = self.publisher.is_none() && !self.config.ui.screen_reader;
+        let pending = (frames && limit > 0 && output.stdout.len() > limit)
+            .then(|| output.stdout.split_off(limit));
+        self.history.push(output);
+        self.recorded += 1;
+        self.unsaved = true;
+        self.scroll = 0;
+        self.runs.record();
+        if let Some(pending) = pending {
+            self.flood = Some(Flood {
+                total: limit + pending.len(),
+                pending: pending.into(),
+                paused: false,
+            });
+            self.open(Overlay::Flood);
+        }
+        if let Some(limit) = self.config.history.limit {
+            let excess = self.history.len().saturating_sub(limit);
+            self.history.drop_oldest(excess);
+            if excess > 0 {
+                let oldest = self.recorded - self.history.len();
+                self.notes = self.notes.split_off(&oldest);
+                self.starred = self.starred.split_off(&oldest);
+                self.fixes = self.fixes.split_off(&oldest);
+                self.warnings = self.warnings.split_off(&oldest);
+            }
+        }
+    }
+
+    /// Warn about the entry if its command took longer than its budget, and notify the terminal
+    /// if enabled. (See [`crate::budget`])
+    fn check_budget(&mut self, id: usize, cmd: &str, elapsed: Duration) {
+        let Some((prefix, budget)) = budget::find(cmd, &self.config.budgets) else {
+            return;
+        };
+        if elapsed <= budget {
+            return;
+        }
+
+        let warning = self.messages.format(
+            "budget-exceeded",
+            &[
+                ("elapsed", &budget::format(elapsed)),
+                ("budget", &budget::format(budget)),
+                ("prefix", &prefix),
+            ],
+        );
+        if self.config.ui.notify_over_budget {
+            let notification = integration::notification(&format!("{}: {}", cmd, warning));
+            let written = write!(self.out, "{}", self.caps.osc(&notification))
+                .and_then(|()| self.out.flush());
+            if let Err(e) = written {
+                self.fail(e.into());
+            }
+        }
+        self.warnings.entry(id).or_default().push(warning);
+    }
+
+    /// Warn about the entry if a program of its command was stopped by a resource limit. (See
+    /// [`crate::process`])
+    #[cfg(unix)]
+    fn check_limits(&mut self, id: usize) {
+        let warning = match process::exceeded() {
+            Some(process::Exceeded::Cpu(cpu)) => self
+                .messages
+                .format("limit-cpu", &[("limit", &budget::format(cpu))]),
+            Some(process::Exceeded::Memory(memory)) => {
+                self.messages.format("limit-memory", &[("limit", &memory)])
+            }
+            None => return,
+        };
+        self.warnings.entry(id).or_default().push(warning);
+    }
+
+    /// Play the cue of the configuration for the command completed last, if it is one of the
+    /// commands cued. The commands of the rc file are never cued. (See [`config::Cues`])
+    fn cue(&mut self, cmd: &str, elapsed: Duration) {
+        let cues = &self.config.cues;
+        let failed = self.failed_since(self.recorded - 1);
+        let cued = match cues.on {
+            CueOn::Never => false,
+            CueOn::Always => true,
+            CueOn::Failure => failed,
+            CueOn::Success => !failed,
+        };
+        if !cued || self.rc.is_some() || elapsed < Duration::from_secs(cues.after) {
+            return;
+        }
+
+        let sequence = match cues.sound {
+            CueSound::Bell => integration::BELL.to_string(),
+            CueSound::Notification => {
+                let key = if failed { "cue-failed" } else { "cue-done" };
+                let message = self.messages.format(key, &[("command", &cmd)]);
+                self.caps.osc(&integration::notification(&message))
+            }
+        };
+        let written = write!(self.out, "{}", sequence).and_then(|()| self.out.flush());
+        if let Err(e) = written {
+            self.fail(e.into());
+        }
+    }
+
+    /// Clear the history.
+    fn clear_history(&mut self) {
+        self.history.clear();
+        self.scroll = 0;
+        self.unsaved = true;
+        self.notes.clear();
+        self.starred.clear();
+        self.fixes.clear();
+        self.warnings.clear();
+        #[cfg(feature = "script")]
+        if let Some(script) = &self.script {
+            script.clear();
+        }
+        if let Some(publisher) = &mut self.publisher {
+            publisher.push(UiEvent::HistoryCleared);
+        }
+    }
+
+    /// Handle an input from the front-end embedding the shell.
+    fn embedded_input(&mut self, input: UiInput) -> anyhow::Result<Next> {
+        let next = match input {
+            UiInput::Key { key } => match key.parse::<Key>() {
+                Ok(key) => self.input(Event::Key(KeyEvent::new(key.code, key.modifiers)))?,
+                Err(e) => {
+                    self.report(e.to_string());
+                    Next::Continue
+                }
+            },
+            UiInput::Text { text } => {
+                for c in text.chars() {
+                    self.macros.record(Key {
+                        code: KeyCode::Char(c),
+                        modifiers: KeyModifiers::NONE,
+                    });
+                    self.insert_char(c);
+                }
+                Next::Continue
+            }
+            UiInput::Run { command, stdin } => match self.inject(&command, &stdin) {
+                Some(next) => next?,
+                None => {
+                    self.report("a command is waiting for input".to_string());
+                    Next::Continue
+                }
+            },
+        };
+        self.overlays.append(&mut self.held);
+        if !std::mem::take(&mut self.overlays).is_empty() {
+            self.report(self.messages.get("overlay-embedded"));
+        }
+
+        Ok(next)
+    }
+
+    /// Report an error to the front-end embedding the shell, if any.
+    fn report(&mut self, message: String) {
+        if let Some(publisher) = &mut self.publisher {
+            publisher.push(UiEvent::Error { message });
+        }
+    }
+
+    /// Report an internal error that does not stop the shell, such as failing to write the
+    /// journal, in the banner at the top of the screen until it is dismissed. (Or to the
+    /// front-end embedding the shell, if any) An error already in the banner is not repeated.
+    fn fail(&mut self, error: anyhow::Error) {
+        let message = error.to_string();
+        if self.publisher.is_some() {
+            self.report(format!("{:#}", error));
+        } else if !self.errors.iter().any(|(reported, _)| *reported == message) {
+            let causes = error.chain().skip(1).map(ToString::to_string).collect();
+            self.errors.push((message, causes));
+        }
+    }
+
+    /// Send the changes since the last call to the front-end embedding the shell, if any.
+    fn publish(&mut self) -> anyhow::Result<()> {
+        let prompt = self.prompt();
+        let (view, completions) = match &self.state {
+            State::Idle(line, completions) => (
+                View::Idle {
+                    prompt,
+                    command: line.to_string(),
+                    cursor: line.cursor(),
+                },
+                completions.clone(),
+            ),
+            State::Running(prepare, stdin, line) => (
+                View::Running {
+                    prompt,
+                    command: prepare.command.clone(),
+                    stdin: typed_stdin(stdin, line),
+                },
+                None,
+            ),
+        };
+
+        match &mut self.publisher {
+            Some(publisher) => publisher.update(view, completions),
+            None => Ok(()),
+        }
+    }
+
+    /// Run a command on behalf of a client (the control socket, or an embedding front-end), as
+    /// if it was typed at the prompt, supplying `stdin` if it asks for input. What the user was
+    /// typing is put back afterwards. This returns `None` if a command is waiting for input.
+    fn inject(&mut self, command: &str, stdin: &[String]) -> Option<anyhow::Result<Next>> {
+        let typed = self.state.prompt_line()?.clone();
+
+        self.transition(state::Event::Edit(LineBuffer::from(command)));
+        let mut next = self.execute_command();
+        if let (Ok(_), State::Running(_, input, _)) = (&next, &mut self.state) {
+            input.extend_from_slice(stdin);
+            next = self.continue_execution();
+        }
+        if self.state.prompt_line().is_some_and(LineBuffer::is_empty) {
+            self.transition(state::Event::Edit(typed));
+        }
+
+        Some(next)
+    }
+
+    /// Insert a character at the cursor, or into the input of the running command.
+    fn insert_char(&mut self, c: char) {
+        self.state.line_mut().insert(c);
+        self.scroll = 0;
+        if let State::Idle(_, Some(ref mut comp)) = self.state {
+            *comp = comp
+                .iter()
+                .filter_map(|i| i.strip_prefix(c))
+                .map(str::to_string)
+                .collect::<Vec<_>>();
+        }
+    }
+
+    /// Edit the line being typed: the command, or the input of the running command. The
+    /// completions shown are dropped if the edit changed anything.
+    fn edit(&mut self, edit: impl FnOnce(&mut LineBuffer) -> bool) {
+        if edit(self.state.line_mut()) {
+            // The line edited is shown.
+            self.scroll = 0;
+            if let State::Idle(_, ref mut comp) = self.state {
+                *comp = None;
+            }
+        }
+    }
+
+    /// Move the shell to the state the event leads to. (See [`State::next`])
+    fn transition(&mut self, event: state::Event) {
+        // The prompt of a remote executor is asked for again once a command is done.
+        if let state::Event::Done = event {
+            self.cached_prompt.get_mut().take();
+        }
+        if let Transition::To(state) = self.state.next(event) {
+            self.state = state;
+        }
+    }
+
+    /// Delete the character before the cursor. At the start of a line of input of the running
+    /// command, this goes back to the end of the line before.
+    fn delete_backward(&mut self) {
+        if let State::Running(_, ref mut stdin, ref mut line) = self.state {
+            if line.is_empty() {
+                if let Some(previous) = stdin.pop() {
+                    *line = LineBuffer::from(previous);
+                }
+                return;
+            }
+        }
+        self.edit(|line| line.delete_backward().is_some());
+    }
+
+    /// Continue the execution of the command.
+    fn continue_execution(&mut self) -> anyhow::Result<Next> {
+        let (prepare, stdin) = match self.state {
+            State::Running(ref prep, ref stdin, ref line) => {
+                (prep.clone(), typed_stdin(stdin, line))
+            }
+            State::Idle(..) => return Ok(Next::Continue),
+        };
+
+        self._final_execution(&prepare.command, Some(stdin))
+    }
+
+    /// Execute the command, keeping it in the journal.
+    fn execute_command(&mut self) -> anyhow::Result<Next> {
+        let Some(cmd) = self.state.prompt_line().map(LineBuffer::to_string) else {
+            return Ok(Next::Continue);
+        };
+        // The commands of the files sourced are not kept.
+        let sourced = self.sourcing > 0;
+        let directory = self.directory();
+        let recorded = self.recorded;
+        let run = self.runs.open().map(|run| run.name.clone());
+        let mut fixing = self.fixing.take();
+
+        // `!$` and `!*` are the last argument and all the arguments of the previous command.
+        let cmd = match (
+            recalls(&cmd).next().is_some() && !self.is_comment(&cmd),
+            self.commands.last(),
+        ) {
+            (false, _) => cmd,
+            (true, Some(previous)) => recall_arguments(&cmd, previous),
+            (true, None) => {
+                let error = self.messages.get("bang-nothing");
+                self.transition(state::Event::Done);
+                self.record(cmd, Vec::new(), vec![error]);
+                return Ok(Next::Continue);
+            }
+        };
+        // A quick substitution, `^OLD^NEW`, runs the last command with the first `OLD` replaced
+        // by `NEW`, shown and kept as it is run.
+        let cmd = match cmd.starts_with('^') {
+            true => match self.substitute(&cmd) {
+                Ok(fixed) => {
+                    fixing = Some(self.last_entry());
+                    fixed
+                }
+                Err(error) => {
+                    self.transition(state::Event::Done);
+                    self.record(cmd, Vec::new(), vec![error]);
+                    return Ok(Next::Continue);
+                }
+            },
+            false => cmd,
+        };
+
+        let next = self.run_command(cmd.clone());
+        if !sourced {
+            // Only the commands run within a run belong to it, not the ones starting or ending it.
+            let run = run.filter(|run| self.runs.open().is_some_and(|open| open.name == *run));
+            let exit = match (&next, &self.state) {
+                // Still reading the input of the command.
+                (_, State::Running(..)) => None,
+                (Err(_), _) => Some(1),
+                // The exit code the executor told, if any, or else whether the command failed.
+                (Ok(_), _) => Some(match self.exit_code_since(recorded) {
+                    Some(code) => i64::from(code),
+                    None => i64::from(self.failed_since(recorded)),
+                }),
+            };
+            let row = self.journal(&cmd, directory.as_deref(), exit, run.as_deref());
+            if let Some(row) = row.filter(|_| self.recorded > recorded) {
+                self.journaled = Some((self.recorded - 1, row));
+            }
+            if let Some((fixes, fixes_row)) = fixing.filter(|_| self.recorded > recorded) {
+                self.fixes.insert(self.recorded - 1, fixes);
+                if let (Some(store), Some(row), Some(fixes_row)) = (&self.store, row, fixes_row) {
+                    if let Err(e) = store.link(row, fixes_row) {
+                        self.fail(e);
+                    }
+                }
+            }
+        }
+
+        next
+    }
+
+    /// Run the command as typed: through the user script, aliases and builtins. Comments are
+    /// recorded as they are, without running anything.
+    fn run_command(&mut self, cmd: String) -> anyhow::Result<Next> {
+        if self.is_comment(&cmd) {
+            self.transition(state::Event::Done);
+            self.record(cmd, Vec::new(), Vec::new());
+            return Ok(Next::Continue);
+        }
+        #[cfg(feature = "script")]
+        let cmd = match self.script.as_ref().and_then(|script| script.command(&cmd)) {
+            Some(cmd) if cmd.is_empty() => {
+                self.transition(state::Event::Done);
+                return Ok(Next::Continue);
+            }
+            Some(cmd) => cmd,
+            None => cmd,
+        };
+
+        let cmd = self.expand(&cmd);
+        if cmd.starts_with(':') {
+            return self.builtin(&cmd);
+        }
+        let syntax = self.executor.syntax();
+        let expands = [
+            Expansion::Tilde,
+            Expansion::Substitution,
+            Expansion::Arithmetic,
+        ]
+        .map(|expansion| syntax.expands(expansion));
+        let cmd = match expands[0] {
+            true => tilde::expand(&cmd, &self.config.directories),
+            false => cmd,
+        };
+        let expanded = match expands[1] {
+            true => self.substitute_commands(&cmd),
+            false => Ok(cmd.clone()),
+        };
+        let expanded = expanded.and_then(|cmd| match expands[2] {
+            true => arithmetic::expand(&cmd).map_err(|e| {
+                self.messages
+                    .format("arithmetic-failed", &[("error", &e.to_string())])
+            }),
+            false => Ok(cmd),
+        });
+        let cmd = match expanded {
+            Ok(cmd) => cmd,
+            Err(error) => {
+                self.transition(state::Event::Done);
+                self.record(cmd, Vec::new(), vec![error]);
+                return Ok(Next::Continue);
+            }
+        };
+        // The builtins of the executor are run as they are, without preparing them.
+        if self.registered(&cmd).is_some() {
+            return self._final_execution(&cmd, None);
+        }
+
+        let prepare = self.executor.prepare(&cmd);
+        self.transition(state::Event::Run(prepare.clone()));
+
+        match prepare.stdin_required {
+            true => Ok(Next::Continue),
+            false => self._final_execution(&prepare.command, None),
+        }
+    }
+
+    /// Run the commands substituted in a command, `$(COMMAND)`, and put their output in their
+    /// place. This returns the error to show if one of them failed. (See [`crate::substitution`])
+    fn substitute_commands(&mut self, cmd: &str) -> Result<String, String> {
+        let mut cmd = cmd.to_string();
+        let mut from = 0;
+        while let Some((start, end)) = substitution::find(&cmd, from) {
+            let inner = cmd[start + 2..end].to_string();
+            let output = self.capture(&inner)?;
+            // The output is quoted, so the executor reads it as words, and nothing more: within
+            // quotes as part of them, or else each word on its own.
+            let syntax = self.executor.syntax();
+            let output = match syntax.last_word(&cmd[..start]).open {
+                Some(open) => syntax.escape(&output, Some(open)),
+                None => output
+                    .split_whitespace()
+                    .map(|word| syntax.quote(word))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            };
+            cmd.replace_range(start..=end, &output);
+            from = start + output.len();
+        }
+        Ok(cmd)
+    }
+
+    /// Run a command substituted in another, after its own substitutions, and return its output.
+    fn capture(&mut self, cmd: &str) -> Result<String, String> {
+        let cmd = self.substitute_commands(cmd)?;
+        let cmd = self.executor.prepare(&cmd).command;
+        let failed = |error: &str| {
+            self.messages.format(
+                "substitution-failed",
+                &[("command", &cmd.trim()), ("error", &error)],
+            )
+        };
+        let input = command::CommandInput {
+            prompt: self.prompt(),
+            command: cmd.clone(),
+            stdin: None,
+            cancel: command::Cancel::new(),
+            size: self.size.clone(),
+            #[cfg(feature = "tokio")]
+            runtime: self.runtime.clone(),
+        };
+        let program = cmd.split_whitespace().next();
+        let output = match self
+            .builtins
+            .iter()
+            .find(|builtin| Some(builtin.name.as_str()) == program)
+        {
+            Some(builtin) => (builtin.handler)(&mut self.context, input),
+            None => self.executor.execute(&mut self.context, input),
+        };
+        // Exiting or clearing the screen is left to the commands run as they are.
+        let output = match output {
+            Ok(command::OutputAction::Command(output)) => output,
+            Ok(command::OutputAction::Stream(stream)) => stream.collect(),
+            Ok(_) => return Ok(String::new()),
+            Err(e) => return Err(failed(&format!("{:#}", e))),
+        };
+        if output.failed() {
+            let error = match (output.stderr.first(), output.exit_code) {
+                (Some(error), _) => error.clone(),
+                (None, code) => self
+                    .messages
+                    .format("exited", &[("code", &code.unwrap_or_default())]),
+            };
+            return Err(failed(&error));
+        }
+
+        let substituted = output.stdout.join(" ").trim_end().to_string();
+        if self.config.history.record_substitutions {
+            self.push_history(output);
+        }
+        Ok(substituted)
+    }
+
+    /// Keep a command run in the journal, to be recalled in this session and, if shared, in the
+    /// others. This returns the id of the command in the journal, if it was kept there.
+    fn journal(
+        &mut self,
+        cmd: &str,
+        directory: Option<&Path>,
+        exit: Option<i64>,
+        run: Option<&str>,
+    ) -> Option<i64> {
+        if cmd.trim().is_empty() {
+            return None;
+        }
+        self.commands.push(cmd.trim().to_string());
+        let store = self.store.as_ref()?;
+        match store.append(cmd, directory, exit, run) {
+            Ok(id) => id,
+            Err(e) => {
+                self.fail(e);
+                None
+            }
+        }
+    }
+
+    /// Check whether the command of any of the entries added to the history since `recorded`
+    /// entries were failed. (See [`command::CommandOutput::failed`])
+    fn failed_since(&self, recorded: usize) -> bool {
+        self.history.failed_among_last(self.recorded - recorded)
+    }
+
+    /// The exit code of the last entry added to the history since it held `recorded` entries, if
+    /// any was, and the executor told it.
+    fn exit_code_since(&self, recorded: usize) -> Option<i32> {
+        let last = self.history.len().checked_sub(1)?;
+        (self.recorded > recorded)
+            .then(|| self.history.get(last))
+            .flatten()?
+            .exit_code
+    }
+
+    /// Add the output of a command done to the history, with the warnings about the limits and
+    /// the budget it went over, and the cue of its end.
+    fn finish_output(&mut self, mut output: command::CommandOutput, cmd: &str, elapsed: Duration) {
+        if output.duration.is_zero() {
+            output.duration = elapsed;
+        }
+        let recorded = self.recorded;
+        self.push_history(output);
+        if self.recorded > recorded {
+            #[cfg(unix)]
+            self.check_limits(recorded);
+            self.check_budget(recorded, cmd, elapsed);
+            self.cue(cmd, elapsed);
+        }
+    }
+
+    /// Execute the command and return the next action.
+    fn _final_execution(&mut self, cmd: &str, stdin: Option<Vec<String>>) -> anyhow::Result<Next> {
+        self.report_title()?;
+        let directory = self.directory();
+        let prompt = self.prompt();
+        let cancel = command::Cancel::new();
+        let input = command::CommandInput {
+            prompt,
+            command: cmd.to_string(),
+            stdin,
+            cancel: cancel.clone(),
+            size: self.size.clone(),
+            #[cfg(feature = "tokio")]
+            runtime: self.runtime.clone(),
+        };
+        let program = cmd.split_whitespace().next();
+        #[cfg(unix)]
+        process::configure(
+            self.config.limits.of(cmd),
+            self.process_settings(cmd),
+            self.config.child_env.of(cmd),
+        );
+        let started = Instant::now();
+        let output = match self
+            .builtins
+            .iter()
+            .find(|builtin| Some(builtin.name.as_str()) == program)
+        {
+            Some(builtin) => (builtin.handler)(&mut self.context, input)?,
+            None => self.executor.execute(&mut self.context, input)?,
+        };
+        let elapsed = started.elapsed();
+        #[cfg(unix)]
+        process::configure(
+            config::Limits::default(),
+            config::Process::default(),
+            config::ChildEnv::default(),
+        );
+        // A program handed the terminal drew on it, and may have left it as the shell did not.
+        #[cfg(unix)]
+        let handed = process::handed();
+        #[cfg(unix)]
+        if handed {
+            self.retake_terminal();
+        }
+        #[cfg(not(unix))]
+        let handed = false;
+        self.transition(state::Event::Done);
+        self.update_project();
+        if let Some(directory) =
+            directory.filter(|_| self.config.history.suggestions > 0 && !self.isolated)
+        {
+            if let Err(e) = usage::record(&directory, cmd) {
+                self.fail(e);
+            }
+        }
+        if self.config.history.suggest_on_empty {
+            self.suggest();
+        }
+
+        match output {
+            command::OutputAction::Command(command_output) => {
+                self.finish_output(command_output, cmd, elapsed);
+            }
+            command::OutputAction::Stream(stream) => {
+                let command::OutputStream {
+                    prompt,
+                    command,
+                    stdin,
+                    chunks,
+                } = stream;
+                let budget = memory::budget(&self.config.history)
+                    .map_or(STREAM_BUDGET, |budget| budget.min(STREAM_BUDGET));
+                let mut streaming = Streaming {
+                    output: command::CommandOutput {
+                        prompt,
+                        command,
+                        stdin,
+                        ..Default::default()
+                    },
+                    chunks,
+                    tail: VecDeque::new(),
+                    started,
+                    cancel,
+                    paused: false,
+                    bytes: 0,
+                    budget,
+                    dropped: 0,
+                };
+                // Without frames drawn, the output is waited for whole.
+                if self.publisher.is_some() || self.config.ui.screen_reader {
+                    while let Ok(chunk) = streaming.chunks.recv() {
+                        streaming.take(chunk);
+                    }
+                    self.record_stream(streaming, false);
+                } else {
+                    self.transition(state::Event::Run(command::Prepare {
+                        command: cmd.to_string(),
+                        stdin_required: false,
+                    }));
+                    self.streaming = Some(streaming);
+                }
+            }
+            command::OutputAction::Exit => {
+                return Ok(Next::Exit("".to_string()));
+            }
+            command::OutputAction::Clear => {
+                self.clear_history();
+                return Ok(Next::Clear);
+            }
+        }
+
+        match handed {
+            true => Ok(Next::Clear),
+            false => Ok(Next::Continue),
+        }
+    }
+
+    /// Set the terminal up again for the full screen interface, after a program of the executor
+    /// ran on it: the program may have left the alternate screen, or turned off the mouse and
+    /// the bracketed paste. (See [`crate::process`])
+    #[cfg(unix)]
+    fn retake_terminal(&mut self) {
+        let mut written = crossterm::queue!(self.out, EnableBracketedPaste);
+        if self.config.ui.alternate_screen {
+            written = written.and_then(|()| crossterm::queue!(self.out, EnterAlternateScreen));
+        }
+        if self.config.ui.mouse {
+            written = written.and_then(|()| crossterm::queue!(self.out, EnableMouseCapture));
+        }
+        if let Err(e) = written.and_then(|()| self.out.flush()) {
+            self.fail(e.into());
+        }
+    }
+}
+
+///
+/// [`Leftovers`] terminates or detaches the jobs the executor left, when the shell is dropped:
+/// whether it exits, fails or panics. (See [`crate::process`])
+///
+struct Leftovers(config::OnExit);
+
+impl Drop for Leftovers {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        match self.0 {
+            config::OnExit::Terminate => process::terminate(),
+            config::OnExit::Detach => process::detach(),
+        }
+    }
+}
+
+/// The program of the command under the cursor: the first word of the part of the line (between
+/// `|`, `;` and `&`) the cursor is in, past its environment assignments.
+fn program_at(line: &str, cursor: usize) -> Option<&str> {
+    let separator = |c: char| matches!(c, '|' | ';' | '&');
+    let before = line.get(..cursor)?;
+    let start = before.rfind(separator).map_or(0, |index| index + 1);
+    let end = line[start..]
+        .find(separator)
+        .map_or(line.len(), |index| start + index);
+
+    assignments::split(&line[start..end])
+        .1
+        .split_whitespace()
+        .next()
+}
+
+/// The stages of a command: the commands of its pipelines and lists, between `|`, `;` and `&`
+/// outside of quotes, as the start and end of each, in bytes, with no whitespace around them.
+fn stages(command: &str) -> Vec<(usize, usize)> {
+    let mut stages = Vec::new();
+    let mut push = |start: usize, end: usize| {
+        let part = &command[start..end];
+        let trimmed = part.trim_start();
+        let start = start + part.len() - trimmed.len();
+        let end = start + trimmed.trim_end().len();
+        if start < end {
+            stages.push((start, end));
+        }
+    };
+    let mut open = None;
+    let mut start = 0;
+    for (index, c) in command.char_indices() {
+        match open {
+            Some(quote) if c == quote => open = None,
+            Some(_) => {}
+            None if matches!(c, '\'' | '"') => open = Some(c),
+            None if matches!(c, '|' | ';' | '&') => {
+                push(start, index);
+                start = index + 1;
+            }
+            None => {}
+        }
+    }
+    push(start, command.len());
+    stages
+}
+
+/// The last argument of a command, if it has any.
+fn last_argument(command: &str) -> Option<&str> {
+    let mut words = command.split_whitespace();
+    words.next()?;
+    words.next_back()
+}
+
+/// The `!$` and `!*` of a command, as their index and the character after `!`. Those within single
+/// quotes, or escaped with `\`, are left as they are, as in bash. (See `crate::substitution`)
+fn recalls(cmd: &str) -> impl Iterator<Item = (usize, u8)> + '_ {
+    let bytes = cmd.as_bytes();
+    let mut quote = None;
+    let mut escaped = false;
+    bytes.iter().enumerate().filter_map(move |(index, &byte)| {
+        match (quote, byte) {
+            _ if escaped => escaped = false,
+            (Some(b'\''), b'\'') | (Some(b'"'), b'"') => quote = None,
+            (Some(b'\''), _) => {}
+            (_, b'\\') => escaped = true,
+            (None, b'\'' | b'"') => quote = Some(byte),
+            (_, b'!') if matches!(bytes.get(index + 1), Some(b'$' | b'*')) => {
+                return bytes.get(index + 1).map(|&recall| (index, recall));
+            }
+            _ => {}
+        }
+        None
+    })
+}
+
+/// Expand `!$` to the last argument of the previous command, and `!*` to all of its arguments.
+fn recall_arguments(cmd: &str, previous: &str) -> String {
+    let arguments = previous
+        .trim_start()
+        .split_once(char::is_whitespace)
+        .map_or("", |(_, arguments)| arguments.trim());
+    let mut expanded = String::with_capacity(cmd.len());
+    let mut start = 0;
+    for (index, recall) in recalls(cmd) {
+        expanded.push_str(&cmd[start..index]);
+        expanded.push_str(match recall {
+            b'$' => last_argument(previous).unwrap_or_default(),
+            _ => arguments,
+        });
+        start = index + 2;
+    }
+    expanded.push_str(&cmd[start..]);
+    expanded
+}
+
+/// Complete a command. The arguments of the builtins of the executor are completed from their
+/// completions, and the rest by the executor, along with the names of the builtins. A last word
+/// starting with `~` completes to the names of the named directories, or else is expanded for the
+/// executor to complete the path. (See [`crate::tilde`]) A name after `$` or `${` completes to the
+/// names of the variables of the context, closing the braces, for the syntaxes expanding them. The
+/// executor completes the last word unquoted, and its completions are quoted the way the word is,
+/// in the syntax of the executor. (See [`crate::syntax`])
+fn complete<T: command::Execute>(
+    executor: &T,
+    context: &T::Context,
+    builtins: &[command::Builtin<T::Context>],
+    directories: &BTreeMap<String, String>,
+    cmd: &str,
+) -> anyhow::Result<(String, Vec<String>)> {
+    let syntax = executor.syntax();
+    let tilde = syntax.expands(Expansion::Tilde);
+    let word = cmd.rsplit(char::is_whitespace).next().unwrap_or_default();
+    if let Some(name) = word
+        .strip_prefix('~')
+        .filter(|name| tilde && !name.contains('/'))
+    {
+        let names = directories
+            .keys()
+            .map(|name| format!("{}/", name))
+            .collect::<Vec<_>>();
+        let (fixed, variable) = complete_among(&names, name);
+        if !fixed.is_empty() || !variable.is_empty() {
+            return Ok((fixed, variable));
+        }
+    }
+    if let Some(dollar) = word
+        .rfind('$')
+        .filter(|_| syntax.expands(Expansion::Variables))
+    {
+        let after = &word[dollar + 1..];
+        let (name, close) = match after.strip_prefix('{') {
+            Some(name) => (name, "}"),
+            None => (after, ""),
+        };
+        if name.chars().all(|c| c == '_' || c.is_ascii_alphanumeric()) {
+            let names = executor
+                .variables(context)
+                .into_iter()
+                .map(|variable| format!("{}{}", variable, close))
+                .collect::<Vec<_>>();
+            return Ok(complete_among(&names, name));
+        }
+    }
+    let expanded = tilde::word(word, directories)
+        .filter(|_| tilde)
+        .map(|path| format!("{}{}", &cmd[..cmd.len() - word.len()], path));
+    let cmd = expanded.as_deref().unwrap_or(cmd);
+    let word = syntax.last_word(cmd);
+    let unquoted = format!("{}{}", &cmd[..word.start], word.value);
+    let completion = || -> anyhow::Result<(String, Vec<String>)> {
+        let (fixed, variable) = executor.completion(context, &unquoted)?;
+        let variable = variable.iter().map(|rest| syntax.escape(rest, word.open));
+        Ok((syntax.escape(&fixed, word.open), variable.collect()))
+    };
+    let line = cmd.trim_start();
+    if let Some((program, arguments)) = line.split_once(char::is_whitespace) {
+        return match builtins.iter().find(|builtin| builtin.name == program) {
+            Some(builtin) => {
+                let argument = arguments.rsplit(char::is_whitespace).next();
+                Ok(complete_among(
+                    &builtin.completions,
+                    argument.unwrap_or_default(),
+                ))
+            }
+            None => completion(),
+        };
+    }
+
+    let (fixed, variable) = completion()?;
+    if !fixed.is_empty() || builtins.is_empty() {
+        return Ok((fixed, variable));
+    }
+    let candidates = variable
+        .into_iter()
+        .map(|completion| format!("{}{}", line, completion))
+        .chain(builtins.iter().map(|builtin| builtin.name.clone()))
+        .collect::<Vec<_>>();
+    Ok(complete_among(&candidates, line))
+}
+
+/// Complete the host typed after a program connecting to other machines, with the descriptions of
+/// the hosts by the line they complete to. This returns `None` if the line is not typing a host.
+/// (See [`crate::hosts`])
+fn complete_hosts(
+    programs: &[String],
+    line: &str,
+    messages: &Catalog,
+) -> Option<(String, Vec<String>, BTreeMap<String, String>)> {
+    let (typed, suffix) = hosts::word(line, programs)?;
+    let start = &line[..line.len() - typed.len()];
+    let hosts = hosts::load();
+    let names = hosts
+        .iter()
+        .map(|host| format!("{}{}", host.name, suffix))
+        .collect::<Vec<_>>();
+    let descriptions = hosts
+        .iter()
+        .zip(&names)
+        .map(|(host, name)| {
+            let description = host
+                .description
+                .clone()
+                .unwrap_or_else(|| messages.get("host-known"));
+            (format!("{}{}", start, name), description)
+        })
+        .collect();
+    let (fixed, variable) = complete_among(&names, typed);
+    Some((fixed, variable, descriptions))
+}
+
+/// Complete the process typed after `kill`, by id, or after the programs taking names, by name,
+/// with the descriptions of the processes by the line they complete to. This returns `None` if
+/// the line is not typing a process. (See [`crate::ps`])
+fn complete_processes(line: &str) -> Option<(String, Vec<String>, BTreeMap<String, String>)> {
+    let (typed, by_name) = ps::word(line)?;
+    let start = &line[..line.len() - typed.len()];
+    let processes = ps::list();
+    let mut candidates = Vec::<(String, String)>::new();
+    for process in &processes {
+        match by_name {
+            true => match candidates
+                .iter_mut()
+                .find(|(name, _)| *name == process.name)
+            {
+                Some((_, pids)) => pids.push_str(&format!(" {}", process.pid)),
+                None => candidates.push((process.name.clone(), process.pid.to_string())),
+            },
+            false => candidates.push((process.pid.to_string(), process.name.clone())),
+        }
+    }
+    let words = candidates
+        .iter()
+        .map(|(word, _)| word.clone())
+        .collect::<Vec<_>>();
+    let descriptions = candidates
+        .into_iter()
+        .map(|(word, description)| (format!("{}{}", start, word), description))
+        .collect();
+    let (fixed, variable) = complete_among(&words, typed);
+    Some((fixed, variable, descriptions))
+}
+
+/// Put the line chosen with `:pick` in the command, in place of `{}`, or else at its end.
+fn fill(template: &str, line: &str) -> String {
+    match template {
+        "" => line.to_string(),
+        template if template.contains("{}") => template.replace("{}", line),
+        template => format!("{} {}", template, line),
+    }
+}
+
+/// The processes whose name contains the text, ignoring case.
+fn filter_processes<'a>(text: &str, processes: &'a [ps::Process]) -> Vec<&'a ps::Process> {
+    let text = text.to_lowercase();
+    processes
+        .iter()
+        .filter(|process| process.name.to_lowercase().contains(&text))
+        .collect()
+}
+
+/// Order the completions of a line by how the commands they lead to were used: as recorded in the
+/// journal, or else by the commands of the session. (See [`crate::rank`])
+fn rank_completions(
+    ranker: &dyn Ranker,
+    store: Option<&Store>,
+    commands: &[String],
+    line: &str,
+    completions: Vec<String>,
+) -> Vec<String> {
+    let texts = completions
+        .iter()
+        .map(|completion| format!("{}{}", line.trim_start(), completion))
+        .collect::<Vec<_>>();
+    let candidates = texts
+        .iter()
+        .map(|text| {
+            let (count, age) = store
+                .and_then(|store| store.usage(text).ok())
+                .unwrap_or_else(|| {
+                    let count = commands.iter().filter(|command| command.starts_with(text));
+                    (count.count() as u64, None)
+                });
+            Candidate { text, count, age }
+        })
+        .collect::<Vec<_>>();
+
+    rank::rank(ranker, &candidates)
+        .into_iter()
+        .map(|index| completions[index].clone())
+        .collect()
+}
+
+/// The completion of a word among candidates: the rest of the candidate if only one starts with
+/// the word, or else the rest of every candidate starting with it, to choose from.
+fn complete_among(candidates: &[String], word: &str) -> (String, Vec<String>) {
+    let mut matching = Vec::<String>::new();
+    for rest in candidates
+        .iter()
+        .filter_map(|candidate| candidate.strip_prefix(word))
+    {
+        if !matching.iter().any(|matched| matched == rest) {
+            matching.push(rest.to_string());
+        }
+    }
+
+    match matching.len() {
+        1 => (matching.remove(0), Vec::new()),
+        _ => (String::new(), matching),
+    }
+}
+
+/// Check whether the passphrase typed on the lock screen is the one of the SHA-256 (in lowercase
+/// hexadecimal), comparing every digit so the time taken tells nothing of the hash.
+fn unlocks(typed: &str, hash: &str) -> bool {
+    let digest: String = Sha256::digest(typed.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    digest.len() == hash.len()
+        && digest
+            .bytes()
+            .zip(hash.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// The input of a running command: the lines supplied so far, and the line being typed, if any.
+fn typed_stdin(stdin: &[String], line: &LineBuffer) -> Vec<String> {
+    let typed = (!line.is_empty()).then(|| line.to_string());
+    stdin.iter().cloned().chain(typed).collect()
+}
+
+/// Render a line being typed, with the character under the cursor highlighted (a space stands
+/// for the cursor at the end of the line), and its comment, starting at the index given, dimmed.
+fn render_line<'a>(
+    line: &'a LineBuffer,
+    style: Style,
+    theme: &Theme,
+    comment: Option<usize>,
+) -> Vec<Span<'a>> {
+    let comment = comment.unwrap_or(usize::MAX);
+    // The part of the line starting at an index, split where the comment starts.
+    let styled = |text: &'a str, offset: usize| {
+        let split = comment.saturating_sub(offset).min(text.len());
+        [
+            Span::styled(&text[..split], style),
+            Span::styled(&text[split..], theme.hint),
+        ]
+    };
+    let before = line.before();
+    let under = line.under().unwrap_or_default();
+    let cursor = match under.is_empty() {
+        true => Span::styled(" ", theme.cursor),
+        false => Span::styled(under, theme.cursor),
+    };
+    let offset = line.cursor() + under.len();
+
+    let mut spans = styled(before, 0).to_vec();
+    spans.push(cursor);
+    spans.extend(styled(&line.as_str()[offset..], offset));
+    spans.retain(|span| !span.content.is_empty());
+    spans
+}
+
+/// Render the keybindings of the keymap, grouped by category.
+fn render_keymap<'a>(keymap: &Keymap, theme: &Theme, messages: &Catalog) -> Vec<Line<'a>> {
+    let keys = Action::ALL
+        .iter()
+        .map(|action| {
+            let keys = keymap.keys(*action);
+            let keys = keys.iter().map(Key::to_string).collect::<Vec<_>>();
+            (*action, keys.join(", "))
+        })
+        .filter(|(_, keys)| !keys.is_empty())
+        .collect::<Vec<_>>();
+    let width = keys
+        .iter()
+        .map(|(_, keys)| keys.chars().count())
+        .max()
+        .unwrap_or_default();
+
+    let mut lines = Vec::new();
+    for category in Category::ALL {
+        let actions = keys
+            .iter()
+            .filter(|(action, _)| action.category() == *category)
+            .collect::<Vec<_>>();
+        if actions.is_empty() {
+            continue;
+        }
+
+        if !lines.is_empty() {
+            lines.push(Line::default());
+        }
+        lines.push(Line::styled(
+            format!(" {}", messages.get(category.message())),
+            theme.title,
+        ));
+        lines.extend(actions.into_iter().map(|(action, keys)| {
+            Line::from(vec![
+                Span::styled(
+                    format!("  {:<width$}  ", keys, width = width),
+                    theme.command,
+                ),
+                Span::styled(messages.get(&action.message()), theme.hint),
+            ])
+        }));
+    }
+    let texts = keymap.texts().collect::<Vec<_>>();
+    if !texts.is_empty() {
+        let width = texts
+            .iter()
+            .map(|(key, ..)| key.to_string().chars().count())
+            .max()
+            .unwrap_or_default()
+            .max(width);
+        lines.push(Line::default());
+        lines.push(Line::styled(
+            format!(" {}", messages.get("help-texts")),
+            theme.title,
+        ));
+        lines.extend(texts.into_iter().map(|(key, text, run)| {
+            let message = match run {
+                true => "help-text-run",
+                false => "help-text-insert",
+            };
+            Line::from(vec![
+                Span::styled(
+                    format!("  {:<width$}  ", key.to_string(), width = width),
+                    theme.command,
+                ),
+                Span::styled(messages.format(message, &[("text", &text)]), theme.hint),
+            ])
+        }));
+    }
+    lines.push(Line::default());
+    lines.push(Line::styled(
+        format!(" {}", messages.get("help-close")),
+        theme.hint,
+    ));
+
+    lines
+}
+
+/// Render the startup screen shown while the executor is being initialized.
+fn render_startup(frame: &mut Frame, theme: &Theme, messages: &Catalog, log: &[String]) {
+    let mut lines = vec![Line::from(Span::styled(
+        messages.get("startup"),
+        theme.title,
+    ))];
+    lines.extend(
+        log.iter()
+            .map(|message| Line::from(Span::styled(format!("  {}", message), theme.hint))),
+    );
+
+    let startup_para = Paragraph::new(lines).wrap(Wrap { trim: true });
+    frame.render_widget(startup_para, frame.area());
+}
+
+/// Render the screen listing the problems found in the configuration file.
+fn render_config_error(frame: &mut Frame, theme: &Theme, messages: &Catalog, error: &ConfigError) {
+    let mut lines = vec![
+        Line::from(Span::styled(
+            messages.get("config-error-title"),
+            theme.title,
+        )),
+        Line::from(Span::styled(
+            error.path.display().to_string(),
+            theme.command,
+        )),
+        Line::default(),
+    ];
+    lines.extend(
+        error
+            .issues
+            .iter()
+            .map(|issue| Line::from(Span::styled(format!("  {}", issue), theme.error))),
+    );
+    lines.push(Line::default());
+    lines.push(Line::from(Span::styled(
+        messages.get("config-error-continue"),
+        theme.hint,
+    )));
+
+    let error_para = Paragraph::new(lines).wrap(Wrap { trim: false });
+    frame.render_widget(error_para, frame.area());
+}
+
+/// Render a placeholder asking for a larger terminal if the frame is smaller than [`MIN_SIZE`],
+/// rather than a layout that does not fit. This returns whether the placeholder was rendered.
+fn render_too_small(frame: &mut Frame, theme: &Theme, messages: &Catalog) -> bool {
+    let area = frame.area();
+    let (width, height) = MIN_SIZE;
+    if area.width >= width && area.height >= height {
+        return false;
+    }
+
+    let placeholder = messages.format(
+        "terminal-too-small",
+        &[("width", &width), ("height", &height)],
+    );
+    // Centered on the lines it takes unwrapped, which is close enough for a short message.
+    let lines = (placeholder.chars().count() as u16).div_ceil(area.width.max(1));
+    let top = area.height.saturating_sub(lines) / 2;
+    let area = Rect {
+        y: area.y + top,
+        height: area.height - top,
+        ..area
+    };
+    let paragraph = Paragraph::new(Line::styled(placeholder, theme.hint))
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+    frame.render_widget(paragraph, area);
+    true
+}
+
+/// A rectangle of the given size, centered in the area (and clipped to it).
+pub(crate) fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recalled_arguments_are_expanded() {
+        let previous = "mkdir -p build/out";
+        assert_eq!(recall_arguments("cd !$", previous), "cd build/out");
+        assert_eq!(recall_arguments("rmdir !*", previous), "rmdir -p build/out");
+        assert_eq!(
+            recall_arguments("echo \"!$\"!$", previous),
+            "echo \"build/out\"build/out"
+        );
+        assert_eq!(recall_arguments("ls !$", "ls"), "ls ");
+    }
+
+    #[test]
+    fn recalled_arguments_are_left_quoted_or_escaped() {
+        let previous = "mkdir -p build/out";
+        assert_eq!(
+            recall_arguments("echo '!$' !*", previous),
+            "echo '!$' -p build/out"
+        );
+        assert_eq!(
+            recall_arguments("echo \\!$ \\!*", previous),
+            "echo \\!$ \\!*"
+        );
+        assert_eq!(
+            recall_arguments("echo \"'\"!$", previous),
+            "echo \"'\"build/out"
+        );
+        assert_eq!(recalls("echo 'it''s !*'").count(), 0);
+        assert_eq!(recalls("echo !").count(), 0);
+    }
+
+    #[test]
+    fn the_lock_is_unlocked_by_the_passphrase_of_the_hash() {
+        let hash = "992db5e2595725ef68eb5066e014da8c7f6baca0afa96f1021b56b66782133f2";
+        assert!(unlocks("let me in", hash));
+        assert!(!unlocks("let me out", hash));
+        assert!(!unlocks("let me in", &hash[..32]));
+    }
 }
@@ -0,0 +1,341 @@
+//! Runbooks: Markdown documents whose code blocks are run, step by step, in the shell.
+//!
+//! `sheller runbook FILE.md` starts the shell with the commands of the shell code blocks of the
+//! document (fenced with ```` ``` ```` or `~~~`, with no language or one of [`LANGUAGES`]) as
+//! steps. Each command is offered in turn below the prompt:
+//!
+//! - `y` (or `Enter`) runs it, through the executor as if typed.
+//! - `e` puts it on the prompt, to be edited: the command run next is the step, noted as edited.
+//! - `n` (or `s`) skips it, noted in the history.
+//! - `q` (or `Esc`) stops the runbook, leaving the rest of the steps.
+//!
+//! In `console` blocks, only the lines starting with `$ ` are commands, the rest being their
+//! expected output. Elsewhere every line is a command, save for comments (`#`) and blank lines,
+//! and a line ending with `\` goes on with the next one.
+//!
+//! Once every step is done, or the runbook stopped, a copy of the document annotated with the
+//! results is written next to it, as `FILE.results.md`: after each block, the commands run with
+//! their output, and the ones skipped or left.
+//!
+//! `:source FILE --confirm-each` steps through a script the same way, every line being a step,
+//! with no copy written: the decisions are left in the history alone.
+//!
+//! ```rust
+//! use shelgon::runbook::Runbook;
+//!
+//! let runbook = Runbook::parse(
+//!     "deploy.md",
+//!     "# Deploy\n\n```bash\nmake build\n# then\nmake \\\n  deploy\n```\n\n```json\n{}\n```\n",
+//! );
+//! assert_eq!(runbook.commands(), ["make build", "make deploy"]);
+//! ```
+
+use std::path::{Path, PathBuf};
+
+/// The languages of the code blocks holding commands, besides blocks with no language.
+pub const LANGUAGES: &[&str] = &["sh", "bash", "zsh", "shell", "console", "sheller"];
+
+///
+/// [`Runbook`] is a Markdown document, along with the steps of its code blocks and how far they
+/// are done.
+///
+#[derive(Debug, Clone)]
+pub struct Runbook {
+    /// The path of the document.
+    path: PathBuf,
+    /// The document.
+    source: String,
+    /// The index past the closing fence of each code block holding commands.
+    blocks: Vec<usize>,
+    /// The steps, in order.
+    steps: Vec<Step>,
+    /// The step offered, or run, now.
+    current: usize,
+    /// The number of entries of the history when the current step was run, or put on the prompt
+    /// to be edited, with whether it was.
+    pub(crate) started: Option<(usize, bool)>,
+    /// The `:source` command stepping through a script, rather than a runbook, with whether it
+    /// goes on after a command failing.
+    pub(crate) sourced: Option<(String, bool)>,
+}
+
+///
+/// [`Step`] is a command of a code block, with what became of it.
+///
+#[derive(Debug, Clone)]
+struct Step {
+    /// The block the command is in.
+    block: usize,
+    /// The line of the document the command starts on, from 1.
+    line: usize,
+    /// The command, as written.
+    command: String,
+    /// What became of it.
+    outcome: Outcome,
+}
+
+///
+/// [`Outcome`] is what became of a step.
+///
+#[derive(Debug, Clone)]
+enum Outcome {
+    /// Not run yet.
+    Left,
+    /// Skipped.
+    Skipped,
+    /// Run, as this command, with this output.
+    Ran(String, Vec<String>),
+}
+
+impl Runbook {
+    /// Read a runbook from a file.
+    pub fn read(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("unable to read `{}`: {}", path.display(), e))?;
+        Ok(Self::parse(path, &source))
+    }
+
+    /// Parse a runbook, the document read from the path.
+    pub fn parse(path: impl Into<PathBuf>, source: &str) -> Self {
+        let mut runbook = Self {
+            path: path.into(),
+            source: source.to_string(),
+            blocks: Vec::new(),
+            steps: Vec::new(),
+            current: 0,
+            started: None,
+            sourced: None,
+        };
+        // The fence of the block being read, with its language, if any.
+        let mut fence: Option<(&str, &str)> = None;
+        let mut commands = Vec::new();
+        let mut offset = 0;
+        for (index, line) in source.split_inclusive('\n').enumerate() {
+            offset += line.len();
+            let trimmed = line.trim();
+            match fence {
+                None => {
+                    let Some(opening) = opening(trimmed) else {
+                        continue;
+                    };
+                    fence = Some((opening, trimmed[opening.len()..].trim()));
+                    commands.clear();
+                }
+                Some((opening, language)) if closes(trimmed, opening) => {
+                    fence = None;
+                    runbook.block(offset, &commands, language);
+                }
+                Some(_) => commands.push((index + 1, line.trim_end_matches(['\r', '\n']))),
+            }
+        }
+        if let Some((_, language)) = fence {
+            runbook.block(source.len(), &commands, language);
+        }
+        runbook
+    }
+
+    /// A script stepped through by `:source`, every line of it being a step, save for comments
+    /// and blank lines.
+    pub(crate) fn script(
+        path: impl Into<PathBuf>,
+        source: &str,
+        command: &str,
+        keep_going: bool,
+    ) -> Self {
+        let steps = source
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(line, command)| Step {
+                block: 0,
+                line,
+                command: command.to_string(),
+                outcome: Outcome::Left,
+            });
+        Self {
+            path: path.into(),
+            source: source.to_string(),
+            blocks: Vec::new(),
+            steps: steps.collect(),
+            current: 0,
+            started: None,
+            sourced: Some((command.to_string(), keep_going)),
+        }
+    }
+
+    /// Add the steps of a code block ending at `end`, if it holds commands, from its lines and
+    /// their number.
+    fn block(&mut self, end: usize, lines: &[(usize, &str)], language: &str) {
+        let language = language.split_whitespace().next().unwrap_or_default();
+        if !language.is_empty() && !LANGUAGES.contains(&language.to_ascii_lowercase().as_str()) {
+            return;
+        }
+        let lines = lines
+            .iter()
+            .filter_map(|(number, line)| match language == "console" {
+                true => Some((*number, line.strip_prefix("$ ")?)),
+                false => Some((*number, *line)),
+            });
+        let mut commands = Vec::new();
+        // The command going on over several lines, with the line it starts on.
+        let mut continued = (0, String::new());
+        for (number, line) in lines {
+            let line = line.trim();
+            if continued.1.is_empty() {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                continued.0 = number;
+            }
+            match line.strip_suffix('\\') {
+                Some(line) => continued.1.push_str(line),
+                None => {
+                    continued.1.push_str(line);
+                    commands.push(std::mem::take(&mut continued));
+                }
+            }
+        }
+        commands.extend(Some(continued).filter(|(_, rest)| !rest.is_empty()));
+        if commands.is_empty() {
+            return;
+        }
+
+        let block = self.blocks.len();
+        self.blocks.push(end);
+        self.steps
+            .extend(commands.into_iter().map(|(line, command)| Step {
+                block,
+                line,
+                command: command.trim().to_string(),
+                outcome: Outcome::Left,
+            }));
+    }
+
+    /// The commands of the steps, in order.
+    pub fn commands(&self) -> Vec<&str> {
+        self.steps
+            .iter()
+            .map(|step| step.command.as_str())
+            .collect()
+    }
+
+    /// The path of the document.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path of the copy annotated with the results, next to the document: `FILE.results.md`
+    /// for `FILE.md`.
+    pub fn results_path(&self) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        self.path.with_file_name(format!("{}.results.md", stem))
+    }
+
+    /// The step offered now, by its position, the line it starts on and its command, or `None`
+    /// once every step is done.
+    pub(crate) fn current(&self) -> Option<(usize, usize, &str)> {
+        let step = self.steps.get(self.current)?;
+        Some((self.current + 1, step.line, &step.command))
+    }
+
+    /// The number of steps.
+    pub(crate) fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// The number of steps run.
+    pub(crate) fn ran(&self) -> usize {
+        let ran = |step: &&Step| matches!(step.outcome, Outcome::Ran(..));
+        self.steps.iter().filter(ran).count()
+    }
+
+    /// The number of steps skipped.
+    pub(crate) fn skipped(&self) -> usize {
+        let skipped = |step: &&Step| matches!(step.outcome, Outcome::Skipped);
+        self.steps.iter().filter(skipped).count()
+    }
+
+    /// Skip the current step, going on with the next one.
+    pub(crate) fn skip(&mut self) {
+        if let Some(step) = self.steps.get_mut(self.current) {
+            step.outcome = Outcome::Skipped;
+            self.current += 1;
+        }
+        self.started = None;
+    }
+
+    /// Note the current step as run, as the command given with its output, going on with the
+    /// next one.
+    pub(crate) fn complete(&mut self, command: String, output: Vec<String>) {
+        if let Some(step) = self.steps.get_mut(self.current) {
+            step.outcome = Outcome::Ran(command, output);
+            self.current += 1;
+        }
+        self.started = None;
+    }
+
+    /// The document annotated with the results: after each code block holding commands, the
+    /// commands run with their output, and the ones skipped or left.
+    pub fn annotated(&self) -> String {
+        let mut annotated = String::with_capacity(self.source.len());
+        let mut from = 0;
+        for (block, &end) in self.blocks.iter().enumerate() {
+            annotated.push_str(&self.source[from..end]);
+            from = end;
+            if !annotated.ends_with('\n') {
+                annotated.push('\n');
+            }
+
+            let mut results = String::new();
+            for step in self.steps.iter().filter(|step| step.block == block) {
+                match &step.outcome {
+                    Outcome::Left => results.push_str(&format!("# left: {}\n", step.command)),
+                    Outcome::Skipped => results.push_str(&format!("# skipped: {}\n", step.command)),
+                    Outcome::Ran(command, output) => {
+                        results.push_str(&format!("$ {}\n", command));
+                        for line in output {
+                            results.push_str(line);
+                            results.push('\n');
+                        }
+                    }
+                }
+            }
+            // The fence is longer than any run of backticks of the output.
+            let mut fence = "```".to_string();
+            while results.contains(&fence) {
+                fence.push('`');
+            }
+            annotated.push_str(&format!(
+                "\nResults:\n\n{}console\n{}{}\n",
+                fence, results, fence
+            ));
+        }
+        annotated.push_str(&self.source[from..]);
+        annotated
+    }
+
+    /// Write the document annotated with the results next to it, and return the path written.
+    /// (See [`Runbook::results_path`])
+    pub fn write(&self) -> std::io::Result<PathBuf> {
+        let path = self.results_path();
+        std::fs::write(&path, self.annotated())?;
+        Ok(path)
+    }
+}
+
+/// The fence a line opens a code block with, if it does.
+fn opening(line: &str) -> Option<&str> {
+    let marker = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let length = line.len() - line.trim_start_matches(marker).len();
+    // The language of a block fenced with backticks holds none.
+    let valid = length >= 3 && !(marker == '`' && line[length..].contains('`'));
+    valid.then_some(&line[..length])
+}
+
+/// Check whether a line closes the code block opened with the fence.
+fn closes(line: &str, fence: &str) -> bool {
+    let marker = fence.chars().next().unwrap_or('`');
+    line.len() >= fence.len() && line.chars().all(|c| c == marker)
+}
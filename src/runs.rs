@@ -0,0 +1,183 @@
+//! Named runs: sequences of commands grouped under a name.
+//!
+//! `:begin NAME` starts a run, and `:end` closes it. The entries added to the history in between
+//! belong to the run: they are drawn under a header with its name, folded away behind it with
+//! `:run fold [NAME]` and shown again with `:run unfold [NAME]`. The commands of a run are kept in
+//! the journal along with its name, to be listed with `:history --run NAME`. (See
+//! `crate::journal`)
+//!
+//! `:run export NAME [FILE]` writes a run as a Markdown section, such as for an incident timeline:
+//! every command with its output, and how long after the start of the run it was done. Comments
+//! typed along the way (see [`crate::syntax::Syntax::comment`]) are written as text, making the
+//! run a runbook.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+use crate::command::CommandOutput;
+
+///
+/// [`Runs`] is the runs of the session, oldest first.
+///
+#[derive(Debug, Default)]
+pub(crate) struct Runs {
+    /// The runs, oldest first. Only the last one may still be open.
+    runs: Vec<Run>,
+}
+
+///
+/// [`Run`] is a sequence of entries of the history, grouped under a name.
+///
+#[derive(Debug)]
+pub(crate) struct Run {
+    /// The name of the run.
+    pub(crate) name: String,
+    /// The first entry of the run, counting every entry ever added to the history.
+    pub(crate) first: usize,
+    /// The entry past the last one of the run, once it is closed.
+    pub(crate) end: Option<usize>,
+    /// Whether the entries of the run are hidden behind its header.
+    pub(crate) folded: bool,
+    /// When the run started.
+    started: Instant,
+    /// How long after the start of the run each of its entries was added.
+    times: Vec<Duration>,
+}
+
+impl Runs {
+    /// The run still open, if any.
+    pub(crate) fn open(&self) -> Option<&Run> {
+        self.runs.last().filter(|run| run.end.is_none())
+    }
+
+    /// Start a run, with the entries added from `first` on. The open run, if any, is ended first.
+    pub(crate) fn begin(&mut self, name: &str, first: usize) {
+        self.end(first);
+        self.runs.push(Run {
+            name: name.to_string(),
+            first,
+            end: None,
+            folded: false,
+            started: Instant::now(),
+            times: Vec::new(),
+        });
+    }
+
+    /// Close the open run before the entry `end`. This returns the run closed, if one was open.
+    pub(crate) fn end(&mut self, end: usize) -> Option<&Run> {
+        let run = self.runs.last_mut().filter(|run| run.end.is_none())?;
+        run.end = Some(end);
+        Some(run)
+    }
+
+    /// Note an entry added to the history, to the open run.
+    pub(crate) fn record(&mut self) {
+        if let Some(run) = self.runs.last_mut().filter(|run| run.end.is_none()) {
+            run.times.push(run.started.elapsed());
+        }
+    }
+
+    /// The last run with the name, or else the last run.
+    pub(crate) fn find(&self, name: Option<&str>) -> Option<&Run> {
+        let mut runs = self.runs.iter().rev();
+        match name {
+            Some(name) => runs.find(|run| run.name == name),
+            None => runs.next(),
+        }
+    }
+
+    /// The last run with the name, or else the last run, to change it. (See [`Runs::find`])
+    pub(crate) fn find_mut(&mut self, name: Option<&str>) -> Option<&mut Run> {
+        let mut runs = self.runs.iter_mut().rev();
+        match name {
+            Some(name) => runs.find(|run| run.name == name),
+            None => runs.next(),
+        }
+    }
+
+    /// The run an entry belongs to, with its position among the runs.
+    pub(crate) fn containing(&self, id: usize) -> Option<(usize, &Run)> {
+        self.runs
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, run)| run.contains(id))
+    }
+
+    /// The runs, oldest first.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Run> {
+        self.runs.iter()
+    }
+}
+
+impl Run {
+    /// Check whether an entry belongs to the run.
+    pub(crate) fn contains(&self, id: usize) -> bool {
+        id >= self.first && self.end.map_or(true, |end| id < end)
+    }
+
+    /// The number of entries of the run.
+    pub(crate) fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    /// The run as a Markdown section, with its entries, given by their id, the notes attached to
+    /// them and the ones starred. Entries made of a comment, starting with the marker given, are
+    /// written as text.
+    pub(crate) fn markdown<'a>(
+        &self,
+        entries: impl IntoIterator<Item = (usize, Cow<'a, CommandOutput>)>,
+        notes: &BTreeMap<usize, String>,
+        starred: &BTreeSet<usize>,
+        comment: Option<&str>,
+    ) -> String {
+        let mut markdown = format!("## {}\n", self.name);
+        for (id, entry) in entries {
+            let Some(time) = id
+                .checked_sub(self.first)
+                .and_then(|index| self.times.get(index))
+            else {
+                continue;
+            };
+            let star = if starred.contains(&id) { " ★" } else { "" };
+            let text = comment
+                .and_then(|marker| entry.command.trim_start().strip_prefix(marker))
+                .filter(|_| entry.stdout.is_empty() && entry.stderr.is_empty());
+            match text {
+                Some(text) => markdown.push_str(&format!(
+                    "\n**+{}**{} {}\n",
+                    elapsed(*time),
+                    star,
+                    text.trim()
+                )),
+                None => {
+                    markdown.push_str(&format!(
+                        "\n**+{}**{}\n\n```console\n",
+                        elapsed(*time),
+                        star
+                    ));
+                    markdown.push_str(&format!("{} {}\n", entry.prompt, entry.command));
+                    for line in entry.stdin.iter().chain(&entry.stdout).chain(&entry.stderr) {
+                        markdown.push_str(line);
+                        markdown.push('\n');
+                    }
+                    markdown.push_str("```\n");
+                }
+            }
+            if let Some(note) = notes.get(&id) {
+                markdown.push_str(&format!("\n_{}_\n", note));
+            }
+        }
+        markdown
+    }
+}
+
+/// A duration as `M:SS`, or `H:MM:SS` past an hour.
+fn elapsed(time: Duration) -> String {
+    let seconds = time.as_secs();
+    match seconds / 3_600 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    }
+}
@@ -0,0 +1,289 @@
+//! User scripts, written in [Rhai](https://rhai.rs).
+//!
+//! On startup, the shell runs `init.rhai` from the configuration directory (next to
+//! `config.toml`), if there is one. The script customizes the shell by defining hooks, which the
+//! shell calls when something happens:
+//!
+//! - `fn prompt(prompt)` returns the prompt to show instead of the one of the executor.
+//! - `fn on_command(command)` is called before a command runs. Returning a string runs that
+//!   string instead, and returning `""` cancels the command.
+//! - `fn on_output(entry)` is called when an entry is added to the history, with a map holding
+//!   its `prompt`, `command`, `stdin`, `stdout` and `stderr`, its `exit_code` (`()` if unknown)
+//!   and its `duration`, in milliseconds.
+//! - `fn on_start()` is called once, when the shell is ready.
+//!
+//! Scripts act on the shell through these functions:
+//!
+//! - `history()` returns the history, as an array of entries.
+//! - `run(command)` runs a command, as if it was typed at the prompt.
+//! - `trigger(action)` triggers an action of the keymap, such as `"clear-screen"`.
+//! - `bind(key, action)` binds a key to an action, on top of its other keys. (See
+//!   [`crate::keymap`])
+//!
+//! The commands, actions and bindings requested by a hook are applied once it returns. Anything
+//! printed (`print`, `debug`) is added to the history, and the errors raised by the script are
+//! shown in a banner at the top of the screen. A hook that fails is not called again.
+//!
+//! ```rhai
+//! bind("ctrl+k", "clear-screen");
+//!
+//! fn prompt(prompt) {
+//!     `[${history().len()}] ${prompt}`
+//! }
+//!
+//! fn on_command(command) {
+//!     if command == "ll" { "ls -l" }
+//! }
+//! ```
+
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope, AST};
+
+use crate::config;
+use crate::keymap::{Action, Key};
+use crate::protocol::Entry;
+
+/// The name of the script, in the configuration directory.
+pub(crate) const NAME: &str = "init.rhai";
+
+/// The number of operations a hook may run, so a runaway script does not hang the shell.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// The hooks the shell calls.
+const HOOKS: &[&str] = &["prompt", "on_command", "on_output", "on_start"];
+
+/// A change to the shell requested by the script.
+pub(crate) enum Effect {
+    /// Run a command.
+    Run(String),
+    /// Trigger an action.
+    Action(Action),
+    /// Bind a key to an action.
+    Bind(Key, Action),
+}
+
+/// The state shared between the shell and the functions called by the script.
+#[derive(Default)]
+struct Bridge {
+    /// The history, as seen by the script.
+    history: Vec<Entry>,
+    /// The changes requested by the script.
+    effects: Vec<Effect>,
+    /// The lines printed by the script.
+    output: Vec<String>,
+    /// The errors raised by the script.
+    errors: Vec<String>,
+}
+
+///
+/// [`Script`] is the user script, loaded and ready to be called.
+///
+pub(crate) struct Script {
+    /// The engine running the script.
+    engine: Engine,
+    /// The compiled script.
+    ast: AST,
+    /// The variables of the script.
+    scope: RefCell<Scope<'static>>,
+    /// The hooks defined by the script, and not failed yet.
+    hooks: RefCell<HashSet<&'static str>>,
+    /// The state shared with the functions called by the script.
+    bridge: Rc<RefCell<Bridge>>,
+}
+
+impl Script {
+    /// The path of the script. (`init.rhai` in the configuration directory)
+    pub(crate) fn path() -> Option<PathBuf> {
+        config::config_dir().map(|dir| dir.join(NAME))
+    }
+
+    /// Load the script and run it, if there is one.
+    pub(crate) fn load() -> Option<anyhow::Result<Self>> {
+        let path = Self::path().filter(|path| path.exists())?;
+        Some(Self::load_from(&path))
+    }
+
+    /// Load a script and run it.
+    fn load_from(path: &Path) -> anyhow::Result<Self> {
+        let bridge = Rc::new(RefCell::new(Bridge::default()));
+        let engine = engine(&bridge);
+
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        let hooks = ast
+            .iter_functions()
+            .filter_map(|function| HOOKS.iter().find(|hook| **hook == function.name))
+            .copied()
+            .collect();
+
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+
+        Ok(Self {
+            engine,
+            ast,
+            scope: RefCell::new(scope),
+            hooks: RefCell::new(hooks),
+            bridge,
+        })
+    }
+
+    /// Call a hook, if the script defines it. A hook that fails is disabled, and its error is
+    /// reported.
+    fn call(&self, hook: &'static str, args: impl FuncArgs) -> Option<Dynamic> {
+        if !self.hooks.borrow().contains(hook) {
+            return None;
+        }
+
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope.borrow_mut(),
+            &self.ast,
+            hook,
+            args,
+        );
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.hooks.borrow_mut().remove(hook);
+                self.bridge
+                    .borrow_mut()
+                    .errors
+                    .push(format!("{}: {}", hook, e));
+                None
+            }
+        }
+    }
+
+    /// The prompt to show, given the one of the executor.
+    pub(crate) fn prompt(&self, prompt: String) -> String {
+        match self.call("prompt", (prompt.clone(),)) {
+            Some(value) if value.is_string() => value.to_string(),
+            _ => prompt,
+        }
+    }
+
+    /// The command to run in place of `command`, if the script rewrites it.
+    pub(crate) fn command(&self, command: &str) -> Option<String> {
+        self.call("on_command", (command.to_string(),))
+            .filter(Dynamic::is_string)
+            .map(|value| value.to_string())
+    }
+
+    /// Tell the script about a new history entry. The history seen by the script is trimmed to
+    /// `limit` entries, like the one of the shell.
+    pub(crate) fn output(&self, entry: Entry, limit: Option<usize>) {
+        let value = to_dynamic(&entry);
+        {
+            let mut bridge = self.bridge.borrow_mut();
+            bridge.history.push(entry);
+            if let Some(limit) = limit {
+                let excess = bridge.history.len().saturating_sub(limit);
+                bridge.history.drain(..excess);
+            }
+        }
+
+        self.call("on_output", (value,));
+    }
+
+    /// Tell the script the history was cleared.
+    pub(crate) fn clear(&self) {
+        self.bridge.borrow_mut().history.clear();
+    }
+
+    /// Tell the script the shell is ready.
+    pub(crate) fn start(&self) {
+        self.call("on_start", ());
+    }
+
+    /// Take the changes requested by the script, along with what it printed and the errors it
+    /// raised since the last call.
+    pub(crate) fn take(&self) -> (Vec<Effect>, Vec<String>, Vec<String>) {
+        let mut bridge = self.bridge.borrow_mut();
+        (
+            std::mem::take(&mut bridge.effects),
+            std::mem::take(&mut bridge.output),
+            std::mem::take(&mut bridge.errors),
+        )
+    }
+}
+
+/// Create the engine, with the functions scripts use to act on the shell.
+fn engine(bridge: &Rc<RefCell<Bridge>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    let shared = bridge.clone();
+    engine.on_print(move |line| shared.borrow_mut().output.push(line.to_string()));
+    let shared = bridge.clone();
+    engine.on_debug(move |line, _, _| shared.borrow_mut().output.push(line.to_string()));
+
+    let shared = bridge.clone();
+    engine.register_fn("history", move || -> Array {
+        shared.borrow().history.iter().map(to_dynamic).collect()
+    });
+    let shared = bridge.clone();
+    engine.register_fn("run", move |command: &str| {
+        shared
+            .borrow_mut()
+            .effects
+            .push(Effect::Run(command.to_string()));
+    });
+    let shared = bridge.clone();
+    engine.register_fn(
+        "trigger",
+        move |action: &str| -> Result<(), Box<EvalAltResult>> {
+            let action = self::action(action)?;
+            shared.borrow_mut().effects.push(Effect::Action(action));
+            Ok(())
+        },
+    );
+    let shared = bridge.clone();
+    engine.register_fn(
+        "bind",
+        move |key: &str, action: &str| -> Result<(), Box<EvalAltResult>> {
+            let key = key.parse::<Key>().map_err(|e| e.to_string())?;
+            let action = self::action(action)?;
+            shared.borrow_mut().effects.push(Effect::Bind(key, action));
+            Ok(())
+        },
+    );
+
+    engine
+}
+
+/// Look up an action by name, for the script.
+fn action(name: &str) -> Result<Action, Box<EvalAltResult>> {
+    Action::from_name(name).ok_or_else(|| format!("unknown action `{}`", name).into())
+}
+
+/// A history entry, as seen by the script.
+fn to_dynamic(entry: &Entry) -> Dynamic {
+    let lines =
+        |lines: &[String]| Dynamic::from_array(lines.iter().cloned().map(Dynamic::from).collect());
+
+    let mut map = Map::new();
+    map.insert("prompt".into(), entry.prompt.clone().into());
+    map.insert("command".into(), entry.command.clone().into());
+    map.insert("stdin".into(), lines(&entry.stdin));
+    map.insert("stdout".into(), lines(&entry.stdout));
+    map.insert("stderr".into(), lines(&entry.stderr));
+    let exit_code = entry
+        .exit_code
+        .map_or(Dynamic::UNIT, |code| i64::from(code).into());
+    map.insert("exit_code".into(), exit_code);
+    let duration = i64::try_from(entry.duration.as_millis()).unwrap_or(i64::MAX);
+    map.insert("duration".into(), duration.into());
+
+    Dynamic::from_map(map)
+}
@@ -0,0 +1,359 @@
+//! Detachable sessions.
+//!
+//! A session runs the shell in a background process (see [`crate::renderer::App::serve`]), and
+//! serves its full screen interface over a unix socket. [`attach`] connects the terminal to a
+//! session: key presses and resizes are sent to the session, and the session draws directly on
+//! the terminal. Detaching (`Ctrl+Q` by default, see [`crate::keymap`]) or closing the terminal
+//! leaves the session, and the commands it is running, alive.
+//!
+//! Only one client is attached to a session at a time. Attaching to a session that already has a
+//! client detaches the previous one.
+//!
+//! # Protocol
+//!
+//! The client sends [`crossterm::event::Event`]s as JSON, one per line, starting with a
+//! [`crossterm::event::Event::Resize`] carrying the size of its terminal. The session replies
+//! with the raw output of the terminal, which the client copies to its terminal as is.
+//!
+//! # Remote control
+//!
+//! A session also listens on a control socket (see [`control()`]), taking JSON-RPC 2.0 calls, one
+//! per line. `run` runs a command and returns its output, `history` returns the history, and
+//! `subscribe` streams every new history entry as an `output` notification:
+//!
+//! ```text
+//! --> {"jsonrpc": "2.0", "id": 1, "method": "run", "params": {"command": "ls"}}
+//! <-- {"jsonrpc": "2.0", "id": 1, "result": [{"prompt": "$", "command": "ls", ...}]}
+//! ```
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, MetadataExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use crossterm::{
+    event::{
+        DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, Event,
+    },
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use crate::config::Config;
+use crate::control::Listener;
+use crate::keymap::Action;
+use crate::renderer::Input;
+
+/// How often the client checks whether the session went away while waiting for input.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The directory holding the sockets of the sessions. (`$XDG_RUNTIME_DIR/sheller`, or a
+/// per-user directory in the temporary directory) This fails if the directory exists, but is not
+/// the user's own with mode `0700`: in the temporary directory, another user could have made it
+/// to take the sessions over.
+pub fn directory() -> io::Result<PathBuf> {
+    let directory = match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("sheller"),
+        // SAFETY: `getuid` has no preconditions, and cannot fail.
+        None => std::env::temp_dir().join(format!("sheller-{}", unsafe { libc::getuid() })),
+    };
+    match private(&directory) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(directory),
+        checked => checked.map(|()| directory),
+    }
+}
+
+/// The socket of a session. This fails if the name would lead out of the directory of the
+/// sessions, holding a `/` or `..`.
+pub fn socket(name: &str) -> io::Result<PathBuf> {
+    Ok(directory()?.join(format!("{}.sock", named(name)?)))
+}
+
+/// The control socket of a session. (See [Remote control](self#remote-control)) This fails as
+/// [`socket`] does.
+pub fn control(name: &str) -> io::Result<PathBuf> {
+    Ok(directory()?.join(format!("{}.ctl", named(name)?)))
+}
+
+/// Check that a directory is the user's own, with mode `0700`, and no symbolic link.
+fn private(directory: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(directory)?;
+    // SAFETY: `getuid` has no preconditions, and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o777 != 0o700 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is not a directory of the user alone, with mode 0700",
+                directory.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Check that the name of a session stays within the directory of the sessions.
+fn named(name: &str) -> io::Result<&str> {
+    match name.is_empty() || name.contains('/') || name.contains("..") {
+        true => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid session name `{}`", name),
+        )),
+        false => Ok(name),
+    }
+}
+
+/// The names of the sessions, running or not cleaned up after a crash.
+pub fn sessions() -> Vec<String> {
+    let Ok(entries) = directory().and_then(fs::read_dir) else {
+        return Vec::new();
+    };
+
+    let mut sessions = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_socket()))
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            name.strip_suffix(".sock").map(str::to_string)
+        })
+        .collect::<Vec<_>>();
+    sessions.sort();
+
+    sessions
+}
+
+/// Attach the terminal to a session, until the user detaches or the session exits.
+pub fn attach(name: &str) -> anyhow::Result<()> {
+    let path = socket(name)?;
+    let stream = UnixStream::connect(&path)
+        .map_err(|e| anyhow::anyhow!("unable to attach to session `{}`: {}", name, e))?;
+
+    let mouse = Config::load().unwrap_or_default().ui.mouse;
+    crossterm::terminal::enable_raw_mode()?;
+    crossterm::execute!(io::stdout(), EnterAlternateScreen, EnableBracketedPaste)?;
+    if mouse {
+        crossterm::execute!(io::stdout(), EnableMouseCapture)?;
+    }
+
+    let response = forward(stream);
+
+    crossterm::terminal::disable_raw_mode()?;
+    if mouse {
+        crossterm::execute!(io::stdout(), DisableMouseCapture)?;
+    }
+    crossterm::execute!(
+        io::stdout(),
+        DisableBracketedPaste,
+        LeaveAlternateScreen,
+        crossterm::cursor::Show
+    )?;
+
+    response
+}
+
+/// Forward the events of the terminal to the session, and the output of the session to the
+/// terminal.
+fn forward(stream: UnixStream) -> anyhow::Result<()> {
+    let keys = Config::load().unwrap_or_default().keys;
+    let mut writer = stream.try_clone()?;
+    let closed = Arc::new(AtomicBool::new(false));
+
+    let (columns, rows) = crossterm::terminal::size()?;
+    send(&mut writer, &Event::Resize(columns, rows))?;
+
+    let output = {
+        let closed = closed.clone();
+        let mut reader = stream;
+        std::thread::spawn(move || {
+            let mut stdout = io::stdout();
+            let mut buffer = [0; 8192];
+            while let Ok(read @ 1..) = reader.read(&mut buffer) {
+                if stdout
+                    .write_all(&buffer[..read])
+                    .and_then(|_| stdout.flush())
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            closed.store(true, Ordering::SeqCst);
+        })
+    };
+
+    while !closed.load(Ordering::SeqCst) {
+        if !crossterm::event::poll(POLL_INTERVAL)? {
+            continue;
+        }
+
+        let event = crossterm::event::read()?;
+        if let Event::Key(key) = &event {
+            if keys.action(key, false) == Some(Action::Detach) {
+                break;
+            }
+        }
+        if send(&mut writer, &event).is_err() {
+            break;
+        }
+    }
+
+    writer.shutdown(std::net::Shutdown::Both)?;
+    let _ = output.join();
+
+    Ok(())
+}
+
+/// Send an event to the session.
+fn send(writer: &mut impl Write, event: &Event) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(event)?;
+    line.push(b'\n');
+    writer.write_all(&line)?;
+
+    Ok(())
+}
+
+///
+/// [`Server`] is the session end of the sockets: it accepts clients, and turns what they send
+/// (key presses, or calls on the control socket) into input for the shell.
+///
+pub(crate) struct Server {
+    /// The path of the socket, removed when the server is dropped.
+    path: PathBuf,
+    /// The input sent by the clients.
+    events: mpsc::Receiver<Input>,
+    /// The client currently attached.
+    client: Client,
+    /// The control socket.
+    _control: Listener,
+}
+
+impl Server {
+    /// Listen on the socket of a session, and on its control socket. This fails if the session
+    /// is already running.
+    pub(crate) fn bind(path: &Path, control: &Path) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(parent)?;
+            private(parent)?;
+        }
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                anyhow::bail!("a session is already running on {}", path.display());
+            }
+            // The socket of a session that did not exit cleanly.
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        let (sender, events) = mpsc::channel();
+        let client = Client::default();
+        let control = Listener::bind(control, sender.clone())?;
+
+        let attached = client.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().filter_map(Result::ok) {
+                let Ok(reader) = stream.try_clone() else {
+                    continue;
+                };
+                attached.replace(stream);
+
+                let sender = sender.clone();
+                std::thread::spawn(move || {
+                    let events = BufReader::new(reader)
+                        .lines()
+                        .map_while(Result::ok)
+                        .filter_map(|line| serde_json::from_str::<Event>(&line).ok());
+                    for event in events {
+                        if sender.send(Input::Event(event)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            events,
+            client,
+            _control: control,
+        })
+    }
+
+    /// Wait for the next input from a client, or for `tick` at most, if given.
+    pub(crate) fn next(&self, tick: Option<Duration>) -> io::Result<Input> {
+        let input = match tick {
+            Some(tick) => match self.events.recv_timeout(tick) {
+                Err(mpsc::RecvTimeoutError::Timeout) => return Ok(Input::Tick),
+                input => input.map_err(|e| e.to_string()),
+            },
+            None => self.events.recv().map_err(|e| e.to_string()),
+        };
+        input.map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, e))
+    }
+
+    /// The output to the client currently attached.
+    pub(crate) fn client(&self) -> Client {
+        self.client.clone()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+///
+/// [`Client`] writes to the client currently attached. While no client is attached, the output
+/// is dropped.
+///
+#[derive(Clone, Default)]
+pub(crate) struct Client(Arc<Mutex<Option<UnixStream>>>);
+
+impl Client {
+    /// Attach a new client, detaching the previous one.
+    fn replace(&self, stream: UnixStream) {
+        if let Ok(mut client) = self.0.lock() {
+            if let Some(previous) = client.replace(stream) {
+                let _ = previous.shutdown(std::net::Shutdown::Both);
+            }
+        }
+    }
+}
+
+impl Write for Client {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Ok(mut client) = self.0.lock() else {
+            return Ok(buf.len());
+        };
+        if let Some(stream) = client.as_mut() {
+            if stream.write_all(buf).is_err() {
+                // The client went away.
+                *client = None;
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Ok(mut client) = self.0.lock() {
+            if let Some(stream) = client.as_mut() {
+                let _ = stream.flush();
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+//! The first-run setup wizard.
+//!
+//! When the shell starts without a configuration file, it walks the user through a few questions
+//! (one [`Step`] each) and writes the answers to a new configuration file. Skipping the wizard
+//! still creates the file, so the wizard is only ever shown once.
+
+use std::{fs, path::PathBuf};
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
+    Frame,
+};
+
+use crate::config::{self, Config};
+use crate::i18n::Catalog;
+use crate::renderer::centered;
+use crate::theme::{self, Theme};
+
+/// The header written to the configuration file created by the wizard.
+const HEADER: &str = "# Configuration of sheller, created by the setup wizard.\n";
+
+/// A value written to the configuration file by a choice.
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    /// A string value.
+    String(&'static str),
+    /// An integer value.
+    Integer(i64),
+    /// The key is left out, keeping its default.
+    Default,
+}
+
+/// A question of the wizard, setting a single key of the configuration file.
+struct Step {
+    /// The section of the key.
+    section: &'static str,
+    /// The key.
+    key: &'static str,
+    /// The message key of the question.
+    question: &'static str,
+    /// The choices, as the message key of their label (or the label itself, for names that are
+    /// not translated) and the value they set.
+    choices: Vec<(&'static str, Value)>,
+}
+
+/// The questions of the wizard.
+fn steps() -> Vec<Step> {
+    vec![
+        Step {
+            section: "ui",
+            key: "theme",
+            question: "setup-theme",
+            choices: theme::BUILTIN
+                .iter()
+                .map(|name| (*name, Value::String(name)))
+                .collect(),
+        },
+        Step {
+            section: "ui",
+            key: "background",
+            question: "setup-background",
+            choices: vec![
+                ("setup-auto", Value::Default),
+                ("setup-light", Value::String("light")),
+                ("setup-dark", Value::String("dark")),
+            ],
+        },
+        Step {
+            section: "ui",
+            key: "locale",
+            question: "setup-locale",
+            choices: vec![
+                ("setup-auto", Value::Default),
+                ("English", Value::String("en")),
+                ("Deutsch", Value::String("de")),
+                ("Español", Value::String("es")),
+                ("Français", Value::String("fr")),
+            ],
+        },
+        Step {
+            section: "history",
+            key: "limit",
+            question: "setup-history",
+            choices: vec![
+                ("setup-unlimited", Value::Default),
+                ("1000", Value::Integer(1000)),
+                ("10000", Value::Integer(10000)),
+            ],
+        },
+    ]
+}
+
+/// What the wizard does after a key press.
+pub(crate) enum Outcome {
+    /// The wizard goes on.
+    Continue,
+    /// The wizard is done (or skipped), and the configuration file was written.
+    Done,
+}
+
+///
+/// [`Wizard`] is the state of the setup wizard.
+///
+pub(crate) struct Wizard {
+    /// The questions.
+    steps: Vec<Step>,
+    /// The index of the current question.
+    step: usize,
+    /// The index of the selected choice of every question.
+    selected: Vec<usize>,
+}
+
+impl Wizard {
+    /// Start the wizard, with the choices matching the configuration preselected.
+    pub(crate) fn new(config: &Config) -> Self {
+        let steps = steps();
+        let selected = steps
+            .iter()
+            .map(|step| match (step.section, step.key) {
+                ("ui", "theme") => step
+                    .choices
+                    .iter()
+                    .position(|(name, _)| *name == config.ui.theme)
+                    .unwrap_or_default(),
+                _ => 0,
+            })
+            .collect();
+
+        Self {
+            steps,
+            step: 0,
+            selected,
+        }
+    }
+
+    /// Check if the setup wizard should be shown, which is when there is no configuration file.
+    pub(crate) fn required() -> bool {
+        Config::path().is_some_and(|path| !path.exists())
+    }
+
+    /// The theme selected in the wizard, to preview it.
+    pub(crate) fn theme(&self) -> &'static str {
+        match self.value("ui", "theme") {
+            Value::String(name) => name,
+            _ => "default",
+        }
+    }
+
+    /// The value selected for a key.
+    fn value(&self, section: &str, key: &str) -> Value {
+        self.steps
+            .iter()
+            .zip(&self.selected)
+            .find(|(step, _)| step.section == section && step.key == key)
+            .and_then(|(step, selected)| step.choices.get(*selected))
+            .map_or(Value::Default, |(_, value)| *value)
+    }
+
+    /// Handle a key press.
+    pub(crate) fn input(&mut self, key: KeyEvent, config: &mut Config) -> anyhow::Result<Outcome> {
+        let count = self.steps[self.step].choices.len();
+        let selected = &mut self.selected[self.step];
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                *selected = selected.checked_sub(1).unwrap_or(count - 1)
+            }
+            KeyCode::Down | KeyCode::Char('j') | KeyCode::Tab => {
+                *selected = (*selected + 1) % count
+            }
+            KeyCode::Left | KeyCode::Backspace => self.step = self.step.saturating_sub(1),
+            KeyCode::Enter if self.step + 1 < self.steps.len() => self.step += 1,
+            KeyCode::Enter => {
+                self.write(config)?;
+                return Ok(Outcome::Done);
+            }
+            KeyCode::Esc => {
+                create()?;
+                return Ok(Outcome::Done);
+            }
+            _ => {}
+        }
+
+        Ok(Outcome::Continue)
+    }
+
+    /// Write the answers to the configuration file, and apply them to the configuration.
+    fn write(&self, config: &mut Config) -> anyhow::Result<()> {
+        for (step, selected) in self.steps.iter().zip(&self.selected) {
+            let value = match step.choices.get(*selected).map(|(_, value)| *value) {
+                Some(Value::String(value)) => toml_edit::Value::from(value),
+                Some(Value::Integer(value)) => toml_edit::Value::from(value),
+                Some(Value::Default) | None => continue,
+            };
+            config::store(step.section, step.key, value)?;
+        }
+        let path = create()?;
+
+        // Only the keys asked about are taken from the file, keeping the settings given on the
+        // command line.
+        let written = Config::load_from(&path)?;
+        config.ui.theme = written.ui.theme;
+        config.ui.background = written.ui.background;
+        config.ui.locale = written.ui.locale;
+        config.history.limit = written.history.limit;
+
+        Ok(())
+    }
+
+    /// Render the current question.
+    pub(crate) fn render(&self, frame: &mut Frame, theme: &Theme, messages: &Catalog) {
+        let step = &self.steps[self.step];
+
+        let mut lines = vec![
+            Line::styled(messages.get("setup-intro"), theme.hint),
+            Line::default(),
+            Line::styled(messages.get(step.question), theme.command),
+            Line::default(),
+        ];
+        lines.extend(step.choices.iter().enumerate().map(|(index, (label, _))| {
+            match index == self.selected[self.step] {
+                true => Line::styled(format!(" {} ", messages.get(label)), theme.selection),
+                false => Line::raw(format!(" {} ", messages.get(label))),
+            }
+        }));
+        lines.push(Line::default());
+        lines.push(Line::styled(messages.get("setup-keys"), theme.hint));
+
+        let title = messages.format(
+            "setup-title",
+            &[("step", &(self.step + 1)), ("steps", &self.steps.len())],
+        );
+        let width = lines
+            .iter()
+            .map(Line::width)
+            .chain([title.chars().count() + 2])
+            .max()
+            .unwrap_or_default() as u16;
+        let area = centered(frame.area(), width + 4, lines.len() as u16 + 2);
+        let block = Block::bordered()
+            .title(Span::styled(format!(" {} ", title), theme.title))
+            .border_style(theme.border);
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+}
+
+/// Put the header on top of the configuration file, creating the file if the wizard did not
+/// write any setting to it.
+fn create() -> anyhow::Result<PathBuf> {
+    let path = Config::path()
+        .ok_or_else(|| anyhow::anyhow!("unable to determine the configuration path"))?;
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => format!("{}\n{}", HEADER, source),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => HEADER.to_string(),
+        Err(e) => return Err(e.into()),
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, source)?;
+
+    Ok(path)
+}
@@ -0,0 +1,172 @@
+//! The states of the shell, and the transitions between them.
+//!
+//! The shell is either idle, with a command being typed at the prompt, or running a command that
+//! reads its input. What moves it from one state to the other is an [`Event`]: a line put at the
+//! prompt, a command started, or a command done. [`State::next`] tells where an event leads from
+//! a state as a [`Transition`], without side effects, and the renderer applies it. Events that
+//! make no sense in a state (recalling a command while another one runs) leave it as it is.
+//!
+//! The editing of the line itself is not a transition: both states hold a [`LineBuffer`], reached
+//! through [`State::line_mut`] whatever the state.
+
+use crate::command::Prepare;
+use crate::editor::LineBuffer;
+
+///
+/// [`State`] is what the shell is doing.
+///
+#[derive(Debug)]
+pub(crate) enum State {
+    /// The shell is idle. This is the default state of the shell.
+    /// This is when the user is typing the command. This state holds the incomplete command along
+    /// with the cursor, and the completions.
+    Idle(LineBuffer, Option<Vec<String>>),
+    /// The shell is running. This is when the command is being executed. This state holds the
+    /// contextual information about the command, the lines of stdin supplied to it so far, and
+    /// the line of stdin being typed.
+    Running(Prepare, Vec<String>, LineBuffer),
+}
+
+///
+/// [`Event`] is something that happened to the shell, which may change its state.
+///
+#[derive(Debug)]
+pub(crate) enum Event {
+    /// A line is put at the prompt: recalled, picked, corrected, or typed by a client.
+    Edit(LineBuffer),
+    /// A command is started, reading its input if it asks for it.
+    Run(Prepare),
+    /// The command is done, or dropped: back to an empty prompt.
+    Done,
+}
+
+///
+/// [`Transition`] is where an [`Event`] leads the shell.
+///
+#[derive(Debug)]
+pub(crate) enum Transition {
+    /// The shell stays in its state.
+    Stay,
+    /// The shell moves to another state.
+    To(State),
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State::Idle(LineBuffer::new(), None)
+    }
+}
+
+impl State {
+    /// Where the event leads from the state.
+    pub(crate) fn next(&self, event: Event) -> Transition {
+        match (self, event) {
+            (State::Idle(..), Event::Edit(line)) => Transition::To(State::Idle(line, None)),
+            (State::Idle(..), Event::Run(prepare)) => {
+                Transition::To(State::Running(prepare, Vec::new(), LineBuffer::new()))
+            }
+            (State::Running(..), Event::Edit(_) | Event::Run(_)) => Transition::Stay,
+            (_, Event::Done) => Transition::To(State::default()),
+        }
+    }
+
+    /// The line being typed: the command at the prompt, or the input of the running command.
+    pub(crate) fn line(&self) -> &LineBuffer {
+        match self {
+            State::Idle(line, _) | State::Running(_, _, line) => line,
+        }
+    }
+
+    /// The line being typed, to edit it. (See [`State::line`])
+    pub(crate) fn line_mut(&mut self) -> &mut LineBuffer {
+        match self {
+            State::Idle(line, _) | State::Running(_, _, line) => line,
+        }
+    }
+
+    /// The command at the prompt, if the shell is idle.
+    pub(crate) fn prompt_line(&self) -> Option<&LineBuffer> {
+        match self {
+            State::Idle(line, _) => Some(line),
+            State::Running(..) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The command `cat`, reading its input.
+    fn cat() -> Prepare {
+        Prepare {
+            command: "cat".to_string(),
+            stdin_required: true,
+        }
+    }
+
+    /// Where the event leads from the state, if it moves.
+    fn moved(state: &State, event: Event) -> Option<State> {
+        match state.next(event) {
+            Transition::Stay => None,
+            Transition::To(state) => Some(state),
+        }
+    }
+
+    #[test]
+    fn idle_takes_the_line_put_at_the_prompt_without_its_completions() {
+        let idle = State::Idle(LineBuffer::from("ls"), Some(vec!["ls -l".to_string()]));
+        match moved(&idle, Event::Edit(LineBuffer::from("git status"))) {
+            Some(State::Idle(line, None)) => assert_eq!(line.as_str(), "git status"),
+            state => panic!("expected an idle prompt, got {:?}", state),
+        }
+    }
+
+    #[test]
+    fn idle_runs_a_command_with_no_input_yet() {
+        match moved(
+            &State::Idle(LineBuffer::from("cat"), None),
+            Event::Run(cat()),
+        ) {
+            Some(State::Running(prepare, stdin, line)) => {
+                assert_eq!(prepare.command, "cat");
+                assert!(stdin.is_empty());
+                assert!(line.is_empty());
+            }
+            state => panic!("expected a running command, got {:?}", state),
+        }
+    }
+
+    #[test]
+    fn running_ignores_lines_and_other_commands() {
+        let running = State::Running(cat(), vec!["a".to_string()], LineBuffer::from("b"));
+        assert!(moved(&running, Event::Edit(LineBuffer::from("ls"))).is_none());
+        assert!(moved(&running, Event::Run(cat())).is_none());
+    }
+
+    #[test]
+    fn done_goes_back_to_an_empty_prompt_from_either_state() {
+        let states = [
+            State::Idle(LineBuffer::from("ls"), Some(vec!["ls -l".to_string()])),
+            State::Running(cat(), vec!["a".to_string()], LineBuffer::from("b")),
+        ];
+        for state in states {
+            match moved(&state, Event::Done) {
+                Some(State::Idle(line, None)) => assert!(line.is_empty()),
+                state => panic!("expected an empty prompt, got {:?}", state),
+            }
+        }
+    }
+
+    #[test]
+    fn the_line_is_the_prompt_only_while_idle() {
+        let idle = State::Idle(LineBuffer::from("ls"), None);
+        assert_eq!(idle.line().as_str(), "ls");
+        assert_eq!(idle.prompt_line().map(LineBuffer::as_str), Some("ls"));
+
+        let mut running = State::Running(cat(), Vec::new(), LineBuffer::from("input"));
+        running.line_mut().insert('!');
+        assert_eq!(running.line().as_str(), "input!");
+        assert!(running.prompt_line().is_none());
+    }
+}
@@ -0,0 +1,65 @@
+//! Command substitution: `$(COMMAND)` in a command stands for the output of `COMMAND`.
+//!
+//! Before a command runs, every `$(COMMAND)` in it is run first, through the same executor and
+//! builtins, and replaced with its output, its lines joined with spaces. The output is quoted in
+//! the syntax of the executor (see [`crate::syntax`]), so that it only ever stands for words: each
+//! word of it on its own, or escaped within the quotes the substitution is in. `$(cat f.txt)`
+//! holding `x; echo done` is the words `x;`, `echo` and `done`, never a second command.
+//!
+//! Substitutions nest: the ones in `COMMAND` are run before it. If one of them fails, exiting with
+//! a code other than `0` (or writing to its error output, when the executor cannot tell its exit
+//! code), the command is not run, and the error is shown instead.
+//!
+//! The commands substituted are not added to the history, unless `history.record_substitutions`
+//! is turned on. (See [`crate::config::History`]) Substitutions within single quotes, or escaped
+//! with `\`, are left as they are, as are arithmetic expansions, `$((...))`.
+
+/// The first substitution of a command starting at `from` or after, as the indices of its `$` and
+/// of its closing parenthesis. Unterminated substitutions are left as they are.
+pub(crate) fn find(command: &str, from: usize) -> Option<(usize, usize)> {
+    let bytes = command.as_bytes();
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, &byte) in bytes.iter().enumerate() {
+        match (quote, byte) {
+            _ if escaped => escaped = false,
+            (Some(b'\''), b'\'') | (Some(b'"'), b'"') => quote = None,
+            (Some(b'\''), _) => {}
+            (_, b'\\') => escaped = true,
+            (None, b'\'' | b'"') => quote = Some(byte),
+            (_, b'$')
+                if index >= from
+                    && bytes.get(index + 1) == Some(&b'(')
+                    && bytes.get(index + 2) != Some(&b'(') =>
+            {
+                if let Some(close) = closing(command, index + 2) {
+                    return Some((index, close));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The index of the parenthesis closing the one opened before `start`.
+fn closing(command: &str, start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, byte) in command.bytes().enumerate().skip(start) {
+        match (quote, byte) {
+            _ if escaped => escaped = false,
+            (Some(b'\''), b'\'') | (Some(b'"'), b'"') => quote = None,
+            (Some(b'\''), _) => {}
+            (_, b'\\') => escaped = true,
+            (Some(_), _) => {}
+            (None, b'\'' | b'"') => quote = Some(byte),
+            (None, b'(') => depth += 1,
+            (None, b')') if depth == 0 => return Some(index),
+            (None, b')') => depth -= 1,
+            (None, _) => {}
+        }
+    }
+    None
+}
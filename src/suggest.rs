@@ -0,0 +1,273 @@
+//! Commands written from a description, by a language model.
+//!
+//! Once a backend is set up in the `[suggest]` section of the configuration file (see
+//! [`crate::config::Suggest`]), a line starting with `#` describes a command rather than being
+//! one: `Enter` sends the description to the backend (`F5` does so for any line), and the command
+//! it proposes is shown for confirmation. Confirming puts the command on the prompt, to be
+//! reviewed and run like any other. A proposed command is never run by itself.
+//!
+//! Backends can also explain commands, in place of the built-in explanation from the help of the
+//! program. (See the `explain-command` action of [`crate::keymap`])
+//!
+//! Two backends are built in: [`Program`] runs a local program, such as `ollama run llama3.2`,
+//! and [`Http`] calls an OpenAI-compatible chat completions API. Other backends implement
+//! [`Backend`], and are plugged in with [`crate::renderer::App::with_suggester`].
+//!
+//! # Example
+//!
+//! ```rust
+//! use shelgon::suggest::{Backend, Request};
+//!
+//! struct Canned;
+//!
+//! impl Backend for Canned {
+//!     fn suggest(&self, request: &Request) -> anyhow::Result<String> {
+//!         match request.description.as_str() {
+//!             "list the files" => Ok("ls -la".to_string()),
+//!             description => anyhow::bail!("no idea how to {}", description),
+//!         }
+//!     }
+//! }
+//!
+//! let request = Request {
+//!     description: "list the files".to_string(),
+//!     cwd: None,
+//!     history: Vec::new(),
+//! };
+//! assert_eq!(Canned.suggest(&request).unwrap(), "ls -la");
+//! ```
+
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+use crate::config::{Suggest, SuggestBackend};
+
+/// What the model is told before the request.
+const INSTRUCTIONS: &str = "You write shell commands. Reply with a single command doing what the \
+user describes, on one line, without explanations or formatting.";
+
+/// What the model is told before a command to explain.
+const EXPLAIN: &str = "You explain shell commands. Explain what the command does, then each of \
+its parts on its own line, as `part: meaning`. Use plain text, without formatting.";
+
+/// The number of previous commands sent along with the description, as context.
+pub(crate) const RECENT: usize = 10;
+
+/// How long the `http` backend waits for an answer.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+///
+/// [`Request`] is a description of a command, along with what the model needs to know to write
+/// it.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    /// What the command should do, in the words of the user.
+    pub description: String,
+    /// The working directory the command will run in.
+    pub cwd: Option<PathBuf>,
+    /// The last commands run, oldest first.
+    pub history: Vec<String>,
+}
+
+impl Request {
+    /// The request, as a message for the model.
+    pub fn message(&self) -> String {
+        let mut message = String::new();
+        if let Some(cwd) = &self.cwd {
+            message.push_str(&format!("Working directory: {}\n", cwd.display()));
+        }
+        if !self.history.is_empty() {
+            message.push_str("Previous commands:\n");
+            for command in &self.history {
+                message.push_str(&format!("  {}\n", command));
+            }
+        }
+        message.push_str(&format!("Command to write: {}\n", self.description));
+
+        message
+    }
+}
+
+///
+/// [`Backend`] writes a command from its description.
+///
+pub trait Backend {
+    /// Write a command doing what the request describes. The reply can be wrapped in a code
+    /// block, which is removed before the command is proposed.
+    fn suggest(&self, request: &Request) -> anyhow::Result<String>;
+
+    /// Explain what a command does, as text. Backends that cannot explain commands return `None`,
+    /// and the explanation is built from the help of the program instead.
+    fn explain(&self, _command: &str) -> Option<anyhow::Result<String>> {
+        None
+    }
+}
+
+///
+/// [`Program`] runs a local program for every request, passing the instructions and the request
+/// on its input, and reading the command on its output.
+///
+#[derive(Debug, Clone)]
+pub struct Program {
+    /// The program.
+    program: String,
+    /// The arguments of the program.
+    args: Vec<String>,
+}
+
+impl Program {
+    /// Create the backend from a command line, such as `ollama run llama3.2`. The arguments are
+    /// separated by whitespace.
+    pub fn new(command: &str) -> anyhow::Result<Self> {
+        let mut words = command.split_whitespace().map(str::to_string);
+        let program = words
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("the program of the `program` backend is empty"))?;
+
+        Ok(Self {
+            program,
+            args: words.collect(),
+        })
+    }
+}
+
+impl Program {
+    /// Run the program on a message, preceded by the instructions.
+    fn ask(&self, instructions: &str, message: &str) -> anyhow::Result<String> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("unable to run `{}`: {}", self.program, e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            write!(stdin, "{}\n\n{}", instructions, message)?;
+        }
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("`{}` failed: {}", self.program, stderr.trim());
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl Backend for Program {
+    fn suggest(&self, request: &Request) -> anyhow::Result<String> {
+        self.ask(INSTRUCTIONS, &request.message())
+    }
+
+    fn explain(&self, command: &str) -> Option<anyhow::Result<String>> {
+        Some(self.ask(EXPLAIN, command))
+    }
+}
+
+///
+/// [`Http`] calls an OpenAI-compatible chat completions API. (It is not [`Debug`], which would
+/// show the API key)
+///
+#[derive(Clone)]
+pub struct Http {
+    /// The client making the calls.
+    agent: ureq::Agent,
+    /// The chat completions endpoint.
+    url: String,
+    /// The model.
+    model: String,
+    /// The API key, sent as a bearer token.
+    api_key: Option<String>,
+}
+
+impl Http {
+    /// Create the backend.
+    pub fn new(url: impl Into<String>, model: impl Into<String>, api_key: Option<String>) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(TIMEOUT))
+            .build()
+            .into();
+
+        Self {
+            agent,
+            url: url.into(),
+            model: model.into(),
+            api_key,
+        }
+    }
+}
+
+impl Http {
+    /// Send a message to the model, preceded by the instructions, and return its answer.
+    fn ask(&self, instructions: &str, message: &str) -> anyhow::Result<String> {
+        let body = json!({
+            "model": self.model,
+            "messages": [
+                { "role": "system", "content": instructions },
+                { "role": "user", "content": message },
+            ],
+            "temperature": 0,
+        });
+
+        let mut call = self.agent.post(&self.url);
+        if let Some(api_key) = &self.api_key {
+            call = call.header("Authorization", format!("Bearer {}", api_key));
+        }
+        let reply = call.send_json(body)?.body_mut().read_json::<Value>()?;
+
+        reply["choices"][0]["message"]["content"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("unexpected answer from {}", self.url))
+    }
+}
+
+impl Backend for Http {
+    fn suggest(&self, request: &Request) -> anyhow::Result<String> {
+        self.ask(INSTRUCTIONS, &request.message())
+    }
+
+    fn explain(&self, command: &str) -> Option<anyhow::Result<String>> {
+        Some(self.ask(EXPLAIN, command))
+    }
+}
+
+/// Create the backend set up in the configuration, if any.
+pub(crate) fn from_config(config: &Suggest) -> anyhow::Result<Option<Box<dyn Backend>>> {
+    let backend: Box<dyn Backend> = match config.backend {
+        None => return Ok(None),
+        Some(SuggestBackend::Program) => Box::new(Program::new(&config.program)?),
+        Some(SuggestBackend::Http) => {
+            let api_key = match &config.api_key_env {
+                Some(name) => Some(
+                    std::env::var(name)
+                        .map_err(|_| anyhow::anyhow!("the API key `{}` is not set", name))?,
+                ),
+                None => None,
+            };
+            Box::new(Http::new(&config.url, &config.model, api_key))
+        }
+    };
+
+    Ok(Some(backend))
+}
+
+/// The command in the reply of a backend: its first line, once the code block markers and the
+/// prompt are removed.
+pub(crate) fn command(reply: &str) -> Option<String> {
+    reply
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with("```"))
+        .map(|line| line.strip_prefix("$ ").unwrap_or(line).trim_matches('`'))
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+}
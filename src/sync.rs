@@ -0,0 +1,739 @@
+//! Syncing the command history across machines.
+//!
+//! With the `sync` feature, the journal (see [`crate::history`]) is synced through a storage the
+//! user provides, set up as profiles in the `[sync.NAME]` sections of the configuration file (see
+//! [`crate::config::SyncProfile`]):
+//!
+//! ```toml
+//! [sync.home]
+//! backend = "webdav"
+//! url = "https://dav.example.com/sheller"
+//! user = "me"
+//! password_env = "DAV_PASSWORD"
+//!
+//! [sync.work]
+//! backend = "s3"
+//! url = "https://s3.eu-west-1.amazonaws.com/my-bucket/sheller"
+//! region = "eu-west-1"
+//!
+//! [sync.server]
+//! backend = "ssh"
+//! url = "me@server.example.com:.sheller-sync"
+//! ```
+//!
+//! `:sync [PROFILE]` in the shell, or `sheller history sync [PROFILE]`, syncs every profile, or
+//! the one named.
+//!
+//! Every machine writes its whole journal to an object of its own (`MACHINE.history`), and reads
+//! the objects of the others, adding the commands it did not have. The journals only ever grow,
+//! and a command is the same on every machine (the same text, run at the same time), so merging
+//! them in any order, any number of times, gives the same history everywhere: syncing never
+//! conflicts, even from machines syncing at once.
+//!
+//! The objects are encrypted with XChaCha20-Poly1305, with a key derived from a passphrase by
+//! Argon2id, so the storage never sees the commands. The passphrase is read from the environment
+//! (`SHELLER_SYNC_PASSPHRASE` unless the profile names another variable) or from a file, and must
+//! be the same on every machine.
+//!
+//! Three backends are built in: [`S3`], [`WebDav`] and [`Ssh`]. Other storages implement
+//! [`Backend`], and are synced with [`sync`].
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::{cell::RefCell, collections::BTreeMap};
+//!
+//! use shelgon::sync::Backend;
+//!
+//! #[derive(Default)]
+//! struct Memory(RefCell<BTreeMap<String, Vec<u8>>>);
+//!
+//! impl Backend for Memory {
+//!     fn list(&self) -> anyhow::Result<Vec<String>> {
+//!         Ok(self.0.borrow().keys().cloned().collect())
+//!     }
+//!
+//!     fn get(&self, name: &str) -> anyhow::Result<Vec<u8>> {
+//!         self.0
+//!             .borrow()
+//!             .get(name)
+//!             .cloned()
+//!             .ok_or_else(|| anyhow::anyhow!("no object `{}`", name))
+//!     }
+//!
+//!     fn put(&self, name: &str, content: &[u8]) -> anyhow::Result<()> {
+//!         self.0.borrow_mut().insert(name.to_string(), content.to_vec());
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let storage = Memory::default();
+//! storage.put("a.history", b"...").unwrap();
+//! assert_eq!(storage.list().unwrap(), ["a.history"]);
+//! ```
+
+use std::{
+    fs,
+    io::Write,
+    process::{Command, Stdio},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::Engine;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, OsRng},
+    AeadCore, KeyInit, XChaCha20Poly1305, XNonce,
+};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::config::{self, SyncBackend, SyncProfile};
+use crate::journal::{Entry, Store};
+
+/// The extension of the objects holding the journal of a machine.
+const EXTENSION: &str = ".history";
+
+/// The file holding the name of this machine, in the data directory.
+const MACHINE: &str = "machine";
+
+/// The first bytes of every object, followed by the salt, the nonce and the encrypted journal.
+const MAGIC: &[u8] = b"sheller-sync-1\n";
+
+/// The length of the salt the key is derived with.
+const SALT: usize = 16;
+
+/// The length of the nonce of XChaCha20-Poly1305.
+const NONCE: usize = 24;
+
+/// How long the `s3` and `webdav` backends wait for a request.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The body of the `PROPFIND` request listing a WebDAV collection.
+const PROPFIND: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<propfind xmlns="DAV:"><prop><resourcetype/></prop></propfind>"#;
+
+///
+/// [`Backend`] is a storage of objects the journal is synced through.
+///
+pub trait Backend {
+    /// The names of the objects stored.
+    fn list(&self) -> anyhow::Result<Vec<String>>;
+
+    /// Read an object.
+    fn get(&self, name: &str) -> anyhow::Result<Vec<u8>>;
+
+    /// Write an object, replacing it if it exists.
+    fn put(&self, name: &str, content: &[u8]) -> anyhow::Result<()>;
+}
+
+///
+/// [`Report`] is the outcome of a sync.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Report {
+    /// The number of commands added to the journal.
+    pub received: usize,
+    /// The number of other machines synced with.
+    pub machines: usize,
+    /// The number of commands in the journal, as written to the storage.
+    pub sent: usize,
+}
+
+/// Sync the journal through a storage, with the passphrase the objects are encrypted with.
+pub fn sync(backend: &dyn Backend, passphrase: &str) -> anyhow::Result<Report> {
+    let store = Store::open()?;
+    let own = format!("{}{}", machine()?, EXTENSION);
+
+    let mut report = Report::default();
+    for name in backend.list()? {
+        if !name.ends_with(EXTENSION) {
+            continue;
+        }
+        let content = backend.get(&name)?;
+        let plain = decrypt(&content, passphrase)
+            .map_err(|e| anyhow::anyhow!("unable to read `{}`: {}", name, e))?;
+        let entries = serde_json::from_slice::<Vec<Entry>>(&plain)?;
+        report.received += store.insert(&entries)?;
+        if name != own {
+            report.machines += 1;
+        }
+    }
+
+    let entries = store.entries()?;
+    backend.put(&own, &encrypt(&serde_json::to_vec(&entries)?, passphrase)?)?;
+    report.sent = entries.len();
+
+    Ok(report)
+}
+
+/// Sync the journal through the storage of a profile of the configuration.
+pub fn sync_profile(profile: &SyncProfile) -> anyhow::Result<Report> {
+    let backend = from_config(profile)?;
+    sync(backend.as_ref(), &passphrase(profile)?)
+}
+
+/// Create the backend of a profile.
+fn from_config(profile: &SyncProfile) -> anyhow::Result<Box<dyn Backend>> {
+    let env = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("`{}` is not set", name))
+    };
+
+    let backend: Box<dyn Backend> = match profile.backend {
+        None => anyhow::bail!("no backend is set up"),
+        Some(SyncBackend::S3) => Box::new(S3::new(
+            &profile.url,
+            &profile.region,
+            env(&profile.access_key_env)?,
+            env(&profile.secret_key_env)?,
+        )?),
+        Some(SyncBackend::WebDav) => {
+            let password = match &profile.password_env {
+                Some(name) => Some(env(name)?),
+                None => None,
+            };
+            Box::new(WebDav::new(&profile.url, profile.user.clone(), password))
+        }
+        Some(SyncBackend::Ssh) => Box::new(Ssh::new(&profile.url)?),
+    };
+
+    Ok(backend)
+}
+
+/// The passphrase of a profile: the content of its file, or else its environment variable.
+fn passphrase(profile: &SyncProfile) -> anyhow::Result<String> {
+    let passphrase = match &profile.passphrase_file {
+        Some(path) => fs::read_to_string(path)?
+            .trim_end_matches(['\r', '\n'])
+            .to_string(),
+        None => std::env::var(&profile.passphrase_env).unwrap_or_default(),
+    };
+    if passphrase.is_empty() {
+        anyhow::bail!(
+            "no passphrase: set `{}`, or `passphrase_file`",
+            profile.passphrase_env
+        );
+    }
+
+    Ok(passphrase)
+}
+
+/// The name of this machine among the ones syncing, picked at random the first time.
+fn machine() -> anyhow::Result<String> {
+    let dir =
+        config::data_dir().ok_or_else(|| anyhow::anyhow!("unable to determine the data path"))?;
+    let path = dir.join(MACHINE);
+    match fs::read_to_string(&path) {
+        Ok(name) if !name.trim().is_empty() => return Ok(name.trim().to_string()),
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let mut bytes = [0u8; 8];
+    OsRng.fill_bytes(&mut bytes);
+    let name = hex(&bytes);
+    fs::create_dir_all(&dir)?;
+    fs::write(&path, &name)?;
+
+    Ok(name)
+}
+
+/// Encrypt a journal with a passphrase.
+fn encrypt(plain: &[u8], passphrase: &str) -> anyhow::Result<Vec<u8>> {
+    let mut salt = [0u8; SALT];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let encrypted = cipher(passphrase, &salt)?
+        .encrypt(&nonce, plain)
+        .map_err(|_| anyhow::anyhow!("unable to encrypt the journal"))?;
+
+    Ok([MAGIC, &salt, &nonce, &encrypted].concat())
+}
+
+/// Decrypt a journal encrypted with [`encrypt`].
+fn decrypt(content: &[u8], passphrase: &str) -> anyhow::Result<Vec<u8>> {
+    let rest = content
+        .strip_prefix(MAGIC)
+        .filter(|rest| rest.len() >= SALT + NONCE)
+        .ok_or_else(|| anyhow::anyhow!("not a journal"))?;
+    let (salt, rest) = rest.split_at(SALT);
+    let (nonce, encrypted) = rest.split_at(NONCE);
+
+    cipher(passphrase, salt)?
+        .decrypt(XNonce::from_slice(nonce), encrypted)
+        .map_err(|_| anyhow::anyhow!("wrong passphrase, or damaged journal"))
+}
+
+/// The cipher keyed by the passphrase, with the salt.
+fn cipher(passphrase: &str, salt: &[u8]) -> anyhow::Result<XChaCha20Poly1305> {
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("unable to derive the key: {}", e))?;
+
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+///
+/// [`S3`] stores the objects in a bucket of Amazon S3, or of a compatible storage (such as MinIO
+/// or Cloudflare R2), under a prefix. (It is not [`Debug`], which would show the secret key)
+///
+pub struct S3 {
+    /// The client making the calls.
+    agent: ureq::Agent,
+    /// The scheme and host of the endpoint, such as `https://s3.eu-west-1.amazonaws.com`.
+    endpoint: String,
+    /// The host of the endpoint, as signed.
+    host: String,
+    /// The bucket.
+    bucket: String,
+    /// The prefix of the objects, empty or ending with `/`.
+    prefix: String,
+    /// The region of the bucket.
+    region: String,
+    /// The access key identifier.
+    access_key: String,
+    /// The secret access key.
+    secret_key: String,
+}
+
+impl S3 {
+    /// Create the backend, for a URL of the form `https://HOST/BUCKET[/PREFIX]`.
+    pub fn new(
+        url: &str,
+        region: &str,
+        access_key: impl Into<String>,
+        secret_key: impl Into<String>,
+    ) -> anyhow::Result<Self> {
+        let invalid = || anyhow::anyhow!("expected `https://HOST/BUCKET[/PREFIX]`, not `{}`", url);
+        let (scheme, rest) = url.split_once("://").ok_or_else(invalid)?;
+        let (host, path) = rest.split_once('/').ok_or_else(invalid)?;
+        let path = path.trim_matches('/');
+        let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+        if host.is_empty() || bucket.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            agent: agent(),
+            endpoint: format!("{}://{}", scheme, host),
+            host: host.to_string(),
+            bucket: bucket.to_string(),
+            prefix: match prefix {
+                "" => String::new(),
+                prefix => format!("{}/", prefix),
+            },
+            region: region.to_string(),
+            access_key: access_key.into(),
+            secret_key: secret_key.into(),
+        })
+    }
+
+    /// Make a request signed with AWS Signature Version 4, returning the body of the response.
+    fn request(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        body: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        let path = format!("/{}/{}", self.bucket, key)
+            .split('/')
+            .map(encode)
+            .collect::<Vec<_>>()
+            .join("/");
+        let mut query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", encode(name), encode(value)))
+            .collect::<Vec<_>>();
+        query.sort();
+        let query = query.join("&");
+
+        let time = timestamp(SystemTime::now());
+        let date = &time[..8];
+        let payload = hex(&Sha256::digest(body));
+        let headers = format!(
+            "host:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n",
+            self.host, payload, time
+        );
+        let signed = "host;x-amz-content-sha256;x-amz-date";
+        let canonical = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, path, query, headers, signed, payload
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            time,
+            scope,
+            hex(&Sha256::digest(canonical.as_bytes()))
+        );
+        let mut key = format!("AWS4{}", self.secret_key).into_bytes();
+        for part in [date, &self.region, "s3", "aws4_request"] {
+            key = hmac(&key, part.as_bytes())?;
+        }
+        let signature = hex(&hmac(&key, to_sign.as_bytes())?);
+
+        let url = match query.as_str() {
+            "" => format!("{}{}", self.endpoint, path),
+            query => format!("{}{}?{}", self.endpoint, path, query),
+        };
+        let request = ureq::http::Request::builder()
+            .method(method)
+            .uri(url)
+            .header("x-amz-content-sha256", &payload)
+            .header("x-amz-date", &time)
+            .header(
+                "Authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    self.access_key, scope, signed, signature
+                ),
+            )
+            .body(body.to_vec())?;
+
+        read(self.agent.run(request)?)
+    }
+}
+
+impl Backend for S3 {
+    fn list(&self) -> anyhow::Result<Vec<String>> {
+        let mut names = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", self.prefix.as_str())];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.as_str()));
+            }
+            let listing = String::from_utf8(self.request("GET", "", &query, &[])?)?;
+            names.extend(
+                elements(&listing, "Key")
+                    .into_iter()
+                    .filter_map(|key| Some(key.strip_prefix(&self.prefix)?.to_string()))
+                    .filter(|name| !name.contains('/')),
+            );
+
+            token = elements(&listing, "NextContinuationToken")
+                .into_iter()
+                .next();
+            if token.is_none() {
+                break;
+            }
+        }
+
+        Ok(names)
+    }
+
+    fn get(&self, name: &str) -> anyhow::Result<Vec<u8>> {
+        self.request("GET", &format!("{}{}", self.prefix, name), &[], &[])
+    }
+
+    fn put(&self, name: &str, content: &[u8]) -> anyhow::Result<()> {
+        self.request("PUT", &format!("{}{}", self.prefix, name), &[], content)?;
+        Ok(())
+    }
+}
+
+///
+/// [`WebDav`] stores the objects in a WebDAV collection, such as a folder of Nextcloud, created if
+/// needed. (It is not [`Debug`], which would show the password)
+///
+pub struct WebDav {
+    /// The client making the calls.
+    agent: ureq::Agent,
+    /// The URL of the collection, ending with `/`.
+    url: String,
+    /// The value of the `Authorization` header, with basic authentication.
+    authorization: Option<String>,
+}
+
+impl WebDav {
+    /// Create the backend, for the URL of a collection.
+    pub fn new(url: &str, user: Option<String>, password: Option<String>) -> Self {
+        let authorization = match (user, password) {
+            (None, None) => None,
+            (user, password) => Some(format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(format!(
+                    "{}:{}",
+                    user.unwrap_or_default(),
+                    password.unwrap_or_default()
+                ))
+            )),
+        };
+
+        Self {
+            agent: agent(),
+            url: format!("{}/", url.trim_end_matches('/')),
+            authorization,
+        }
+    }
+
+    /// Make a request, returning the body of the response.
+    fn request(&self, method: &str, url: &str, body: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut request = ureq::http::Request::builder().method(method).uri(url);
+        if let Some(authorization) = &self.authorization {
+            request = request.header("Authorization", authorization);
+        }
+        if method == "PROPFIND" {
+            request = request
+                .header("Depth", "1")
+                .header("Content-Type", "application/xml");
+        }
+
+        read(self.agent.run(request.body(body.to_vec())?)?)
+    }
+}
+
+impl Backend for WebDav {
+    fn list(&self) -> anyhow::Result<Vec<String>> {
+        let listing = match self.request("PROPFIND", &self.url, PROPFIND.as_bytes()) {
+            Ok(listing) => String::from_utf8(listing)?,
+            Err(e) if matches!(e.downcast_ref(), Some(ureq::Error::StatusCode(404))) => {
+                self.request("MKCOL", &self.url, &[])?;
+                return Ok(Vec::new());
+            }
+            Err(e) => return Err(e),
+        };
+
+        // The collection itself is listed too, as a name ending with `/`.
+        Ok(elements(&listing, "href")
+            .iter()
+            .filter_map(|href| href.rsplit('/').next())
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn get(&self, name: &str) -> anyhow::Result<Vec<u8>> {
+        self.request("GET", &format!("{}{}", self.url, encode(name)), &[])
+    }
+
+    fn put(&self, name: &str, content: &[u8]) -> anyhow::Result<()> {
+        self.request("PUT", &format!("{}{}", self.url, encode(name)), content)?;
+        Ok(())
+    }
+}
+
+///
+/// [`Ssh`] stores the objects in a directory of another machine, reached with `ssh`, created if
+/// needed. The machine must be reachable without typing a password, such as with a key loaded in
+/// `ssh-agent`.
+///
+#[derive(Debug, Clone)]
+pub struct Ssh {
+    /// The machine, as `[USER@]HOST`.
+    destination: String,
+    /// The directory, quoted for the shell of the machine.
+    directory: String,
+}
+
+impl Ssh {
+    /// Create the backend, for a location of the form `[USER@]HOST:DIR`, with `DIR` relative to
+    /// the home directory unless it is absolute.
+    pub fn new(location: &str) -> anyhow::Result<Self> {
+        let (destination, directory) = location
+            .split_once(':')
+            .filter(|(destination, directory)| !destination.is_empty() && !directory.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("expected `[USER@]HOST:DIR`, not `{}`", location))?;
+        let directory = directory.strip_prefix("~/").unwrap_or(directory);
+
+        Ok(Self {
+            destination: destination.to_string(),
+            directory: quote(directory),
+        })
+    }
+
+    /// Run a command on the machine, with an input, returning its output.
+    fn run(&self, command: &str, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut child = Command::new("ssh")
+            .args(["-o", "BatchMode=yes", "--", &self.destination, command])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(input)?;
+        }
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "ssh {}: {}",
+                self.destination,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output.stdout)
+    }
+}
+
+impl Backend for Ssh {
+    fn list(&self) -> anyhow::Result<Vec<String>> {
+        let command = format!("mkdir -p {dir} && ls -1 {dir}", dir = self.directory);
+        Ok(String::from_utf8(self.run(&command, &[])?)?
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn get(&self, name: &str) -> anyhow::Result<Vec<u8>> {
+        self.run(&format!("cat {}/{}", self.directory, quote(name)), &[])
+    }
+
+    fn put(&self, name: &str, content: &[u8]) -> anyhow::Result<()> {
+        // Written aside, then renamed, so other machines never read a partial object.
+        let path = format!("{}/{}", self.directory, quote(name));
+        self.run(
+            &format!("cat > {path}.new && mv {path}.new {path}", path = path),
+            content,
+        )?;
+        Ok(())
+    }
+}
+
+/// The client of the `s3` and `webdav` backends.
+fn agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .allow_non_standard_methods(true)
+        .build()
+        .into()
+}
+
+/// Read the whole body of a response.
+fn read(mut response: ureq::http::Response<ureq::Body>) -> anyhow::Result<Vec<u8>> {
+    Ok(response
+        .body_mut()
+        .with_config()
+        .limit(u64::MAX)
+        .read_to_vec()?)
+}
+
+/// The text of the XML elements of a name, whatever their namespace prefix.
+fn elements(xml: &str, name: &str) -> Vec<String> {
+    let mut texts = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+        let local = tag.rsplit(':').next().unwrap_or(tag);
+        if local != name {
+            continue;
+        }
+        if let Some(close) = rest.find("</") {
+            texts.push(
+                rest[..close]
+                    .replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&quot;", "\"")
+                    .replace("&apos;", "'")
+                    .replace("&amp;", "&"),
+            );
+        }
+    }
+
+    texts
+}
+
+/// Percent-encode everything but the unreserved characters of URIs.
+fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Quote a word for a POSIX shell.
+fn quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// Bytes as lowercase hexadecimal digits.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The HMAC-SHA256 of data.
+fn hmac(key: &[u8], data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key)
+        .map_err(|_| anyhow::anyhow!("invalid signing key"))?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// A time as `YYYYMMDDTHHMMSSZ`, in UTC.
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let (days, seconds) = (seconds / 86_400, seconds % 86_400);
+
+    // The civil date of a day since the epoch. (Howard Hinnant's `civil_from_days`)
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted + 2) / 5 + 1;
+    let month = if shifted < 10 {
+        shifted + 3
+    } else {
+        shifted - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3_600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn journal_is_decrypted_as_it_was_encrypted() -> anyhow::Result<()> {
+        let plain = br#"[{"command":"ls","time":1700000000}]"#;
+        let encrypted = encrypt(plain, "correct horse")?;
+        assert!(encrypted.starts_with(MAGIC));
+        assert!(!encrypted.windows(plain.len()).any(|window| window == plain));
+
+        assert_eq!(decrypt(&encrypted, "correct horse")?, plain);
+        // Each encryption takes a salt and a nonce of its own.
+        assert_ne!(encrypt(plain, "correct horse")?, encrypted);
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_passphrase_is_refused() -> anyhow::Result<()> {
+        let encrypted = encrypt(b"[]", "correct horse")?;
+        let error = match decrypt(&encrypted, "battery staple") {
+            Ok(_) => panic!("decrypted with the wrong passphrase"),
+            Err(error) => error,
+        };
+        assert_eq!(error.to_string(), "wrong passphrase, or damaged journal");
+        assert!(decrypt(b"not encrypted", "correct horse").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,446 @@
+//! The syntax of the commands of an executor: how words are quoted, where comments start, and
+//! which expansions the shell applies before running them.
+//!
+//! The shell reads the line being typed to complete the word under the cursor, quote what it
+//! inserts, dim comments and expand `~`, `$(COMMAND)` and `$((EXPRESSION))`. Those rules are the
+//! ones of the language the executor runs, as told by [`crate::command::Execute::syntax`]:
+//!
+//! - [`Posix`], the default, quotes with `'` and `"`, escapes with `\`, starts comments with `#`
+//!   and applies every expansion.
+//! - [`Sh`] is the syntax of [`Posix`] for commands run by `sh` itself, which expands `~`,
+//!   `$(COMMAND)` and `$((EXPRESSION))` with its own rules: the shell leaves them to it.
+//! - [`PowerShell`] escapes with `` ` ``, doubles quotes within quotes, and leaves `$(...)` to
+//!   PowerShell, which reads it as a subexpression.
+//! - [`Sql`] doubles quotes within quotes, starts comments with `--` and expands nothing.
+//! - [`Http`] has no quotes: what is inserted is percent-encoded, as in a URL, and nothing is
+//!   expanded.
+//!
+//! Other languages implement [`Syntax`].
+//!
+//! ```rust
+//! use shelgon::syntax::{Posix, PowerShell, Sql, Syntax};
+//!
+//! assert_eq!(Posix.escape("My Files", None), "My\\ Files");
+//! assert_eq!(Posix.escape("it's", Some('\'')), "it'\\''s");
+//! assert_eq!(PowerShell.escape("My Files", None), "My` Files");
+//! assert_eq!(Sql.quote("it's"), "'it''s'");
+//!
+//! let word = Posix.last_word("cat 'My Fi");
+//! assert_eq!((word.start, word.open, word.value.as_str()), (4, Some('\''), "My Fi"));
+//! assert_eq!(Sql.comment_start("select 1 -- one"), Some(9));
+//! assert_eq!(Posix.words("cd 'My Files' My\\ Files ''"), ["cd", "My Files", "My Files", ""]);
+//! ```
+
+///
+/// [`Expansion`] is an expansion the shell applies to a command before the executor runs it, or
+/// completes from.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expansion {
+    /// `~` and `~NAME`, for the home and named directories. (See `crate::tilde`)
+    Tilde,
+    /// `$(COMMAND)`, for the output of the command. (See `crate::substitution`)
+    Substitution,
+    /// `$((EXPRESSION))`, for its value. (See `crate::arithmetic`)
+    Arithmetic,
+    /// `$NAME` and `${NAME}`, completed to the names of the variables of the context.
+    Variables,
+}
+
+///
+/// [`Word`] is the last word of a line, as the syntax reads it.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word {
+    /// The index of the start of the word in the line.
+    pub start: usize,
+    /// The quote left open at the end of the line, if any.
+    pub open: Option<char>,
+    /// The word unquoted.
+    pub value: String,
+}
+
+///
+/// [`Syntax`] is the syntax of the commands of an executor.
+///
+pub trait Syntax {
+    ///
+    /// The characters opening (and closing) quotes.
+    ///
+    fn quotes(&self) -> &'static [char];
+
+    ///
+    /// The character escaping the next one, outside of quotes and within `"`, if any.
+    ///
+    fn escape_char(&self) -> Option<char>;
+
+    ///
+    /// Whether a quote doubled within quotes stands for itself, as `''` in SQL strings.
+    ///
+    fn doubles_quotes(&self) -> bool {
+        false
+    }
+
+    ///
+    /// What starts a comment, running to the end of the line, if anything.
+    ///
+    fn comment(&self) -> Option<&'static str>;
+
+    ///
+    /// Whether the shell applies an expansion to the commands.
+    ///
+    fn expands(&self, expansion: Expansion) -> bool;
+
+    ///
+    /// Escape text inserted in a word, within the quote left open, if any, so that the word
+    /// holds it as it is.
+    ///
+    fn escape(&self, text: &str, open: Option<char>) -> String;
+
+    ///
+    /// Quote text as a word of its own, if it needs to.
+    ///
+    fn quote(&self, text: &str) -> String;
+
+    ///
+    /// The last word of a line. Whitespace within quotes, or escaped, is part of the word.
+    ///
+    fn last_word(&self, line: &str) -> Word {
+        let mut word = Word {
+            start: 0,
+            open: None,
+            value: String::new(),
+        };
+        let escape = self.escape_char();
+        let mut escaped = false;
+        // The quote just closed, standing for itself if opened again at once.
+        let mut closed = None;
+        for (index, c) in line.char_indices() {
+            if escaped {
+                word.value.push(c);
+                escaped = false;
+                continue;
+            }
+            let reopened = closed.take() == Some(c) && self.doubles_quotes();
+            match word.open {
+                Some(open) if c == open => {
+                    word.open = None;
+                    closed = Some(c);
+                }
+                None | Some('"') if Some(c) == escape => escaped = true,
+                Some(_) => word.value.push(c),
+                None if self.quotes().contains(&c) => {
+                    if reopened {
+                        word.value.push(c);
+                    }
+                    word.open = Some(c);
+                }
+                None if c.is_whitespace() => {
+                    word.start = index + c.len_utf8();
+                    word.value.clear();
+                }
+                None => word.value.push(c),
+            }
+        }
+        word
+    }
+
+    ///
+    /// The words of a line, unquoted. Whitespace within quotes, or escaped, is part of a word.
+    ///
+    fn words(&self, line: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut word: Option<String> = None;
+        let escape = self.escape_char();
+        let mut open = None;
+        let mut escaped = false;
+        // The quote just closed, standing for itself if opened again at once.
+        let mut closed = None;
+        for c in line.chars() {
+            if escaped {
+                word.get_or_insert_with(String::new).push(c);
+                escaped = false;
+                continue;
+            }
+            let reopened = closed.take() == Some(c) && self.doubles_quotes();
+            match open {
+                Some(quote) if c == quote => {
+                    open = None;
+                    closed = Some(c);
+                }
+                None | Some('"') if Some(c) == escape => {
+                    word.get_or_insert_with(String::new);
+                    escaped = true;
+                }
+                Some(_) => word.get_or_insert_with(String::new).push(c),
+                None if self.quotes().contains(&c) => {
+                    let word = word.get_or_insert_with(String::new);
+                    if reopened {
+                        word.push(c);
+                    }
+                    open = Some(c);
+                }
+                None if c.is_whitespace() => words.extend(word.take()),
+                None => word.get_or_insert_with(String::new).push(c),
+            }
+        }
+        words.extend(word);
+        words
+    }
+
+    ///
+    /// The index where the comment of a line starts, if any: its marker, outside of quotes, at
+    /// the start of a word.
+    ///
+    fn comment_start(&self, line: &str) -> Option<usize> {
+        let marker = self.comment()?;
+        let escape = self.escape_char();
+        let mut open = None;
+        let mut escaped = false;
+        let mut previous = None;
+        for (index, c) in line.char_indices() {
+            let start = previous.map_or(true, char::is_whitespace);
+            previous = Some(c);
+            if escaped {
+                escaped = false;
+                continue;
+            }
+            match open {
+                Some(quote) if c == quote => open = None,
+                None | Some('"') if Some(c) == escape => escaped = true,
+                Some(_) => {}
+                None if self.quotes().contains(&c) => open = Some(c),
+                None if start && line[index..].starts_with(marker) => return Some(index),
+                None => {}
+            }
+        }
+        None
+    }
+}
+
+///
+/// [`Posix`] is the syntax of POSIX shells, such as `sh` and `bash`.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Posix;
+
+impl Posix {
+    /// Check whether a shell splits words on a character, or treats it as special, outside of
+    /// quotes.
+    fn special(c: char) -> bool {
+        c.is_whitespace() || "'\"\\$`!*?[]{}()<>|&;#".contains(c)
+    }
+}
+
+impl Syntax for Posix {
+    fn quotes(&self) -> &'static [char] {
+        &['\'', '"']
+    }
+
+    fn escape_char(&self) -> Option<char> {
+        Some('\\')
+    }
+
+    fn comment(&self) -> Option<&'static str> {
+        Some("#")
+    }
+
+    fn expands(&self, _expansion: Expansion) -> bool {
+        true
+    }
+
+    fn escape(&self, text: &str, open: Option<char>) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match open {
+                Some('\'') if c == '\'' => escaped.push_str("'\\''"),
+                Some('"') if matches!(c, '"' | '\\' | '$' | '`') => escaped.extend(['\\', c]),
+                None if Self::special(c) => escaped.extend(['\\', c]),
+                _ => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    fn quote(&self, text: &str) -> String {
+        match text.contains(Self::special) {
+            true => format!("'{}'", text.replace('\'', "'\\''")),
+            false => text.to_string(),
+        }
+    }
+}
+
+///
+/// [`Sh`] is the syntax of commands handed to `sh`, as by `crate::command::system`: the one of
+/// [`Posix`], but for the expansions `sh` applies itself, such as `$((a+1))` reading the variable
+/// `a` of `sh`, which the shell does not know.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sh;
+
+impl Syntax for Sh {
+    fn quotes(&self) -> &'static [char] {
+        Posix.quotes()
+    }
+
+    fn escape_char(&self) -> Option<char> {
+        Posix.escape_char()
+    }
+
+    fn comment(&self) -> Option<&'static str> {
+        Posix.comment()
+    }
+
+    fn expands(&self, expansion: Expansion) -> bool {
+        matches!(expansion, Expansion::Variables)
+    }
+
+    fn escape(&self, text: &str, open: Option<char>) -> String {
+        Posix.escape(text, open)
+    }
+
+    fn quote(&self, text: &str) -> String {
+        Posix.quote(text)
+    }
+}
+
+///
+/// [`PowerShell`] is the syntax of PowerShell.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PowerShell;
+
+impl PowerShell {
+    /// Check whether PowerShell splits words on a character, or treats it as special, outside of
+    /// quotes.
+    fn special(c: char) -> bool {
+        c.is_whitespace() || "'\"`$(){}[];,|&<>@#".contains(c)
+    }
+}
+
+impl Syntax for PowerShell {
+    fn quotes(&self) -> &'static [char] {
+        &['\'', '"']
+    }
+
+    fn escape_char(&self) -> Option<char> {
+        Some('`')
+    }
+
+    fn doubles_quotes(&self) -> bool {
+        true
+    }
+
+    fn comment(&self) -> Option<&'static str> {
+        Some("#")
+    }
+
+    fn expands(&self, expansion: Expansion) -> bool {
+        matches!(expansion, Expansion::Tilde | Expansion::Variables)
+    }
+
+    fn escape(&self, text: &str, open: Option<char>) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match open {
+                Some('\'') if c == '\'' => escaped.push_str("''"),
+                Some('"') if matches!(c, '"' | '`' | '$') => escaped.extend(['`', c]),
+                None if Self::special(c) => escaped.extend(['`', c]),
+                _ => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    fn quote(&self, text: &str) -> String {
+        match text.contains(Self::special) {
+            true => format!("'{}'", text.replace('\'', "''")),
+            false => text.to_string(),
+        }
+    }
+}
+
+///
+/// [`Sql`] is the syntax of SQL: strings in `'`, identifiers in `"`.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sql;
+
+impl Syntax for Sql {
+    fn quotes(&self) -> &'static [char] {
+        &['\'', '"']
+    }
+
+    fn escape_char(&self) -> Option<char> {
+        None
+    }
+
+    fn doubles_quotes(&self) -> bool {
+        true
+    }
+
+    fn comment(&self) -> Option<&'static str> {
+        Some("--")
+    }
+
+    fn expands(&self, _expansion: Expansion) -> bool {
+        false
+    }
+
+    // Outside of quotes, nothing escapes: the text is inserted as it is.
+    fn escape(&self, text: &str, open: Option<char>) -> String {
+        match open {
+            Some(quote) => text.replace(quote, &format!("{}{}", quote, quote)),
+            None => text.to_string(),
+        }
+    }
+
+    fn quote(&self, text: &str) -> String {
+        format!("'{}'", text.replace('\'', "''"))
+    }
+}
+
+///
+/// [`Http`] is the syntax of HTTP requests, such as `GET /users?page=2`.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Http;
+
+impl Syntax for Http {
+    fn quotes(&self) -> &'static [char] {
+        &[]
+    }
+
+    fn escape_char(&self) -> Option<char> {
+        None
+    }
+
+    fn comment(&self) -> Option<&'static str> {
+        Some("#")
+    }
+
+    fn expands(&self, _expansion: Expansion) -> bool {
+        false
+    }
+
+    // What a URL cannot hold is percent-encoded.
+    fn escape(&self, text: &str, _open: Option<char>) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c.is_whitespace() || "\"<>`{}|\\^".contains(c) {
+                true => {
+                    let mut bytes = [0; 4];
+                    for byte in c.encode_utf8(&mut bytes).bytes() {
+                        escaped.push_str(&format!("%{:02X}", byte));
+                    }
+                }
+                false => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    fn quote(&self, text: &str) -> String {
+        self.escape(text, None)
+    }
+}
@@ -0,0 +1,218 @@
+//! Detection of terminal capabilities.
+//!
+//! [`TermCaps`] tells whether the shell runs inside tmux, GNU screen, or over ssh, and adapts the
+//! escape sequences of the renderer accordingly.
+//!
+//! The queries talk to the terminal directly, so they must run in raw mode and before the
+//! event reader is started (otherwise the replies are read as key presses).
+
+use crate::theme::{Appearance, ColorSupport};
+
+/// How long to wait for the terminal to reply to a query.
+#[cfg(unix)]
+const QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// How long to wait for the terminal to reply to a query over ssh, where round trips are slower.
+#[cfg(unix)]
+const REMOTE_QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(1000);
+
+/// The OSC sequences tmux handles itself: window titles, working directory reports and the
+/// clipboard. (It forwards the latter to the outer terminal)
+const TMUX_OSC: &[&str] = &["0", "1", "2", "7", "52"];
+
+/// The OSC sequences GNU screen handles itself: window titles.
+const SCREEN_OSC: &[&str] = &["0", "1", "2"];
+
+/// A terminal multiplexer the shell is running in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Multiplexer {
+    /// tmux. (`$TMUX`)
+    Tmux,
+    /// GNU screen. (`$STY`)
+    Screen,
+}
+
+///
+/// [`TermCaps`] describes what sits between the shell and the terminal emulator, which decides
+/// how escape sequences have to be sent.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct TermCaps {
+    /// The terminal multiplexer the shell is running in, if any.
+    pub(crate) multiplexer: Option<Multiplexer>,
+    /// Whether the shell is running over ssh.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    pub(crate) ssh: bool,
+}
+
+impl TermCaps {
+    /// Detect tmux, GNU screen and ssh from the environment.
+    pub(crate) fn detect() -> Self {
+        let set = |name| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+        let term = std::env::var("TERM").unwrap_or_default();
+
+        let multiplexer = if set("TMUX") || term.starts_with("tmux") {
+            Some(Multiplexer::Tmux)
+        } else if set("STY") || term.starts_with("screen") {
+            Some(Multiplexer::Screen)
+        } else {
+            None
+        };
+
+        Self {
+            multiplexer,
+            ssh: set("SSH_CONNECTION") || set("SSH_CLIENT") || set("SSH_TTY"),
+        }
+    }
+
+    /// Prepare an OSC sequence (`ESC ] number ; ...`) for the terminal.
+    ///
+    /// Sequences the multiplexer understands are sent as is, and it acts on them. Others would be
+    /// dropped by the multiplexer, so they are wrapped in a passthrough sequence that reaches the
+    /// outer terminal. (tmux requires `allow-passthrough` for these)
+    pub(crate) fn osc(&self, sequence: &str) -> String {
+        let number = sequence
+            .strip_prefix("\x1b]")
+            .and_then(|rest| rest.split(';').next())
+            .unwrap_or_default();
+
+        match self.multiplexer {
+            Some(Multiplexer::Tmux) if !TMUX_OSC.contains(&number) => {
+                format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+            }
+            Some(Multiplexer::Screen) if !SCREEN_OSC.contains(&number) => {
+                format!("\x1bP{}\x1b\\", sequence)
+            }
+            _ => sequence.to_string(),
+        }
+    }
+
+    /// How long to wait for the terminal to reply to a query.
+    #[cfg(unix)]
+    fn query_timeout(&self) -> std::time::Duration {
+        match self.ssh {
+            true => REMOTE_QUERY_TIMEOUT,
+            false => QUERY_TIMEOUT,
+        }
+    }
+}
+
+/// Detect whether the terminal has a light or a dark background.
+///
+/// The terminal is asked for its background color (OSC 11). Terminals that do not support the
+/// query are detected through the `COLORFGBG` environment variable, if set.
+#[cfg_attr(not(unix), allow(unused_variables))]
+pub(crate) fn background(caps: &TermCaps) -> Option<Appearance> {
+    #[cfg(unix)]
+    if let Some(appearance) = query_background(caps) {
+        return Some(appearance);
+    }
+
+    colorfgbg()
+}
+
+/// Ask the terminal for its background color.
+///
+/// The query is followed by a primary device attributes request (DA1), which every terminal
+/// answers. This tells apart a terminal that ignores OSC 11 from a slow one, without waiting for
+/// the whole timeout.
+#[cfg(unix)]
+fn query_background(caps: &TermCaps) -> Option<Appearance> {
+    use std::io::{Read, Write};
+    use std::os::fd::AsRawFd;
+    use std::time::Instant;
+
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    tty.write_all(b"\x1b]11;?\x07\x1b[c").ok()?;
+    tty.flush().ok()?;
+
+    let deadline = Instant::now() + caps.query_timeout();
+    let mut response = Vec::new();
+    while !answered_device_attributes(&response) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+
+        let mut fd = libc::pollfd {
+            fd: tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `fd` is a valid `pollfd` for the duration of the call, and the count matches.
+        let ready = unsafe { libc::poll(&mut fd, 1, remaining.as_millis() as libc::c_int) };
+        if ready <= 0 {
+            break;
+        }
+
+        let mut buffer = [0; 64];
+        match tty.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => response.extend_from_slice(&buffer[..read]),
+        }
+    }
+
+    parse_background(&String::from_utf8_lossy(&response))
+}
+
+/// Check if the response contains the reply to the device attributes request. (`ESC [ ? ... c`)
+#[cfg(unix)]
+fn answered_device_attributes(response: &[u8]) -> bool {
+    response
+        .windows(3)
+        .position(|window| window == b"\x1b[?")
+        .is_some_and(|start| response[start..].contains(&b'c'))
+}
+
+/// Parse the reply to the OSC 11 query. (`ESC ] 11 ; rgb:RRRR/GGGG/BBBB` followed by `BEL` or
+/// `ESC \`)
+#[cfg(unix)]
+fn parse_background(response: &str) -> Option<Appearance> {
+    let (_, color) = response.split_once("]11;rgb:")?;
+    let color = color.split(['\x07', '\x1b']).next()?;
+
+    let mut channels = color.split('/').map(|channel| {
+        let value = u32::from_str_radix(channel, 16).ok()?;
+        let max = 16u32.checked_pow(channel.len() as u32)?.checked_sub(1)?;
+        u8::try_from(value * 255 / max.max(1)).ok()
+    });
+
+    let (red, green, blue) = (channels.next()??, channels.next()??, channels.next()??);
+    Some(Appearance::from_rgb(red, green, blue))
+}
+
+/// Detect the background from `COLORFGBG` (`fg;bg`, with the ANSI color index of each).
+fn colorfgbg() -> Option<Appearance> {
+    let value = std::env::var("COLORFGBG").ok()?;
+    let background = value.rsplit(';').next()?.parse::<u8>().ok()?;
+
+    match background {
+        7 | 15 => Some(Appearance::Light),
+        _ => Some(Appearance::Dark),
+    }
+}
+
+/// Detect the colors supported by the terminal, from `COLORTERM` and `TERM`. Colors are disabled
+/// altogether if `NO_COLOR` is set (see <https://no-color.org>) or the terminal is `dumb`.
+pub(crate) fn colors() -> ColorSupport {
+    if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        return ColorSupport::NoColor;
+    }
+
+    let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+    let term = std::env::var("TERM").unwrap_or_default();
+
+    if matches!(colorterm.as_str(), "truecolor" | "24bit") || term.ends_with("-direct") {
+        ColorSupport::TrueColor
+    } else if term.contains("256color") {
+        ColorSupport::Ansi256
+    } else if term == "dumb" {
+        ColorSupport::NoColor
+    } else {
+        ColorSupport::Ansi16
+    }
+}
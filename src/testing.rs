@@ -0,0 +1,316 @@
+//! Integration tests of executors against the real shell, without a terminal.
+//!
+//! A [`MockTerminal`] runs an [`App`] on an in-memory screen, feeding it the key presses of a
+//! [`Script`] rather than reading them from a TTY. Once the keys run out (or the shell exits), it
+//! returns a [`Transcript`] of where the shell ended: the commands run, the prompt, the line being
+//! typed and the text on the screen, with assertion helpers for each.
+//!
+//! The shell is kept apart from the files of the user: the user script, the rc file, the journal
+//! and the usage counts are left alone. It speaks English, on a dark background, unless the
+//! configuration says otherwise. The configuration is still loaded from the configuration file,
+//! so tests that must not depend on it pass their own with [`App::with_config`].
+//!
+//! [`Echo`] is an executor to run the shell with, echoing the commands.
+//!
+//! [`synthetic_history`] makes up a history of any length, mixing the kinds of output the shell
+//! renders, to measure how the shell copes with a long one. (See `sheller --stress N`, and the
+//! benches)
+//!
+//! # Example
+//!
+//! ```rust
+//! use shelgon::config::Config;
+//! use shelgon::renderer::App;
+//! use shelgon::testing::{Echo, MockTerminal, Script};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let app = App::new_with_executor(
+//!     # #[cfg(feature = "tokio")]
+//!     tokio::runtime::Runtime::new()?,
+//!     Echo::default(),
+//!     (),
+//! )
+//! .with_config(Config::default());
+//!
+//! let script = Script::new().line("hello").text("wor").key("Left");
+//! let transcript = MockTerminal::new(80, 24).run(app, script)?;
+//!
+//! transcript.assert_history_contains("hello");
+//! transcript.assert_prompt("$");
+//! transcript.assert_screen_contains("hello");
+//! assert_eq!(transcript.line.as_str(), "wor");
+//! assert_eq!(transcript.line.cursor(), 2);
+//! assert_eq!(transcript.exit, None);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+
+use crossterm::event::{Event, KeyCode, KeyEvent};
+use ratatui::{backend::TestBackend, Terminal};
+
+use crate::command::{CommandInput, CommandOutput, Execute, OutputAction, Prepare};
+use crate::editor::LineBuffer;
+use crate::keymap::Key;
+use crate::renderer::App;
+
+///
+/// [`Script`] is the sequence of events fed to the shell, in order: key presses, or any other
+/// terminal event.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    events: VecDeque<Event>,
+}
+
+impl Script {
+    /// Create an empty script.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Type the text, a key press per character.
+    pub fn text(self, text: &str) -> Self {
+        text.chars().fold(self, |script, c| {
+            script.event(Event::Key(KeyEvent::from(KeyCode::Char(c))))
+        })
+    }
+
+    /// Press a key, written as in the `[keys]` section of the configuration, such as `Enter`,
+    /// `Ctrl+R` or `Alt+Left`. (See [`crate::keymap`])
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key cannot be parsed.
+    pub fn key(self, key: &str) -> Self {
+        let key = match key.parse::<Key>() {
+            Ok(key) => key,
+            Err(e) => panic!("invalid key `{}` in the script: {}", key, e),
+        };
+        self.event(Event::Key(KeyEvent::new(key.code, key.modifiers)))
+    }
+
+    /// Type the line, and press `Enter`.
+    pub fn line(self, line: &str) -> Self {
+        self.text(line).key("Enter")
+    }
+
+    /// Feed any other terminal event.
+    pub fn event(mut self, event: Event) -> Self {
+        self.events.push_back(event);
+        self
+    }
+}
+
+impl IntoIterator for Script {
+    type Item = Event;
+    type IntoIter = std::collections::vec_deque::IntoIter<Event>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.events.into_iter()
+    }
+}
+
+///
+/// [`MockTerminal`] is an in-memory terminal of a fixed size, running the shell without a TTY.
+///
+pub struct MockTerminal {
+    terminal: Terminal<TestBackend>,
+}
+
+impl MockTerminal {
+    /// Create a terminal of the given number of columns and rows.
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            //
+            // # Safety: creating a terminal only fails when the backend does, and a `TestBackend`
+            // never does.
+            //
+            #[allow(clippy::expect_used)]
+            terminal: Terminal::new(TestBackend::new(width, height)).expect("test backend failed"),
+        }
+    }
+
+    /// Run the app on the terminal through the script, until it exits or the script runs out.
+    ///
+    /// Errors are the ones the shell itself stops on, such as an executor failing to start.
+    pub fn run<T: Execute>(&mut self, app: App<T>, script: Script) -> anyhow::Result<Transcript> {
+        app.rehearse(&mut self.terminal, script)
+    }
+}
+
+///
+/// [`Transcript`] is where the shell ended once a [`Script`] ran out, or once it exited.
+///
+pub struct Transcript {
+    /// The message the shell exited with, or `None` if it was still running.
+    pub exit: Option<String>,
+    /// The commands run, with their output, oldest first.
+    pub history: Vec<CommandOutput>,
+    /// The prompt shown last.
+    pub prompt: String,
+    /// The line being typed, with the cursor: the command at the prompt, or the input of the
+    /// running command.
+    pub line: LineBuffer,
+    /// The text on the screen, a string per row, without trailing spaces.
+    pub screen: Vec<String>,
+}
+
+impl Transcript {
+    /// Check that the command was run.
+    ///
+    /// # Panics
+    ///
+    /// Panics, listing the commands run, if the command is not among them.
+    pub fn assert_history_contains(&self, command: &str) {
+        if !self.history.iter().any(|entry| entry.command == command) {
+            let commands = self
+                .history
+                .iter()
+                .map(|entry| entry.command.as_str())
+                .collect::<Vec<_>>();
+            panic!(
+                "`{}` is not in the history, which holds {:?}",
+                command, commands
+            );
+        }
+    }
+
+    /// Check that the prompt shown last is the given one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prompt is another one.
+    pub fn assert_prompt(&self, prompt: &str) {
+        assert_eq!(self.prompt, prompt, "the prompt is not the expected one");
+    }
+
+    /// Check that the text is on the screen, within a row.
+    ///
+    /// # Panics
+    ///
+    /// Panics, showing the screen, if the text is not on it.
+    pub fn assert_screen_contains(&self, text: &str) {
+        if !self.screen.iter().any(|row| row.contains(text)) {
+            panic!(
+                "`{}` is not on the screen:\n{}",
+                text,
+                self.screen.join("\n")
+            );
+        }
+    }
+}
+
+/// An executor echoing the commands, completing them among a list of words.
+#[derive(Debug, Clone, Default)]
+pub struct Echo {
+    /// The words the commands complete to.
+    pub words: Vec<String>,
+}
+
+impl Execute for Echo {
+    type Context = ();
+
+    fn prompt(&self, _: &Self::Context) -> String {
+        "$".to_string()
+    }
+
+    fn prepare(&self, cmd: &str) -> Prepare {
+        Prepare {
+            command: cmd.to_string(),
+            stdin_required: false,
+        }
+    }
+
+    fn execute(&self, _: &mut Self::Context, input: CommandInput) -> anyhow::Result<OutputAction> {
+        Ok(OutputAction::Command(CommandOutput {
+            prompt: input.prompt,
+            command: input.command.clone(),
+            stdin: Vec::new(),
+            stdout: vec![input.command],
+            stderr: Vec::new(),
+            ..Default::default()
+        }))
+    }
+
+    fn completion(
+        &self,
+        _: &Self::Context,
+        incomplete_command: &str,
+    ) -> anyhow::Result<(String, Vec<String>)> {
+        let matching = self
+            .words
+            .iter()
+            .filter_map(|word| word.strip_prefix(incomplete_command))
+            .map(str::to_string)
+            .collect();
+        Ok((String::new(), matching))
+    }
+}
+
+/// A made-up history of the given number of entries, cycling through the kinds of output: lines
+/// of text, some longer than the screen, a JSON object, a table, and errors.
+pub fn synthetic_history(entries: usize) -> Vec<CommandOutput> {
+    (0..entries)
+        .map(|index| {
+            let (command, stdout, stderr) = match index % 4 {
+                0 => (
+                    format!("ls -la ~/projects/{}", index),
+                    (0..8)
+                        .map(|line| format!("-rw-r--r-- 1 user user {:>6} file-{}.rs", line * 97, line))
+                        .chain([format!("{} ", "a long line wrapped over the screen").repeat(6)])
+                        .collect(),
+                    Vec::new(),
+                ),
+                1 => (
+                    format!("curl -s https://example.com/api/items/{}", index),
+                    vec![format!(
+                        r#"{{"id": {}, "name": "item {}", "tags": ["a", "b", "c"], "nested": {{"ok": true, "count": {}}}}}"#,
+                        index, index, index * 3
+                    )],
+                    Vec::new(),
+                ),
+                2 => (
+                    "ps".to_string(),
+                    ["PID\tTTY\tTIME\tCMD", "1\tpts/0\t00:00:01\tinit"]
+                        .into_iter()
+                        .map(str::to_string)
+                        .chain((0..6).map(|line| format!("{}\tpts/{}\t00:00:0{}\tworker-{}", 100 + line, line, line, line)))
+                        .collect(),
+                    Vec::new(),
+                ),
+                _ => (
+                    format!("cat missing-{}.txt", index),
+                    Vec::new(),
+                    vec![format!("cat: missing-{}.txt: No such file or directory", index)],
+                ),
+            };
+            CommandOutput {
+                prompt: "$".to_string(),
+                command,
+                stdin: Vec::new(),
+                stdout,
+                stderr,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// The file `fixture` of `tests/fixtures`, copied as `name` to a directory of the test `test`
+/// alone, where the test is free to change it.
+#[cfg(test)]
+pub(crate) fn fixture(test: &str, fixture: &str, name: &str) -> anyhow::Result<std::path::PathBuf> {
+    let dir = std::env::temp_dir().join(format!("shelgon-{}-{}", std::process::id(), test));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(name);
+    std::fs::copy(
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(fixture),
+        &path,
+    )?;
+    Ok(path)
+}
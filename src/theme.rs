@@ -0,0 +1,461 @@
+//! Color themes for the terminal UI.
+//!
+//! Every style used by the renderer is looked up from the active [`Theme`], so switching the
+//! theme (via the `ui.theme` configuration key or the `:theme` command) re-styles the whole
+//! shell, including the history that is already on screen.
+//!
+//! Every built-in theme comes in a light and a dark variant. The variant is picked from the
+//! background color reported by the terminal on startup, unless the `ui.background`
+//! configuration key overrides it.
+//!
+//! Themes are written with truecolor RGB values. On terminals without truecolor support the
+//! colors are mapped to the nearest entry of the 256 or 16 color palette (see
+//! [`Theme::adapted`]).
+//!
+//! # Example
+//!
+//! ```rust
+//! use shelgon::theme::{Appearance, Theme};
+//!
+//! let theme = Theme::builtin("gruvbox", Appearance::Light).unwrap_or_default();
+//! assert_eq!(theme.name, "gruvbox");
+//! assert_eq!(theme.appearance, Appearance::Light);
+//! assert!(Theme::builtin("no-such-theme", Appearance::Dark).is_none());
+//! ```
+
+use ratatui::style::{Color, Modifier, Style};
+
+/// The names of the built-in themes, in the order they are listed in the theme picker.
+pub const BUILTIN: &[&str] = &["default", "high-contrast", "gruvbox", "solarized", "nord"];
+
+///
+/// [`Appearance`] is the brightness of the terminal background a theme is designed for.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Appearance {
+    /// A light background with dark text.
+    Light,
+    /// A dark background with light text.
+    #[default]
+    Dark,
+}
+
+impl Appearance {
+    /// The appearance matching a background color, from its relative luminance.
+    pub fn from_rgb(red: u8, green: u8, blue: u8) -> Self {
+        let luminance =
+            0.2126 * f32::from(red) + 0.7152 * f32::from(green) + 0.0722 * f32::from(blue);
+        match luminance > 127.5 {
+            true => Appearance::Light,
+            false => Appearance::Dark,
+        }
+    }
+}
+
+///
+/// [`ColorSupport`] is the set of colors a terminal is able to display.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorSupport {
+    /// 24-bit RGB colors.
+    #[default]
+    TrueColor,
+    /// The 256 color palette (`xterm-256color`).
+    Ansi256,
+    /// The 16 basic ANSI colors.
+    Ansi16,
+    /// No colors at all, only text attributes (bold, reversed, ...). This is what `NO_COLOR`
+    /// asks for.
+    NoColor,
+}
+
+impl ColorSupport {
+    /// Map a color to the nearest color the terminal supports.
+    ///
+    /// ```rust
+    /// use ratatui::style::Color;
+    /// use shelgon::theme::ColorSupport;
+    ///
+    /// let grey = Color::Rgb(200, 200, 200);
+    /// assert_eq!(ColorSupport::TrueColor.adapt(grey), grey);
+    /// assert_eq!(ColorSupport::Ansi256.adapt(grey), Color::Indexed(251));
+    /// assert_eq!(ColorSupport::Ansi16.adapt(grey), Color::Gray);
+    /// assert_eq!(ColorSupport::NoColor.adapt(grey), Color::Reset);
+    /// ```
+    pub fn adapt(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorSupport::TrueColor, _) => color,
+            (ColorSupport::NoColor, _) => Color::Reset,
+            (ColorSupport::Ansi256, Color::Rgb(r, g, b)) => Color::Indexed(nearest_256(r, g, b)),
+            (ColorSupport::Ansi16, Color::Rgb(r, g, b)) => nearest_16(r, g, b),
+            (ColorSupport::Ansi16, Color::Indexed(index)) if index >= 16 => {
+                let (r, g, b) = indexed_rgb(index);
+                nearest_16(r, g, b)
+            }
+            (_, color) => color,
+        }
+    }
+}
+
+///
+/// [`Theme`] is the set of styles used to render the shell.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// The name of the theme.
+    pub name: String,
+    /// The terminal background this variant of the theme is designed for.
+    pub appearance: Appearance,
+    /// The prompt shown before every command.
+    pub prompt: Style,
+    /// The command entered by the user.
+    pub command: Style,
+    /// The input supplied to a command.
+    pub stdin: Style,
+    /// The output of a command.
+    pub stdout: Style,
+    /// The error output of a command.
+    pub stderr: Style,
+    /// The cursor in the command editor.
+    pub cursor: Style,
+    /// The completion candidates shown below the command.
+    pub completion: Style,
+    /// Secondary text, such as progress messages and hints.
+    pub hint: Style,
+    /// Titles of screens and overlays.
+    pub title: Style,
+    /// Errors reported by the shell itself.
+    pub error: Style,
+    /// Borders of overlays.
+    pub border: Style,
+    /// The selected item in a list.
+    pub selection: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::plain(Appearance::Dark)
+    }
+}
+
+impl Theme {
+    /// Look up a built-in theme by its name, in the variant for the given appearance.
+    pub fn builtin(name: &str, appearance: Appearance) -> Option<Self> {
+        let theme = match (name, appearance) {
+            ("default", _) => Self::plain(appearance),
+            ("high-contrast", _) => Self::high_contrast(appearance),
+            ("gruvbox", Appearance::Dark) => Self::palette(
+                name,
+                appearance,
+                Palette {
+                    fg: Color::Rgb(235, 219, 178),
+                    bg: Color::Rgb(40, 40, 40),
+                    surface: Color::Rgb(80, 73, 69),
+                    accent: Color::Rgb(250, 189, 47),
+                    secondary: Color::Rgb(131, 165, 152),
+                    error: Color::Rgb(251, 73, 52),
+                    muted: Color::Rgb(146, 131, 116),
+                },
+            ),
+            ("gruvbox", Appearance::Light) => Self::palette(
+                name,
+                appearance,
+                Palette {
+                    fg: Color::Rgb(60, 56, 54),
+                    bg: Color::Rgb(251, 241, 199),
+                    surface: Color::Rgb(235, 219, 178),
+                    accent: Color::Rgb(181, 118, 20),
+                    secondary: Color::Rgb(7, 102, 120),
+                    error: Color::Rgb(157, 0, 6),
+                    muted: Color::Rgb(124, 111, 100),
+                },
+            ),
+            ("solarized", Appearance::Dark) => Self::palette(
+                name,
+                appearance,
+                Palette {
+                    fg: Color::Rgb(147, 161, 161),
+                    bg: Color::Rgb(0, 43, 54),
+                    surface: Color::Rgb(7, 54, 66),
+                    accent: Color::Rgb(38, 139, 210),
+                    secondary: Color::Rgb(42, 161, 152),
+                    error: Color::Rgb(220, 50, 47),
+                    muted: Color::Rgb(88, 110, 117),
+                },
+            ),
+            ("solarized", Appearance::Light) => Self::palette(
+                name,
+                appearance,
+                Palette {
+                    fg: Color::Rgb(88, 110, 117),
+                    bg: Color::Rgb(253, 246, 227),
+                    surface: Color::Rgb(238, 232, 213),
+                    accent: Color::Rgb(38, 139, 210),
+                    secondary: Color::Rgb(42, 161, 152),
+                    error: Color::Rgb(220, 50, 47),
+                    muted: Color::Rgb(147, 161, 161),
+                },
+            ),
+            ("nord", Appearance::Dark) => Self::palette(
+                name,
+                appearance,
+                Palette {
+                    fg: Color::Rgb(216, 222, 233),
+                    bg: Color::Rgb(46, 52, 64),
+                    surface: Color::Rgb(67, 76, 94),
+                    accent: Color::Rgb(136, 192, 208),
+                    secondary: Color::Rgb(129, 161, 193),
+                    error: Color::Rgb(191, 97, 106),
+                    muted: Color::Rgb(97, 110, 136),
+                },
+            ),
+            ("nord", Appearance::Light) => Self::palette(
+                name,
+                appearance,
+                Palette {
+                    fg: Color::Rgb(46, 52, 64),
+                    bg: Color::Rgb(236, 239, 244),
+                    surface: Color::Rgb(216, 222, 233),
+                    accent: Color::Rgb(94, 129, 172),
+                    secondary: Color::Rgb(76, 86, 106),
+                    error: Color::Rgb(191, 97, 106),
+                    muted: Color::Rgb(129, 137, 153),
+                },
+            ),
+            _ => return None,
+        };
+
+        Some(theme)
+    }
+
+    /// Map every color of the theme to the nearest color the terminal supports.
+    ///
+    /// With [`ColorSupport::NoColor`] the colors are dropped entirely, and styles that relied on
+    /// a background color (the cursor, the selection, ...) are rendered reversed instead.
+    pub fn adapted(mut self, colors: ColorSupport) -> Self {
+        for style in [
+            &mut self.prompt,
+            &mut self.command,
+            &mut self.stdin,
+            &mut self.stdout,
+            &mut self.stderr,
+            &mut self.cursor,
+            &mut self.completion,
+            &mut self.hint,
+            &mut self.title,
+            &mut self.error,
+            &mut self.border,
+            &mut self.selection,
+        ] {
+            if colors == ColorSupport::NoColor {
+                style.fg = None;
+                if style.bg.take().is_some() {
+                    *style = style.add_modifier(Modifier::REVERSED);
+                }
+                continue;
+            }
+
+            style.fg = style.fg.map(|color| colors.adapt(color));
+            style.bg = style.bg.map(|color| colors.adapt(color));
+        }
+
+        self
+    }
+
+    /// The default theme, built from the terminal's own colors.
+    fn plain(appearance: Appearance) -> Self {
+        let (cursor, completion) = match appearance {
+            Appearance::Dark => (Color::White, Color::Rgb(200, 200, 200)),
+            Appearance::Light => (Color::Black, Color::Rgb(60, 60, 60)),
+        };
+        let contrast = match appearance {
+            Appearance::Dark => Color::Black,
+            Appearance::Light => Color::White,
+        };
+
+        Self {
+            name: "default".to_string(),
+            appearance,
+            prompt: Style::default().fg(Color::Blue),
+            command: Style::default().add_modifier(Modifier::BOLD),
+            stdin: Style::default(),
+            stdout: Style::default(),
+            stderr: Style::default().fg(Color::Red),
+            cursor: Style::default().bg(cursor).fg(contrast),
+            completion: Style::default().bg(completion).fg(contrast),
+            hint: Style::default().add_modifier(Modifier::DIM),
+            title: Style::default().add_modifier(Modifier::BOLD),
+            error: Style::default().fg(Color::Red),
+            border: Style::default().fg(Color::Blue),
+            selection: Style::default().bg(Color::Blue).fg(Color::White),
+        }
+    }
+
+    /// A theme using only bold, basic colors with the strongest contrast against the background.
+    fn high_contrast(appearance: Appearance) -> Self {
+        let (fg, bg, accent, error) = match appearance {
+            Appearance::Dark => (
+                Color::White,
+                Color::Black,
+                Color::LightYellow,
+                Color::LightRed,
+            ),
+            Appearance::Light => (Color::Black, Color::White, Color::Blue, Color::Red),
+        };
+        let bold = |color| Style::default().fg(color).add_modifier(Modifier::BOLD);
+
+        Self {
+            name: "high-contrast".to_string(),
+            appearance,
+            prompt: bold(accent),
+            command: bold(fg),
+            stdin: Style::default().fg(fg),
+            stdout: Style::default().fg(fg),
+            stderr: bold(error),
+            cursor: Style::default().bg(fg).fg(bg),
+            completion: Style::default().bg(fg).fg(bg),
+            hint: Style::default().fg(fg).add_modifier(Modifier::ITALIC),
+            title: bold(fg).add_modifier(Modifier::UNDERLINED),
+            error: bold(error),
+            border: bold(fg),
+            selection: Style::default()
+                .bg(accent)
+                .fg(bg)
+                .add_modifier(Modifier::BOLD),
+        }
+    }
+
+    /// Build a theme from a palette of colors.
+    fn palette(name: &str, appearance: Appearance, palette: Palette) -> Self {
+        Self {
+            name: name.to_string(),
+            appearance,
+            prompt: Style::default().fg(palette.accent),
+            command: Style::default().fg(palette.fg).add_modifier(Modifier::BOLD),
+            stdin: Style::default().fg(palette.secondary),
+            stdout: Style::default().fg(palette.fg),
+            stderr: Style::default().fg(palette.error),
+            cursor: Style::default().bg(palette.fg).fg(palette.bg),
+            completion: Style::default().bg(palette.surface).fg(palette.fg),
+            hint: Style::default().fg(palette.muted),
+            title: Style::default()
+                .fg(palette.accent)
+                .add_modifier(Modifier::BOLD),
+            error: Style::default().fg(palette.error),
+            border: Style::default().fg(palette.secondary),
+            selection: Style::default().bg(palette.accent).fg(palette.bg),
+        }
+    }
+}
+
+/// The colors a built-in theme is derived from.
+struct Palette {
+    /// The main text color.
+    fg: Color,
+    /// The background color, used for text drawn on top of `fg`.
+    bg: Color,
+    /// A background slightly lighter than `bg`, for popups.
+    surface: Color,
+    /// The primary accent color.
+    accent: Color,
+    /// The secondary accent color.
+    secondary: Color,
+    /// The color of errors.
+    error: Color,
+    /// The color of secondary text.
+    muted: Color,
+}
+
+/// The intensities of the 6x6x6 color cube of the 256 color palette.
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The RGB values of the 16 basic colors (as used by xterm), in palette order.
+const ANSI: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The squared distance between two colors.
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).unsigned_abs().pow(2);
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// The RGB value of an entry of the 256 color palette.
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI[usize::from(index)],
+        16..=231 => {
+            let index = index - 16;
+            (
+                CUBE[usize::from(index / 36)],
+                CUBE[usize::from(index / 6 % 6)],
+                CUBE[usize::from(index % 6)],
+            )
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+/// The index of the nearest color in the 256 color palette (from the color cube, or the gray
+/// ramp).
+fn nearest_256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |value: u8| {
+        (0..CUBE.len())
+            .min_by_key(|&i| (i32::from(CUBE[i]) - i32::from(value)).unsigned_abs())
+            .unwrap_or_default() as u8
+    };
+    let cube = 16 + 36 * level(r) + 6 * level(g) + level(b);
+
+    let average = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+    let gray = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+
+    [cube, gray]
+        .into_iter()
+        .min_by_key(|&index| distance(indexed_rgb(index), (r, g, b)))
+        .unwrap_or(cube)
+}
+
+/// The nearest of the 16 basic colors.
+fn nearest_16(r: u8, g: u8, b: u8) -> Color {
+    const COLORS: [Color; 16] = [
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::Gray,
+        Color::DarkGray,
+        Color::LightRed,
+        Color::LightGreen,
+        Color::LightYellow,
+        Color::LightBlue,
+        Color::LightMagenta,
+        Color::LightCyan,
+        Color::White,
+    ];
+
+    ANSI.iter()
+        .zip(COLORS)
+        .min_by_key(|(rgb, _)| distance(**rgb, (r, g, b)))
+        .map_or(Color::Reset, |(_, color)| color)
+}
@@ -0,0 +1,87 @@
+//! Tilde expansion, and named directories.
+//!
+//! A word of a command starting with `~` stands for a directory: `~` alone (or `~/...`) for the
+//! home directory, `~NAME` for a named directory of the `[directories]` section of the
+//! configuration (see [`crate::config::Config::directories`]), or else for the home directory of
+//! the user `NAME`. A named directory may itself start with `~`:
+//!
+//! ```toml
+//! [directories]
+//! proj = "~/code/proj"
+//! ```
+//!
+//! `cd ~proj/src` then runs `cd /home/me/code/proj/src`. Words are expanded before the command is
+//! prepared, so every executor gets the paths, and `Tab` completes the names of the named
+//! directories, and the paths under them. Words within quotes, or whose name is unknown, are left
+//! as they are.
+
+use std::collections::BTreeMap;
+
+/// Expand the words of a command starting with `~`.
+pub(crate) fn expand(command: &str, directories: &BTreeMap<String, String>) -> String {
+    let mut expanded = String::with_capacity(command.len());
+    let mut quote = None;
+    let mut start = true;
+    let mut rest = command;
+    while let Some(c) = rest.chars().next() {
+        if quote.is_none() && start && c == '~' {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            if let Some(word) = self::word(&rest[..end], directories) {
+                expanded.push_str(&word);
+                rest = &rest[end..];
+                start = false;
+                continue;
+            }
+        }
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            _ => {}
+        }
+        start = quote.is_none() && c.is_whitespace();
+        expanded.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    expanded
+}
+
+/// Expand a word starting with `~`. This returns `None` if it does not start with `~`, or if the
+/// name after it is unknown.
+pub(crate) fn word(word: &str, directories: &BTreeMap<String, String>) -> Option<String> {
+    let rest = word.strip_prefix('~')?;
+    let (name, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let directory = match name {
+        "" => std::env::var("HOME").ok()?,
+        name => match directories.get(name) {
+            // A named directory starting with `~NAME` is not expanded again, to never loop.
+            Some(directory) if directory == "~" || directory.starts_with("~/") => {
+                self::word(directory, &BTreeMap::new())?
+            }
+            Some(directory) => directory.clone(),
+            None => home(name)?,
+        },
+    };
+    match path {
+        "" => Some(directory),
+        path => Some(format!("{}{}", directory.trim_end_matches('/'), path)),
+    }
+}
+
+/// The home directory of a user, from `/etc/passwd`.
+#[cfg(unix)]
+fn home(user: &str) -> Option<String> {
+    let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines().find_map(|line| {
+        let fields = line.split(':').collect::<Vec<_>>();
+        match fields.as_slice() {
+            [name, _, _, _, _, home, ..] if *name == user => Some(home.to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// The home directory of a user, unknown outside of unix.
+#[cfg(not(unix))]
+fn home(_user: &str) -> Option<String> {
+    None
+}
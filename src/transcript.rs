@@ -0,0 +1,207 @@
+//! Transcripts: the commands of a session with their output, archived in a file.
+//!
+//! A transcript is written by `:transcript save FILE`, and restored with
+//! `sheller --restore FILE`, which starts the shell with its entries in the history. Transcripts
+//! are handled outside of the shell with the `sheller transcript` subcommands:
+//!
+//! ```bash
+//! sheller transcript cat FILE                  # print the commands and their output
+//! sheller transcript unpack FILE > entries.jsonl
+//! sheller transcript pack FILE < entries.jsonl
+//! ```
+//!
+//! Unpacked, a transcript is a JSON line per entry, as the entries of [`crate::protocol`].
+//!
+//! # Format
+//!
+//! A transcript file starts with the [`MAGIC`] bytes and the version of the format, as two
+//! bytes in little-endian order. The rest is a zstd frame, holding the [`Transcript`] encoded
+//! with MessagePack: when and where it was written, and the entries. A transcript of a newer
+//! version than [`VERSION`] is refused, rather than read wrong, and one of an older version is
+//! upgraded as it is read.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use shelgon::protocol::Entry;
+//! use shelgon::transcript::Transcript;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let entry = Entry {
+//!     prompt: "$".to_string(),
+//!     command: "echo hello".to_string(),
+//!     stdin: Vec::new(),
+//!     stdout: vec!["hello".to_string()],
+//!     stderr: Vec::new(),
+//!     note: Some("greeting".to_string()),
+//!     starred: true,
+//!     exit_code: Some(0),
+//!     duration: Duration::from_millis(3),
+//! };
+//! let transcript = Transcript::new(vec![entry.clone()]);
+//!
+//! let mut file = Vec::new();
+//! transcript.write(&mut file)?;
+//! let read = Transcript::read(file.as_slice())?;
+//!
+//! assert_eq!(read.entries, vec![entry]);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::command::CommandOutput;
+use crate::migrate::{self, Migration};
+use crate::protocol::Entry;
+
+/// The bytes every transcript file starts with.
+pub const MAGIC: &[u8; 4] = b"SHTR";
+
+/// The version of the format written.
+pub const VERSION: u16 = migrate::current(MIGRATIONS) as u16;
+
+/// The migrations of the transcripts of earlier versions. (See [`crate::migrate`])
+const MIGRATIONS: &[Migration<Value>] = &[];
+
+/// The compression level of zstd, favoring speed: transcripts are written as the shell runs.
+const LEVEL: i32 = 3;
+
+///
+/// [`Transcript`] is an archived session: the commands run, with their output.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transcript {
+    /// When and where the transcript was written.
+    pub meta: Meta,
+    /// The entries of the history, oldest first.
+    pub entries: Vec<Entry>,
+}
+
+///
+/// [`Meta`] is when and where a transcript was written.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Meta {
+    /// The time the transcript was written, in seconds since the Unix epoch.
+    pub created: u64,
+    /// The version of the shell that wrote it.
+    pub shell: String,
+    /// The working directory of the shell, if known.
+    pub cwd: Option<String>,
+    /// The environment variables changed in the session, with their value, or `None` if they were
+    /// unset. Only autosaves record them. (See `crate::autosave`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, Option<String>>,
+}
+
+impl Meta {
+    /// The metadata of a transcript written now, here.
+    pub fn now() -> Self {
+        Self {
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            shell: env!("CARGO_PKG_VERSION").to_string(),
+            cwd: std::env::current_dir()
+                .ok()
+                .map(|dir| dir.display().to_string()),
+            env: BTreeMap::new(),
+        }
+    }
+}
+
+impl Transcript {
+    /// Create a transcript of the entries, written now.
+    pub fn new(entries: Vec<Entry>) -> Self {
+        Self {
+            meta: Meta::now(),
+            entries,
+        }
+    }
+
+    /// Write the transcript in the transcript format.
+    pub fn write(&self, mut output: impl Write) -> anyhow::Result<()> {
+        output.write_all(MAGIC)?;
+        output.write_all(&VERSION.to_le_bytes())?;
+        let mut encoder = zstd::Encoder::new(output, LEVEL)?;
+        rmp_serde::encode::write_named(&mut encoder, self)?;
+        encoder.finish()?.flush()?;
+        Ok(())
+    }
+
+    /// Read a transcript written in the transcript format.
+    pub fn read(mut input: impl Read) -> anyhow::Result<Self> {
+        let mut header = [0; 6];
+        input
+            .read_exact(&mut header)
+            .map_err(|_| anyhow::anyhow!("not a transcript: the file is too short"))?;
+        if header[..4] != MAGIC[..] {
+            anyhow::bail!("not a transcript: the file does not start with {:?}", MAGIC);
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        migrate::check("the transcript", version.into(), MIGRATIONS)?;
+
+        let decoder = zstd::Decoder::new(input)?;
+        let mut transcript: Value = rmp_serde::from_read(decoder)?;
+        migrate::upgrade("the transcript", version.into(), MIGRATIONS, |migration| {
+            migration(&mut transcript)
+        })?;
+        Ok(serde_json::from_value(transcript)?)
+    }
+
+    /// Read a transcript from JSON lines, an entry per line, as written by [`Transcript::unpack`].
+    /// Empty lines are skipped.
+    pub fn pack(input: impl BufRead) -> anyhow::Result<Self> {
+        let mut entries = Vec::new();
+        for (index, line) in input.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line)
+                .map_err(|e| anyhow::anyhow!("line {}: {}", index + 1, e))?;
+            entries.push(entry);
+        }
+        Ok(Self::new(entries))
+    }
+
+    /// Write the entries as JSON lines, an entry per line.
+    pub fn unpack(&self, mut output: impl Write) -> anyhow::Result<()> {
+        for entry in &self.entries {
+            serde_json::to_writer(&mut output, entry)?;
+            output.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Write the entries as they were shown: the prompt and the command, starred or not, followed
+    /// by the input, the output, the error output and the note.
+    pub fn cat(&self, mut output: impl Write) -> anyhow::Result<()> {
+        for entry in &self.entries {
+            if entry.starred {
+                write!(output, "★ ")?;
+            }
+            writeln!(output, "{} {}", entry.prompt, entry.command)?;
+            for line in entry.stdin.iter().chain(&entry.stdout).chain(&entry.stderr) {
+                writeln!(output, "{}", line)?;
+            }
+            if let Some(note) = &entry.note {
+                writeln!(output, "✎ {}", note)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The entries, as the history of the shell holds them.
+    pub fn into_history(self) -> Vec<CommandOutput> {
+        self.entries.into_iter().map(CommandOutput::from).collect()
+    }
+}
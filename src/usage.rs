@@ -0,0 +1,128 @@
+//! The commands run in each directory.
+//!
+//! Every command run by the shell is counted against the working directory it ran in, in
+//! `directories.json` in the data directory (`$XDG_DATA_HOME/sheller`). The commands run most
+//! often and most recently in the working directory (see [`crate::rank`]) are then suggested,
+//! below an empty prompt or on a key press. (See `history.suggestions` in
+//! [`crate::config::History`])
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::migrate;
+use crate::rank::{self, Candidate, Frecency, Ranker};
+
+/// The name of the file holding the commands, in the data directory.
+const NAME: &str = "directories.json";
+
+/// The format of the file holding the commands. (See [`crate::migrate`])
+const FORMAT: migrate::Json = migrate::Json {
+    name: NAME,
+    migrations: &[],
+};
+
+/// The number of commands kept per directory. The commands ranked last by [`Frecency`] are
+/// forgotten first.
+pub(crate) const MAX_COMMANDS: usize = 100;
+
+/// How often a command was run in a directory.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Usage {
+    /// The number of times the command was run.
+    count: u64,
+    /// When the command was last run, in seconds since the Unix epoch.
+    last: u64,
+}
+
+/// The commands run in each directory, by directory and by command.
+type Directories = BTreeMap<String, BTreeMap<String, Usage>>;
+
+/// The path of the file holding the commands.
+fn path() -> anyhow::Result<PathBuf> {
+    config::data_dir()
+        .map(|dir| dir.join(NAME))
+        .ok_or_else(|| anyhow::anyhow!("unable to determine the data path"))
+}
+
+/// Read the commands run in each directory, or nothing if the file does not exist yet.
+fn read() -> anyhow::Result<Directories> {
+    Ok(FORMAT.read(&path()?)?.unwrap_or_default())
+}
+
+/// Count a command run in a directory.
+pub(crate) fn record(directory: &Path, command: &str) -> anyhow::Result<()> {
+    let command = command.trim();
+    if command.is_empty() {
+        return Ok(());
+    }
+
+    let path = path()?;
+    let mut directories = read()?;
+    let commands = directories
+        .entry(directory.to_string_lossy().into_owned())
+        .or_default();
+    let usage = commands.entry(command.to_string()).or_default();
+    usage.count += 1;
+    usage.last = now();
+
+    if commands.len() > MAX_COMMANDS {
+        let forgotten = ranked(commands, &Frecency)
+            .into_iter()
+            .skip(MAX_COMMANDS)
+            .collect::<Vec<_>>();
+        commands.retain(|command, _| !forgotten.contains(command));
+    }
+
+    FORMAT.write(&path, &directories)
+}
+
+/// The `limit` commands ranked first in a directory, best first. Among commands ranked the same,
+/// the most recent comes first.
+pub(crate) fn frequent(
+    directory: &Path,
+    limit: usize,
+    ranker: &dyn Ranker,
+) -> anyhow::Result<Vec<String>> {
+    let directories = read()?;
+    let Some(commands) = directories.get(directory.to_string_lossy().as_ref()) else {
+        return Ok(Vec::new());
+    };
+
+    Ok(ranked(commands, ranker).into_iter().take(limit).collect())
+}
+
+/// The commands, best ranked first.
+fn ranked(commands: &BTreeMap<String, Usage>, ranker: &dyn Ranker) -> Vec<String> {
+    // The most recent first, as the ranking keeps the order of the commands ranked the same.
+    let mut commands = commands.iter().collect::<Vec<_>>();
+    commands.sort_by_key(|(_, usage)| std::cmp::Reverse(usage.last));
+
+    let now = now();
+    let candidates = commands
+        .iter()
+        .map(|(command, usage)| Candidate {
+            text: command,
+            count: usage.count,
+            age: Some(Duration::from_secs(now.saturating_sub(usage.last))),
+        })
+        .collect::<Vec<_>>();
+
+    rank::rank(ranker, &candidates)
+        .into_iter()
+        .map(|index| commands[index].0.clone())
+        .collect()
+}
+
+/// The current time, in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
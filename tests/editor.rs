@@ -0,0 +1,248 @@
+//! Property tests of the line editor: arbitrary key presses, unicode and pastes, typed into the
+//! real shell, must never make it panic, nor leave the cursor off the boundary of a character.
+//! The cursor moves a grapheme cluster at a time.
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use proptest::prelude::*;
+use shelgon::config::Config;
+use shelgon::editor::LineBuffer;
+use shelgon::renderer::App;
+use shelgon::testing::{Echo, MockTerminal, Script};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Text mixing ASCII, accents (precomposed or not), wide characters and emoji.
+fn text() -> impl Strategy<Value = String> {
+    "[a-z é\u{301}日本語🦀|;:#-]{0,8}"
+}
+
+/// The keys editing the line, or moving around the shell. Keys handing the terminal over to
+/// another program (the manual) or leaving the shell are left out.
+fn key() -> impl Strategy<Value = Event> {
+    let special = prop::sample::select(vec![
+        KeyCode::Left,
+        KeyCode::Right,
+        KeyCode::Up,
+        KeyCode::Down,
+        KeyCode::Home,
+        KeyCode::End,
+        KeyCode::Backspace,
+        KeyCode::Delete,
+        KeyCode::Tab,
+        KeyCode::BackTab,
+        KeyCode::Enter,
+        KeyCode::Esc,
+        KeyCode::F(1),
+    ]);
+    let modifiers = prop::sample::select(vec![
+        KeyModifiers::NONE,
+        KeyModifiers::SHIFT,
+        KeyModifiers::CONTROL,
+        KeyModifiers::ALT,
+    ]);
+
+    prop_oneof![
+        4 => any::<char>().prop_map(|c| Event::Key(KeyEvent::from(KeyCode::Char(c)))),
+        4 => prop::sample::select(vec!['a', ' ', 'é', '\u{301}', '日', '🦀', '|', '#', ':', '?'])
+            .prop_map(|c| Event::Key(KeyEvent::from(KeyCode::Char(c)))),
+        4 => special.prop_map(|code| Event::Key(KeyEvent::from(code))),
+        1 => (prop::char::range('a', 'z'), modifiers)
+            .prop_filter("leaves the shell", |(c, modifiers)| {
+                *modifiers != KeyModifiers::CONTROL || !matches!(c, 'c' | 'd' | 'q')
+            })
+            .prop_map(|(c, modifiers)| Event::Key(KeyEvent::new(KeyCode::Char(c), modifiers))),
+        1 => text().prop_map(Event::Paste),
+    ]
+}
+
+/// An edit of a line, as done by [`LineBuffer`].
+#[derive(Debug, Clone)]
+enum Edit {
+    Insert(char),
+    InsertStr(String),
+    DeleteBackward,
+    DeleteForward,
+    Left,
+    Right,
+    Home,
+    End,
+    WordLeft,
+    WordRight,
+    DeleteWordBackward,
+    DeleteToStart,
+    Undo,
+}
+
+fn edit() -> impl Strategy<Value = Edit> {
+    prop_oneof![
+        any::<char>().prop_map(Edit::Insert),
+        text().prop_map(Edit::InsertStr),
+        Just(Edit::DeleteBackward),
+        Just(Edit::DeleteForward),
+        Just(Edit::Left),
+        Just(Edit::Right),
+        Just(Edit::Home),
+        Just(Edit::End),
+        Just(Edit::WordLeft),
+        Just(Edit::WordRight),
+        Just(Edit::DeleteWordBackward),
+        Just(Edit::DeleteToStart),
+        Just(Edit::Undo),
+    ]
+}
+
+fn app() -> anyhow::Result<App<Echo>> {
+    let app = App::new_with_executor(
+        #[cfg(feature = "tokio")]
+        tokio::runtime::Runtime::new()?,
+        Echo {
+            words: ["écho", "écho", "日本語", "e\u{301}cho"]
+                .map(str::to_string)
+                .to_vec(),
+        },
+        (),
+    );
+    Ok(app.with_config(Config::default()))
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn shell_survives_any_keys(events in prop::collection::vec(key(), 0..64)) {
+        let script = events.into_iter().fold(Script::new(), Script::event);
+        let transcript = app()
+            .and_then(|app| MockTerminal::new(40, 12).run(app, script))
+            .map_err(|e| TestCaseError::fail(format!("{:#}", e)))?;
+
+        let line = &transcript.line;
+        prop_assert!(line.cursor() <= line.as_str().len());
+        prop_assert!(line.as_str().is_char_boundary(line.cursor()));
+    }
+
+    #[test]
+    fn shell_survives_any_size(
+        width in 0u16..48,
+        height in 0u16..16,
+        events in prop::collection::vec(key(), 0..32),
+    ) {
+        let script = events.into_iter().fold(Script::new(), Script::event);
+        app()
+            .and_then(|app| MockTerminal::new(width, height).run(app, script))
+            .map_err(|e| TestCaseError::fail(format!("{:#}", e)))?;
+    }
+}
+
+#[test]
+fn small_terminal_shows_placeholder() -> anyhow::Result<()> {
+    let transcript = MockTerminal::new(19, 4).run(app()?, Script::new().line("hello"))?;
+    transcript.assert_screen_contains("terminal too small");
+    transcript.assert_history_contains("hello");
+
+    let transcript = MockTerminal::new(20, 5).run(app()?, Script::new().line("hello"))?;
+    transcript.assert_screen_contains("hello");
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn line_buffer_matches_a_list_of_graphemes(edits in prop::collection::vec(edit(), 0..64)) {
+        let mut line = LineBuffer::new();
+        // The text, and the cursor as a byte offset into it, moving a grapheme cluster at a time.
+        let (mut text, mut cursor) = (String::new(), 0);
+        let previous = |text: &str, cursor: usize| text[..cursor].graphemes(true).next_back().map(str::to_string);
+        let under = |text: &str, cursor: usize| text[cursor..].graphemes(true).next().map(str::to_string);
+
+        for edit in edits {
+            match &edit {
+                &Edit::Insert(c) => {
+                    line.insert(c);
+                    text.insert(cursor, c);
+                    cursor += c.len_utf8();
+                }
+                Edit::InsertStr(inserted) => {
+                    line.insert_str(inserted);
+                    text.insert_str(cursor, inserted);
+                    cursor += inserted.len();
+                }
+                Edit::DeleteBackward => {
+                    let deleted = previous(&text, cursor);
+                    if let Some(deleted) = &deleted {
+                        cursor -= deleted.len();
+                        text.replace_range(cursor..cursor + deleted.len(), "");
+                    }
+                    prop_assert_eq!(line.delete_backward(), deleted);
+                }
+                Edit::DeleteForward => {
+                    let deleted = under(&text, cursor);
+                    if let Some(deleted) = &deleted {
+                        text.replace_range(cursor..cursor + deleted.len(), "");
+                    }
+                    prop_assert_eq!(line.delete_forward(), deleted);
+                }
+                Edit::Left => {
+                    let moved = previous(&text, cursor);
+                    prop_assert_eq!(line.move_left(), moved.is_some());
+                    cursor -= moved.map_or(0, |moved| moved.len());
+                }
+                Edit::Right => {
+                    let moved = under(&text, cursor);
+                    prop_assert_eq!(line.move_right(), moved.is_some());
+                    cursor += moved.map_or(0, |moved| moved.len());
+                }
+                Edit::Home => {
+                    line.move_home();
+                    cursor = 0;
+                }
+                Edit::End => {
+                    line.move_end();
+                    cursor = text.len();
+                }
+                // The words and the undo are checked against the line itself, rather than
+                // modelled.
+                Edit::WordLeft => {
+                    line.move_word_left();
+                }
+                Edit::WordRight => {
+                    line.move_word_right();
+                }
+                Edit::DeleteWordBackward => {
+                    line.delete_word_backward();
+                }
+                Edit::DeleteToStart => {
+                    let deleted = (cursor > 0).then(|| text.drain(..cursor).collect::<String>());
+                    cursor = 0;
+                    prop_assert_eq!(line.delete_to_start(), deleted);
+                }
+                Edit::Undo => {
+                    line.undo();
+                }
+            }
+            prop_assert!(line.as_str().is_char_boundary(line.cursor()));
+            if matches!(
+                edit,
+                Edit::WordLeft | Edit::WordRight | Edit::DeleteWordBackward | Edit::Undo
+            ) {
+                text = line.as_str().to_string();
+                cursor = line.cursor();
+            }
+
+            prop_assert_eq!(line.as_str(), text.as_str());
+            prop_assert_eq!(line.cursor(), cursor);
+            prop_assert_eq!(line.under().map(str::to_string), under(&text, cursor));
+        }
+    }
+}
+
+#[test]
+fn combining_marks_and_emoji_are_one_step_of_the_cursor() {
+    let mut line = LineBuffer::from("cafe\u{301} 👍🏽!");
+    line.move_left();
+    assert_eq!(line.under(), Some("!"));
+    line.move_left();
+    assert_eq!(line.under(), Some("👍🏽"));
+    assert_eq!(line.delete_backward().as_deref(), Some(" "));
+    assert_eq!(line.delete_backward().as_deref(), Some("e\u{301}"));
+    assert_eq!(line.as_str(), "caf👍🏽!");
+    assert_eq!(line.delete_forward().as_deref(), Some("👍🏽"));
+    assert_eq!(line.as_str(), "caf!");
+}
@@ -0,0 +1,28 @@
+-- The history database as written by the first version of the shell to record its version.
+CREATE TABLE commands (
+    id INTEGER PRIMARY KEY,
+    command TEXT NOT NULL,
+    directory TEXT,
+    exit INTEGER,
+    time INTEGER NOT NULL
+);
+CREATE INDEX commands_command ON commands (command);
+CREATE INDEX commands_directory ON commands (directory, time);
+CREATE INDEX commands_exit ON commands (exit, time);
+CREATE INDEX commands_time ON commands (time);
+CREATE VIRTUAL TABLE commands_text USING fts5 (
+    command,
+    content = 'commands',
+    content_rowid = 'id',
+    tokenize = 'trigram'
+);
+CREATE TRIGGER commands_insert AFTER INSERT ON commands BEGIN
+    INSERT INTO commands_text (rowid, command) VALUES (new.id, new.command);
+END;
+CREATE TRIGGER commands_delete AFTER DELETE ON commands BEGIN
+    INSERT INTO commands_text (commands_text, rowid, command)
+        VALUES ('delete', old.id, old.command);
+END;
+INSERT INTO commands (command, directory, exit, time) VALUES ('cargo build', '/src', 0, 1700000000);
+INSERT INTO commands (command, directory, exit, time) VALUES ('cargo tset', '/src', 101, 1700000060);
+PRAGMA user_version = 1;
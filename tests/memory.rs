@@ -0,0 +1,188 @@
+//! Round trips of the history through its memory budget: the entries compressed in memory, or
+//! spilled to disk, must read back as they were written, down to how each command ended. The
+//! output of a command streamed is held within the budget too, however long it runs.
+
+use std::time::Duration;
+
+use shelgon::command::{
+    Chunk, CommandInput, CommandOutput, Execute, OutputAction, OutputStream, Prepare,
+};
+use shelgon::config::Config;
+use shelgon::renderer::App;
+use shelgon::testing::{Echo, MockTerminal, Script};
+
+/// The size of the output of each entry, in bytes.
+const OUTPUT: usize = 16 * 1024;
+
+/// Lines of hexadecimal digits out of a generator, which do not compress: the entries holding them
+/// are spilled whole.
+fn noise(seed: u64) -> Vec<String> {
+    let mut state = seed.wrapping_mul(6_364_136_223_846_793_005) | 1;
+    (0..OUTPUT / 64)
+        .map(|_| {
+            (0..4)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    format!("{:016x}", state)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Lines repeating themselves, which compress well: the entries holding them are compressed.
+fn repeated(index: usize) -> Vec<String> {
+    vec![format!("{:>63}", format!("line of entry {}", index)); OUTPUT / 64]
+}
+
+/// A history of 2 MiB, the older half not compressing and the newer half compressing, with every
+/// way a command ends.
+fn history() -> Vec<CommandOutput> {
+    (0..128)
+        .map(|index| CommandOutput {
+            prompt: format!("{}$", index),
+            command: format!("command {}", index),
+            stdin: vec![format!("input {}", index)],
+            stdout: if index < 64 {
+                noise(index as u64)
+            } else {
+                repeated(index)
+            },
+            stderr: match index % 3 {
+                0 => vec![format!("warning {}", index)],
+                _ => Vec::new(),
+            },
+            exit_code: match index % 4 {
+                0 => None,
+                1 => Some(0),
+                2 => Some(1),
+                _ => Some(128 + 9),
+            },
+            duration: Duration::from_millis(index as u64 * 37 + 1),
+        })
+        .collect()
+}
+
+/// Check that the entries are the same, field by field.
+fn assert_same(read: &CommandOutput, written: &CommandOutput) {
+    assert_eq!(read.prompt, written.prompt);
+    assert_eq!(read.command, written.command, "{}", written.command);
+    assert_eq!(read.stdin, written.stdin, "{}", written.command);
+    assert_eq!(read.stdout, written.stdout, "{}", written.command);
+    assert_eq!(read.stderr, written.stderr, "{}", written.command);
+    assert_eq!(read.exit_code, written.exit_code, "{}", written.command);
+    assert_eq!(read.duration, written.duration, "{}", written.command);
+}
+
+#[test]
+fn compressed_and_spilled_entries_read_back_as_written() -> anyhow::Result<()> {
+    // The entries are spilled to the data directory, which is the test's own.
+    let data = std::env::temp_dir().join(format!("shelgon-memory-{}", std::process::id()));
+    std::env::set_var("XDG_DATA_HOME", &data);
+
+    let mut config = Config::default();
+    config.history.memory_budget = Some(1);
+    let written = history();
+    let app = App::new_with_executor(
+        #[cfg(feature = "tokio")]
+        tokio::runtime::Runtime::new()?,
+        Echo::default(),
+        (),
+    )
+    .with_config(config)
+    .with_history(written.clone());
+
+    let transcript = MockTerminal::new(80, 20).run(app, Script::new().line("echo"))?;
+    // The newer half fits the budget once compressed, the older half only once spilled: the
+    // segment file was made (and removed once the shell was done with it).
+    let spilled = data.join("sheller/spill").is_dir();
+    let _ = std::fs::remove_dir_all(&data);
+    assert!(spilled, "nothing was spilled");
+
+    assert_eq!(transcript.history.len(), written.len() + 1);
+    for (read, written) in transcript.history.iter().zip(&written) {
+        assert_same(read, written);
+    }
+    Ok(())
+}
+
+/// An executor streaming `LINES` lines of output for every command, and then its exit code.
+struct Flood;
+
+/// The lines streamed by [`Flood`], 5 MiB or so along with their strings.
+const LINES: usize = 100_000;
+
+impl Execute for Flood {
+    type Context = ();
+
+    fn prompt(&self, _: &Self::Context) -> String {
+        "$".to_string()
+    }
+
+    fn prepare(&self, cmd: &str) -> Prepare {
+        Prepare {
+            command: cmd.to_string(),
+            stdin_required: false,
+        }
+    }
+
+    fn execute(&self, _: &mut Self::Context, input: CommandInput) -> anyhow::Result<OutputAction> {
+        let (stream, sender) = OutputStream::channel(input.prompt, input.command, Vec::new());
+        std::thread::spawn(move || {
+            for index in 0..LINES {
+                let line = format!("{:>31}", index);
+                if sender.send(Chunk::Stdout(line)).is_err() {
+                    return;
+                }
+            }
+            let _ = sender.send(Chunk::Exit(3));
+        });
+        Ok(OutputAction::Stream(stream))
+    }
+
+    fn completion(&self, _: &Self::Context, _: &str) -> anyhow::Result<(String, Vec<String>)> {
+        Ok((String::new(), Vec::new()))
+    }
+}
+
+#[test]
+fn streamed_output_is_held_within_the_budget() -> anyhow::Result<()> {
+    let mut config = Config::default();
+    config.history.memory_budget = Some(1);
+    let app = App::new_with_executor(
+        #[cfg(feature = "tokio")]
+        tokio::runtime::Runtime::new()?,
+        Flood,
+        (),
+    )
+    .with_config(config);
+
+    let transcript = MockTerminal::new(80, 20).run(app, Script::new().line("yes"))?;
+
+    let [entry] = &transcript.history[..] else {
+        panic!("{} entries in the history", transcript.history.len());
+    };
+    let bytes = entry
+        .stdout
+        .iter()
+        .map(|line| line.len() + std::mem::size_of::<String>())
+        .sum::<usize>();
+    assert!(bytes <= 1024 * 1024, "{} bytes held", bytes);
+    assert!(entry.stdout.len() < LINES);
+    // The lines kept are the first ones, in order, and the command still ended as it did.
+    assert_eq!(entry.stdout.first().map(|line| line.trim()), Some("0"));
+    assert_eq!(
+        entry.stdout.last().map(|line| line.trim()),
+        Some((entry.stdout.len() - 1).to_string().as_str())
+    );
+    assert_eq!(entry.exit_code, Some(3));
+
+    let dropped = format!(
+        "{} more lines of output dropped",
+        LINES - entry.stdout.len()
+    );
+    transcript.assert_screen_contains(&dropped);
+    Ok(())
+}